@@ -0,0 +1,55 @@
+//! Compares the speed of the interpreter stepping through every instruction with that of the
+//! interpreter running decoded blocks. Run with `cargo bench`.
+
+use five::emulator::{Emulator, StopReason};
+use std::time::{Duration, Instant};
+
+const INSTRUCTIONS: u64 = 20_000_000;
+
+// Sums and mixes an array of doublewords in place, over and over.
+const PROGRAM: [u32; 12] = [
+    0x0000_2597, // auipc a1, 2
+    0x2000_0613, // li a2, 512
+    0x0005_8693, // mv a3, a1
+    0x0006_b703, // ld a4, 0(a3)
+    0x00a7_0733, // add a4, a4, a0
+    0x02e7_07b3, // mul a5, a4, a4
+    0x00f5_4533, // xor a0, a0, a5
+    0x00e6_b023, // sd a4, 0(a3)
+    0x0086_8693, // addi a3, a3, 8
+    0xfff6_0613, // addi a2, a2, -1
+    0xfe06_12e3, // bnez a2, -28
+    0xfd5f_f06f, // jal zero, -44
+];
+
+/// Runs the program and returns the time it took and the checksum in a0.
+fn run(block_cache: bool) -> (Duration, u64) {
+    let image = PROGRAM
+        .iter()
+        .flat_map(|instruction| instruction.to_le_bytes())
+        .collect::<Vec<_>>();
+    let mut emulator = Emulator::default();
+    emulator.load_image(&image).unwrap();
+    emulator.set_block_cache(block_cache);
+    let start = Instant::now();
+    assert_eq!(emulator.run_for(INSTRUCTIONS), StopReason::InstructionLimit);
+    (start.elapsed(), emulator.cpu().x().readu(10))
+}
+
+fn main() {
+    let (stepped, expected) = run(false);
+    let (blocks, checksum) = run(true);
+    assert_eq!(checksum, expected, "the block cache changed the result");
+    for (name, time) in [("step", stepped), ("block cache", blocks)] {
+        println!(
+            "{:12} {:8.2?} {:8.1} MIPS",
+            name,
+            time,
+            INSTRUCTIONS as f64 / time.as_secs_f64() / 1e6
+        );
+    }
+    println!(
+        "speedup      {:.2}x",
+        stepped.as_secs_f64() / blocks.as_secs_f64()
+    );
+}
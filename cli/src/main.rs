@@ -1,13 +1,29 @@
+mod monitor;
+
 use clap::Parser;
 use five::{
     emulator::{
-        cpu::{csr::Csr, Cpu},
-        Emulator,
+        cpu::csr::Csr,
+        elf::Elf,
+        linux::Linux,
+        lockstep::TrapPolicy,
+        profile::Profiler,
+        replay::{Clock, InputLog},
+        semihosting::Semihosting,
+        snapshot::Snapshot,
+        trace::CommitLog,
+        Emulator, Region, StopReason,
     },
-    isa::csr::user_level::CYCLE,
+    isa::{csr::user_level::INSTRET, privileged::mode::PrivilegeMode},
 };
-use std::fs::File;
-use std::io::Result;
+use monitor::Monitor;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Result, Write};
+use std::net::TcpListener;
+use std::ops::Range;
+
+// The address that riscv-tests write the result to.
+const TOHOST: u64 = 0x80001000;
 
 #[derive(Parser)]
 struct Opts {
@@ -15,32 +31,318 @@ struct Opts {
     timeout: u64,
     #[clap(short, long, action)]
     debug: bool,
+    #[clap(
+        long,
+        conflicts_with_all = [
+            "log_commits",
+            "profile",
+            "timeout",
+            "save_at",
+            "linux",
+            "semihosting",
+        ]
+    )]
+    gdb: Option<u16>,
+    #[clap(long, action)]
+    ebreak_to_host: bool,
+    #[clap(short, long, action)]
+    interactive: bool,
+    #[clap(long)]
+    symbols: Option<String>,
+    /// Writes a Spike-compatible commit log to the file
+    #[clap(long)]
+    log_commits: Option<String>,
+    /// Logs only the pcs in <start>:<end> (hexadecimal)
+    #[clap(long, value_parser = parse_range, requires = "log_commits")]
+    log_range: Option<Range<u64>>,
+    /// Logs only the instructions in the privilege mode (m, s or u)
+    #[clap(long, value_parser = parse_privilege, requires = "log_commits")]
+    log_privilege: Option<PrivilegeMode>,
+    /// Writes the instruction mix, the hot spots and the trap counts to the file
+    #[clap(long)]
+    profile: Option<String>,
+    /// Writes the call stacks of the profile to the file in the folded format of flame graphs
+    #[clap(long, requires = "profile")]
+    profile_folded: Option<String>,
+    /// Runs alongside a reference commit log and stops at the first mismatch
+    #[clap(long, conflicts_with_all = ["linux", "semihosting"])]
+    lockstep: Option<String>,
+    /// Lets five take traps that the reference log has no exception lines for
+    #[clap(long, action, requires = "lockstep")]
+    lockstep_skip_traps: bool,
+    /// Saves a snapshot when the number of instructions retired reaches the value
+    #[clap(long)]
+    save_at: Option<u64>,
+    /// The file that --save-at writes the snapshot to
+    #[clap(long, default_value = "five.snapshot")]
+    snapshot: String,
+    /// Restores a snapshot before running
+    #[clap(long)]
+    restore: Option<String>,
+    /// Derives the time CSR from the mtime of the clint, which advances with the instructions,
+    /// instead of the wall clock
+    #[clap(long, action)]
+    deterministic: bool,
+    /// Records the nondeterministic inputs of the run to the file
+    #[clap(long, conflicts_with = "replay")]
+    record: Option<String>,
+    /// Replays the inputs recorded by --record
+    #[clap(long)]
+    replay: Option<String>,
+    /// The number of harts that share the memory and the devices
+    #[clap(long, default_value_t = 1)]
+    harts: usize,
+    /// The number of instructions that a hart runs before the next hart does
+    #[clap(long)]
+    quantum: Option<u64>,
+    /// Takes checkpoints so that the monitor can go back, which turns the block cache off
+    #[clap(long, action, requires = "interactive", conflicts_with_all = ["linux", "semihosting"])]
+    reverse: bool,
+    /// The number of instructions between the checkpoints that the monitor goes back from
+    #[clap(long, default_value_t = 1_000_000, requires = "reverse")]
+    checkpoint_interval: u64,
+    /// Runs the input as a static Linux executable in user mode
+    #[clap(long, action)]
+    linux: bool,
+    /// Serves semihosting calls with the files in the directory
+    #[clap(long, conflicts_with = "linux")]
+    semihosting: Option<String>,
+    /// The base address of the memory (hexadecimal)
+    #[clap(long, value_parser = parse_address, default_value = "80000000")]
+    memory_base: u64,
+    /// The size of the memory, optionally with a K, M or G suffix
+    #[clap(long, value_parser = parse_size, default_value = "1G")]
+    memory_size: u64,
+    /// Steps through every instruction instead of running decoded blocks
+    #[clap(long, action)]
+    no_block_cache: bool,
+    /// Maps the file at <address>:<path> as ROM (hexadecimal address)
+    #[clap(long, value_parser = parse_mapping)]
+    rom: Vec<(u64, String)>,
+    /// Maps the file at <address>:<path> as flash (hexadecimal address)
+    #[clap(long, value_parser = parse_mapping)]
+    flash: Vec<(u64, String)>,
+    /// Writes the writes to flash back to its files
+    #[clap(long, action, requires = "flash")]
+    persist_flash: bool,
     input: String,
+    /// The arguments of the Linux executable or of the semihosting command line
+    #[clap(last = true)]
+    args: Vec<String>,
+}
+
+fn parse_address(value: &str) -> std::result::Result<u64, String> {
+    u64::from_str_radix(value.trim_start_matches("0x"), 16)
+        .map_err(|_| format!("invalid address: {}", value))
+}
+
+fn parse_range(value: &str) -> std::result::Result<Range<u64>, String> {
+    let (start, end) = value
+        .split_once(':')
+        .ok_or(format!("expected <start>:<end>: {}", value))?;
+    Ok(parse_address(start)?..parse_address(end)?)
+}
+
+fn parse_mapping(value: &str) -> std::result::Result<(u64, String), String> {
+    let (address, path) = value
+        .split_once(':')
+        .ok_or(format!("expected <address>:<path>: {}", value))?;
+    Ok((parse_address(address)?, path.to_string()))
+}
+
+fn parse_size(value: &str) -> std::result::Result<u64, String> {
+    let (digits, unit) = match value.char_indices().last() {
+        Some((i, 'K' | 'k')) => (&value[..i], 1 << 10),
+        Some((i, 'M' | 'm')) => (&value[..i], 1 << 20),
+        Some((i, 'G' | 'g')) => (&value[..i], 1 << 30),
+        _ => (value, 1),
+    };
+    digits
+        .parse::<u64>()
+        .ok()
+        .and_then(|size| size.checked_mul(unit))
+        .ok_or(format!("invalid size: {}", value))
+}
+
+fn parse_privilege(value: &str) -> std::result::Result<PrivilegeMode, String> {
+    match value {
+        "m" => Ok(PrivilegeMode::Machine),
+        "s" => Ok(PrivilegeMode::Supervisor),
+        "u" => Ok(PrivilegeMode::User),
+        _ => Err(format!("invalid privilege mode: {}", value)),
+    }
+}
+
+/// The files that the emulator writes when it stops.
+struct Outputs {
+    record: Option<String>,
+    profile: Option<String>,
+    profile_folded: Option<String>,
 }
 
 fn main() -> Result<()> {
     let opts = Opts::parse();
     let input = opts.input;
-    let file = File::open(&input)?;
+    let linux = opts.linux;
+    // the guest exits with its own exit code instead of through tohost
+    let hosted = linux || opts.semihosting.is_some();
+    let outputs = Outputs {
+        record: opts.record.clone(),
+        profile: opts.profile.clone(),
+        profile_folded: opts.profile_folded.clone(),
+    };
     let mut emulator = Emulator::default();
-    emulator.load(file)?;
-    let terminator = Some(|cpu: &Cpu| {
-        if opts.timeout > 0 && cpu.csr.read(CYCLE) > opts.timeout {
-            println!("timeout");
-            return Some(0);
-        }
-        let value = cpu.bus.load64(0x80001000);
-        if value == 1 {
-            Some(1)
-        } else {
-            None
-        }
-    });
-    let result = emulator.run(opts.debug, terminator);
-    if result == 1 {
-        println!("PASS: {}", input);
+    emulator.set_harts(opts.harts);
+    if let Some(quantum) = opts.quantum {
+        emulator.set_quantum(quantum);
+    }
+    emulator.set_memory(opts.memory_base, opts.memory_size);
+    for (address, path) in opts.rom {
+        emulator.map(Region::rom(address, path)?)?;
+    }
+    for (address, path) in opts.flash {
+        emulator.map(Region::flash(address, path, opts.persist_flash)?)?;
+    }
+    let args = [input.clone()]
+        .into_iter()
+        .chain(opts.args)
+        .collect::<Vec<_>>();
+    let mut profiler = Profiler::default();
+    if linux {
+        let elf = fs::read(&input)?;
+        // the profile names the functions unless the symbol table cannot be read
+        if opts.profile.is_some() {
+            profiler.add_symbols(&Elf::symbols(&elf).unwrap_or_default());
+        }
+        let env = std::env::vars()
+            .map(|(key, value)| format!("{}={}", key, value))
+            .collect::<Vec<_>>();
+        emulator.load_linux(Linux::default(), &elf, &args, &env)?;
     } else {
-        println!("FAIL({}): {}", result, input);
+        emulator.load(File::open(&input)?)?;
+    }
+    if let Some(root) = opts.semihosting {
+        let mut semihosting = Semihosting::default();
+        semihosting.set_root(root);
+        semihosting.set_cmdline(args.join(" "));
+        emulator.set_semihosting(semihosting);
+    }
+    if let Some(path) = opts.restore {
+        emulator.restore(&Snapshot::load(&path)?);
+    }
+    emulator.set_ebreak_to_host(opts.ebreak_to_host);
+    if opts.deterministic {
+        emulator.set_clock(Clock::Mtime);
+    }
+    if opts.record.is_some() {
+        emulator.record_inputs();
+    }
+    if let Some(path) = opts.replay {
+        emulator.replay_inputs(InputLog::load(&path)?);
+    }
+    if let Some(port) = opts.gdb {
+        let listener = TcpListener::bind(("127.0.0.1", port))?;
+        println!("waiting for gdb on port {}", port);
+        emulator.gdb(listener)?;
+        return finish(emulator, &outputs);
+    }
+    emulator.set_debug(opts.debug);
+    emulator.set_block_cache(!opts.no_block_cache);
+    if !hosted {
+        emulator.set_tohost(TOHOST);
+    }
+    if let Some(path) = opts.log_commits {
+        let mut log = CommitLog::create(&path)?;
+        if let Some(range) = opts.log_range {
+            log.set_range(range);
+        }
+        if let Some(prv) = opts.log_privilege {
+            log.set_privilege(prv);
+        }
+        emulator.set_commit_log(log);
+    }
+    if outputs.profile.is_some() {
+        if let Some(path) = &opts.symbols {
+            // the text symbols of the output of `nm`
+            for line in fs::read_to_string(path)?.lines() {
+                if let [address, "t" | "T", name] = line.split_whitespace().collect::<Vec<_>>()[..]
+                {
+                    if let Ok(address) = u64::from_str_radix(address, 16) {
+                        profiler.add_symbol(name, address, 0);
+                    }
+                }
+            }
+        }
+        emulator.set_profiler(profiler);
+    }
+    if let Some(path) = opts.lockstep {
+        let reference = BufReader::new(File::open(path)?);
+        let mut lockstep = emulator.lockstep()?;
+        if opts.lockstep_skip_traps {
+            lockstep.set_trap_policy(TrapPolicy::Skip);
+        }
+        match lockstep.run(reference)? {
+            Some(mismatch) => print!("{}", mismatch),
+            None => println!("MATCH: {} instructions", lockstep.instructions()),
+        }
+        return Ok(());
+    }
+    if opts.interactive {
+        if opts.reverse {
+            emulator.enable_reverse(opts.checkpoint_interval)?;
+        }
+        let mut monitor = Monitor::new(emulator);
+        if let Some(symbols) = opts.symbols {
+            monitor.load_symbols(&symbols)?;
+        }
+        monitor.run()?;
+        return finish(monitor.into_emulator(), &outputs);
+    }
+    if let Some(instret) = opts.save_at {
+        let reason = emulator.run_until(|cpu| cpu.csr.read(INSTRET) >= instret);
+        if reason != StopReason::Condition {
+            println!("FAIL({:?}): {}", reason, input);
+            return finish(emulator, &outputs);
+        }
+        emulator.snapshot().save(&opts.snapshot)?;
+        println!("saved {} at {} instructions", opts.snapshot, instret);
+    }
+    let reason = if opts.timeout > 0 {
+        emulator.run_for(opts.timeout)
+    } else {
+        emulator.run()
+    };
+    if reason == StopReason::InstructionLimit {
+        println!("timeout");
+    }
+    match reason {
+        // the exit code of the guest becomes the exit code of the emulator
+        StopReason::Exit(code) if hosted => {
+            finish(emulator, &outputs)?;
+            std::process::exit(code as i32);
+        }
+        StopReason::Exit(0) => println!("PASS: {}", input),
+        StopReason::Exit(code) => println!("FAIL({}): {}", code, input),
+        reason => println!("FAIL({:?}): {}", reason, input),
+    }
+    finish(emulator, &outputs)
+}
+
+/// Saves the recorded inputs and the profile, and flushes the flash and the commit log.
+fn finish(mut emulator: Emulator, outputs: &Outputs) -> Result<()> {
+    emulator.flush()?;
+    if let (Some(path), Some(log)) = (&outputs.record, emulator.take_input_log()) {
+        log.save(path)?;
+    }
+    if let (Some(path), Some(profiler)) = (&outputs.profile, emulator.take_profiler()) {
+        let mut writer = BufWriter::new(File::create(path)?);
+        profiler.report(&mut writer)?;
+        writer.flush()?;
+        if let Some(path) = &outputs.profile_folded {
+            let mut writer = BufWriter::new(File::create(path)?);
+            profiler.write_folded(&mut writer)?;
+            writer.flush()?;
+        }
     }
-    Ok(())
+    emulator.finish_commit_log()
 }
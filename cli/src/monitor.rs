@@ -0,0 +1,349 @@
+use five::{
+    emulator::{
+        cpu::{csr::Csr, Cpu},
+        Emulator, StopReason,
+    },
+    isa::{
+        csr::{csraddress, csrname},
+        register::{fregister, xregister},
+    },
+};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io::{self, BufRead, Result, Write};
+
+const HELP: &str = "\
+step [n]                    execute n instructions (default 1)
+continue                    run until a breakpoint, a watchpoint or the end
+reverse-step [n]            go back n instructions (default 1, with --reverse)
+reverse-continue            go back to a breakpoint, a watchpoint or the oldest checkpoint
+break <addr|symbol>         set a breakpoint
+watch <addr|symbol>         stop when the doubleword at the address changes
+delete <addr|symbol>        remove a breakpoint or a watchpoint
+info                        list breakpoints and watchpoints
+x/<n><b|h|w|g> <addr>       examine memory
+regs                        print the pc and the integer registers
+fregs                       print the floating point registers
+csr <name|addr>             print a CSR
+disas [addr] [n]            disassemble n instructions (default pc and 8)
+set [reg] <register> <val>  write a register, the pc or a CSR
+quit                        exit the monitor";
+
+/// An interactive monitor that controls the emulator from the terminal.
+pub struct Monitor {
+    emulator: Emulator,
+    symbols: HashMap<String, u64>,
+    watchpoints: BTreeMap<u64, u64>,
+}
+
+impl Monitor {
+    pub fn new(emulator: Emulator) -> Self {
+        Self {
+            emulator,
+            symbols: HashMap::new(),
+            watchpoints: BTreeMap::new(),
+        }
+    }
+
+    pub fn into_emulator(self) -> Emulator {
+        self.emulator
+    }
+
+    /// Loads symbols from the output of `nm`.
+    pub fn load_symbols(&mut self, path: &str) -> Result<()> {
+        for line in fs::read_to_string(path)?.lines() {
+            if let [address, _, name] = line.split_whitespace().collect::<Vec<_>>()[..] {
+                if let Ok(address) = u64::from_str_radix(address, 16) {
+                    self.symbols.insert(name.to_string(), address);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn run(&mut self) -> Result<()> {
+        let stdin = io::stdin();
+        let mut lines = stdin.lock().lines();
+        let mut last = String::new();
+        self.print_location();
+        loop {
+            print!("(five) ");
+            io::stdout().flush()?;
+            let Some(line) = lines.next() else {
+                return Ok(());
+            };
+            let line = line?;
+            // an empty line repeats the last command
+            let line = if line.trim().is_empty() { last } else { line };
+            let words = line.split_whitespace().collect::<Vec<_>>();
+            match words[..] {
+                [] => {}
+                ["quit" | "q"] => return Ok(()),
+                [command, ref arguments @ ..] => {
+                    if let Err(message) = self.execute(command, arguments) {
+                        println!("{}", message);
+                    }
+                }
+            }
+            last = line;
+        }
+    }
+
+    fn execute(&mut self, command: &str, arguments: &[&str]) -> std::result::Result<(), String> {
+        match (command, arguments) {
+            ("help" | "h", []) => println!("{}", HELP),
+            ("step" | "s", []) => self.resume(Some(1)),
+            ("step" | "s", [n]) => self.resume(Some(parse_number(n)?)),
+            ("continue" | "c", []) => self.resume(None),
+            ("reverse-step" | "rs", []) => self.reverse(Some(1)),
+            ("reverse-step" | "rs", [n]) => self.reverse(Some(parse_number(n)?)),
+            ("reverse-continue" | "rc", []) => self.reverse(None),
+            ("break" | "b", [location]) => {
+                let address = self.parse_address(location)?;
+                self.emulator.add_breakpoint(address);
+                println!("breakpoint at {:x}", address);
+            }
+            ("watch" | "w", [location]) => {
+                let address = self.parse_address(location)?;
+                let value = self.load(address, 8)?;
+                self.watchpoints.insert(address, value);
+                println!("watchpoint at {:x}", address);
+            }
+            ("delete" | "d", [location]) => {
+                let address = self.parse_address(location)?;
+                if !self.emulator.remove_breakpoint(address)
+                    && self.watchpoints.remove(&address).is_none()
+                {
+                    return Err(format!("no breakpoint or watchpoint at {:x}", address));
+                }
+            }
+            ("info" | "i", []) => {
+                for address in self.emulator.breakpoints() {
+                    println!("breakpoint {:x}", address);
+                }
+                for address in self.watchpoints.keys() {
+                    println!("watchpoint {:x}", address);
+                }
+            }
+            ("regs", []) => {
+                println!("{:4}:{:16x}", "pc", self.cpu().pc());
+                println!("{}", self.cpu().x());
+            }
+            ("fregs", []) => println!("{}", self.cpu().f()),
+            ("csr", [name]) => {
+                let address = csraddress(name)
+                    .or_else(|| parse_number(name).ok())
+                    .filter(|address| self.cpu().csr.contains(*address))
+                    .ok_or(format!("unknown csr: {}", name))?;
+                println!(
+                    "{} = 0x{:x}",
+                    csrname(address),
+                    self.cpu().csr.read(address)
+                );
+            }
+            ("disas", []) => self.disassemble(self.cpu().pc(), 8),
+            ("disas", [location]) => self.disassemble(self.parse_address(location)?, 8),
+            ("disas", [location, n]) => {
+                self.disassemble(self.parse_address(location)?, parse_number(n)?)
+            }
+            ("set", ["reg", register, value] | [register, value]) => {
+                self.set_register(register, parse_number(value)?)?
+            }
+            (examine, [location]) if examine == "x" || examine.starts_with("x/") => {
+                let (count, size) = parse_examine(examine)?;
+                self.examine(self.parse_address(location)?, count, size)?;
+            }
+            _ => return Err(format!("unknown command: {} (try help)", command)),
+        }
+        Ok(())
+    }
+
+    fn cpu(&self) -> &Cpu {
+        self.emulator.cpu()
+    }
+
+    fn parse_address(&self, location: &str) -> std::result::Result<u64, String> {
+        match self.symbols.get(location) {
+            Some(address) => Ok(*address),
+            None => parse_number(location),
+        }
+    }
+
+    fn load(&self, address: u64, size: u64) -> std::result::Result<u64, String> {
+        let bus = &self.cpu().bus;
+        let mapped = address
+            .checked_add(size)
+            .is_some_and(|end| (address..end).all(|a| bus.is_mapped(a)));
+        if !mapped {
+            return Err(format!("cannot access memory at {:x}", address));
+        }
+        Ok(match size {
+            1 => bus.load8(address) as u64,
+            2 => bus.load16(address) as u64,
+            4 => bus.load32(address) as u64,
+            _ => bus.load64(address),
+        })
+    }
+
+    fn set_register(&mut self, register: &str, value: u64) -> std::result::Result<(), String> {
+        let cpu = self.emulator.cpu_mut();
+        if register == "pc" {
+            cpu.set_pc(value);
+        } else if let Some(register) = xregister(register) {
+            cpu.x_mut().writeu(register, value);
+        } else if let Some(register) = fregister(register) {
+            cpu.f_mut().writes(register, value as u32);
+        } else if let Some(address) = csraddress(register).filter(|a| cpu.csr.contains(*a)) {
+            // the fields that are not writable keep their values as for the guest
+            cpu.csr.csrrw(address, value);
+        } else {
+            return Err(format!("unknown register: {}", register));
+        }
+        Ok(())
+    }
+
+    fn examine(&self, address: u64, count: u64, size: u64) -> std::result::Result<(), String> {
+        let per_line = 16 / size;
+        let cannot_access = || format!("cannot access memory at {:x}", address);
+        for line in 0..count.div_ceil(per_line) {
+            let start = address
+                .checked_add(line * per_line * size)
+                .ok_or_else(cannot_access)?;
+            let mut output = format!("{:x}:", start);
+            for i in 0..per_line.min(count - line * per_line) {
+                let address = start.checked_add(i * size).ok_or_else(cannot_access)?;
+                let value = self.load(address, size)?;
+                output += &format!(" {:0width$x}", value, width = size as usize * 2);
+            }
+            println!("{}", output);
+        }
+        Ok(())
+    }
+
+    fn disassemble(&self, address: u64, count: u64) {
+        let symbols = self
+            .symbols
+            .iter()
+            .map(|(name, address)| (*address, name))
+            .collect::<HashMap<_, _>>();
+        for i in 0..count {
+            let Some(address) = address.checked_add(i * 4) else {
+                break;
+            };
+            if let Some(name) = symbols.get(&address) {
+                println!("{}:", name);
+            }
+            let marker = if address == self.cpu().pc() {
+                "=>"
+            } else {
+                "  "
+            };
+            match self.cpu().disassemble(address) {
+                Some(description) => {
+                    println!("{} {:x}: {}", marker, address, description.assembly())
+                }
+                None if self.cpu().bus.is_mapped(address) => {
+                    println!("{} {:x}: unknown", marker, address)
+                }
+                None => break,
+            }
+        }
+    }
+
+    fn print_location(&self) {
+        let pc = self.cpu().pc();
+        match self.cpu().disassemble(pc) {
+            Some(description) => println!("{:x}: {}", pc, description.assembly()),
+            None => println!("{:x}", pc),
+        }
+    }
+
+    /// Runs up to the number of instructions, or until something stops the hart.
+    fn resume(&mut self, steps: Option<u64>) {
+        let mut executed = 0;
+        let mut hit = None;
+        // the hart leaves debug mode when the monitor resumes it
+        self.emulator.cpu_mut().resume();
+        let watchpoints = &self.watchpoints;
+        let reason = self.emulator.run_until(|cpu| {
+            executed += 1;
+            hit = watchpoints
+                .iter()
+                .map(|(address, old)| (*address, *old, cpu.bus.load64(*address)))
+                .find(|(_, old, new)| old != new);
+            hit.is_some() || Some(executed) == steps
+        });
+        match reason {
+            StopReason::Breakpoint(_) => println!("breakpoint"),
+            StopReason::Exit(code) => println!("exit({})", code),
+            StopReason::Halt => println!("halted"),
+            StopReason::DebugMode => println!("debug mode"),
+            _ => {}
+        }
+        if let Some((address, old, new)) = hit {
+            println!("watchpoint {:x}: {:x} -> {:x}", address, old, new);
+            self.watchpoints.insert(address, new);
+        }
+        self.print_location();
+    }
+
+    /// Goes back up to the number of instructions, or to the last breakpoint or the last value
+    /// of a watchpoint before its current one.
+    fn reverse(&mut self, steps: Option<u64>) {
+        let watchpoints = &self.watchpoints;
+        let reason = match steps {
+            Some(steps) => self.emulator.reverse_for(steps),
+            None => self.emulator.reverse_until(|cpu| {
+                watchpoints
+                    .iter()
+                    .any(|(address, value)| cpu.bus.load64(*address) != *value)
+            }),
+        };
+        match reason {
+            StopReason::Breakpoint(_) => println!("breakpoint"),
+            StopReason::StartOfHistory => println!("start of history"),
+            _ => {}
+        }
+        // the watchpoints compare against the values at the new position
+        for (address, value) in self.watchpoints.iter_mut() {
+            let old = self.emulator.cpu().bus.load64(*address);
+            if reason == StopReason::Condition && old != *value {
+                println!("watchpoint {:x}: {:x} -> {:x}", address, old, value);
+            }
+            *value = old;
+        }
+        self.print_location();
+    }
+}
+
+/// Parses a hexadecimal number prefixed with 0x or a decimal number.
+fn parse_number(value: &str) -> std::result::Result<u64, String> {
+    match value.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => value.parse(),
+    }
+    .map_err(|_| format!("invalid number: {}", value))
+}
+
+/// Parses the count and the unit size of `x/<n><b|h|w|g>`.
+fn parse_examine(examine: &str) -> std::result::Result<(u64, u64), String> {
+    let format = examine.strip_prefix("x/").unwrap_or_default();
+    let digits = format.chars().take_while(char::is_ascii_digit).count();
+    let count = match &format[..digits] {
+        "" => 1,
+        count => parse_number(count)?,
+    };
+    let mut size = 4;
+    for unit in format[digits..].chars() {
+        size = match unit {
+            'b' => 1,
+            'h' => 2,
+            'w' => 4,
+            'g' => 8,
+            // only hexadecimal output is supported
+            'x' => size,
+            _ => return Err(format!("invalid format: {}", examine)),
+        };
+    }
+    Ok((count, size))
+}
@@ -1,32 +1,1105 @@
 mod bus;
 pub mod cpu;
+pub mod debug;
+pub mod elf;
+pub mod gdb;
+pub mod linux;
+pub mod lockstep;
+pub mod profile;
+pub mod replay;
+mod reverse;
+pub mod semihosting;
+pub mod snapshot;
+#[cfg(test)]
+pub(crate) mod testing;
+pub mod trace;
 
-use crate::emulator::{
-    bus::{memory::MEMORY_BASE_ADDRESS, Size},
-    cpu::Cpu,
+pub use bus::region::Region;
+
+use crate::{
+    emulator::{
+        bus::{
+            clint::Clint,
+            memory::{Memory, PAGE_SIZE},
+            SystemBus,
+        },
+        cpu::{csr::Csr, Cpu, Step},
+        debug::DebugModule,
+        elf::Elf,
+        gdb::GdbStub,
+        linux::{Linux, STACK_SIZE},
+        lockstep::Lockstep,
+        profile::Profiler,
+        replay::{Clock, InputLog, Inputs, Replay},
+        reverse::History,
+        semihosting::Semihosting,
+        snapshot::Snapshot,
+        trace::CommitLog,
+    },
+    isa::{csr::user_level::INSTRET, privileged::cause::Cause},
 };
+use std::collections::BTreeSet;
 use std::fs::File;
-use std::io::{BufReader, Read, Result};
+use std::io::{Error, ErrorKind, Read, Result};
+use std::net::TcpListener;
+
+/// The reason why the emulator has stopped running.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StopReason {
+    /// The pc has reached a breakpoint, or an EBREAK has been handed to the host.
+    Breakpoint(u64),
+    /// The hart has taken a trap while stopping on traps is enabled.
+    Trap(Cause),
+    /// The guest has written an exit code to tohost.
+    Exit(u64),
+    /// The requested number of instructions has been executed.
+    InstructionLimit,
+    /// The predicate passed to `run_until` has been satisfied.
+    Condition,
+    /// The hart is in debug mode and waits for the debug module to resume it.
+    DebugMode,
+    /// The hart can no longer make progress.
+    Halt,
+    /// Reverse execution has reached the oldest checkpoint.
+    StartOfHistory,
+}
 
-#[derive(Default)]
+// The number of steps that a hart runs before the next hart does.
+const DEFAULT_QUANTUM: u64 = 1000;
+
+/// The harts that share a bus, scheduled round-robin. The hart that runs owns the bus while the
+/// other harts wait with a detached bus.
 pub struct Emulator {
     cpu: Cpu,
+    // every hart by id, where the slot of the hart that runs holds a placeholder
+    harts: Vec<Cpu>,
+    current: usize,
+    quantum: u64,
+    // the steps that the hart has taken in its quantum
+    slice: u64,
+    // the harts that have waited for an interrupt in a row
+    idle: usize,
+    debug: bool,
+    tohost: Option<u64>,
+    stop_on_trap: bool,
+    breakpoints: BTreeSet<u64>,
+    commit_log: Option<CommitLog>,
+    profiler: Option<Profiler>,
+    history: Option<History>,
+    linux: Option<Linux>,
+    semihosting: Option<Semihosting>,
+    block_cache: bool,
+}
+
+impl Default for Emulator {
+    fn default() -> Self {
+        Self {
+            cpu: Cpu::default(),
+            harts: vec![Cpu::new(0, SystemBus::detached())],
+            current: 0,
+            quantum: DEFAULT_QUANTUM,
+            slice: 0,
+            idle: 0,
+            debug: false,
+            tohost: None,
+            stop_on_trap: false,
+            breakpoints: BTreeSet::new(),
+            commit_log: None,
+            profiler: None,
+            history: None,
+            linux: None,
+            semihosting: None,
+            block_cache: true,
+        }
+    }
 }
 
 impl Emulator {
-    pub fn load(&mut self, file: File) -> Result<()> {
-        let buffer = BufReader::new(file);
-        for (address, byte) in buffer.bytes().enumerate() {
-            self.cpu.bus.memory.store(
-                address as u64 + MEMORY_BASE_ADDRESS,
-                byte? as u64,
-                Size::Byte,
-            );
+    pub fn load(&mut self, mut file: File) -> Result<()> {
+        let mut image = vec![];
+        file.read_to_end(&mut image)?;
+        self.load_image(&image)
+    }
+
+    /// Replaces the memory with an empty memory of the size at the base. Every hart starts from
+    /// the base with the stack pointer at the end of the memory.
+    pub fn set_memory(&mut self, base: u64, size: u64) {
+        self.set_modified();
+        self.cpu.bus.memory = Memory::new(base, size);
+        let end = self.cpu.bus.memory.end();
+        for cpu in self.harts_mut() {
+            cpu.start_at(base, end);
+            cpu.clear_blocks();
+        }
+    }
+
+    /// Maps a host file into the address space as ROM or flash.
+    pub fn map(&mut self, region: Region) -> Result<()> {
+        self.set_modified();
+        self.cpu.bus.map(region)
+    }
+
+    /// Writes the flash regions back to their files.
+    pub fn flush(&mut self) -> Result<()> {
+        self.cpu.bus.flush()
+    }
+
+    /// Copies the image to the start of the memory.
+    pub fn load_image(&mut self, image: &[u8]) -> Result<()> {
+        let base = self.cpu.bus.memory.base();
+        if !self.cpu.bus.memory.store_bytes(base, image) {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "the image is larger than the memory",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Loads a static Linux executable and runs it in user mode from now on, with the host
+    /// serving its system calls. The first argument is the name of the program. Reverse
+    /// execution is turned off, since it would serve the system calls again. Unless the memory
+    /// holds the segments with the stack above them, it moves to the first segment and grows to
+    /// hold them. The addresses of the devices become memory.
+    pub fn load_linux(
+        &mut self,
+        mut linux: Linux,
+        elf: &[u8],
+        args: &[String],
+        env: &[String],
+    ) -> Result<()> {
+        let elf = Elf::parse(elf)?;
+        let memory = &self.cpu.bus.memory;
+        let start = elf.segments.iter().map(|segment| segment.address).min();
+        let top = elf.end().checked_add(STACK_SIZE);
+        if let (Some(start), Some(top)) = (start, top) {
+            if start < memory.base() || top > memory.end() {
+                let base = start - start % PAGE_SIZE as u64;
+                self.set_memory(base, (top - base).max(memory.size()));
+            }
         }
+        self.cpu.bus.hide_devices();
+        linux.load(&mut self.cpu, &elf, args, env)?;
+        for cpu in self.harts_mut() {
+            cpu.ecall_to_host = true;
+        }
+        self.linux = Some(linux);
+        self.history = None;
         Ok(())
     }
 
-    pub fn run(&mut self, debug: bool, terminator: Option<impl Fn(&Cpu) -> Option<u64>>) -> u64 {
-        self.cpu.run(debug, terminator)
+    /// Returns the hart that runs next.
+    pub fn cpu(&self) -> &Cpu {
+        &self.cpu
+    }
+
+    pub fn cpu_mut(&mut self) -> &mut Cpu {
+        self.set_modified();
+        &mut self.cpu
+    }
+
+    /// Sets the number of harts that share the bus. Every hart starts from reset with its id in
+    /// mhartid, and hart 0 runs first.
+    pub fn set_harts(&mut self, harts: usize) {
+        self.set_modified();
+        self.switch_to(0);
+        let harts = harts.max(1);
+        self.harts = (0..harts)
+            .map(|hart| {
+                let mut cpu = Cpu::new(hart, SystemBus::detached());
+                cpu.ebreak_to_host = self.cpu.ebreak_to_host;
+                cpu.ecall_to_host = self.cpu.ecall_to_host;
+                cpu.semihosting = self.cpu.semihosting;
+                cpu.clock = self.cpu.clock;
+                #[cfg(feature = "jit")]
+                {
+                    cpu.jit_threshold = self.cpu.jit_threshold;
+                }
+                cpu.shared = harts > 1;
+                cpu.start_at(self.cpu.bus.memory.base(), self.cpu.bus.memory.end());
+                cpu
+            })
+            .collect();
+        self.cpu.shared = harts > 1;
+        self.cpu.bus.clint = Clint::new(harts);
+        self.idle = 0;
+    }
+
+    pub fn harts(&self) -> usize {
+        self.harts.len()
+    }
+
+    /// Returns the hart with the id. Only the hart that runs next, i.e. `cpu()`, has the bus; the
+    /// bus of the other harts is detached and must not be accessed.
+    pub fn hart(&self, hart: usize) -> &Cpu {
+        if hart == self.current {
+            &self.cpu
+        } else {
+            &self.harts[hart]
+        }
+    }
+
+    pub fn hart_mut(&mut self, hart: usize) -> &mut Cpu {
+        self.set_modified();
+        if hart == self.current {
+            &mut self.cpu
+        } else {
+            &mut self.harts[hart]
+        }
+    }
+
+    /// Sets the number of steps that a hart runs before the next hart does.
+    pub fn set_quantum(&mut self, quantum: u64) {
+        self.quantum = quantum.max(1);
+    }
+
+    /// Hands the bus over to the hart and makes it run next.
+    fn switch_to(&mut self, hart: usize) {
+        self.slice = 0;
+        if hart == self.current {
+            return;
+        }
+        std::mem::swap(&mut self.cpu.bus, &mut self.harts[hart].bus);
+        std::mem::swap(&mut self.cpu, &mut self.harts[hart]);
+        // the hart that ran takes its slot back and the placeholder moves to the new slot
+        self.harts.swap(hart, self.current);
+        self.current = hart;
+    }
+
+    /// Counts a step of the hart that runs and hands over to the next hart at the end of the
+    /// quantum or when the hart waits for an interrupt. Returns false when every hart waits for
+    /// an interrupt that nothing can raise.
+    fn schedule(&mut self, step: Step) -> bool {
+        let next = (self.current + 1) % self.harts.len();
+        match step {
+            Step::Retired | Step::Trap(_) | Step::Syscall | Step::Semihosting => {
+                if let Some(history) = &mut self.history {
+                    history.advance();
+                }
+                self.retire(1);
+            }
+            Step::Idle => {
+                self.idle += 1;
+                if self.idle >= self.harts.len() {
+                    // every hart waits: advance the time to the first deadline of a timer
+                    let clint = &mut self.cpu.bus.clint;
+                    match (0..clint.harts())
+                        .filter_map(|hart| clint.next_deadline(hart))
+                        .min()
+                    {
+                        Some(deadline) => clint.advance_to(deadline),
+                        None => {
+                            self.idle = 0;
+                            return false;
+                        }
+                    }
+                    self.idle = 0;
+                }
+                self.switch_to(next);
+            }
+            _ => {}
+        }
+        true
+    }
+
+    /// Counts the instructions that the hart has retired and hands over to the next hart at the
+    /// end of the quantum.
+    fn retire(&mut self, instructions: u64) {
+        self.idle = 0;
+        self.slice += instructions;
+        if self.slice >= self.quantum {
+            self.switch_to((self.current + 1) % self.harts.len());
+        }
+    }
+
+    fn harts_mut(&mut self) -> impl Iterator<Item = &mut Cpu> {
+        std::iter::once(&mut self.cpu).chain(self.harts.iter_mut())
+    }
+
+    /// Halts and resumes the hart through the debug module. Fails with more than one hart.
+    pub fn debug_module(&mut self) -> Result<DebugModule<'_>> {
+        self.require_single_hart("the debug module")?;
+        self.set_modified();
+        Ok(DebugModule::new(&mut self.cpu))
+    }
+
+    /// Captures the state of the machine.
+    pub fn snapshot(&self) -> Snapshot {
+        capture(&self.cpu, &self.harts, self.current, self.slice)
+    }
+
+    /// Replaces the state of the machine with the snapshot.
+    pub fn restore(&mut self, snapshot: &Snapshot) {
+        self.set_modified();
+        self.apply(snapshot);
+    }
+
+    fn apply(&mut self, snapshot: &Snapshot) {
+        if snapshot.harts() != self.harts.len() {
+            self.set_harts(snapshot.harts());
+        }
+        let (base, size) = snapshot.memory();
+        if (self.cpu.bus.memory.base(), self.cpu.bus.memory.size()) != (base, size) {
+            self.set_memory(base, size);
+        }
+        self.switch_to(snapshot.current());
+        self.slice = snapshot.slice();
+        self.idle = 0;
+        for hart in 0..self.harts.len() {
+            let cpu = if hart == self.current {
+                &mut self.cpu
+            } else {
+                &mut self.harts[hart]
+            };
+            snapshot.restore_hart(hart, cpu);
+        }
+        snapshot.restore_bus(&mut self.cpu.bus);
+    }
+
+    /// Steps the hart alongside a reference commit log. Fails with more than one hart or while
+    /// the host serves system calls or semihosting.
+    pub fn lockstep(&mut self) -> Result<Lockstep<'_>> {
+        self.require_single_hart("lockstep")?;
+        self.require_unhosted("lockstep")?;
+        self.set_modified();
+        Ok(Lockstep::new(&mut self.cpu))
+    }
+
+    /// Prints every instruction and the registers it changes.
+    pub fn set_debug(&mut self, debug: bool) {
+        self.debug = debug;
+    }
+
+    /// Sets the address of the tohost word that the guest writes `(code << 1) | 1` to on exit.
+    pub fn set_tohost(&mut self, address: u64) {
+        self.tohost = Some(address);
+    }
+
+    pub fn set_stop_on_trap(&mut self, stop_on_trap: bool) {
+        self.stop_on_trap = stop_on_trap;
+    }
+
+    /// Stops at EBREAK instead of raising a breakpoint exception in the guest. The pc is left at
+    /// the EBREAK, so the host has to move it before resuming.
+    pub fn set_ebreak_to_host(&mut self, ebreak_to_host: bool) {
+        for cpu in self.harts_mut() {
+            cpu.ebreak_to_host = ebreak_to_host;
+        }
+    }
+
+    /// Serves the semihosting calls, i.e. the EBREAKs between `slli zero, zero, 0x1f` and
+    /// `srai zero, zero, 7`, instead of raising breakpoint exceptions for them. Reverse
+    /// execution is turned off.
+    pub fn set_semihosting(&mut self, semihosting: Semihosting) {
+        self.history = None;
+        for cpu in self.harts_mut() {
+            cpu.semihosting = true;
+        }
+        self.semihosting = Some(semihosting);
+    }
+
+    /// Sets the source of the time CSR. `Clock::Mtime` makes runs reproducible.
+    pub fn set_clock(&mut self, clock: Clock) {
+        for cpu in self.harts_mut() {
+            cpu.clock = clock;
+        }
+    }
+
+    /// Records the nondeterministic inputs from now on.
+    pub fn record_inputs(&mut self) {
+        self.cpu.bus.inputs = Inputs::Record(InputLog::default());
+    }
+
+    /// Feeds the inputs of a recorded run to the machine instead of the inputs of the host.
+    pub fn replay_inputs(&mut self, log: InputLog) {
+        self.cpu.bus.inputs = Inputs::Replay(Replay::new(log));
+    }
+
+    /// Stops recording and returns the inputs recorded so far.
+    pub fn take_input_log(&mut self) -> Option<InputLog> {
+        match std::mem::take(&mut self.cpu.bus.inputs) {
+            Inputs::Record(log) => Some(log),
+            inputs => {
+                self.cpu.bus.inputs = inputs;
+                None
+            }
+        }
+    }
+
+    /// Writes a line to the commit log for every instruction retired from now on.
+    pub fn set_commit_log(&mut self, log: CommitLog) {
+        self.commit_log = Some(log);
+    }
+
+    /// Stops writing the commit log and flushes it.
+    pub fn finish_commit_log(&mut self) -> Result<()> {
+        match self.commit_log.take() {
+            Some(log) => log.finish(&mut self.cpu),
+            None => Ok(()),
+        }
+    }
+
+    /// Counts the instructions retired and the traps taken from now on.
+    pub fn set_profiler(&mut self, profiler: Profiler) {
+        self.profiler = Some(profiler);
+    }
+
+    /// Stops profiling and returns the counts so far.
+    pub fn take_profiler(&mut self) -> Option<Profiler> {
+        self.profiler.take()
+    }
+
+    pub fn add_breakpoint(&mut self, address: u64) {
+        self.breakpoints.insert(address);
+    }
+
+    pub fn remove_breakpoint(&mut self, address: u64) -> bool {
+        self.breakpoints.remove(&address)
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = &u64> {
+        self.breakpoints.iter()
+    }
+
+    /// Executes a single instruction.
+    pub fn step(&mut self) -> StopReason {
+        self.run_for(1)
+    }
+
+    /// Executes up to the number of instructions.
+    pub fn run_for(&mut self, instructions: u64) -> StopReason {
+        self.execute(Some(instructions), None)
+    }
+
+    /// Executes instructions until the predicate holds after an instruction.
+    pub fn run_until(&mut self, mut predicate: impl FnMut(&Cpu) -> bool) -> StopReason {
+        self.execute(None, Some(&mut predicate))
+    }
+
+    /// Executes instructions until something stops the emulator.
+    pub fn run(&mut self) -> StopReason {
+        self.execute(None, None)
+    }
+
+    /// Runs the straight-line code from blocks decoded once instead of stepping through every
+    /// instruction, when nothing has to see the instructions one by one. It is on by default.
+    pub fn set_block_cache(&mut self, enabled: bool) {
+        self.block_cache = enabled;
+    }
+
+    /// Sets the number of times that a block runs before it is compiled. Tests set it to 0 so
+    /// that the compiled code runs every block.
+    #[cfg(feature = "jit")]
+    #[doc(hidden)]
+    pub fn set_jit_threshold(&mut self, threshold: u32) {
+        for cpu in self.harts_mut() {
+            cpu.jit_threshold = threshold;
+        }
+    }
+
+    fn execute(
+        &mut self,
+        limit: Option<u64>,
+        mut predicate: Option<&mut dyn FnMut(&Cpu) -> bool>,
+    ) -> StopReason {
+        if limit == Some(0) {
+            return StopReason::InstructionLimit;
+        }
+        let blocks = self.block_cache
+            && predicate.is_none()
+            && !self.debug
+            && self.breakpoints.is_empty()
+            && self.commit_log.is_none()
+            && self.profiler.is_none()
+            && self.history.is_none();
+        // the instruction at the current pc is executed even if it has a breakpoint so that
+        // the emulator can resume from the breakpoint
+        let mut executed = 0;
+        loop {
+            if blocks {
+                // the blocks stop after a store to tohost, which is checked right away
+                let budget = limit
+                    .map_or(u64::MAX, |limit| limit - executed)
+                    .min(self.quantum.saturating_sub(self.slice));
+                let retired = self.cpu.run_blocks(budget, self.tohost);
+                if retired > 0 {
+                    self.retire(retired);
+                    executed += retired;
+                    if let Some(code) = self.exit_code() {
+                        return StopReason::Exit(code);
+                    }
+                    if Some(executed) == limit {
+                        return StopReason::InstructionLimit;
+                    }
+                    continue;
+                }
+            }
+            if let Some(history) = &mut self.history {
+                history.checkpoint(|| capture(&self.cpu, &self.harts, self.current, self.slice));
+            }
+            if let Some(log) = &mut self.commit_log {
+                log.begin(&mut self.cpu);
+            }
+            if let Some(profiler) = &mut self.profiler {
+                profiler.begin(&self.cpu);
+            }
+            let step = self.cpu.step(self.debug);
+            // the commit log shows the result of the call as a write of the instruction
+            let exit = match step {
+                Step::Syscall => self
+                    .linux
+                    .as_mut()
+                    .and_then(|linux| linux.syscall(&mut self.cpu)),
+                Step::Semihosting => self
+                    .semihosting
+                    .as_mut()
+                    .and_then(|semihosting| semihosting.call(&mut self.cpu)),
+                _ => None,
+            };
+            if let Some(log) = &mut self.commit_log {
+                log.commit(&mut self.cpu, step);
+            }
+            if let Some(profiler) = &mut self.profiler {
+                profiler.commit(&self.cpu, step);
+            }
+            if !self.schedule(step) {
+                return StopReason::Halt;
+            }
+            match step {
+                Step::Idle => continue,
+                Step::Halted => return StopReason::Halt,
+                Step::Breakpoint => return StopReason::Breakpoint(self.cpu.pc()),
+                Step::Debug => return StopReason::DebugMode,
+                Step::Trap(cause) if self.stop_on_trap => return StopReason::Trap(cause),
+                _ => {}
+            }
+            // taking an interrupt does not retire an instruction
+            if !matches!(step, Step::Trap(Cause::Interrupt(_))) {
+                executed += 1;
+            }
+            if let Some(code) = exit.or_else(|| self.exit_code()) {
+                return StopReason::Exit(code);
+            }
+            let pc = self.cpu.pc();
+            if self.breakpoints.contains(&pc) {
+                return StopReason::Breakpoint(pc);
+            }
+            if predicate
+                .as_mut()
+                .is_some_and(|predicate| predicate(&self.cpu))
+            {
+                return StopReason::Condition;
+            }
+            if Some(executed) == limit {
+                return StopReason::InstructionLimit;
+            }
+        }
+    }
+
+    /// Steps the harts until one of them makes progress, as the original run did.
+    fn advance(&mut self) -> Step {
+        loop {
+            let step = self.cpu.step(false);
+            if !self.schedule(step) {
+                return Step::Halted;
+            }
+            if step != Step::Idle {
+                return step;
+            }
+        }
+    }
+
+    /// Takes checkpoints so that the emulator can run backwards. The inputs from the host are
+    /// recorded so that re-execution reads the same values. Fails while the host serves system
+    /// calls or semihosting, since re-execution would serve them again.
+    pub fn enable_reverse(&mut self, interval: u64) -> Result<()> {
+        if self.linux.is_some() || self.semihosting.is_some() {
+            return Err(Error::new(
+                ErrorKind::Unsupported,
+                "reverse execution cannot replay system calls or semihosting",
+            ));
+        }
+        self.history = Some(History::new(interval));
+        if matches!(self.cpu.bus.inputs, Inputs::Live) && self.cpu.clock == Clock::WallClock {
+            self.cpu.bus.inputs = Inputs::Record(InputLog::default());
+        }
+        Ok(())
+    }
+
+    /// Goes back a single instruction.
+    pub fn reverse_step(&mut self) -> StopReason {
+        self.reverse_for(1)
+    }
+
+    /// Goes back up to the number of instructions.
+    pub fn reverse_for(&mut self, instructions: u64) -> StopReason {
+        let Some(history) = &self.history else {
+            return StopReason::StartOfHistory;
+        };
+        let Some(oldest) = history.oldest() else {
+            return StopReason::StartOfHistory;
+        };
+        match history.position().checked_sub(instructions) {
+            Some(position) if position >= oldest => {
+                self.rewind(|emulator| emulator.travel(position));
+                StopReason::InstructionLimit
+            }
+            _ => {
+                self.rewind(|emulator| emulator.travel(oldest));
+                StopReason::StartOfHistory
+            }
+        }
+    }
+
+    /// Goes back to the last breakpoint.
+    pub fn reverse_continue(&mut self) -> StopReason {
+        self.reverse_until(|_| false)
+    }
+
+    /// Goes back to the last state at a breakpoint or where the predicate holds.
+    pub fn reverse_until(&mut self, mut predicate: impl FnMut(&Cpu) -> bool) -> StopReason {
+        self.rewind(|emulator| {
+            let Some(history) = &emulator.history else {
+                return StopReason::StartOfHistory;
+            };
+            let mut end = history.position();
+            // search the intervals between the checkpoints from the newest to the oldest
+            while let Some(start) = end.checked_sub(1).and_then(|p| emulator.before(p)) {
+                emulator.travel(start);
+                let mut stop = None;
+                for position in start..end {
+                    if position > start {
+                        emulator.advance();
+                        emulator.exit_code();
+                    }
+                    let pc = emulator.cpu.pc();
+                    if emulator.breakpoints.contains(&pc) {
+                        stop = Some((position, StopReason::Breakpoint(pc)));
+                    } else if predicate(&emulator.cpu) {
+                        stop = Some((position, StopReason::Condition));
+                    }
+                }
+                if let Some((position, reason)) = stop {
+                    emulator.travel(position);
+                    return reason;
+                }
+                end = start;
+            }
+            if let Some(oldest) = emulator.history.as_ref().and_then(History::oldest) {
+                emulator.travel(oldest);
+            }
+            StopReason::StartOfHistory
+        })
+    }
+
+    fn before(&self, position: u64) -> Option<u64> {
+        self.history.as_ref()?.before(position)
+    }
+
+    /// Restores the last checkpoint at or before the position and re-executes up to it.
+    fn travel(&mut self, position: u64) {
+        let Some(mut history) = self.history.take() else {
+            return;
+        };
+        if let Some(snapshot) = history.rewind(position) {
+            self.apply(snapshot);
+        }
+        self.history = Some(history);
+        while self
+            .history
+            .as_ref()
+            .is_some_and(|h| h.position() < position)
+        {
+            if !matches!(
+                self.advance(),
+                Step::Retired | Step::Trap(_) | Step::Syscall | Step::Semihosting
+            ) {
+                break;
+            }
+            // the guest sees the acknowledgement of tohost as in the original run
+            self.exit_code();
+        }
+    }
+
+    /// Replays the recorded inputs while going back, and discards the inputs after the new
+    /// position.
+    fn rewind<T>(&mut self, travel: impl FnOnce(&mut Self) -> T) -> T {
+        let recording = match std::mem::take(&mut self.cpu.bus.inputs) {
+            Inputs::Record(log) => {
+                self.cpu.bus.inputs = Inputs::Replay(Replay::new(log));
+                true
+            }
+            inputs => {
+                self.cpu.bus.inputs = inputs;
+                false
+            }
+        };
+        let result = travel(self);
+        if let (true, Inputs::Replay(replay)) =
+            (recording, std::mem::take(&mut self.cpu.bus.inputs))
+        {
+            let mut log = replay.into_log();
+            for hart in 0..self.harts.len() {
+                log.truncate(hart, self.hart(hart).csr.read(INSTRET));
+            }
+            self.cpu.bus.inputs = Inputs::Record(log);
+        }
+        result
+    }
+
+    /// Fails with more than one hart for the drivers that step only `cpu()` instead of going
+    /// through the scheduler.
+    fn require_single_hart(&self, driver: &str) -> Result<()> {
+        if self.harts.len() > 1 {
+            return Err(Error::new(
+                ErrorKind::Unsupported,
+                format!("{} runs only one hart", driver),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Fails while the host serves system calls or semihosting for the drivers that step the
+    /// hart by itself, since they would take the calls as retired instructions.
+    fn require_unhosted(&self, driver: &str) -> Result<()> {
+        if self.linux.is_some() || self.semihosting.is_some() {
+            return Err(Error::new(
+                ErrorKind::Unsupported,
+                format!("{} cannot serve system calls or semihosting", driver),
+            ));
+        }
+        Ok(())
+    }
+
+    fn set_modified(&mut self) {
+        if let Some(history) = &mut self.history {
+            history.set_modified();
+        }
+    }
+
+    /// Returns the exit code written to tohost and acknowledges it.
+    fn exit_code(&mut self) -> Option<u64> {
+        let tohost = self.tohost.filter(|a| self.cpu.bus.is_mapped(*a))?;
+        let value = self.cpu.bus.load64(tohost);
+        if value & 1 == 0 {
+            return None;
+        }
+        self.cpu.bus.store64(tohost, 0);
+        Some(value >> 1)
+    }
+
+    /// Waits for a connection from GDB on the listener and serves it. Fails with more than one
+    /// hart or while the host serves system calls or semihosting.
+    pub fn gdb(&mut self, listener: TcpListener) -> Result<()> {
+        self.require_single_hart("the gdb stub")?;
+        self.require_unhosted("the gdb stub")?;
+        let (stream, _) = listener.accept()?;
+        GdbStub::new(&mut self.cpu, stream).serve()
+    }
+}
+
+/// Captures the harts in the order of their ids.
+fn capture(cpu: &Cpu, harts: &[Cpu], current: usize, slice: u64) -> Snapshot {
+    let harts = (0..harts.len())
+        .map(|hart| if hart == current { cpu } else { &harts[hart] })
+        .collect::<Vec<_>>();
+    Snapshot::new(&harts, current, slice)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        emulator::{bus::memory::MEMORY_BASE_ADDRESS, testing::emulator},
+        isa::{
+            csr::{
+                machine_level::{MEPC, MHARTID, MIE, MSTATUS, MTVEC},
+                user_level::{CYCLE, TIME},
+            },
+            privileged::cause::Exception,
+        },
+    };
+
+    const NOP: u32 = 0x0000_0013;
+    // sd a0, 0(a1)
+    const SD: u32 = 0x00a5_b023;
+    const ECALL: u32 = 0x0000_0073;
+    const EBREAK: u32 = 0x0010_0073;
+    const WFI: u32 = 0x1050_0073;
+    // csrr a0, mhartid
+    const CSRR_MHARTID: u32 = 0xf140_2573;
+    const TOHOST: u64 = MEMORY_BASE_ADDRESS + 0x1000;
+
+    #[test]
+    fn run_for_ok() {
+        let mut emulator = emulator(&[NOP; 4]);
+        assert_eq!(emulator.run_for(0), StopReason::InstructionLimit);
+        assert_eq!(emulator.step(), StopReason::InstructionLimit);
+        assert_eq!(emulator.run_for(2), StopReason::InstructionLimit);
+        assert_eq!(emulator.cpu().pc(), MEMORY_BASE_ADDRESS + 12);
+    }
+
+    #[test]
+    fn run_for_interrupt_ok() {
+        let mut emulator = emulator(&[NOP; 4]);
+        emulator.set_block_cache(false);
+        let cpu = emulator.cpu_mut();
+        cpu.csr.write(MTVEC, MEMORY_BASE_ADDRESS + 8);
+        cpu.csr.write(MIE, 1 << 7);
+        cpu.csr.write(MSTATUS, 1 << 3);
+        cpu.bus.store64(0x0200_4000, 0);
+        // the interrupt is taken before the two instructions of the handler
+        assert_eq!(emulator.run_for(2), StopReason::InstructionLimit);
+        assert_eq!(emulator.cpu().csr.read(INSTRET), 2);
+        assert_eq!(emulator.cpu().pc(), MEMORY_BASE_ADDRESS + 16);
+    }
+
+    #[test]
+    fn breakpoint_resume_ok() {
+        let mut emulator = emulator(&[NOP; 4]);
+        emulator.add_breakpoint(MEMORY_BASE_ADDRESS + 4);
+        emulator.add_breakpoint(MEMORY_BASE_ADDRESS + 8);
+        assert_eq!(
+            emulator.run_for(10),
+            StopReason::Breakpoint(MEMORY_BASE_ADDRESS + 4)
+        );
+        assert_eq!(
+            emulator.run_for(10),
+            StopReason::Breakpoint(MEMORY_BASE_ADDRESS + 8)
+        );
+        assert!(emulator.remove_breakpoint(MEMORY_BASE_ADDRESS + 4));
+        assert_eq!(
+            emulator.run_until(|cpu| cpu.pc() == MEMORY_BASE_ADDRESS + 12),
+            StopReason::Condition
+        );
+    }
+
+    #[test]
+    fn exit_ok() {
+        let mut emulator = emulator(&[SD, SD]);
+        emulator.set_tohost(TOHOST);
+        emulator.cpu_mut().x_mut().writeu(10, 3 << 1 | 1);
+        emulator.cpu_mut().x_mut().writeu(11, TOHOST);
+        assert_eq!(emulator.run(), StopReason::Exit(3));
+        assert_eq!(emulator.cpu().bus.load64(TOHOST), 0);
+        assert_eq!(emulator.run(), StopReason::Exit(3));
+    }
+
+    #[test]
+    fn stop_on_trap_ok() {
+        let mut emulator = emulator(&[ECALL]);
+        emulator
+            .cpu_mut()
+            .csr
+            .write(MTVEC, MEMORY_BASE_ADDRESS + 0x100);
+        emulator.set_stop_on_trap(true);
+        assert_eq!(
+            emulator.run(),
+            StopReason::Trap(Cause::Exception(Exception::EnvironmentCallFromMachineMode))
+        );
+        assert_eq!(emulator.cpu().pc(), MEMORY_BASE_ADDRESS + 0x100);
+    }
+
+    #[test]
+    fn ebreak_to_host_ok() {
+        let mut emulator = emulator(&[NOP, EBREAK]);
+        emulator.set_ebreak_to_host(true);
+        assert_eq!(
+            emulator.run(),
+            StopReason::Breakpoint(MEMORY_BASE_ADDRESS + 4)
+        );
+        emulator.cpu_mut().set_pc(MEMORY_BASE_ADDRESS + 8);
+        assert_eq!(emulator.step(), StopReason::InstructionLimit);
+    }
+
+    #[test]
+    fn memory_ok() {
+        let mut emulator = Emulator::default();
+        emulator.set_harts(2);
+        emulator.set_memory(0x4000_0000, 0x10000);
+        for hart in 0..2 {
+            assert_eq!(emulator.hart(hart).pc(), 0x4000_0000);
+            assert_eq!(emulator.hart(hart).x().readu(2), 0x4001_0000);
+        }
+        assert!(emulator.load_image(&SD.to_le_bytes()).is_ok());
+        assert!(emulator.load_image(&[0; 0x10001]).is_err());
+        emulator.cpu_mut().x_mut().writeu(10, 42);
+        emulator.cpu_mut().x_mut().writeu(11, 0x4000_fff8);
+        emulator.step();
+        assert_eq!(emulator.cpu().bus.load64(0x4000_fff8), 42);
+        assert!(!emulator.cpu().bus.is_mapped(MEMORY_BASE_ADDRESS));
+    }
+
+    #[test]
+    fn harts_ok() {
+        let mut emulator = emulator(&[
+            // auipc t0, 0
+            0x0000_0297,
+            CSRR_MHARTID,
+            // slli a1, a0, 3
+            0x0035_1593,
+            // add a1, a1, t0
+            0x0055_85b3,
+            // addi a0, a0, 1
+            0x0015_0513,
+            // sd a0, 0x100(a1)
+            0x10a5_b023,
+            WFI,
+        ]);
+        emulator.set_harts(2);
+        emulator.set_quantum(1);
+        assert_eq!(emulator.harts(), 2);
+        assert_eq!(emulator.hart(1).csr.read(MHARTID), 1);
+        // every hart waits for an interrupt that nothing raises
+        assert_eq!(emulator.run(), StopReason::Halt);
+        let bus = &emulator.cpu().bus;
+        assert_eq!(bus.load64(MEMORY_BASE_ADDRESS + 0x100), 1);
+        assert_eq!(bus.load64(MEMORY_BASE_ADDRESS + 0x108), 2);
+        assert_eq!(emulator.hart(1).x().readu(10), 2);
+
+        let snapshot = emulator.snapshot();
+        let mut restored = Emulator::default();
+        restored.restore(&snapshot);
+        assert_eq!(restored.harts(), 2);
+        assert_eq!(restored.snapshot(), snapshot);
+
+        // the drivers that step one hart refuse to run the others
+        assert!(restored.lockstep().is_err());
+        assert!(restored.debug_module().is_err());
+        let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        assert!(restored.gdb(listener).is_err());
+    }
+
+    #[test]
+    fn ipi_ok() {
+        let mut emulator = emulator(&[
+            CSRR_MHARTID,
+            // bnez a0, 20
+            0x0005_1a63,
+            // lui a1, 0x2000
+            0x0200_05b7,
+            // li a2, 1
+            0x0010_0613,
+            // sw a2, 4(a1)
+            0x00c5_a223,
+            WFI,
+            // li t0, MSI
+            0x0080_0293,
+            // csrw mie, t0
+            0x3042_9073,
+            WFI,
+            // li a3, 7
+            0x0070_0693,
+            // jal zero, -4
+            0xffdf_f06f,
+        ]);
+        emulator.set_harts(2);
+        // hart 1 waits until hart 0 raises its software interrupt
+        assert_eq!(emulator.run_for(100), StopReason::InstructionLimit);
+        assert_eq!(emulator.hart(1).x().readu(13), 7);
+        assert_eq!(emulator.hart(0).x().readu(13), 0);
+    }
+
+    #[test]
+    fn lrsc_ok() {
+        let mut emulator = emulator(&[
+            // auipc t0, 0
+            0x0000_0297,
+            // li t1, 10
+            0x00a0_0313,
+            // addi t2, t0, 0x100
+            0x1002_8393,
+            // lr.d a0, (t2)
+            0x1003_b52f,
+            // addi a0, a0, 1
+            0x0015_0513,
+            // sc.d a1, a0, (t2)
+            0x18a3_b5af,
+            // add a3, a3, a1
+            0x00b6_86b3,
+            // bnez a1, -16
+            0xfe05_98e3,
+            // addi t1, t1, -1
+            0xfff3_0313,
+            // bnez t1, -24
+            0xfe03_14e3,
+            WFI,
+        ]);
+        emulator.set_harts(2);
+        emulator.set_quantum(1);
+        // both harts increment the counter ten times, and the SC of one hart fails whenever
+        // the other has stored to the counter since its LR
+        assert_eq!(emulator.run(), StopReason::Halt);
+        assert_eq!(emulator.cpu().bus.load64(MEMORY_BASE_ADDRESS + 0x100), 20);
+        assert!(emulator.hart(0).x().readu(13) + emulator.hart(1).x().readu(13) > 0);
+    }
+
+    #[test]
+    fn block_cache_ok() {
+        let mut program = vec![
+            // addi a0, a0, 1
+            0x0015_0513,
+            SD,
+            // ld a2, 0(a1)
+            0x0005_b603,
+            // add a3, a3, a2
+            0x00c6_86b3,
+            // jal zero, -16
+            0xff1f_f06f,
+        ];
+        program.resize(64, NOP);
+        program.extend([
+            // addi a4, a4, 1
+            0x0017_0713,
+            // ld t1, 0(t0)
+            0x0002_b303,
+            // addi t1, t1, 97
+            0x0613_0313,
+            // sd t1, 0(t0)
+            0x0062_b023,
+            // mret
+            0x3020_0073,
+        ]);
+        // the timer interrupt moves mtimecmp on every time it is taken
+        let mut emulators = [true, false].map(|enabled| {
+            let mut emulator = emulator(&program);
+            emulator.set_block_cache(enabled);
+            emulator.set_clock(Clock::Mtime);
+            let cpu = emulator.cpu_mut();
+            cpu.x_mut().writeu(5, 0x0200_4000);
+            cpu.x_mut().writeu(11, MEMORY_BASE_ADDRESS + 0x2000);
+            cpu.csr.write(MTVEC, MEMORY_BASE_ADDRESS + 0x100);
+            cpu.csr.write(MIE, 1 << 7);
+            cpu.csr.write(MSTATUS, 1 << 3);
+            cpu.bus.store64(0x0200_4000, 150);
+            emulator
+        });
+        let state = |emulator: &Emulator| {
+            let cpu = emulator.cpu();
+            [CYCLE, TIME, INSTRET, MEPC]
+                .map(|csr| cpu.csr.read(csr))
+                .into_iter()
+                .chain([cpu.pc(), cpu.bus.load64(0x0200_bff8)])
+                .chain(cpu.x().snapshot())
+                .collect::<Vec<_>>()
+        };
+        for instructions in [7, 1000, 333] {
+            for emulator in emulators.iter_mut() {
+                assert_eq!(emulator.run_for(instructions), StopReason::InstructionLimit);
+            }
+            assert_eq!(state(&emulators[0]), state(&emulators[1]));
+        }
+        assert!(emulators[0].cpu().x().readu(14) > 10);
+
+        // a store to the code is seen by the next block
+        for emulator in emulators.iter_mut() {
+            emulator.cpu_mut().csr.write(MIE, 0);
+            emulator
+                .cpu_mut()
+                .bus
+                .store32(MEMORY_BASE_ADDRESS + 12, NOP);
+            assert_eq!(emulator.run_for(50), StopReason::InstructionLimit);
+        }
+        assert_eq!(state(&emulators[0]), state(&emulators[1]));
     }
 }
@@ -1,6 +1,20 @@
+pub mod clint;
 pub mod memory;
-use crate::emulator::bus::memory::Memory;
+pub mod region;
+pub mod uart;
+use crate::emulator::{
+    bus::{clint::Clint, memory::Memory, region::Region, uart::Uart},
+    replay::Inputs,
+};
+use crate::isa::privileged::cause::{Cause, Exception};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::io::{self, Error, ErrorKind};
 
+// The size of a reservation set of LR/SC.
+const RESERVATION_SIZE: u64 = 8;
+
+#[derive(Clone, Copy)]
 pub enum Size {
     Byte = 1,
     Halfword = 2,
@@ -8,16 +22,212 @@ pub enum Size {
     Doubleword = 8,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Access {
+    Load,
+    Store,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MemoryAccess {
+    pub access: Access,
+    pub address: u64,
+    pub size: u64,
+    pub value: u64,
+}
+
+/// The bus that the harts share, with the memory, the devices, the host files mapped as ROM or
+/// flash, the reservations of LR/SC and the inputs from the host.
 #[derive(Default)]
 pub struct SystemBus {
     pub memory: Memory,
+    pub clint: Clint,
+    pub uart: Uart,
+    regions: Vec<Region>,
+    pub(crate) inputs: Inputs,
+    reservations: BTreeMap<usize, u64>,
+    // whether the addresses of the CLINT and the UART are memory instead
+    devices_hidden: bool,
+    observing: bool,
+    accesses: RefCell<Vec<MemoryAccess>>,
+    last_store: u64,
 }
 
 impl SystemBus {
-    pub fn load(&self, address: u64, size: Size) -> u64 {
+    /// Returns a bus without memory for a hart that waits for its turn to run.
+    pub(crate) fn detached() -> Self {
+        Self {
+            memory: Memory::new(0, 0),
+            clint: Clint::default(),
+            uart: Uart::default(),
+            regions: vec![],
+            inputs: Inputs::default(),
+            reservations: BTreeMap::new(),
+            devices_hidden: false,
+            observing: false,
+            accesses: RefCell::default(),
+            last_store: 0,
+        }
+    }
+
+    /// Reserves the reservation set that contains the address for the hart, as LR does.
+    pub fn reserve(&mut self, hart: usize, address: u64) {
+        self.reservations
+            .insert(hart, address & !(RESERVATION_SIZE - 1));
+    }
+
+    /// Returns the harts with their reservation sets.
+    pub(crate) fn reservations(&self) -> Vec<(u64, u64)> {
+        self.reservations
+            .iter()
+            .map(|(hart, set)| (*hart as u64, *set))
+            .collect()
+    }
+
+    pub(crate) fn restore_reservations(&mut self, reservations: &[(u64, u64)]) {
+        self.reservations = reservations
+            .iter()
+            .map(|(hart, set)| (*hart as usize, *set))
+            .collect();
+    }
+
+    /// Returns the bases and the contents of the regions.
+    pub(crate) fn region_contents(&self) -> Vec<(u64, Vec<u8>)> {
+        self.regions
+            .iter()
+            .map(|region| (region.base(), region.data().to_vec()))
+            .collect()
+    }
+
+    /// Writes the contents to the regions at their bases. The contents of the regions that are
+    /// not mapped anymore, or that have another size, are dropped.
+    pub(crate) fn restore_regions(&mut self, contents: &[(u64, Vec<u8>)]) {
+        for (base, data) in contents {
+            if let Some(region) = self.regions.iter_mut().find(|r| r.base() == *base) {
+                region.restore(data);
+            }
+        }
+    }
+
+    /// Releases the reservation of the hart and returns true if it was on the reservation set
+    /// that contains the address, i.e. whether SC succeeds.
+    pub fn release(&mut self, hart: usize, address: u64) -> bool {
+        self.reservations.remove(&hart) == Some(address & !(RESERVATION_SIZE - 1))
+    }
+
+    /// Unmaps the CLINT and the UART so that a program in user mode can use their addresses as
+    /// memory. The timer keeps running.
+    pub(crate) fn hide_devices(&mut self) {
+        self.devices_hidden = true;
+    }
+
+    fn is_clint(&self, address: u64) -> bool {
+        !self.devices_hidden && self.clint.contains(address)
+    }
+
+    fn is_uart(&self, address: u64) -> bool {
+        !self.devices_hidden && self.uart.contains(address)
+    }
+
+    pub fn is_mapped(&self, address: u64) -> bool {
+        self.is_clint(address)
+            || self.is_uart(address)
+            || self.region(address).is_some()
+            || self.memory.contains(address)
+    }
+
+    /// Maps the region, which must not overlap the memory, the devices or the other regions.
+    pub fn map(&mut self, region: Region) -> io::Result<()> {
+        let (start, end) = (region.base(), region.end());
+        // the devices are small enough that their first and last bytes tell if they overlap
+        let overlaps = (start..end).contains(&self.memory.base())
+            || (start..end).contains(&clint::CLINT_BASE_ADDRESS)
+            || (start..end).contains(&uart::UART_BASE_ADDRESS)
+            || [start, end - 1].iter().any(|a| self.is_mapped(*a))
+            || self
+                .regions
+                .iter()
+                .any(|r| start < r.end() && r.base() < end);
+        if overlaps {
+            return Err(Error::new(
+                ErrorKind::AddrInUse,
+                format!("the region at {:#x}..{:#x} overlaps the bus", start, end),
+            ));
+        }
+        self.regions.push(region);
+        Ok(())
+    }
+
+    pub fn regions(&self) -> &[Region] {
+        &self.regions
+    }
+
+    /// Writes the flash regions back to their files.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.regions.iter_mut().try_for_each(Region::flush)
+    }
+
+    fn region(&self, address: u64) -> Option<&Region> {
+        self.regions.iter().find(|r| r.contains(address))
+    }
+
+    fn read(&self, address: u64, size: Size) -> u64 {
+        if self.is_clint(address) {
+            return self.clint.load(address, size);
+        }
+        if self.is_uart(address) {
+            return self.uart.load(address, size);
+        }
+        if let Some(region) = self.region(address) {
+            return region.load(address, size);
+        }
         self.memory.load(address, size)
     }
 
+    fn observe(&self, access: Access, address: u64, size: Size, value: u64) {
+        if self.observing {
+            let size = size as u64;
+            let mask = if size == 8 {
+                u64::MAX
+            } else {
+                (1 << (size * 8)) - 1
+            };
+            self.accesses.borrow_mut().push(MemoryAccess {
+                access,
+                address,
+                size,
+                value: value & mask,
+            });
+        }
+    }
+
+    /// Starts or stops recording the loads and stores issued by instructions.
+    pub fn set_observing(&mut self, observing: bool) {
+        self.observing = observing;
+        self.accesses.get_mut().clear();
+    }
+
+    /// Returns the accesses recorded since the last call.
+    pub fn take_accesses(&mut self) -> Vec<MemoryAccess> {
+        std::mem::take(self.accesses.get_mut())
+    }
+
+    /// Reads memory without recording the access.
+    pub fn peek(&self, address: u64, size: Size) -> u64 {
+        self.read(address, size)
+    }
+
+    /// Fetches an instruction. Fetches are not recorded as loads.
+    pub fn fetch(&self, address: u64) -> u32 {
+        self.read(address, Size::Word) as u32
+    }
+
+    pub fn load(&self, address: u64, size: Size) -> u64 {
+        let value = self.read(address, size);
+        self.observe(Access::Load, address, size, value);
+        value
+    }
+
     pub fn load8(&self, address: u64) -> u8 {
         self.load(address, Size::Byte) as u8
     }
@@ -34,10 +244,48 @@ impl SystemBus {
         self.load(address, Size::Doubleword)
     }
 
+    /// Writes the value as the host does, so a store to ROM succeeds.
     pub fn store(&mut self, address: u64, value: u64, size: Size) {
+        self.observe(Access::Store, address, size, value);
+        self.last_store = address;
+        // a store breaks the reservations of every hart on the bytes it writes
+        if !self.reservations.is_empty() {
+            let end = address.saturating_add(size as u64);
+            self.reservations
+                .retain(|_, set| end <= *set || set.saturating_add(RESERVATION_SIZE) <= address);
+        }
+        if self.is_clint(address) {
+            return self.clint.store(address, value, size);
+        }
+        if self.is_uart(address) {
+            return self.uart.store(address, value, size);
+        }
+        if let Some(region) = self.regions.iter_mut().find(|r| r.contains(address)) {
+            return region.store(address, value, size);
+        }
         self.memory.store(address, value, size);
     }
 
+    /// Returns the address of the last store.
+    pub(crate) fn last_store(&self) -> u64 {
+        self.last_store
+    }
+
+    /// Writes the value as an instruction does, which raises an access fault when any of its
+    /// bytes is in ROM.
+    pub fn try_store(&mut self, address: u64, value: u64, size: Size) -> Result<(), Cause> {
+        let end = address.saturating_add(size as u64);
+        if self
+            .regions
+            .iter()
+            .any(|r| !r.is_writable() && r.base() < end && address < r.end())
+        {
+            return Err(Cause::Exception(Exception::StoreAccessFault));
+        }
+        self.store(address, value, size);
+        Ok(())
+    }
+
     pub fn store8(&mut self, address: u64, value: u8) {
         self.store(address, value as u64, Size::Byte)
     }
@@ -53,4 +301,85 @@ impl SystemBus {
     pub fn store64(&mut self, address: u64, value: u64) {
         self.store(address, value, Size::Doubleword)
     }
+
+    pub fn try_store8(&mut self, address: u64, value: u8) -> Result<(), Cause> {
+        self.try_store(address, value as u64, Size::Byte)
+    }
+
+    pub fn try_store16(&mut self, address: u64, value: u16) -> Result<(), Cause> {
+        self.try_store(address, value as u64, Size::Halfword)
+    }
+
+    pub fn try_store32(&mut self, address: u64, value: u32) -> Result<(), Cause> {
+        self.try_store(address, value as u64, Size::Word)
+    }
+
+    pub fn try_store64(&mut self, address: u64, value: u64) -> Result<(), Cause> {
+        self.try_store(address, value, Size::Doubleword)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::bus::memory::MEMORY_BASE_ADDRESS;
+
+    #[test]
+    fn reservation_ok() {
+        let mut bus = SystemBus::default();
+        let address = MEMORY_BASE_ADDRESS + 0x100;
+        bus.reserve(0, address);
+        assert!(!bus.release(0, address + 8));
+        assert!(!bus.release(0, address));
+
+        // the store of another hart breaks the reservation
+        bus.reserve(0, address);
+        bus.reserve(1, address + 8);
+        bus.store32(address + 4, 1);
+        assert!(!bus.release(0, address));
+        assert!(bus.release(1, address + 8));
+    }
+
+    #[test]
+    fn map_ok() {
+        let mut bus = SystemBus::default();
+        let rom = Region::from_bytes(0x2000_0000, vec![0x13, 0, 0, 0], false).unwrap();
+        let flash = Region::from_bytes(0x2000_1000, vec![0; 4], true).unwrap();
+        assert!(bus.map(rom).is_ok());
+        assert!(bus.map(flash).is_ok());
+        assert_eq!(bus.fetch(0x2000_0000), 0x13);
+        assert!(bus.is_mapped(0x2000_1003));
+        assert!(!bus.is_mapped(0x2000_1004));
+
+        assert_eq!(
+            bus.try_store8(0x2000_0000, 1),
+            Err(Cause::Exception(Exception::StoreAccessFault))
+        );
+        assert_eq!(bus.load8(0x2000_0000), 0x13);
+        assert!(bus.try_store32(0x2000_1000, 0x1234).is_ok());
+        assert_eq!(bus.load32(0x2000_1000), 0x1234);
+        // the host writes ROM
+        bus.store8(0x2000_0000, 1);
+        assert_eq!(bus.load8(0x2000_0000), 1);
+
+        // a store that straddles the end of the flash and the ROM writes neither
+        let flash = Region::from_bytes(0x1fff_fff8, vec![0; 8], true).unwrap();
+        assert!(bus.map(flash).is_ok());
+        assert_eq!(
+            bus.try_store32(0x1fff_fffe, 0x1234_5678),
+            Err(Cause::Exception(Exception::StoreAccessFault))
+        );
+        assert_eq!(bus.load16(0x1fff_fffe), 0);
+        assert_eq!(bus.load8(0x2000_0000), 1);
+
+        for base in [
+            0x2000_0002,
+            0x1fff_fffe,
+            MEMORY_BASE_ADDRESS - 4,
+            0x01ff_0000,
+        ] {
+            let region = Region::from_bytes(base, vec![0; 0x2_0000], false).unwrap();
+            assert!(bus.map(region).is_err(), "{:#x}", base);
+        }
+    }
 }
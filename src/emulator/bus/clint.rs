@@ -0,0 +1,161 @@
+use crate::emulator::bus::Size;
+
+pub const CLINT_BASE_ADDRESS: u64 = 0x0200_0000;
+pub const CLINT_SIZE: u64 = 0x10000;
+/// The ticks of mtime in a second, as on the virt machine of QEMU.
+pub const MTIME_FREQUENCY: u64 = 10_000_000;
+
+const MSIP: u64 = CLINT_BASE_ADDRESS; // Machine software interrupt pending.
+const MTIMECMP: u64 = CLINT_BASE_ADDRESS + 0x4000; // Machine timer compare.
+const MTIME: u64 = CLINT_BASE_ADDRESS + 0xbff8; // Machine timer.
+
+/// The core-local interruptor with an msip and an mtimecmp per hart and a shared mtime.
+pub struct Clint {
+    msip: Vec<u64>,
+    mtimecmp: Vec<u64>,
+    mtime: u64,
+}
+
+impl Default for Clint {
+    fn default() -> Self {
+        Self::new(1)
+    }
+}
+
+impl Clint {
+    pub fn new(harts: usize) -> Self {
+        Self {
+            msip: vec![0; harts],
+            mtimecmp: vec![u64::MAX; harts],
+            mtime: 0,
+        }
+    }
+
+    pub fn harts(&self) -> usize {
+        self.msip.len()
+    }
+
+    pub fn contains(&self, address: u64) -> bool {
+        (CLINT_BASE_ADDRESS..CLINT_BASE_ADDRESS + CLINT_SIZE).contains(&address)
+    }
+
+    pub fn load(&self, address: u64, size: Size) -> u64 {
+        let (base, register) = self.register(address);
+        let shift = (address - base) * 8;
+        match size {
+            Size::Doubleword => register >> shift,
+            _ => (register >> shift) & ((1 << (size as u64 * 8)) - 1),
+        }
+    }
+
+    pub fn store(&mut self, address: u64, value: u64, size: Size) {
+        let (base, register) = self.register(address);
+        let shift = (address - base) * 8;
+        let mask = match size {
+            Size::Doubleword => u64::MAX,
+            _ => ((1 << (size as u64 * 8)) - 1) << shift,
+        };
+        let value = (register & !mask) | ((value << shift) & mask);
+        let harts = self.harts() as u64;
+        if (MSIP..MSIP + 4 * harts).contains(&base) {
+            self.msip[((base - MSIP) / 4) as usize] = value & 1;
+        } else if (MTIMECMP..MTIMECMP + 8 * harts).contains(&base) {
+            self.mtimecmp[((base - MTIMECMP) / 8) as usize] = value;
+        } else if base == MTIME {
+            self.mtime = value;
+        }
+    }
+
+    /// Returns the base address and the value of the register at the address. The addresses
+    /// without a register read as zero.
+    fn register(&self, address: u64) -> (u64, u64) {
+        let harts = self.harts() as u64;
+        if (MSIP..MSIP + 4 * harts).contains(&address) {
+            let hart = (address - MSIP) / 4;
+            (MSIP + hart * 4, self.msip[hart as usize])
+        } else if (MTIMECMP..MTIMECMP + 8 * harts).contains(&address) {
+            let hart = (address - MTIMECMP) / 8;
+            (MTIMECMP + hart * 8, self.mtimecmp[hart as usize])
+        } else if (MTIME..MTIME + 8).contains(&address) {
+            (MTIME, self.mtime)
+        } else {
+            (address, 0)
+        }
+    }
+
+    pub fn mtime(&self) -> u64 {
+        self.mtime
+    }
+
+    pub fn tick(&mut self) {
+        self.mtime = self.mtime.wrapping_add(1);
+    }
+
+    pub fn is_software_interrupt_pending(&self, hart: usize) -> bool {
+        self.msip[hart] != 0
+    }
+
+    pub fn is_timer_interrupt_pending(&self, hart: usize) -> bool {
+        self.mtime >= self.mtimecmp[hart]
+    }
+
+    /// Returns the time at which the timer interrupt of the hart becomes pending, if it is not
+    /// pending yet.
+    pub fn next_deadline(&self, hart: usize) -> Option<u64> {
+        let mtimecmp = self.mtimecmp[hart];
+        if mtimecmp != u64::MAX && self.mtime < mtimecmp {
+            Some(mtimecmp)
+        } else {
+            None
+        }
+    }
+
+    pub fn advance_to(&mut self, time: u64) {
+        self.mtime = time;
+    }
+
+    /// Returns mtime, and the msip and the mtimecmp of every hart.
+    pub fn snapshot(&self) -> Vec<u64> {
+        let mut snapshot = vec![self.mtime];
+        snapshot.extend(self.msip.iter().chain(self.mtimecmp.iter()));
+        snapshot
+    }
+
+    pub fn restore(&mut self, snapshot: &[u64]) {
+        let harts = (snapshot.len() - 1) / 2;
+        self.mtime = snapshot[0];
+        self.msip = snapshot[1..1 + harts].to_vec();
+        self.mtimecmp = snapshot[1 + harts..].to_vec();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn per_hart_registers_ok() {
+        let mut clint = Clint::new(2);
+        clint.store(MSIP + 4, 1, Size::Word);
+        assert!(!clint.is_software_interrupt_pending(0));
+        assert!(clint.is_software_interrupt_pending(1));
+        assert_eq!(clint.load(MSIP + 4, Size::Word), 1);
+        // the msip of a hart that does not exist reads as zero
+        clint.store(MSIP + 8, 1, Size::Word);
+        assert_eq!(clint.load(MSIP + 8, Size::Word), 0);
+
+        clint.store(MTIMECMP + 8, 10, Size::Doubleword);
+        clint.store(MTIME, 10, Size::Doubleword);
+        assert!(!clint.is_timer_interrupt_pending(0));
+        assert!(clint.is_timer_interrupt_pending(1));
+        assert_eq!(clint.next_deadline(0), None);
+        clint.store(MTIMECMP + 4, 0, Size::Word);
+        assert_eq!(clint.next_deadline(0), Some(0xffff_ffff));
+
+        let snapshot = clint.snapshot();
+        let mut restored = Clint::default();
+        restored.restore(&snapshot);
+        assert_eq!(restored.harts(), 2);
+        assert_eq!(restored.snapshot(), snapshot);
+    }
+}
@@ -1,34 +1,219 @@
 use crate::emulator::bus::Size;
 
+/// The size and the base of the memory unless they are configured.
 pub const MEMORY_SIZE: u64 = 1024 * 1024 * 1024;
 pub const MEMORY_BASE_ADDRESS: u64 = 0x8000_0000;
+pub const PAGE_SIZE: usize = 4096;
 
+type Page = Box<[u8; PAGE_SIZE]>;
+
+/// The RAM, whose pages are allocated when they are first written. The pages that have never
+/// been written read as zeros. Every page counts its writes so that the instructions decoded
+/// from it can tell when they are stale.
 pub struct Memory {
-    pub memory: Vec<u8>,
+    base: u64,
+    pages: Vec<Option<Page>>,
+    versions: Vec<u64>,
 }
 
 impl Default for Memory {
     fn default() -> Self {
-        Self {
-            memory: vec![0; MEMORY_SIZE as usize],
-        }
+        Self::new(MEMORY_BASE_ADDRESS, MEMORY_SIZE)
     }
 }
 
 impl Memory {
+    /// Creates the memory of the size, rounded up to a page, at the base.
+    pub fn new(base: u64, size: u64) -> Self {
+        let pages = size.div_ceil(PAGE_SIZE as u64) as usize;
+        Self {
+            base,
+            pages: (0..pages).map(|_| None).collect(),
+            versions: vec![0; pages],
+        }
+    }
+
+    pub fn base(&self) -> u64 {
+        self.base
+    }
+
     pub fn size(&self) -> u64 {
-        MEMORY_BASE_ADDRESS + MEMORY_SIZE
+        (self.pages.len() * PAGE_SIZE) as u64
+    }
+
+    /// Returns the address past the last byte.
+    pub fn end(&self) -> u64 {
+        self.base + self.size()
+    }
+
+    pub fn contains(&self, address: u64) -> bool {
+        (self.base..self.end()).contains(&address)
+    }
+
+    /// Returns the number of writes to the page that holds the address.
+    pub(crate) fn version(&self, address: u64) -> u64 {
+        match self.contains(address) {
+            true => self.versions[(address - self.base) as usize / PAGE_SIZE],
+            false => 0,
+        }
     }
 
+    /// Reads the value. The bytes out of the memory read as zeros.
     pub fn load(&self, address: u64, size: Size) -> u64 {
-        (0..size as usize).fold(0, |acc, i| {
-            acc | (self.memory[(address - MEMORY_BASE_ADDRESS) as usize + i] as u64) << (8 * i)
-        })
+        let size = size as usize;
+        match self.locate(address, size) {
+            Some((page, offset)) => match &self.pages[page] {
+                Some(page) => page[offset..offset + size]
+                    .iter()
+                    .rev()
+                    .fold(0, |acc, byte| acc << 8 | *byte as u64),
+                None => 0,
+            },
+            // the access crosses a page or the end of the memory
+            None => {
+                (0..size as u64).fold(0, |acc, i| acc | (self.byte(address + i) as u64) << (8 * i))
+            }
+        }
     }
 
+    /// Writes the value. The bytes out of the memory are dropped.
     pub fn store(&mut self, address: u64, value: u64, size: Size) {
-        for i in 0..size as usize {
-            self.memory[(address - MEMORY_BASE_ADDRESS) as usize + i] = (value >> (i * 8)) as u8;
+        let size = size as usize;
+        match self.locate(address, size) {
+            Some((page, offset)) => {
+                self.versions[page] += 1;
+                let page = self.pages[page].get_or_insert_with(|| Box::new([0; PAGE_SIZE]));
+                page[offset..offset + size].copy_from_slice(&value.to_le_bytes()[..size]);
+            }
+            None => {
+                for i in 0..size as u64 {
+                    if let Some(byte) = self.byte_mut(address + i) {
+                        *byte = (value >> (i * 8)) as u8;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns the page and the offset in it of an access that stays in a page of the memory.
+    fn locate(&self, address: u64, size: usize) -> Option<(usize, usize)> {
+        if !self.contains(address) {
+            return None;
+        }
+        let offset = (address - self.base) as usize;
+        (offset % PAGE_SIZE + size <= PAGE_SIZE).then_some((offset / PAGE_SIZE, offset % PAGE_SIZE))
+    }
+
+    fn byte(&self, address: u64) -> u8 {
+        if !self.contains(address) {
+            return 0;
+        }
+        let offset = (address - self.base) as usize;
+        match &self.pages[offset / PAGE_SIZE] {
+            Some(page) => page[offset % PAGE_SIZE],
+            None => 0,
         }
     }
+
+    fn byte_mut(&mut self, address: u64) -> Option<&mut u8> {
+        if !self.contains(address) {
+            return None;
+        }
+        let offset = (address - self.base) as usize;
+        self.versions[offset / PAGE_SIZE] += 1;
+        let page = self.pages[offset / PAGE_SIZE].get_or_insert_with(|| Box::new([0; PAGE_SIZE]));
+        Some(&mut page[offset % PAGE_SIZE])
+    }
+
+    /// Returns the bytes in the range, or None unless the memory holds every one of them.
+    pub fn load_bytes(&self, address: u64, count: u64) -> Option<Vec<u8>> {
+        self.check(address, count)?;
+        Some((address..address + count).map(|a| self.byte(a)).collect())
+    }
+
+    /// Writes the bytes, and returns false without writing unless the memory holds the range.
+    pub fn store_bytes(&mut self, address: u64, bytes: &[u8]) -> bool {
+        if self.check(address, bytes.len() as u64).is_none() {
+            return false;
+        }
+        for (a, value) in (address..).zip(bytes) {
+            // zeros leave the pages that have never been written unallocated
+            if *value == 0 && self.byte(a) == 0 {
+                continue;
+            }
+            if let Some(byte) = self.byte_mut(a) {
+                *byte = *value;
+            }
+        }
+        true
+    }
+
+    fn check(&self, address: u64, count: u64) -> Option<()> {
+        let end = address.checked_add(count)?;
+        (address >= self.base && end <= self.end()).then_some(())
+    }
+
+    /// Returns the addresses and the contents of the pages that are not filled with zeros.
+    pub fn snapshot(&self) -> Vec<(u64, &[u8])> {
+        let zero = [0; PAGE_SIZE];
+        self.pages
+            .iter()
+            .enumerate()
+            .filter_map(|(i, page)| Some((i, page.as_deref()?)))
+            .filter(|(_, page)| **page != zero)
+            .map(|(i, page)| (self.base + (i * PAGE_SIZE) as u64, page.as_slice()))
+            .collect()
+    }
+
+    /// Clears the memory and writes the pages. The bytes out of the memory are dropped.
+    pub fn restore<'a>(&mut self, pages: impl IntoIterator<Item = (u64, &'a [u8])>) {
+        self.pages.iter_mut().for_each(|page| *page = None);
+        self.versions.iter_mut().for_each(|version| *version += 1);
+        for (address, page) in pages {
+            for (i, value) in page.iter().enumerate() {
+                if let Some(byte) = address.checked_add(i as u64).and_then(|a| self.byte_mut(a)) {
+                    *byte = *value;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sparse_ok() {
+        let mut memory = Memory::new(0x1000_0000, 3 * PAGE_SIZE as u64 - 1);
+        assert_eq!(memory.size(), 3 * PAGE_SIZE as u64);
+        assert_eq!(memory.end(), 0x1000_3000);
+        assert_eq!(memory.load(0x1000_1000, Size::Doubleword), 0);
+        assert!(memory.pages.iter().all(Option::is_none));
+
+        // a store across pages allocates both of them
+        memory.store(0x1000_0ffe, 0x1122_3344, Size::Word);
+        assert_eq!(memory.load(0x1000_0ffe, Size::Word), 0x1122_3344);
+        assert_eq!(memory.pages.iter().filter(|p| p.is_some()).count(), 2);
+        assert_eq!(memory.load(0x1000_2ffc, Size::Doubleword), 0);
+        assert!(memory.load_bytes(0x1000_2fff, 2).is_none());
+        assert!(!memory.store_bytes(0x0fff_ffff, &[1]));
+
+        let pages = memory
+            .snapshot()
+            .into_iter()
+            .map(|(address, page)| (address, page.to_vec()))
+            .collect::<Vec<_>>();
+        assert_eq!(pages.len(), 2);
+        let mut restored = Memory::new(0x1000_0000, 3 * PAGE_SIZE as u64);
+        restored.restore(pages.iter().map(|(a, p)| (*a, p.as_slice())));
+        assert_eq!(restored.load(0x1000_0ffe, Size::Word), 0x1122_3344);
+
+        // a page at the top of the address space is cut off there
+        let mut top = Memory::new(0xffff_ffff_ffff_e000, PAGE_SIZE as u64);
+        top.restore([(0xffff_ffff_ffff_f800, [1; PAGE_SIZE].as_slice())]);
+        assert_eq!(top.load(0xffff_ffff_ffff_e000, Size::Byte), 0);
+        top.restore([(0xffff_ffff_ffff_dfff, [1; PAGE_SIZE].as_slice())]);
+        assert_eq!(top.load(0xffff_ffff_ffff_effe, Size::Byte), 1);
+    }
 }
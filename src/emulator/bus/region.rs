@@ -0,0 +1,154 @@
+use crate::emulator::bus::Size;
+use std::fs::{File, OpenOptions};
+use std::io::{Error, ErrorKind, Read, Result, Seek, SeekFrom, Write};
+use std::path::Path;
+
+/// A host file mapped into the address space, either as ROM that the guest cannot write or as
+/// flash that it can. The file is read once when it is mapped, and the writes to flash go back
+/// to it on flush only if it is persistent.
+pub struct Region {
+    base: u64,
+    data: Vec<u8>,
+    writable: bool,
+    // the file that the writes are persisted to
+    file: Option<File>,
+    dirty: bool,
+}
+
+impl Region {
+    /// Maps the file as ROM at the base.
+    pub fn rom(base: u64, path: impl AsRef<Path>) -> Result<Self> {
+        let mut data = vec![];
+        File::open(path)?.read_to_end(&mut data)?;
+        Self::new(base, data, false, None)
+    }
+
+    /// Maps the file as flash at the base. The writes of the guest go back to the file if it is
+    /// persistent, and are lost otherwise.
+    pub fn flash(base: u64, path: impl AsRef<Path>, persistent: bool) -> Result<Self> {
+        let mut file = OpenOptions::new().read(true).write(persistent).open(path)?;
+        let mut data = vec![];
+        file.read_to_end(&mut data)?;
+        Self::new(base, data, true, persistent.then_some(file))
+    }
+
+    /// Maps the bytes, which are not backed by a file.
+    pub fn from_bytes(base: u64, data: Vec<u8>, writable: bool) -> Result<Self> {
+        Self::new(base, data, writable, None)
+    }
+
+    fn new(base: u64, data: Vec<u8>, writable: bool, file: Option<File>) -> Result<Self> {
+        if data.is_empty() || base.checked_add(data.len() as u64).is_none() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "the region must be a nonempty range of the address space",
+            ));
+        }
+        Ok(Self {
+            base,
+            data,
+            writable,
+            file,
+            dirty: false,
+        })
+    }
+
+    pub fn base(&self) -> u64 {
+        self.base
+    }
+
+    /// Returns the address past the last byte.
+    pub fn end(&self) -> u64 {
+        self.base + self.data.len() as u64
+    }
+
+    pub fn is_writable(&self) -> bool {
+        self.writable
+    }
+
+    pub fn contains(&self, address: u64) -> bool {
+        (self.base..self.end()).contains(&address)
+    }
+
+    /// Reads the value. The bytes past the end of the region read as zeros.
+    pub fn load(&self, address: u64, size: Size) -> u64 {
+        (0..size as u64).fold(0, |acc, i| {
+            let byte = address
+                .checked_add(i)
+                .filter(|a| self.contains(*a))
+                .map_or(0, |a| self.data[(a - self.base) as usize]);
+            acc | (byte as u64) << (8 * i)
+        })
+    }
+
+    /// Writes the value whether or not the region is writable, as the host does. The bytes past
+    /// the end of the region are dropped.
+    pub fn store(&mut self, address: u64, value: u64, size: Size) {
+        for i in 0..size as u64 {
+            if let Some(a) = address.checked_add(i).filter(|a| self.contains(*a)) {
+                self.data[(a - self.base) as usize] = (value >> (8 * i)) as u8;
+                self.dirty = true;
+            }
+        }
+    }
+
+    pub(crate) fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Replaces the contents with the bytes of a snapshot, which must have the same size.
+    pub(crate) fn restore(&mut self, data: &[u8]) {
+        if self.data.len() == data.len() && self.data != data {
+            self.data.copy_from_slice(data);
+            self.dirty = true;
+        }
+    }
+
+    /// Writes the contents back to the file if they have changed since the last flush.
+    pub fn flush(&mut self) -> Result<()> {
+        if let (Some(file), true) = (&mut self.file, self.dirty) {
+            file.seek(SeekFrom::Start(0))?;
+            file.write_all(&self.data)?;
+            file.flush()?;
+            self.dirty = false;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Region {
+    fn drop(&mut self) {
+        // the host calls flush to see the error
+        let _ = self.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flash_ok() {
+        let path = std::env::temp_dir().join(format!("five-flash-{}", std::process::id()));
+        std::fs::write(&path, [1, 2, 3, 4, 5]).unwrap();
+
+        let mut rom = Region::rom(0x2000_0000, &path).unwrap();
+        assert!(!rom.is_writable());
+        assert_eq!(rom.end(), 0x2000_0005);
+        // the bytes past the end read as zeros
+        assert_eq!(rom.load(0x2000_0002, Size::Word), 0x0005_0403);
+        rom.store(0x2000_0000, 0xff, Size::Byte);
+        rom.flush().unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), [1, 2, 3, 4, 5]);
+
+        let mut flash = Region::flash(0x2000_0000, &path, true).unwrap();
+        flash.store(0x2000_0003, 0xaabb_ccdd, Size::Word);
+        assert_eq!(flash.load(0x2000_0000, Size::Doubleword), 0x00cc_dd03_0201);
+        drop(flash);
+        assert_eq!(std::fs::read(&path).unwrap(), [1, 2, 3, 0xdd, 0xcc]);
+
+        std::fs::write(&path, []).unwrap();
+        assert!(Region::rom(0, &path).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
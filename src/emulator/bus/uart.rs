@@ -0,0 +1,106 @@
+use crate::emulator::bus::Size;
+use std::io::{self, Write};
+
+pub const UART_BASE_ADDRESS: u64 = 0x1000_0000;
+pub const UART_SIZE: u64 = 0x100;
+
+const THR: u64 = UART_BASE_ADDRESS; // Transmitter holding register.
+const IER: u64 = UART_BASE_ADDRESS + 1; // Interrupt enable register.
+const LCR: u64 = UART_BASE_ADDRESS + 3; // Line control register.
+const LSR: u64 = UART_BASE_ADDRESS + 5; // Line status register.
+const SCR: u64 = UART_BASE_ADDRESS + 7; // Scratch register.
+
+// The divisor latch access bit of LCR, which maps the divisor over THR and IER.
+const LCR_DLAB: u64 = 0x80;
+// The transmitter is always empty since the bytes go out as soon as they are written.
+const LSR_THRE: u64 = 0x20;
+const LSR_TEMT: u64 = 0x40;
+
+/// A 16550-compatible UART that only transmits. The bytes written to THR go to the output.
+pub struct Uart {
+    ier: u64,
+    lcr: u64,
+    scr: u64,
+    output: Box<dyn Write>,
+}
+
+impl Default for Uart {
+    fn default() -> Self {
+        Self {
+            ier: 0,
+            lcr: 0,
+            scr: 0,
+            output: Box::new(io::stdout()),
+        }
+    }
+}
+
+impl Uart {
+    pub fn set_output(&mut self, output: impl Write + 'static) {
+        self.output = Box::new(output);
+    }
+
+    pub fn contains(&self, address: u64) -> bool {
+        (UART_BASE_ADDRESS..UART_BASE_ADDRESS + UART_SIZE).contains(&address)
+    }
+
+    /// Reads the register at the address. The registers are a byte wide, so a wider load reads
+    /// the first one.
+    pub fn load(&self, address: u64, _size: Size) -> u64 {
+        match address {
+            IER if self.lcr & LCR_DLAB == 0 => self.ier,
+            LCR => self.lcr,
+            LSR => LSR_THRE | LSR_TEMT,
+            SCR => self.scr,
+            _ => 0,
+        }
+    }
+
+    /// Returns IER, LCR and SCR.
+    pub fn snapshot(&self) -> [u64; 3] {
+        [self.ier, self.lcr, self.scr]
+    }
+
+    pub fn restore(&mut self, snapshot: [u64; 3]) {
+        [self.ier, self.lcr, self.scr] = snapshot;
+    }
+
+    pub fn store(&mut self, address: u64, value: u64, _size: Size) {
+        let value = value & 0xff;
+        match address {
+            THR if self.lcr & LCR_DLAB == 0 => {
+                // the guest cannot do anything about an output that fails
+                let _ = self
+                    .output
+                    .write_all(&[value as u8])
+                    .and_then(|_| self.output.flush());
+            }
+            IER if self.lcr & LCR_DLAB == 0 => self.ier = value & 0xf,
+            LCR => self.lcr = value,
+            SCR => self.scr = value,
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::testing::Output;
+
+    #[test]
+    fn transmit_ok() {
+        let output = Output::default();
+        let mut uart = Uart::default();
+        uart.set_output(output.clone());
+        assert_eq!(uart.load(LSR, Size::Byte) & LSR_THRE, LSR_THRE);
+        uart.store(THR, b'h' as u64, Size::Byte);
+        // the divisor latch hides THR
+        uart.store(LCR, LCR_DLAB | 3, Size::Byte);
+        uart.store(THR, 1, Size::Byte);
+        uart.store(LCR, 3, Size::Byte);
+        uart.store(THR, b'i' as u64, Size::Byte);
+        assert_eq!(*output.0.borrow(), b"hi");
+        assert_eq!(uart.load(LCR, Size::Byte), 3);
+    }
+}
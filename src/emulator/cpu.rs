@@ -1,220 +1,810 @@
+mod block;
 pub mod csr;
 mod decoder;
 mod executor;
-mod f;
+pub mod f;
+#[cfg(feature = "jit")]
+mod jit;
 mod pc;
 mod trap_handler;
-mod x;
-
-use std::time::{SystemTime, UNIX_EPOCH};
+pub mod x;
 
 use crate::{
     emulator::{
-        bus::SystemBus,
+        bus::{clint::MTIME_FREQUENCY, Size, SystemBus},
         cpu::{
-            csr::{ControlAndStatusRegister, Csr},
+            block::{Block, BlockCache},
+            csr::{trigger::TriggerAccess, ControlAndStatusRegister, Csr},
             decoder::{
-                privileged::PrivilegedDecoder, rv32f::Rv32fDecoder, rv32i::Rv32iDecoder,
-                rv32m::Rv32mDecoder, rv64f::Rv64fDecoder, rv64i::Rv64iDecoder, rv64m::Rv64mDecoder,
-                zicsr::ZicsrDecoder, zifencei::ZifenceiDecoder, Decoder,
+                privileged::PrivilegedDecoder, rv32a::Rv32aDecoder, rv32f::Rv32fDecoder,
+                rv32i::Rv32iDecoder, rv32m::Rv32mDecoder, rv64a::Rv64aDecoder, rv64f::Rv64fDecoder,
+                rv64i::Rv64iDecoder, rv64m::Rv64mDecoder, zicsr::ZicsrDecoder,
+                zifencei::ZifenceiDecoder, Decoder,
             },
             executor::{
-                privileged::PrivilegedExecutor, rv32f::Rv32fExecutor, rv32i::Rv32iExecutor,
-                rv32m::Rv32mExecutor, rv64f::Rv64fExecutor, rv64i::Rv64iExecutor,
-                rv64m::Rv64mExecutor, zicsr::ZicsrExecutor, zifencei::ZifenceiExecutor, Executor,
+                privileged::PrivilegedExecutor, rv32a::Rv32aExecutor, rv32f::Rv32fExecutor,
+                rv32i::Rv32iExecutor, rv32m::Rv32mExecutor, rv64a::Rv64aExecutor,
+                rv64f::Rv64fExecutor, rv64i::Rv64iExecutor, rv64m::Rv64mExecutor,
+                zicsr::ZicsrExecutor, zifencei::ZifenceiExecutor, Executor,
             },
             f::FloatingPointRegister,
             pc::ProgramCounter,
             trap_handler::*,
             x::IntegerRegister,
         },
+        replay::Clock,
     },
     isa::{
-        csr::user_level::{CYCLE, INSTRET, TIME},
-        description::Describer,
+        csr::{
+            debug::*,
+            interrupt::*,
+            machine_level::{DCSR, DPC, MHARTID, MIDELEG, MIE, MIP, MSTATUS},
+            status::{STATUS_MIE, STATUS_MPRV, STATUS_SIE, STATUS_UIE},
+            supervisor_level::SIDELEG,
+            trigger::ACTION_DEBUG_MODE,
+            user_level::{CYCLE, INSTRET, TIME},
+        },
+        description::{Describer, Description},
+        instruction::Instruction,
         privileged::{
-            cause::{Cause, Exception},
+            cause::{Cause, Exception, Interrupt},
             mode::PrivilegeMode,
         },
-        register::{fname, xname},
+        register::{fname, xname, SP},
     },
 };
+use std::io;
+
+/// The outcome of a step of the hart.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Step {
+    /// The hart executed an instruction.
+    Retired,
+    /// The hart took a trap.
+    Trap(Cause),
+    /// The hart stopped at an EBREAK to hand control to the host.
+    Breakpoint,
+    /// The hart is in debug mode and waits for the debugger.
+    Debug,
+    /// The hart waits for an interrupt that another hart can raise.
+    Idle,
+    /// The hart executed an ECALL from user mode that the host serves.
+    Syscall,
+    /// The hart executed the EBREAK of a semihosting call that the host serves.
+    Semihosting,
+    /// The hart can no longer make progress.
+    Halted,
+}
+
+// slli zero, zero, 0x1f and srai zero, zero, 7 around the EBREAK of a semihosting call
+const SEMIHOSTING_ENTRY: u32 = 0x01f0_1013;
+const SEMIHOSTING_EXIT: u32 = 0x4070_5013;
 
-#[derive(Default)]
 pub struct Cpu {
-    x: IntegerRegister,
-    f: FloatingPointRegister,
-    pc: ProgramCounter,
+    pub(crate) x: IntegerRegister,
+    pub(crate) f: FloatingPointRegister,
+    pub(crate) pc: ProgramCounter,
     pub csr: ControlAndStatusRegister,
-    prv: PrivilegeMode,
+    pub(crate) prv: PrivilegeMode,
+    pub(crate) wfi: bool,
+    pub(crate) ebreak_to_host: bool,
+    pub(crate) ecall_to_host: bool,
+    pub(crate) semihosting: bool,
+    pub(crate) clock: Clock,
+    // whether other harts share the bus, so that a hart waiting for an interrupt lets them run
+    // instead of advancing the timer
+    pub(crate) shared: bool,
+    blocks: BlockCache,
+    // the number of times that a block runs before it is compiled
+    #[cfg(feature = "jit")]
+    pub(crate) jit_threshold: u32,
     pub bus: SystemBus,
 }
 
+impl Default for Cpu {
+    fn default() -> Self {
+        Self::new(0, SystemBus::default())
+    }
+}
+
 impl Cpu {
-    pub fn run(&mut self, debug: bool, terminator: Option<impl Fn(&Cpu) -> Option<u64>>) -> u64 {
-        while self.pc.read() < self.bus.memory.size() {
-            let xsnapshot = self.x.snapshot();
-            let fsnapshot = self.f.snapshot();
-            // read an address from the pc
-            let address = self.pc.read();
-            // fetch an instruction
-            let instruction = self.bus.load32(address);
-            // decode and execute the instruction
-            let result = if let Some(decoded) = PrivilegedDecoder::decode(instruction) {
-                if debug {
-                    println!("{:x}: {}", address, decoded.describe());
-                }
-                PrivilegedExecutor::execute(
-                    decoded,
-                    &self.prv,
-                    &mut self.pc,
-                    &mut self.x,
-                    &mut self.f,
-                    &mut self.csr,
-                    &mut self.bus,
-                )
-            } else if let Some(decoded) = ZifenceiDecoder::decode(instruction) {
-                if debug {
-                    println!("{:x}: {}", address, decoded.describe());
-                }
-                ZifenceiExecutor::execute(
-                    decoded,
-                    &self.prv,
-                    &mut self.pc,
-                    &mut self.x,
-                    &mut self.f,
-                    &mut self.csr,
-                    &mut self.bus,
-                )
-            } else if let Some(decoded) = ZicsrDecoder::decode(instruction) {
-                if debug {
-                    println!("{:x}: {}", address, decoded.describe());
+    /// Creates the hart with the id that accesses the bus.
+    pub(crate) fn new(hartid: usize, bus: SystemBus) -> Self {
+        let mut cpu = Self {
+            x: IntegerRegister::default(),
+            f: FloatingPointRegister::default(),
+            pc: ProgramCounter::default(),
+            csr: ControlAndStatusRegister::default(),
+            prv: PrivilegeMode::default(),
+            wfi: false,
+            ebreak_to_host: false,
+            ecall_to_host: false,
+            semihosting: false,
+            clock: Clock::default(),
+            shared: false,
+            blocks: BlockCache::default(),
+            #[cfg(feature = "jit")]
+            jit_threshold: jit::THRESHOLD,
+            bus,
+        };
+        cpu.csr.write(MHARTID, hartid as u64);
+        cpu.start_at(cpu.bus.memory.base(), cpu.bus.memory.end());
+        cpu
+    }
+
+    /// Makes the hart start from the pc with the stack pointer at sp.
+    pub(crate) fn start_at(&mut self, pc: u64, sp: u64) {
+        self.pc.jump(pc);
+        self.x.writeu(SP, sp);
+    }
+
+    pub fn x(&self) -> &IntegerRegister {
+        &self.x
+    }
+
+    pub fn x_mut(&mut self) -> &mut IntegerRegister {
+        &mut self.x
+    }
+
+    pub fn f(&self) -> &FloatingPointRegister {
+        &self.f
+    }
+
+    pub fn f_mut(&mut self) -> &mut FloatingPointRegister {
+        &mut self.f
+    }
+
+    pub fn pc(&self) -> u64 {
+        self.pc.read()
+    }
+
+    /// Sets the pc. In debug mode the hart resumes from the new pc.
+    pub fn set_pc(&mut self, address: u64) {
+        if self.is_debug_mode() {
+            self.csr.write(DPC, address);
+        }
+        self.pc.jump(address)
+    }
+
+    pub fn hartid(&self) -> usize {
+        self.csr.read(MHARTID) as usize
+    }
+
+    pub fn prv(&self) -> PrivilegeMode {
+        self.prv
+    }
+
+    pub fn set_prv(&mut self, prv: PrivilegeMode) {
+        self.prv = prv
+    }
+
+    /// Decodes the instruction at the address without executing it.
+    pub fn disassemble(&self, address: u64) -> Option<Description> {
+        if !self.bus.is_mapped(address) {
+            return None;
+        }
+        let instruction = self.bus.fetch(address);
+        PrivilegedDecoder::decode(instruction)
+            .map(|decoded| decoded.describe())
+            .or_else(|| ZifenceiDecoder::decode(instruction).map(|decoded| decoded.describe()))
+            .or_else(|| ZicsrDecoder::decode(instruction).map(|decoded| decoded.describe()))
+            .or_else(|| Rv32iDecoder::decode(instruction).map(|decoded| decoded.describe()))
+            .or_else(|| Rv64iDecoder::decode(instruction).map(|decoded| decoded.describe()))
+            .or_else(|| Rv32mDecoder::decode(instruction).map(|decoded| decoded.describe()))
+            .or_else(|| Rv64mDecoder::decode(instruction).map(|decoded| decoded.describe()))
+            .or_else(|| Rv32aDecoder::decode(instruction).map(|decoded| decoded.describe()))
+            .or_else(|| Rv64aDecoder::decode(instruction).map(|decoded| decoded.describe()))
+            .or_else(|| Rv32fDecoder::decode(instruction).map(|decoded| decoded.describe()))
+            .or_else(|| Rv64fDecoder::decode(instruction).map(|decoded| decoded.describe()))
+    }
+
+    /// Returns the name of the opcode enum, e.g. `Rv32iOpcodeR`, and the mnemonic of the
+    /// instruction.
+    pub fn opcode(instruction: u32) -> Option<(String, String)> {
+        opcode::<PrivilegedDecoder>(instruction)
+            .or_else(|| opcode::<ZifenceiDecoder>(instruction))
+            .or_else(|| opcode::<ZicsrDecoder>(instruction))
+            .or_else(|| opcode::<Rv32iDecoder>(instruction))
+            .or_else(|| opcode::<Rv64iDecoder>(instruction))
+            .or_else(|| opcode::<Rv32mDecoder>(instruction))
+            .or_else(|| opcode::<Rv64mDecoder>(instruction))
+            .or_else(|| opcode::<Rv32aDecoder>(instruction))
+            .or_else(|| opcode::<Rv64aDecoder>(instruction))
+            .or_else(|| opcode::<Rv32fDecoder>(instruction))
+            .or_else(|| opcode::<Rv64fDecoder>(instruction))
+    }
+
+    pub fn is_debug_mode(&self) -> bool {
+        self.csr.is_debug_mode()
+    }
+
+    /// Requests the hart to halt. The hart enters debug mode before its next instruction.
+    pub fn halt(&mut self) {
+        if !self.is_debug_mode() {
+            self.enter_debug_mode(DEBUG_CAUSE_HALTREQ, self.pc.read());
+        }
+    }
+
+    /// Leaves debug mode and resumes from dpc in the privilege mode of dcsr.prv.
+    pub fn resume(&mut self) {
+        if self.is_debug_mode() {
+            self.leave_debug_mode();
+        }
+    }
+
+    /// Executes an instruction from the program buffer of the debugger while the hart is in
+    /// debug mode. An exception stops the instruction without taking a trap, and DRET leaves
+    /// debug mode.
+    pub fn execute_in_debug_mode(&mut self, instruction: u32) -> Result<(), Cause> {
+        if !self.is_debug_mode() {
+            return Err(Cause::Exception(Exception::IllegalInstruction));
+        }
+        let pc = self.pc.read();
+        let result = self.execute(instruction, pc, false);
+        self.pc.jump(pc);
+        match result {
+            Err(Cause::DebugReturn) => {
+                self.leave_debug_mode();
+                Ok(())
+            }
+            result => result,
+        }
+    }
+
+    fn enter_debug_mode(&mut self, cause: u64, pc: u64) {
+        self.csr.write(DPC, pc);
+        self.csr.write_field(DCSR, &DCSR_CAUSE, cause);
+        self.csr.write_field(DCSR, &DCSR_PRV, self.prv as u64);
+        self.csr.set_debug_mode(true);
+        self.prv = PrivilegeMode::Machine;
+        self.pc.jump(pc);
+        self.wfi = false;
+    }
+
+    fn leave_debug_mode(&mut self) {
+        self.prv = PrivilegeMode::from_primitive(self.csr.read_field(DCSR, &DCSR_PRV));
+        if self.prv != PrivilegeMode::Machine {
+            self.csr.write_field(MSTATUS, &STATUS_MPRV, 0);
+        }
+        self.pc.jump(self.csr.read(DPC));
+        self.csr.set_debug_mode(false);
+    }
+
+    /// Returns true when an EBREAK in the privilege mode enters debug mode.
+    fn is_ebreak_to_debug_mode(&self) -> bool {
+        let ebreak = match self.prv {
+            PrivilegeMode::Machine => DCSR_EBREAKM,
+            PrivilegeMode::Supervisor => DCSR_EBREAKS,
+            PrivilegeMode::User => DCSR_EBREAKU,
+        };
+        self.csr.read_field(DCSR, &ebreak) == 1
+    }
+
+    /// Returns true when the EBREAK at the address is between the SLLI and the SRAI that mark a
+    /// semihosting call.
+    fn is_semihosting_call(&self, address: u64) -> bool {
+        address >= 4
+            && self.bus.is_mapped(address - 4)
+            && self.bus.fetch(address - 4) == SEMIHOSTING_ENTRY
+            && self.bus.fetch(address + 4) == SEMIHOSTING_EXIT
+    }
+
+    /// Advances the hart by either taking an interrupt or executing an instruction.
+    pub fn step(&mut self, debug: bool) -> Step {
+        // the hart waits for the debugger
+        if self.is_debug_mode() {
+            return Step::Debug;
+        }
+        if !self.bus.is_mapped(self.pc.read()) {
+            return Step::Halted;
+        }
+        // a single step enters debug mode after the instruction
+        let stepping = self.csr.read_field(DCSR, &DCSR_STEP) == 1;
+        self.update_pending_interrupts();
+        // stay idle until an enabled interrupt becomes pending
+        if self.wfi {
+            let mie = self.csr.read(MIE);
+            if self.csr.read(MIP) & mie == 0 {
+                match self.bus.clint.next_deadline(self.hartid()) {
+                    _ if self.shared => return Step::Idle,
+                    Some(deadline) if mie & MTI != 0 => {
+                        self.bus.clint.advance_to(deadline);
+                        self.update_pending_interrupts();
+                    }
+                    // nothing can wake the hart up
+                    _ => return Step::Halted,
                 }
-                ZicsrExecutor::execute(
-                    decoded,
-                    &self.prv,
-                    &mut self.pc,
-                    &mut self.x,
-                    &mut self.f,
-                    &mut self.csr,
-                    &mut self.bus,
-                )
-            } else if let Some(decoded) = Rv32iDecoder::decode(instruction) {
-                if debug {
-                    println!("{:x}: {}", address, decoded.describe());
+            }
+            self.wfi = false;
+        }
+        // take the interrupt unless it is disabled while stepping
+        let interrupt = if stepping && self.csr.read_field(DCSR, &DCSR_STEPIE) == 0 {
+            None
+        } else {
+            self.pending_interrupt()
+        };
+        if let Some(interrupt) = interrupt {
+            let (prv, pc) = handle_cause(
+                &Cause::Interrupt(interrupt),
+                self.pc.read(),
+                0,
+                self.prv,
+                &mut self.csr,
+            );
+            self.prv = prv;
+            self.pc.jump(pc);
+            if stepping {
+                self.enter_debug_mode(DEBUG_CAUSE_STEP, pc);
+            }
+            return Step::Trap(Cause::Interrupt(interrupt));
+        }
+        let snapshots = debug.then(|| (self.x.snapshot(), self.f.snapshot()));
+        let prv = self.prv;
+        // read an address from the pc
+        let address = self.pc.read();
+        // fetch an instruction
+        let instruction = self.bus.fetch(address);
+        // fire the triggers before the instruction
+        let access = self.data_access(instruction);
+        let triggers = &mut self.csr.triggers;
+        let action = triggers
+            .fire_pending(prv)
+            .or_else(|| triggers.fire(prv, TriggerAccess::Execute, address, instruction as u64))
+            .or_else(|| {
+                access.and_then(|(access, address, data)| triggers.fire(prv, access, address, data))
+            });
+        // decode and execute the instruction
+        let result = match action {
+            Some(ACTION_DEBUG_MODE) => {
+                self.enter_debug_mode(DEBUG_CAUSE_TRIGGER, address);
+                return Step::Debug;
+            }
+            Some(_) => Err(Cause::Exception(Exception::Breakpoint)),
+            None => self.execute(instruction, address, debug),
+        };
+        let triggered = action.is_some();
+
+        if let Some((xsnapshot, fsnapshot)) = snapshots {
+            self.dump(xsnapshot, fsnapshot);
+        }
+
+        let step = match result {
+            // wait for an interrupt from the next instruction, except while stepping
+            Err(Cause::WaitForInterrupt) => {
+                self.wfi = !stepping;
+                self.pc.increment();
+                Step::Retired
+            }
+            // retire the semihosting EBREAK and let the host serve the call
+            Err(Cause::Exception(Exception::Breakpoint))
+                if self.semihosting && !triggered && self.is_semihosting_call(address) =>
+            {
+                self.pc.increment();
+                Step::Semihosting
+            }
+            // stop at the EBREAK in debug mode
+            Err(Cause::Exception(Exception::Breakpoint))
+                if !triggered && self.is_ebreak_to_debug_mode() =>
+            {
+                self.enter_debug_mode(DEBUG_CAUSE_EBREAK, address);
+                return Step::Debug;
+            }
+            // leave the pc at the EBREAK and let the host decide how to resume
+            Err(Cause::Exception(Exception::Breakpoint)) if self.ebreak_to_host && !triggered => {
+                return Step::Breakpoint;
+            }
+            // retire the ECALL and let the host serve the system call
+            Err(Cause::Exception(Exception::EnvironmentCallFromUserMode)) if self.ecall_to_host => {
+                self.pc.increment();
+                Step::Syscall
+            }
+            // handle the trap
+            Err(cause) => {
+                let (prv, pc) =
+                    handle_cause(&cause, self.pc.read(), instruction, self.prv, &mut self.csr);
+                self.prv = prv;
+                self.pc.jump(pc);
+                match cause {
+                    Cause::ExceptionReturn(_) => Step::Retired,
+                    _ => Step::Trap(cause),
                 }
-                Rv32iExecutor::execute(
-                    decoded,
-                    &self.prv,
-                    &mut self.pc,
-                    &mut self.x,
-                    &mut self.f,
-                    &mut self.csr,
-                    &mut self.bus,
-                )
-            } else if let Some(decoded) = Rv64iDecoder::decode(instruction) {
-                if debug {
-                    println!("{:x}: {}", address, decoded.describe());
+            }
+            Ok(()) => {
+                // increment the pc when the pc has not been updated
+                if self.pc.read() == address {
+                    self.pc.increment();
                 }
-                Rv64iExecutor::execute(
-                    decoded,
-                    &self.prv,
-                    &mut self.pc,
-                    &mut self.x,
-                    &mut self.f,
-                    &mut self.csr,
-                    &mut self.bus,
-                )
-            } else if let Some(decoded) = Rv32mDecoder::decode(instruction) {
-                if debug {
-                    println!("{:x}: {}", address, decoded.describe());
+                Step::Retired
+            }
+        };
+        if matches!(step, Step::Retired | Step::Syscall | Step::Semihosting) {
+            self.csr.triggers.retire(prv);
+        }
+        // update the timer
+        self.bus.clint.tick();
+        self.count(1);
+        if stepping {
+            self.enter_debug_mode(DEBUG_CAUSE_STEP, self.pc.read());
+        }
+        step
+    }
+
+    /// Runs the decoded blocks from the pc up to the budget and returns the number of
+    /// instructions retired. The blocks follow one another until a store leaves the memory or
+    /// writes the doubleword at the watched address, so that the host sees the devices and
+    /// tohost change, and the counters are updated once for all of them. Zero means that the
+    /// next instruction has to go through `step`, which is also where an instruction of a block
+    /// that raises an exception takes the trap.
+    pub(crate) fn run_blocks(&mut self, budget: u64, watch: Option<u64>) -> u64 {
+        if budget == 0
+            || self.wfi
+            || self.is_debug_mode()
+            || self.csr.read_field(DCSR, &DCSR_STEP) == 1
+            || !self.csr.triggers.is_idle()
+        {
+            return 0;
+        }
+        self.update_pending_interrupts();
+        if self.pending_interrupt().is_some() {
+            return 0;
+        }
+        // stop where the timer interrupt becomes pending, as stepping would
+        let clint = &self.bus.clint;
+        let budget = match clint.next_deadline(self.hartid()) {
+            Some(deadline) => budget.min(deadline - clint.mtime()),
+            None => budget,
+        };
+        let mut retired = 0;
+        while retired < budget && self.bus.memory.contains(self.pc.read()) {
+            let block = self.blocks.get(&self.bus.memory, self.pc.read());
+            let mut completed = !block.ops.is_empty();
+            // the compiled code runs the whole block, so it waits for a budget that allows it
+            #[cfg(feature = "jit")]
+            if completed && block.ops.len() as u64 <= budget - retired {
+                let start = self.pc.read();
+                if let Some(code) = block.compiled.get(&block.ops, start, self.jit_threshold) {
+                    let count = code.run(self, start);
+                    retired += count;
+                    if count < block.ops.len() as u64 || self.stops_after(&block, watch) {
+                        break;
+                    }
+                    continue;
                 }
-                Rv32mExecutor::execute(
-                    decoded,
-                    &self.prv,
-                    &mut self.pc,
-                    &mut self.x,
-                    &mut self.f,
-                    &mut self.csr,
-                    &mut self.bus,
-                )
-            } else if let Some(decoded) = Rv64mDecoder::decode(instruction) {
-                if debug {
-                    println!("{:x}: {}", address, decoded.describe());
+            }
+            for op in block.ops.iter() {
+                if retired == budget {
+                    completed = false;
+                    break;
                 }
-                Rv64mExecutor::execute(
-                    decoded,
-                    &self.prv,
-                    &mut self.pc,
-                    &mut self.x,
-                    &mut self.f,
-                    &mut self.csr,
-                    &mut self.bus,
-                )
-            } else if let Some(decoded) = Rv32fDecoder::decode(instruction) {
-                if debug {
-                    println!("{:x}: {}", address, decoded.describe());
+                let address = self.pc.read();
+                // the exception leaves the state as it was, so the instruction runs again in
+                // `step`
+                if op.execute(self).is_err() {
+                    completed = false;
+                    break;
                 }
-                Rv32fExecutor::execute(
-                    decoded,
-                    &self.prv,
-                    &mut self.pc,
-                    &mut self.x,
-                    &mut self.f,
-                    &mut self.csr,
-                    &mut self.bus,
-                )
-            } else if let Some(decoded) = Rv64fDecoder::decode(instruction) {
-                if debug {
-                    println!("{:x}: {}", address, decoded.describe());
+                if self.pc.read() == address {
+                    self.pc.increment();
                 }
-                Rv64fExecutor::execute(
-                    decoded,
-                    &self.prv,
-                    &mut self.pc,
-                    &mut self.x,
-                    &mut self.f,
-                    &mut self.csr,
-                    &mut self.bus,
-                )
-            } else {
-                Err(Cause::Exception(Exception::IllegalInstruction))
-            };
+                self.bus.clint.tick();
+                retired += 1;
+            }
+            if !completed || self.stops_after(&block, watch) {
+                break;
+            }
+        }
+        if retired > 0 {
+            self.count(retired);
+        }
+        retired
+    }
+
+    /// Returns whether the blocks stop after the block, since its store has to be seen by the
+    /// devices or by the host that watches the address.
+    fn stops_after(&self, block: &Block, watch: Option<u64>) -> bool {
+        let store = self.bus.last_store();
+        block.store
+            && (!self.bus.memory.contains(store)
+                || watch.is_some_and(|address| address.abs_diff(store) < 8))
+    }
 
+    /// Drops the decoded blocks, which have to be decoded again from the memory.
+    pub(crate) fn clear_blocks(&mut self) {
+        self.blocks.clear();
+    }
+
+    /// Updates the cycle, the time and the instret for the instructions, once the clint has
+    /// ticked for them.
+    fn count(&mut self, instructions: u64) {
+        let cycle = self.csr.read(CYCLE) + instructions;
+        self.csr.write(CYCLE, cycle);
+        let instret = self.csr.read(INSTRET);
+        let time = match self.clock {
+            Clock::Mtime => self.bus.clint.mtime(),
+            // the time is the one before the last instruction
+            Clock::WallClock => self
+                .bus
+                .inputs
+                .time(self.hartid(), instret + instructions - 1),
+        };
+        self.csr.write(TIME, time);
+        self.csr.write(INSTRET, instret + instructions);
+    }
+
+    /// Returns the time CSR in nanoseconds for the host calls that report the time. The time
+    /// follows the machine so that it is replayed and can be deterministic.
+    pub(crate) fn nanoseconds(&self) -> u64 {
+        let time = self.csr.read(TIME);
+        match self.clock {
+            Clock::WallClock => time.saturating_mul(1_000_000_000),
+            Clock::Mtime => time.saturating_mul(1_000_000_000 / MTIME_FREQUENCY),
+        }
+    }
+
+    /// Reads the stdin of the host into the buffer through the inputs, so that the reads are
+    /// recorded and replayed.
+    pub(crate) fn read_stdin(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        let instret = self.csr.read(INSTRET);
+        self.bus.inputs.read_stdin(self.hartid(), instret, buffer)
+    }
+
+    /// Decodes and executes the instruction at the address.
+    fn execute(&mut self, instruction: u32, address: u64, debug: bool) -> Result<(), Cause> {
+        if let Some(decoded) = PrivilegedDecoder::decode(instruction) {
             if debug {
-                self.dump(xsnapshot, fsnapshot);
+                println!("{:x}: {}", address, decoded.describe());
             }
-
-            if let Some(ref func) = terminator {
-                if let Some(result) = func(self) {
-                    return result;
-                }
+            PrivilegedExecutor::execute(
+                decoded,
+                &self.prv,
+                &mut self.pc,
+                &mut self.x,
+                &mut self.f,
+                &mut self.csr,
+                &mut self.bus,
+            )
+        } else if let Some(decoded) = ZifenceiDecoder::decode(instruction) {
+            if debug {
+                println!("{:x}: {}", address, decoded.describe());
+            }
+            // the stores become visible to the fetches, so the decoded blocks are stale
+            self.blocks.clear();
+            ZifenceiExecutor::execute(
+                decoded,
+                &self.prv,
+                &mut self.pc,
+                &mut self.x,
+                &mut self.f,
+                &mut self.csr,
+                &mut self.bus,
+            )
+        } else if let Some(decoded) = ZicsrDecoder::decode(instruction) {
+            if debug {
+                println!("{:x}: {}", address, decoded.describe());
             }
+            ZicsrExecutor::execute(
+                decoded,
+                &self.prv,
+                &mut self.pc,
+                &mut self.x,
+                &mut self.f,
+                &mut self.csr,
+                &mut self.bus,
+            )
+        } else if let Some(decoded) = Rv32iDecoder::decode(instruction) {
+            if debug {
+                println!("{:x}: {}", address, decoded.describe());
+            }
+            Rv32iExecutor::execute(
+                decoded,
+                &self.prv,
+                &mut self.pc,
+                &mut self.x,
+                &mut self.f,
+                &mut self.csr,
+                &mut self.bus,
+            )
+        } else if let Some(decoded) = Rv64iDecoder::decode(instruction) {
+            if debug {
+                println!("{:x}: {}", address, decoded.describe());
+            }
+            Rv64iExecutor::execute(
+                decoded,
+                &self.prv,
+                &mut self.pc,
+                &mut self.x,
+                &mut self.f,
+                &mut self.csr,
+                &mut self.bus,
+            )
+        } else if let Some(decoded) = Rv32mDecoder::decode(instruction) {
+            if debug {
+                println!("{:x}: {}", address, decoded.describe());
+            }
+            Rv32mExecutor::execute(
+                decoded,
+                &self.prv,
+                &mut self.pc,
+                &mut self.x,
+                &mut self.f,
+                &mut self.csr,
+                &mut self.bus,
+            )
+        } else if let Some(decoded) = Rv64mDecoder::decode(instruction) {
+            if debug {
+                println!("{:x}: {}", address, decoded.describe());
+            }
+            Rv64mExecutor::execute(
+                decoded,
+                &self.prv,
+                &mut self.pc,
+                &mut self.x,
+                &mut self.f,
+                &mut self.csr,
+                &mut self.bus,
+            )
+        } else if let Some(decoded) = Rv32aDecoder::decode(instruction) {
+            if debug {
+                println!("{:x}: {}", address, decoded.describe());
+            }
+            Rv32aExecutor::execute(
+                decoded,
+                &self.prv,
+                &mut self.pc,
+                &mut self.x,
+                &mut self.f,
+                &mut self.csr,
+                &mut self.bus,
+            )
+        } else if let Some(decoded) = Rv64aDecoder::decode(instruction) {
+            if debug {
+                println!("{:x}: {}", address, decoded.describe());
+            }
+            Rv64aExecutor::execute(
+                decoded,
+                &self.prv,
+                &mut self.pc,
+                &mut self.x,
+                &mut self.f,
+                &mut self.csr,
+                &mut self.bus,
+            )
+        } else if let Some(decoded) = Rv32fDecoder::decode(instruction) {
+            if debug {
+                println!("{:x}: {}", address, decoded.describe());
+            }
+            Rv32fExecutor::execute(
+                decoded,
+                &self.prv,
+                &mut self.pc,
+                &mut self.x,
+                &mut self.f,
+                &mut self.csr,
+                &mut self.bus,
+            )
+        } else if let Some(decoded) = Rv64fDecoder::decode(instruction) {
+            if debug {
+                println!("{:x}: {}", address, decoded.describe());
+            }
+            Rv64fExecutor::execute(
+                decoded,
+                &self.prv,
+                &mut self.pc,
+                &mut self.x,
+                &mut self.f,
+                &mut self.csr,
+                &mut self.bus,
+            )
+        } else {
+            Err(Cause::Exception(Exception::IllegalInstruction))
+        }
+    }
 
-            // handle the trap
-            if let Err(cause) = result {
-                let (prv, pc) =
-                    handle_cause(&cause, self.pc.read(), instruction, self.prv, &mut self.csr);
-                self.prv = prv;
-                self.pc.jump(pc);
+    /// Returns the kind, the address and the data of the memory access of a load or a store.
+    fn data_access(&self, instruction: u32) -> Option<(TriggerAccess, u64, u64)> {
+        let opcode = instruction & 0b1111111;
+        let funct3 = (instruction >> 12) & 0b111;
+        let rs1 = ((instruction >> 15) & 0b11111) as usize;
+        let rs2 = ((instruction >> 20) & 0b11111) as usize;
+        let size = match funct3 & 0b11 {
+            0 => Size::Byte,
+            1 => Size::Halfword,
+            2 => Size::Word,
+            _ => Size::Doubleword,
+        };
+        let mask = u64::MAX >> (64 - 8 * size as u64);
+        match opcode {
+            // LOAD and LOAD-FP
+            0b0000011 | 0b0000111 => {
+                let offset = (instruction as i32 >> 20) as u64;
+                let address = self.x.readu(rs1).wrapping_add(offset);
+                let data = if self.bus.is_mapped(address) {
+                    self.bus.peek(address, size)
+                } else {
+                    0
+                };
+                Some((TriggerAccess::Load, address, data))
             }
-            // increment the pc when the pc has not been updated
-            else if self.pc.read() == address {
-                self.pc.increment();
+            // STORE and STORE-FP
+            0b0100011 | 0b0100111 => {
+                let offset = ((instruction as i32 >> 25) << 5) as u64
+                    | ((instruction >> 7) & 0b11111) as u64;
+                let address = self.x.readu(rs1).wrapping_add(offset);
+                let data = if opcode == 0b0100011 {
+                    self.x.readu(rs2) & mask
+                } else {
+                    self.f.reads(rs2) as u64
+                };
+                Some((TriggerAccess::Store, address, data))
             }
-            // update the cycle
-            let cycle = self.csr.read(CYCLE) + 1;
-            self.csr.write(CYCLE, cycle);
-            // update the time
-            let time = SystemTime::now().duration_since(UNIX_EPOCH);
-            if let Ok(t) = time {
-                self.csr.write(TIME, t.as_secs());
+            // AMO, of which LR loads and the others store
+            0b0101111 => {
+                let address = self.x.readu(rs1);
+                if instruction >> 27 == 0b00010 {
+                    let data = if self.bus.is_mapped(address) {
+                        self.bus.peek(address, size)
+                    } else {
+                        0
+                    };
+                    Some((TriggerAccess::Load, address, data))
+                } else {
+                    Some((TriggerAccess::Store, address, self.x.readu(rs2) & mask))
+                }
             }
-            // update the instret
-            let instret = self.csr.read(INSTRET) + 1;
-            self.csr.write(INSTRET, instret);
+            _ => None,
+        }
+    }
+
+    fn update_pending_interrupts(&mut self) {
+        let mut mip = self.csr.read(MIP) & !(MSI | MTI);
+        let hart = self.hartid();
+        if self.bus.clint.is_software_interrupt_pending(hart) {
+            mip |= MSI;
         }
-        0
+        if self.bus.clint.is_timer_interrupt_pending(hart) {
+            mip |= MTI;
+        }
+        self.csr.write(MIP, mip);
+    }
+
+    fn pending_interrupt(&self) -> Option<Interrupt> {
+        let pending = self.csr.read(MIP) & self.csr.read(MIE);
+        if pending == 0 {
+            return None;
+        }
+        let mideleg = self.csr.read(MIDELEG);
+        let sideleg = self.csr.read(SIDELEG);
+        let m_enabled =
+            self.prv != PrivilegeMode::Machine || self.csr.read_field(MSTATUS, &STATUS_MIE) == 1;
+        let s_enabled = self.prv == PrivilegeMode::User
+            || (self.prv == PrivilegeMode::Supervisor
+                && self.csr.read_field(MSTATUS, &STATUS_SIE) == 1);
+        let u_enabled =
+            self.prv == PrivilegeMode::User && self.csr.read_field(MSTATUS, &STATUS_UIE) == 1;
+        // in the order of priority
+        [
+            (MEI, Interrupt::MachineExternal),
+            (MSI, Interrupt::MachineSoftware),
+            (MTI, Interrupt::MachineTimer),
+            (SEI, Interrupt::SupervisorExternal),
+            (SSI, Interrupt::SupervisorSoftware),
+            (STI, Interrupt::SupervisorTimer),
+            (UEI, Interrupt::UserExternal),
+            (USI, Interrupt::UserSoftware),
+            (UTI, Interrupt::UserTimer),
+        ]
+        .into_iter()
+        .find(|(bit, _)| {
+            pending & bit != 0
+                && if mideleg & bit == 0 {
+                    m_enabled
+                } else if sideleg & bit == 0 {
+                    s_enabled
+                } else {
+                    u_enabled
+                }
+        })
+        .map(|(_, interrupt)| interrupt)
     }
 
     fn dump(&self, xsnapshot: [u64; 32], fsnapshot: [u64; 32]) {
@@ -240,3 +830,275 @@ impl Cpu {
         println!();
     }
 }
+
+/// Returns the name of the opcode enum and the mnemonic if the decoder decodes the instruction.
+fn opcode<D: Decoder>(instruction: u32) -> Option<(String, String)>
+where
+    Instruction<D::OpcodeR, D::OpcodeI, D::OpcodeS, D::OpcodeB, D::OpcodeU, D::OpcodeJ>: Describer,
+{
+    D::decode(instruction).map(|decoded| {
+        let (format, mnemonic) = decoded.opcode();
+        (format!("{}Opcode{}", D::NAME, format), mnemonic)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        emulator::bus::{clint::CLINT_BASE_ADDRESS, memory::MEMORY_BASE_ADDRESS, region::Region},
+        isa::csr::{
+            machine_level::{MCAUSE, MEPC, MTVAL, MTVEC, TDATA1, TDATA2},
+            status::STATUS_TW,
+            trigger::{TDATA1_DMODE, TRIGGER_TYPE_MCONTROL},
+        },
+    };
+
+    const WFI: u32 = 0x1050_0073;
+    const EBREAK: u32 = 0x0010_0073;
+    const NOP: u32 = 0x0000_0013;
+    // addi a0, a0, 1
+    const ADDI: u32 = 0x0015_0513;
+    const DRET: u32 = 0x7b20_0073;
+    // csrr a0, dcsr
+    const CSRR_DCSR: u32 = 0x7b00_2573;
+    // sd a0, 0(a1)
+    const SD: u32 = 0x00a5_b023;
+    const MTIMECMP: u64 = CLINT_BASE_ADDRESS + 0x4000;
+
+    #[test]
+    fn wfi_advances_to_timer_deadline_ok() {
+        let handler = MEMORY_BASE_ADDRESS + 0x100;
+        let mut cpu = Cpu::default();
+        cpu.bus.store32(MEMORY_BASE_ADDRESS, WFI);
+        cpu.bus.store64(MTIMECMP, 1_000_000);
+        cpu.csr.csrrw(MTVEC, handler);
+        cpu.csr.csrrw(MIE, MTI);
+        cpu.csr.write_field(MSTATUS, &STATUS_MIE, 1);
+        assert_eq!(cpu.step(false), Step::Retired);
+        assert_eq!(
+            cpu.step(false),
+            Step::Trap(Cause::Interrupt(Interrupt::MachineTimer))
+        );
+        assert_eq!(cpu.pc.read(), handler);
+        assert_eq!(cpu.csr.read(CYCLE), 1);
+        assert!(cpu.bus.clint.is_timer_interrupt_pending(0));
+        assert_eq!(cpu.csr.read(MEPC), MEMORY_BASE_ADDRESS + 4);
+    }
+
+    #[test]
+    fn wfi_without_wakeup_source_halts_ok() {
+        let mut cpu = Cpu::default();
+        cpu.bus.store32(MEMORY_BASE_ADDRESS, WFI);
+        assert_eq!(cpu.step(false), Step::Retired);
+        assert_eq!(cpu.step(false), Step::Halted);
+        assert_eq!(cpu.pc.read(), MEMORY_BASE_ADDRESS + 4);
+    }
+
+    #[test]
+    fn wfi_traps_when_tw_is_set_ok() {
+        let mut cpu = Cpu {
+            prv: PrivilegeMode::Supervisor,
+            ..Default::default()
+        };
+        cpu.bus.store32(MEMORY_BASE_ADDRESS, WFI);
+        cpu.csr.write_field(MSTATUS, &STATUS_TW, 1);
+        cpu.csr.csrrw(MTVEC, MEMORY_BASE_ADDRESS + 0x100);
+        assert_eq!(
+            cpu.step(false),
+            Step::Trap(Cause::Exception(Exception::IllegalInstruction))
+        );
+        assert_eq!(cpu.csr.read(MCAUSE), 2);
+        assert_eq!(cpu.pc.read(), MEMORY_BASE_ADDRESS + 0x100);
+    }
+
+    #[test]
+    fn ebreak_ok() {
+        let pc = MEMORY_BASE_ADDRESS + 8;
+        let mut cpu = Cpu::default();
+        cpu.pc.jump(pc);
+        cpu.bus.store32(pc, EBREAK);
+        cpu.csr.csrrw(MTVEC, MEMORY_BASE_ADDRESS + 0x100);
+        assert_eq!(
+            cpu.step(false),
+            Step::Trap(Cause::Exception(Exception::Breakpoint))
+        );
+        assert_eq!(cpu.csr.read(MCAUSE), 3);
+        assert_eq!(cpu.csr.read(MEPC), pc);
+        assert_eq!(cpu.csr.read(MTVAL), pc);
+        assert_eq!(cpu.pc.read(), MEMORY_BASE_ADDRESS + 0x100);
+    }
+
+    #[test]
+    fn ebreak_to_host_ok() {
+        let mut cpu = Cpu {
+            ebreak_to_host: true,
+            ..Default::default()
+        };
+        cpu.bus.store32(MEMORY_BASE_ADDRESS, EBREAK);
+        assert_eq!(cpu.step(false), Step::Breakpoint);
+        assert_eq!(cpu.step(false), Step::Breakpoint);
+        assert_eq!(cpu.pc.read(), MEMORY_BASE_ADDRESS);
+        assert_eq!(cpu.csr.read(MCAUSE), 0);
+        assert_eq!(cpu.csr.read(CYCLE), 0);
+    }
+
+    #[test]
+    fn store_trigger_ok() {
+        let target = MEMORY_BASE_ADDRESS + 0x1000;
+        let mut cpu = Cpu::default();
+        cpu.bus.store32(MEMORY_BASE_ADDRESS, SD);
+        cpu.x.writeu(10, 0xdead);
+        cpu.x.writeu(11, target);
+        cpu.csr.csrrw(MTVEC, MEMORY_BASE_ADDRESS + 0x100);
+        // mcontrol matching stores in M-mode
+        cpu.csr
+            .csrrw(TDATA1, TRIGGER_TYPE_MCONTROL << 60 | 1 << 6 | 1 << 1);
+        cpu.csr.csrrw(TDATA2, target);
+        assert_eq!(
+            cpu.step(false),
+            Step::Trap(Cause::Exception(Exception::Breakpoint))
+        );
+        assert_eq!(cpu.csr.read(MEPC), MEMORY_BASE_ADDRESS);
+        assert_eq!(cpu.bus.load64(target), 0);
+        assert_ne!(cpu.csr.read(TDATA1) & 1 << 20, 0);
+    }
+
+    #[test]
+    fn rom_store_ok() {
+        let rom = 0x2000_0000;
+        let mut cpu = Cpu::default();
+        cpu.bus
+            .map(Region::from_bytes(rom, vec![0; 8], false).unwrap())
+            .unwrap();
+        cpu.bus.store32(MEMORY_BASE_ADDRESS, SD);
+        cpu.x.writeu(10, 0xdead);
+        cpu.x.writeu(11, rom);
+        cpu.csr.csrrw(MTVEC, MEMORY_BASE_ADDRESS + 0x100);
+        assert_eq!(
+            cpu.step(false),
+            Step::Trap(Cause::Exception(Exception::StoreAccessFault))
+        );
+        assert_eq!(cpu.csr.read(MCAUSE), 7);
+        assert_eq!(cpu.csr.read(MTVAL), 0);
+        assert_eq!(cpu.bus.load64(rom), 0);
+    }
+
+    #[test]
+    fn rom_fetch_ok() {
+        let rom = 0x2000_0000;
+        let mut cpu = Cpu::default();
+        let code = [NOP, ADDI].iter().flat_map(|i| i.to_le_bytes()).collect();
+        cpu.bus
+            .map(Region::from_bytes(rom, code, false).unwrap())
+            .unwrap();
+        cpu.pc.jump(rom);
+        assert_eq!(cpu.step(false), Step::Retired);
+        assert_eq!(cpu.step(false), Step::Retired);
+        assert_eq!(cpu.x.readu(10), 1);
+        // the hart halts past the end of the ROM
+        assert_eq!(cpu.step(false), Step::Halted);
+        assert_eq!(cpu.pc(), rom + 8);
+    }
+
+    #[test]
+    fn ebreak_enters_debug_mode_ok() {
+        let pc = MEMORY_BASE_ADDRESS + 4;
+        let mut cpu = Cpu {
+            prv: PrivilegeMode::Supervisor,
+            ebreak_to_host: true,
+            ..Default::default()
+        };
+        cpu.pc.jump(pc);
+        cpu.bus.store32(pc, EBREAK);
+        cpu.csr.write_field(DCSR, &DCSR_EBREAKS, 1);
+        assert_eq!(cpu.step(false), Step::Debug);
+        assert_eq!(cpu.step(false), Step::Debug);
+        assert!(cpu.is_debug_mode());
+        assert_eq!(cpu.prv, PrivilegeMode::Machine);
+        assert_eq!(cpu.csr.read(DPC), pc);
+        assert_eq!(cpu.csr.read_field(DCSR, &DCSR_CAUSE), DEBUG_CAUSE_EBREAK);
+        assert_eq!(cpu.csr.read_field(DCSR, &DCSR_PRV), 0b01);
+        assert_eq!(cpu.csr.read(MCAUSE), 0);
+
+        // dret returns to dpc in dcsr.prv
+        cpu.csr.write(DPC, pc + 4);
+        assert_eq!(cpu.execute_in_debug_mode(DRET), Ok(()));
+        assert!(!cpu.is_debug_mode());
+        assert_eq!(cpu.prv, PrivilegeMode::Supervisor);
+        assert_eq!(cpu.pc.read(), pc + 4);
+    }
+
+    #[test]
+    fn single_step_ok() {
+        let handler = MEMORY_BASE_ADDRESS + 0x100;
+        let mut cpu = Cpu::default();
+        cpu.bus.store32(MEMORY_BASE_ADDRESS, NOP);
+        cpu.bus.store32(MEMORY_BASE_ADDRESS + 4, WFI);
+        cpu.csr.csrrw(MTVEC, handler);
+        cpu.csr.write_field(DCSR, &DCSR_STEP, 1);
+        assert_eq!(cpu.step(false), Step::Retired);
+        assert_eq!(cpu.csr.read(DPC), MEMORY_BASE_ADDRESS + 4);
+        assert_eq!(cpu.csr.read_field(DCSR, &DCSR_CAUSE), DEBUG_CAUSE_STEP);
+
+        // interrupts are disabled while stepping and WFI does not wait
+        cpu.csr.csrrw(MIE, SSI);
+        cpu.csr.write(MIP, SSI);
+        cpu.csr.write_field(MSTATUS, &STATUS_MIE, 1);
+        cpu.resume();
+        assert_eq!(cpu.step(false), Step::Retired);
+        assert_eq!(cpu.csr.read(DPC), MEMORY_BASE_ADDRESS + 8);
+
+        // a step into the trap handler stops at its first instruction
+        cpu.csr.write_field(DCSR, &DCSR_STEPIE, 1);
+        cpu.resume();
+        assert_eq!(
+            cpu.step(false),
+            Step::Trap(Cause::Interrupt(Interrupt::SupervisorSoftware))
+        );
+        assert_eq!(cpu.csr.read(DPC), handler);
+        assert_eq!(cpu.csr.read(MEPC), MEMORY_BASE_ADDRESS + 8);
+    }
+
+    #[test]
+    fn trigger_enters_debug_mode_ok() {
+        let mut cpu = Cpu::default();
+        cpu.bus.store32(MEMORY_BASE_ADDRESS, NOP);
+        cpu.halt();
+        cpu.csr.csrrw(
+            TDATA1,
+            1 << TDATA1_DMODE.start
+                | TRIGGER_TYPE_MCONTROL << 60
+                | ACTION_DEBUG_MODE << 12
+                | 1 << 6
+                | 1 << 2,
+        );
+        cpu.csr.csrrw(TDATA2, MEMORY_BASE_ADDRESS);
+        cpu.resume();
+        assert_eq!(cpu.step(false), Step::Debug);
+        assert_eq!(cpu.csr.read_field(DCSR, &DCSR_CAUSE), DEBUG_CAUSE_TRIGGER);
+        assert_eq!(cpu.csr.read(DPC), MEMORY_BASE_ADDRESS);
+        assert_ne!(cpu.csr.read(TDATA1) & 1 << 20, 0);
+    }
+
+    #[test]
+    fn debug_mode_only_instructions_ok() {
+        let mut cpu = Cpu::default();
+        cpu.bus.store32(MEMORY_BASE_ADDRESS, DRET);
+        cpu.bus.store32(MEMORY_BASE_ADDRESS + 4, CSRR_DCSR);
+        cpu.csr.csrrw(MTVEC, MEMORY_BASE_ADDRESS + 4);
+        for _ in 0..2 {
+            assert_eq!(
+                cpu.step(false),
+                Step::Trap(Cause::Exception(Exception::IllegalInstruction))
+            );
+        }
+        assert_eq!(
+            cpu.execute_in_debug_mode(CSRR_DCSR),
+            Err(Cause::Exception(Exception::IllegalInstruction))
+        );
+        cpu.halt();
+        assert_eq!(cpu.execute_in_debug_mode(CSRR_DCSR), Ok(()));
+        assert_eq!(cpu.x.readu(10), cpu.csr.read(DCSR));
+    }
+}
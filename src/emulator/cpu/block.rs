@@ -0,0 +1,216 @@
+#[cfg(feature = "jit")]
+use crate::emulator::cpu::jit::Compiled;
+use crate::{
+    emulator::{
+        bus::{
+            memory::{Memory, PAGE_SIZE},
+            Size,
+        },
+        cpu::{
+            decoder::{
+                rv32a::Rv32aDecoder, rv32f::Rv32fDecoder, rv32i::Rv32iDecoder, rv32m::Rv32mDecoder,
+                rv64a::Rv64aDecoder, rv64f::Rv64fDecoder, rv64i::Rv64iDecoder, rv64m::Rv64mDecoder,
+                Decoder,
+            },
+            executor::{
+                rv32a::Rv32aExecutor, rv32f::Rv32fExecutor, rv32i::Rv32iExecutor,
+                rv32m::Rv32mExecutor, rv64a::Rv64aExecutor, rv64f::Rv64fExecutor,
+                rv64i::Rv64iExecutor, rv64m::Rv64mExecutor, Executor,
+            },
+            Cpu,
+        },
+    },
+    isa::{instruction::Instruction, privileged::cause::Cause},
+};
+use std::collections::HashMap;
+use std::rc::Rc;
+
+// The most instructions that a block holds.
+const BLOCK_SIZE: usize = 64;
+
+// The major opcodes that end a block after the instruction: the control transfers, since the
+// next instruction is not the next word, and the stores and the atomics, since they can change
+// the code, the devices or tohost.
+const BRANCH: u32 = 0b1100011;
+const JAL: u32 = 0b1101111;
+const JALR: u32 = 0b1100111;
+const STORE: u32 = 0b0100011;
+const STORE_FP: u32 = 0b0100111;
+const AMO: u32 = 0b0101111;
+// The major opcodes that end a block before the instruction, since they can change the state
+// that the hart checks between instructions.
+const SYSTEM: u32 = 0b1110011;
+const MISC_MEM: u32 = 0b0001111;
+
+type Decoded<D> = Instruction<
+    <D as Decoder>::OpcodeR,
+    <D as Decoder>::OpcodeI,
+    <D as Decoder>::OpcodeS,
+    <D as Decoder>::OpcodeB,
+    <D as Decoder>::OpcodeU,
+    <D as Decoder>::OpcodeJ,
+>;
+
+/// An instruction decoded once, with the extension that executes it.
+#[derive(Clone, Copy)]
+pub(crate) enum Op {
+    Rv32i(Decoded<Rv32iDecoder>),
+    Rv64i(Decoded<Rv64iDecoder>),
+    Rv32m(Decoded<Rv32mDecoder>),
+    Rv64m(Decoded<Rv64mDecoder>),
+    Rv32a(Decoded<Rv32aDecoder>),
+    Rv64a(Decoded<Rv64aDecoder>),
+    Rv32f(Decoded<Rv32fDecoder>),
+    Rv64f(Decoded<Rv64fDecoder>),
+}
+
+impl Op {
+    /// Decodes the instruction in the order that `Cpu::execute` tries the extensions.
+    fn decode(instruction: u32) -> Option<Self> {
+        Rv32iDecoder::decode(instruction)
+            .map(Self::Rv32i)
+            .or_else(|| Rv64iDecoder::decode(instruction).map(Self::Rv64i))
+            .or_else(|| Rv32mDecoder::decode(instruction).map(Self::Rv32m))
+            .or_else(|| Rv64mDecoder::decode(instruction).map(Self::Rv64m))
+            .or_else(|| Rv32aDecoder::decode(instruction).map(Self::Rv32a))
+            .or_else(|| Rv64aDecoder::decode(instruction).map(Self::Rv64a))
+            .or_else(|| Rv32fDecoder::decode(instruction).map(Self::Rv32f))
+            .or_else(|| Rv64fDecoder::decode(instruction).map(Self::Rv64f))
+    }
+
+    pub(crate) fn execute(self, cpu: &mut Cpu) -> Result<(), Cause> {
+        let Cpu {
+            prv,
+            pc,
+            x,
+            f,
+            csr,
+            bus,
+            ..
+        } = cpu;
+        match self {
+            Self::Rv32i(decoded) => Rv32iExecutor::execute(decoded, prv, pc, x, f, csr, bus),
+            Self::Rv64i(decoded) => Rv64iExecutor::execute(decoded, prv, pc, x, f, csr, bus),
+            Self::Rv32m(decoded) => Rv32mExecutor::execute(decoded, prv, pc, x, f, csr, bus),
+            Self::Rv64m(decoded) => Rv64mExecutor::execute(decoded, prv, pc, x, f, csr, bus),
+            Self::Rv32a(decoded) => Rv32aExecutor::execute(decoded, prv, pc, x, f, csr, bus),
+            Self::Rv64a(decoded) => Rv64aExecutor::execute(decoded, prv, pc, x, f, csr, bus),
+            Self::Rv32f(decoded) => Rv32fExecutor::execute(decoded, prv, pc, x, f, csr, bus),
+            Self::Rv64f(decoded) => Rv64fExecutor::execute(decoded, prv, pc, x, f, csr, bus),
+        }
+    }
+}
+
+/// The straight-line instructions from an address, decoded from a page of the memory at the
+/// version that the page had then.
+#[derive(Clone)]
+pub(crate) struct Block {
+    pub(crate) ops: Rc<[Op]>,
+    // whether the last instruction is a store
+    pub(crate) store: bool,
+    version: u64,
+    #[cfg(feature = "jit")]
+    pub(crate) compiled: Rc<Compiled>,
+}
+
+impl Block {
+    /// Decodes the instructions from the address up to the end of the block. The block is
+    /// empty when the first instruction has to go through `Cpu::step`.
+    fn translate(memory: &Memory, start: u64) -> Self {
+        let page_end = (start / PAGE_SIZE as u64 + 1) * PAGE_SIZE as u64;
+        let end = page_end.min(memory.end());
+        let mut ops = vec![];
+        let mut address = start;
+        let mut store = false;
+        while address + 4 <= end && ops.len() < BLOCK_SIZE {
+            let instruction = memory.load(address, Size::Word) as u32;
+            let opcode = instruction & 0b1111111;
+            if matches!(opcode, SYSTEM | MISC_MEM) {
+                break;
+            }
+            // an illegal instruction traps in `Cpu::step`
+            let Some(op) = Op::decode(instruction) else {
+                break;
+            };
+            ops.push(op);
+            address += 4;
+            store = matches!(opcode, STORE | STORE_FP | AMO);
+            if store || matches!(opcode, BRANCH | JAL | JALR) {
+                break;
+            }
+        }
+        Self {
+            ops: ops.into(),
+            store,
+            version: memory.version(start),
+            #[cfg(feature = "jit")]
+            compiled: Rc::default(),
+        }
+    }
+}
+
+/// The blocks that a hart has decoded, by their start address. A block is decoded again once
+/// its page has been written, and FENCE.I drops every block.
+#[derive(Default)]
+pub(crate) struct BlockCache {
+    blocks: HashMap<u64, Block>,
+}
+
+impl BlockCache {
+    /// Returns the block at the address, which must be in the memory.
+    pub(crate) fn get(&mut self, memory: &Memory, address: u64) -> Block {
+        let version = memory.version(address);
+        let block = self
+            .blocks
+            .entry(address)
+            .and_modify(|block| {
+                if block.version != version {
+                    *block = Block::translate(memory, address);
+                }
+            })
+            .or_insert_with(|| Block::translate(memory, address));
+        block.clone()
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.blocks.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::bus::memory::MEMORY_BASE_ADDRESS;
+
+    const ADDI: u32 = 0x0015_0513; // addi a0, a0, 1
+    const SD: u32 = 0x00a5_b023; // sd a0, 0(a1)
+    const ECALL: u32 = 0x0000_0073;
+
+    #[test]
+    fn translate_ok() {
+        let mut memory = Memory::default();
+        for (i, instruction) in [ADDI, SD, ADDI, ECALL].iter().enumerate() {
+            memory.store(
+                MEMORY_BASE_ADDRESS + i as u64 * 4,
+                *instruction as u64,
+                Size::Word,
+            );
+        }
+        let mut cache = BlockCache::default();
+        // a store ends the block, and a system instruction is left out of it
+        assert!(cache.get(&memory, MEMORY_BASE_ADDRESS).store);
+        assert_eq!(cache.get(&memory, MEMORY_BASE_ADDRESS).ops.len(), 2);
+        assert_eq!(cache.get(&memory, MEMORY_BASE_ADDRESS + 8).ops.len(), 1);
+        assert_eq!(cache.get(&memory, MEMORY_BASE_ADDRESS + 12).ops.len(), 0);
+
+        // a write to the page decodes the block again
+        memory.store(MEMORY_BASE_ADDRESS + 4, ADDI as u64, Size::Word);
+        assert_eq!(cache.get(&memory, MEMORY_BASE_ADDRESS).ops.len(), 3);
+
+        // a block ends at the end of the page
+        let last = MEMORY_BASE_ADDRESS + PAGE_SIZE as u64 - 4;
+        memory.store(last, ADDI as u64, Size::Word);
+        memory.store(last + 4, ADDI as u64, Size::Word);
+        assert_eq!(cache.get(&memory, last).ops.len(), 1);
+    }
+}
@@ -1,24 +1,206 @@
 mod machine_level;
 mod supervisor_level;
+pub mod trigger;
 mod user_level;
 
-use crate::emulator::cpu::csr::{
-    machine_level::MachineLevelCsr, supervisor_level::SupervisorLevelCsr, user_level::UserLevelCsr,
+use crate::{
+    emulator::cpu::csr::{
+        machine_level::MachineLevelCsr, supervisor_level::SupervisorLevelCsr,
+        trigger::TriggerModule, user_level::UserLevelCsr,
+    },
+    isa::{
+        csr::{
+            debug::*, interrupt::*, machine_level::*, status::*, supervisor_level::*, user_level::*,
+        },
+        privileged::cause::Exception,
+    },
 };
+use std::ops::Range;
 
 #[derive(Default)]
 pub struct ControlAndStatusRegister {
     ucsr: UserLevelCsr,
     scsr: SupervisorLevelCsr,
     mcsr: MachineLevelCsr,
+    pub(crate) triggers: TriggerModule,
+    debug_mode: bool,
+    observing: bool,
+    writes: Vec<u64>,
+}
+
+impl ControlAndStatusRegister {
+    /// Reads a field of the register. The range of the field includes both of its bounds.
+    pub fn read_field(&self, address: u64, field: &Range<usize>) -> u64 {
+        (self.read(address) & field_mask(field.clone())) >> field.start
+    }
+
+    /// Writes a field of the register. The range of the field includes both of its bounds.
+    pub fn write_field(&mut self, address: u64, field: &Range<usize>, value: u64) {
+        let mask = field_mask(field.clone());
+        let t = self.read(address);
+        self.write(address, (t & !mask) | ((value << field.start) & mask));
+    }
+
+    pub fn is_fp_enabled(&self) -> bool {
+        self.read_field(MSTATUS, &STATUS_FS) != 0
+    }
+
+    pub fn set_fp_dirty(&mut self) {
+        self.write_field(MSTATUS, &STATUS_FS, EXTENSION_STATUS_DIRTY);
+    }
+
+    pub fn is_debug_mode(&self) -> bool {
+        self.debug_mode
+    }
+
+    pub(crate) fn set_debug_mode(&mut self, debug_mode: bool) {
+        self.debug_mode = debug_mode;
+    }
+
+    /// Starts or stops recording the registers written by CSR instructions.
+    pub fn set_observing(&mut self, observing: bool) {
+        self.observing = observing;
+        self.writes.clear();
+    }
+
+    /// Returns the addresses of the registers written since the last call.
+    pub fn take_writes(&mut self) -> Vec<u64> {
+        std::mem::take(&mut self.writes)
+    }
+
+    /// Returns the addresses and the values of the registers, except the views of other registers
+    /// and the triggers.
+    pub(crate) fn snapshot(&self) -> Vec<(u64, u64)> {
+        let mut registers = [
+            self.ucsr.addresses(),
+            self.scsr.addresses(),
+            self.mcsr.addresses(),
+        ]
+        .concat()
+        .into_iter()
+        .map(|address| (address, self.read(address)))
+        .collect::<Vec<_>>();
+        registers.sort_unstable();
+        registers
+    }
+
+    /// Writes the registers without legalizing the values. The unknown registers are ignored.
+    pub(crate) fn restore(&mut self, registers: &[(u64, u64)]) {
+        for (address, value) in registers {
+            if self.contains(*address) && !self.triggers.contains(*address) {
+                self.write(*address, *value);
+            }
+        }
+    }
+
+    fn mstatus(&self) -> u64 {
+        let status = self.mcsr.read(MSTATUS) & !field_mask(STATUS_SD);
+        let fs = (status & field_mask(STATUS_FS)) >> STATUS_FS.start;
+        let xs = (status & field_mask(STATUS_XS)) >> STATUS_XS.start;
+        if fs == EXTENSION_STATUS_DIRTY || xs == EXTENSION_STATUS_DIRTY {
+            status | field_mask(STATUS_SD)
+        } else {
+            status
+        }
+    }
+
+    fn supervisor_interrupts(&self) -> u64 {
+        self.mcsr.read(MIDELEG) & DELEGABLE_INTERRUPTS
+    }
+
+    fn write_view(&mut self, address: u64, mask: u64, value: u64) {
+        let t = self.mcsr.read(address);
+        self.mcsr.write(address, (t & !mask) | (value & mask));
+    }
+
+    fn write_mask(&self, address: u64) -> u64 {
+        match address {
+            MVENDORID | MARCHID | MIMPID | MHARTID | MISA | TDATA3 | TINFO => 0,
+            MSTATUS => MSTATUS_WRITE_MASK,
+            SSTATUS => MSTATUS_WRITE_MASK & SSTATUS_MASK,
+            MEDELEG => !(1 << Exception::EnvironmentCallFromMachineMode.to_primitive()),
+            MIDELEG => DELEGABLE_INTERRUPTS,
+            MIE => ALL_INTERRUPTS,
+            MIP => MIP_WRITE_MASK,
+            SIE => self.supervisor_interrupts(),
+            SIP => SIP_WRITE_MASK & self.supervisor_interrupts(),
+            DCSR => DCSR_WRITE_MASK,
+            MEPC | SEPC | UEPC | DPC => !0b11,
+            _ => u64::MAX,
+        }
+    }
+
+    fn legalize(&self, address: u64, value: u64) -> u64 {
+        let current = self.read(address);
+        match address {
+            MSTATUS => {
+                let mpp = field_mask(STATUS_MPP);
+                if (value & mpp) >> STATUS_MPP.start == 0b10 {
+                    (value & !mpp) | (current & mpp)
+                } else {
+                    value
+                }
+            }
+            // only the direct and vectored modes are supported
+            MTVEC | STVEC | UTVEC if value & 0b11 >= 2 => current,
+            // only the bare mode is supported
+            SATP if value >> 60 != 0 => current,
+            // the reserved privilege mode is not written to prv
+            DCSR if value & field_mask(DCSR_PRV) == 0b10 => {
+                (value & !field_mask(DCSR_PRV)) | (current & field_mask(DCSR_PRV))
+            }
+            TDATA1 => self.triggers.legalize(value, self.debug_mode),
+            _ => value,
+        }
+    }
+
+    fn write_legal(&mut self, address: u64, value: u64) {
+        // only the debugger can write the triggers reserved for debug mode
+        if matches!(address, TDATA1 | TDATA2 | TDATA3)
+            && self.triggers.is_debug_only()
+            && !self.debug_mode
+        {
+            return;
+        }
+        let mask = self.write_mask(address);
+        let value = (self.read(address) & !mask) | (value & mask);
+        self.write(address, self.legalize(address, value));
+        if self.observing {
+            self.writes.push(address);
+        }
+    }
 }
 
 impl Csr for ControlAndStatusRegister {
     fn contains(&self, address: u64) -> bool {
-        self.ucsr.contains(address) || self.scsr.contains(address) || self.mcsr.contains(address)
+        matches!(address, SSTATUS | SIE | SIP)
+            || self.ucsr.contains(address)
+            || self.scsr.contains(address)
+            || self.mcsr.contains(address)
+            || self.triggers.contains(address)
+    }
+
+    fn addresses(&self) -> Vec<u64> {
+        let mut addresses = [
+            vec![SSTATUS, SIE, SIP],
+            self.ucsr.addresses(),
+            self.scsr.addresses(),
+            self.mcsr.addresses(),
+            self.triggers.addresses(),
+        ]
+        .concat();
+        addresses.sort_unstable();
+        addresses
     }
 
     fn read(&self, address: u64) -> u64 {
+        match address {
+            MSTATUS => return self.mstatus(),
+            SSTATUS => return self.mstatus() & SSTATUS_MASK,
+            SIE => return self.mcsr.read(MIE) & self.supervisor_interrupts(),
+            SIP => return self.mcsr.read(MIP) & self.supervisor_interrupts(),
+            _ => {}
+        }
         if self.ucsr.contains(address) {
             return self.ucsr.read(address);
         }
@@ -28,10 +210,19 @@ impl Csr for ControlAndStatusRegister {
         if self.mcsr.contains(address) {
             return self.mcsr.read(address);
         }
+        if self.triggers.contains(address) {
+            return self.triggers.read(address);
+        }
         panic!("address not found. {address:x}");
     }
 
     fn write(&mut self, address: u64, value: u64) {
+        match address {
+            SSTATUS => return self.write_view(MSTATUS, SSTATUS_MASK, value),
+            SIE => return self.write_view(MIE, self.supervisor_interrupts(), value),
+            SIP => return self.write_view(MIP, self.supervisor_interrupts(), value),
+            _ => {}
+        }
         if self.ucsr.contains(address) {
             return self.ucsr.write(address, value);
         }
@@ -41,54 +232,157 @@ impl Csr for ControlAndStatusRegister {
         if self.mcsr.contains(address) {
             return self.mcsr.write(address, value);
         }
+        if self.triggers.contains(address) {
+            return self.triggers.write(address, value);
+        }
         panic!("address not found. {address:x}");
     }
 
     fn csrrw(&mut self, address: u64, value: u64) -> u64 {
-        if self.ucsr.contains(address) {
-            return self.ucsr.csrrw(address, value);
-        }
-        if self.scsr.contains(address) {
-            return self.scsr.csrrw(address, value);
-        }
-        if self.mcsr.contains(address) {
-            return self.mcsr.csrrw(address, value);
-        }
-        panic!("address not found. {address:x}");
+        let t = self.read(address);
+        self.write_legal(address, value);
+        t
     }
 
     fn csrrs(&mut self, address: u64, value: u64) -> u64 {
-        if self.ucsr.contains(address) {
-            return self.ucsr.csrrs(address, value);
-        }
-        if self.scsr.contains(address) {
-            return self.scsr.csrrs(address, value);
-        }
-        if self.mcsr.contains(address) {
-            return self.mcsr.csrrs(address, value);
-        }
-        panic!("address not found. {address:x}");
+        let t = self.read(address);
+        self.write_legal(address, t | value);
+        t
     }
 
     fn csrrc(&mut self, address: u64, value: u64) -> u64 {
-        if self.ucsr.contains(address) {
-            return self.ucsr.csrrc(address, value);
-        }
-        if self.scsr.contains(address) {
-            return self.scsr.csrrc(address, value);
-        }
-        if self.mcsr.contains(address) {
-            return self.mcsr.csrrc(address, value);
-        }
-        panic!("address not found. {address:x}");
+        let t = self.read(address);
+        self.write_legal(address, t & !value);
+        t
     }
 }
 
 pub trait Csr {
     fn contains(&self, address: u64) -> bool;
+    fn addresses(&self) -> Vec<u64>;
     fn read(&self, address: u64) -> u64;
     fn write(&mut self, address: u64, value: u64);
     fn csrrw(&mut self, address: u64, value: u64) -> u64;
     fn csrrs(&mut self, address: u64, value: u64) -> u64;
     fn csrrc(&mut self, address: u64, value: u64) -> u64;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::isa::csr::trigger::TDATA1_DMODE;
+
+    #[test]
+    fn sstatus_is_view_of_mstatus_ok() {
+        let mut csr = ControlAndStatusRegister::default();
+        csr.csrrs(MSTATUS, field_mask(STATUS_MIE) | field_mask(STATUS_SIE));
+        assert_eq!(csr.read(SSTATUS) & field_mask(STATUS_MIE), 0);
+        assert_ne!(csr.read(SSTATUS) & field_mask(STATUS_SIE), 0);
+
+        csr.csrrc(SSTATUS, field_mask(STATUS_SIE) | field_mask(STATUS_MIE));
+        assert_eq!(csr.read(MSTATUS) & field_mask(STATUS_SIE), 0);
+        assert_ne!(csr.read(MSTATUS) & field_mask(STATUS_MIE), 0);
+    }
+
+    #[test]
+    fn mstatus_warl_ok() {
+        let mut csr = ControlAndStatusRegister::default();
+        csr.csrrw(MSTATUS, 0);
+        assert_eq!(
+            csr.read(MSTATUS),
+            XLEN_64 << STATUS_UXL.start | XLEN_64 << STATUS_SXL.start
+        );
+
+        // the reserved privilege mode is not written to mpp
+        csr.csrrs(MSTATUS, 0b01 << STATUS_MPP.start);
+        csr.csrrw(MSTATUS, 0b10 << STATUS_MPP.start);
+        assert_eq!(
+            csr.read(MSTATUS) & field_mask(STATUS_MPP),
+            0b01 << STATUS_MPP.start
+        );
+
+        // sd summarizes the dirty state of fs
+        csr.csrrs(SSTATUS, field_mask(STATUS_FS));
+        assert_ne!(csr.read(MSTATUS) & field_mask(STATUS_SD), 0);
+        assert_ne!(csr.read(SSTATUS) & field_mask(STATUS_SD), 0);
+    }
+
+    #[test]
+    fn fp_dirty_ok() {
+        let mut csr = ControlAndStatusRegister::default();
+        assert!(!csr.is_fp_enabled());
+        csr.csrrs(MSTATUS, 0b01 << STATUS_FS.start);
+        assert!(csr.is_fp_enabled());
+        assert_eq!(csr.read(MSTATUS) & field_mask(STATUS_SD), 0);
+        csr.set_fp_dirty();
+        assert_eq!(
+            csr.read(MSTATUS) & field_mask(STATUS_FS),
+            field_mask(STATUS_FS)
+        );
+        assert_ne!(csr.read(MSTATUS) & field_mask(STATUS_SD), 0);
+    }
+
+    #[test]
+    fn sie_sip_follow_mideleg_ok() {
+        let mut csr = ControlAndStatusRegister::default();
+        csr.csrrw(SIE, ALL_INTERRUPTS);
+        assert_eq!(csr.read(MIE), 0);
+
+        csr.csrrw(MIDELEG, ALL_INTERRUPTS);
+        assert_eq!(csr.read(MIDELEG), DELEGABLE_INTERRUPTS);
+        csr.csrrw(SIE, ALL_INTERRUPTS);
+        assert_eq!(csr.read(MIE), DELEGABLE_INTERRUPTS);
+
+        csr.csrrw(SIP, ALL_INTERRUPTS);
+        assert_eq!(csr.read(MIP), SIP_WRITE_MASK);
+        csr.write(MIP, MTI | STI);
+        assert_eq!(csr.read(SIP), STI);
+    }
+
+    #[test]
+    fn epc_is_aligned_ok() {
+        let mut csr = ControlAndStatusRegister::default();
+        csr.csrrw(MEPC, 0x8000_0003);
+        assert_eq!(csr.read(MEPC), 0x8000_0000);
+        csr.csrrw(SEPC, 0x8000_0006);
+        assert_eq!(csr.read(SEPC), 0x8000_0004);
+    }
+
+    #[test]
+    fn dcsr_warl_ok() {
+        let mut csr = ControlAndStatusRegister::default();
+        let reset = csr.read(DCSR);
+        assert_eq!(reset >> DCSR_DEBUGVER.start, DEBUGVER_1_0);
+        assert_eq!(reset & field_mask(DCSR_PRV), 0b11);
+
+        // the cause and the version are read-only
+        csr.csrrw(
+            DCSR,
+            field_mask(DCSR_EBREAKM) | field_mask(DCSR_CAUSE) | 0b01,
+        );
+        assert_eq!(
+            csr.read(DCSR),
+            (reset & !field_mask(DCSR_PRV)) | field_mask(DCSR_EBREAKM) | 0b01
+        );
+        csr.csrrw(DCSR, 0b10);
+        assert_eq!(csr.read(DCSR) & field_mask(DCSR_PRV), 0b01);
+    }
+
+    #[test]
+    fn debug_only_trigger_ok() {
+        let mut csr = ControlAndStatusRegister::default();
+        let tdata1 = 1 << TDATA1_DMODE.start | 2 << 60 | 1 << 12 | 1 << 6 | 1 << 2;
+        csr.csrrw(TDATA1, tdata1);
+        assert_eq!(csr.read(TDATA1) & 1 << TDATA1_DMODE.start, 0);
+        assert_eq!(csr.read(TDATA1) & 1 << 12, 0);
+
+        csr.set_debug_mode(true);
+        csr.csrrw(TDATA1, tdata1);
+        assert_eq!(csr.read(TDATA1), tdata1);
+        csr.set_debug_mode(false);
+        csr.csrrw(TDATA1, 0);
+        csr.csrrw(TDATA2, 0x1000);
+        assert_eq!(csr.read(TDATA1), tdata1);
+        assert_eq!(csr.read(TDATA2), 0);
+    }
+}
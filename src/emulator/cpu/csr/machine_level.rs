@@ -1,4 +1,11 @@
-use crate::{emulator::cpu::csr::Csr, isa::csr::machine_level::*};
+use crate::{
+    emulator::cpu::csr::Csr,
+    isa::{
+        csr::{debug::*, machine_level::*, status::*},
+        extension::Extension,
+        privileged::mode::PrivilegeMode,
+    },
+};
 use std::collections::HashMap;
 
 pub struct MachineLevelCsr {
@@ -10,6 +17,10 @@ impl Csr for MachineLevelCsr {
         self.csr.contains_key(&address)
     }
 
+    fn addresses(&self) -> Vec<u64> {
+        self.csr.keys().cloned().collect()
+    }
+
     fn read(&self, address: u64) -> u64 {
         self.csr[&address]
     }
@@ -39,7 +50,7 @@ impl Csr for MachineLevelCsr {
 
 impl Default for MachineLevelCsr {
     fn default() -> Self {
-        Self {
+        let mut mcsr = Self {
             csr: [
                 // Machine Information Registers (MRO)
                 MVENDORID,
@@ -148,11 +159,6 @@ impl Default for MachineLevelCsr {
                 MHPMEVENT29,
                 MHPMEVENT30,
                 MHPMEVENT31,
-                // Debug/Trace Registers (shared with Debug Mode) (MRW)
-                TSELECT,
-                TDATA1,
-                TDATA2,
-                TDATA3,
                 // Debug Mode Registers (DRW)
                 DCSR,
                 DPC,
@@ -163,6 +169,33 @@ impl Default for MachineLevelCsr {
             .cloned()
             .map(|a| (a, 0))
             .collect::<HashMap<_, _>>(),
-        }
+        };
+        // RV64 with the supported extensions
+        let extensions = [
+            Extension::A,
+            Extension::F,
+            Extension::I,
+            Extension::M,
+            Extension::N,
+            Extension::S,
+            Extension::U,
+        ];
+        let misa = extensions
+            .into_iter()
+            .fold(XLEN_64 << 62, |acc, e| acc | 1 << e as u64);
+        mcsr.write(MISA, misa);
+        mcsr.write(
+            MSTATUS,
+            XLEN_64 << STATUS_UXL.start | XLEN_64 << STATUS_SXL.start,
+        );
+        // the counters and the timer stop in debug mode
+        mcsr.write(
+            DCSR,
+            DEBUGVER_1_0 << DCSR_DEBUGVER.start
+                | 1 << DCSR_STOPCOUNT.start
+                | 1 << DCSR_STOPTIME.start
+                | PrivilegeMode::Machine as u64,
+        );
+        mcsr
     }
 }
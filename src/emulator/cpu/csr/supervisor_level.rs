@@ -10,6 +10,10 @@ impl Csr for SupervisorLevelCsr {
         self.csr.contains_key(&address)
     }
 
+    fn addresses(&self) -> Vec<u64> {
+        self.csr.keys().cloned().collect()
+    }
+
     fn read(&self, address: u64) -> u64 {
         self.csr[&address]
     }
@@ -42,9 +46,9 @@ impl Default for SupervisorLevelCsr {
         Self {
             csr: [
                 // Supervisor Trap Setup (SRW)
-                SSTATUS, SEDELEG, SIDELEG, SIE, STVEC, SCOUNTEREN,
+                SEDELEG, SIDELEG, STVEC, SCOUNTEREN,
                 // Supervisor Trap Handling (SRW)
-                SSCRATCH, SEPC, SCAUSE, STVAL, SIP,
+                SSCRATCH, SEPC, SCAUSE, STVAL,
                 // Supervisor Protection and Translation (SRW)
                 SATP,
             ]
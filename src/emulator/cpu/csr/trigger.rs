@@ -0,0 +1,536 @@
+use crate::{
+    emulator::cpu::csr::Csr,
+    isa::{
+        csr::{machine_level::*, status::field_mask, trigger::*},
+        privileged::mode::PrivilegeMode,
+    },
+};
+use std::ops::Range;
+
+// Number of triggers implemented by the hart.
+pub const TRIGGERS: usize = 4;
+
+/// The kind of an access that address/data triggers match.
+#[derive(Clone, Copy, PartialEq)]
+pub enum TriggerAccess {
+    Execute,
+    Load,
+    Store,
+}
+
+/// The trigger module of the Sdtrig extension. The triggers are accessed through tdata1-3 by
+/// selecting one of them with tselect.
+pub struct TriggerModule {
+    tselect: usize,
+    tdata1: [u64; TRIGGERS],
+    tdata2: [u64; TRIGGERS],
+}
+
+impl Default for TriggerModule {
+    fn default() -> Self {
+        Self {
+            tselect: 0,
+            tdata1: [TRIGGER_TYPE_DISABLED << TDATA1_TYPE.start; TRIGGERS],
+            tdata2: [0; TRIGGERS],
+        }
+    }
+}
+
+fn field(value: u64, field: &Range<usize>) -> u64 {
+    (value & field_mask(field.clone())) >> field.start
+}
+
+fn with_field(value: u64, field: &Range<usize>, field_value: u64) -> u64 {
+    let mask = field_mask(field.clone());
+    (value & !mask) | ((field_value << field.start) & mask)
+}
+
+fn is_enabled(
+    tdata1: u64,
+    prv: PrivilegeMode,
+    m: &Range<usize>,
+    s: &Range<usize>,
+    u: &Range<usize>,
+) -> bool {
+    let enable = match prv {
+        PrivilegeMode::Machine => m,
+        PrivilegeMode::Supervisor => s,
+        PrivilegeMode::User => u,
+    };
+    field(tdata1, enable) == 1
+}
+
+fn compare(condition: u64, value: u64, tdata2: u64) -> bool {
+    let low = tdata2 as u32;
+    let high = (tdata2 >> 32) as u32;
+    let result = match condition & !MATCH_NOT {
+        MATCH_EQUAL => value == tdata2,
+        MATCH_NAPOT => {
+            // the bits up to the least significant zero of tdata2 are ignored
+            let mask = u64::MAX
+                .checked_shl(tdata2.trailing_ones() + 1)
+                .unwrap_or(0);
+            value & mask == tdata2 & mask
+        }
+        MATCH_GE => value >= tdata2,
+        MATCH_LT => value < tdata2,
+        MATCH_MASK_LOW => value as u32 & high == low & high,
+        MATCH_MASK_HIGH => (value >> 32) as u32 & high == low & high,
+        _ => false,
+    };
+    result != (condition & MATCH_NOT != 0)
+}
+
+impl TriggerModule {
+    /// Returns the legal value of tdata1 of the selected trigger. Only the debugger can reserve a
+    /// trigger for debug mode, and only such a trigger can enter debug mode.
+    pub fn legalize(&self, value: u64, debug_mode: bool) -> u64 {
+        let kind = field(value, &TDATA1_TYPE);
+        let fields = match kind {
+            TRIGGER_TYPE_MCONTROL | TRIGGER_TYPE_MCONTROL6 => {
+                let (hit, select) = if kind == TRIGGER_TYPE_MCONTROL {
+                    (MCONTROL_HIT, MCONTROL_SELECT)
+                } else {
+                    (MCONTROL6_HIT0, MCONTROL6_SELECT)
+                };
+                vec![
+                    hit,
+                    select,
+                    MCONTROL_ACTION,
+                    MCONTROL_CHAIN,
+                    MCONTROL_MATCH,
+                    MCONTROL_M,
+                    MCONTROL_S,
+                    MCONTROL_U,
+                    MCONTROL_EXECUTE,
+                    MCONTROL_STORE,
+                    MCONTROL_LOAD,
+                ]
+            }
+            TRIGGER_TYPE_ICOUNT => vec![
+                ICOUNT_HIT,
+                ICOUNT_COUNT,
+                ICOUNT_M,
+                ICOUNT_PENDING,
+                ICOUNT_S,
+                ICOUNT_U,
+                ICOUNT_ACTION,
+            ],
+            _ => return TRIGGER_TYPE_DISABLED << TDATA1_TYPE.start,
+        };
+        let mut mask = fields
+            .into_iter()
+            .fold(field_mask(TDATA1_TYPE), |acc, f| acc | field_mask(f));
+        if debug_mode {
+            mask |= field_mask(TDATA1_DMODE);
+        }
+        let mut value = value & mask;
+        // entering debug mode is only supported by the triggers reserved for debug mode
+        let dmode = field(value, &TDATA1_DMODE) == 1;
+        let action = if kind == TRIGGER_TYPE_ICOUNT {
+            ICOUNT_ACTION
+        } else {
+            MCONTROL_ACTION
+        };
+        if field(value, &action) != ACTION_DEBUG_MODE || !dmode {
+            value = with_field(value, &action, ACTION_BREAKPOINT);
+        }
+        if kind == TRIGGER_TYPE_ICOUNT {
+            return value;
+        }
+        // mcontrol has no range to match NAPOT addresses since maskmax is zero
+        let condition = field(value, &MCONTROL_MATCH);
+        let supported = match condition & !MATCH_NOT {
+            MATCH_NAPOT => kind == TRIGGER_TYPE_MCONTROL6,
+            MATCH_EQUAL | MATCH_MASK_LOW | MATCH_MASK_HIGH => true,
+            MATCH_GE | MATCH_LT => condition & MATCH_NOT == 0,
+            _ => false,
+        };
+        if !supported {
+            value = with_field(value, &MCONTROL_MATCH, MATCH_EQUAL);
+        }
+        // the last trigger has no trigger to chain with
+        if self.tselect == TRIGGERS - 1 {
+            value = with_field(value, &MCONTROL_CHAIN, 0);
+        }
+        value
+    }
+
+    fn is_address_data(&self, index: usize) -> bool {
+        matches!(
+            field(self.tdata1[index], &TDATA1_TYPE),
+            TRIGGER_TYPE_MCONTROL | TRIGGER_TYPE_MCONTROL6
+        )
+    }
+
+    fn is_chained(&self, index: usize) -> bool {
+        self.is_address_data(index) && field(self.tdata1[index], &MCONTROL_CHAIN) == 1
+    }
+
+    fn hit_field(&self, index: usize) -> Range<usize> {
+        match field(self.tdata1[index], &TDATA1_TYPE) {
+            TRIGGER_TYPE_MCONTROL => MCONTROL_HIT,
+            TRIGGER_TYPE_MCONTROL6 => MCONTROL6_HIT0,
+            _ => ICOUNT_HIT,
+        }
+    }
+
+    fn matches(
+        &self,
+        index: usize,
+        prv: PrivilegeMode,
+        access: TriggerAccess,
+        address: u64,
+        data: u64,
+    ) -> bool {
+        if !self.is_address_data(index) {
+            return false;
+        }
+        let tdata1 = self.tdata1[index];
+        let access_field = match access {
+            TriggerAccess::Execute => MCONTROL_EXECUTE,
+            TriggerAccess::Load => MCONTROL_LOAD,
+            TriggerAccess::Store => MCONTROL_STORE,
+        };
+        if field(tdata1, &access_field) == 0
+            || !is_enabled(tdata1, prv, &MCONTROL_M, &MCONTROL_S, &MCONTROL_U)
+        {
+            return false;
+        }
+        let select = if field(tdata1, &TDATA1_TYPE) == TRIGGER_TYPE_MCONTROL {
+            MCONTROL_SELECT
+        } else {
+            MCONTROL6_SELECT
+        };
+        let value = if field(tdata1, &select) == 1 {
+            data
+        } else {
+            address
+        };
+        compare(field(tdata1, &MCONTROL_MATCH), value, self.tdata2[index])
+    }
+
+    /// Returns the action of an address/data trigger that fires on the access. The data is the
+    /// instruction for an execution and the value transferred for a load or a store. The hit
+    /// bits of the triggers that fire are set.
+    pub fn fire(
+        &mut self,
+        prv: PrivilegeMode,
+        access: TriggerAccess,
+        address: u64,
+        data: u64,
+    ) -> Option<u64> {
+        let mut action = None;
+        let mut start = 0;
+        let mut matched = true;
+        for index in 0..TRIGGERS {
+            // all the triggers of a chain have to match
+            matched &= self.matches(index, prv, access, address, data);
+            if !self.is_chained(index) {
+                if matched {
+                    for i in start..=index {
+                        let hit = self.hit_field(i);
+                        self.tdata1[i] = with_field(self.tdata1[i], &hit, 1);
+                    }
+                    // entering debug mode takes priority over a breakpoint exception
+                    action = action.max(Some(field(self.tdata1[index], &MCONTROL_ACTION)));
+                }
+                start = index + 1;
+                matched = true;
+            }
+        }
+        action
+    }
+
+    /// Counts down the icount triggers on an instruction retired in the mode.
+    pub fn retire(&mut self, prv: PrivilegeMode) {
+        for tdata1 in self.tdata1.iter_mut() {
+            if field(*tdata1, &TDATA1_TYPE) != TRIGGER_TYPE_ICOUNT
+                || !is_enabled(*tdata1, prv, &ICOUNT_M, &ICOUNT_S, &ICOUNT_U)
+            {
+                continue;
+            }
+            let count = field(*tdata1, &ICOUNT_COUNT);
+            if count == 1 {
+                *tdata1 = with_field(*tdata1, &ICOUNT_PENDING, 1);
+            }
+            if count > 0 {
+                *tdata1 = with_field(*tdata1, &ICOUNT_COUNT, count - 1);
+            }
+        }
+    }
+
+    /// Returns the action of a pending icount trigger that fires before an instruction in the
+    /// mode.
+    pub fn fire_pending(&mut self, prv: PrivilegeMode) -> Option<u64> {
+        let mut action = None;
+        for tdata1 in self.tdata1.iter_mut() {
+            if field(*tdata1, &TDATA1_TYPE) == TRIGGER_TYPE_ICOUNT
+                && field(*tdata1, &ICOUNT_PENDING) == 1
+                && is_enabled(*tdata1, prv, &ICOUNT_M, &ICOUNT_S, &ICOUNT_U)
+            {
+                *tdata1 = with_field(*tdata1, &ICOUNT_PENDING, 0);
+                *tdata1 = with_field(*tdata1, &ICOUNT_HIT, 1);
+                action = action.max(Some(field(*tdata1, &ICOUNT_ACTION)));
+            }
+        }
+        action
+    }
+
+    /// Returns tselect followed by tdata1 and tdata2 of every trigger.
+    pub(crate) fn snapshot(&self) -> Vec<u64> {
+        [
+            vec![self.tselect as u64],
+            self.tdata1.to_vec(),
+            self.tdata2.to_vec(),
+        ]
+        .concat()
+    }
+
+    /// Restores the triggers from the values returned by `snapshot`.
+    pub(crate) fn restore(&mut self, snapshot: &[u64]) {
+        if let [tselect, rest @ ..] = snapshot {
+            if rest.len() == 2 * TRIGGERS && (*tselect as usize) < TRIGGERS {
+                self.tselect = *tselect as usize;
+                self.tdata1.copy_from_slice(&rest[..TRIGGERS]);
+                self.tdata2.copy_from_slice(&rest[TRIGGERS..]);
+            }
+        }
+    }
+
+    /// Returns true when no trigger can fire, so that the instructions can run without being
+    /// matched.
+    pub fn is_idle(&self) -> bool {
+        self.tdata1
+            .iter()
+            .all(|tdata1| matches!(field(*tdata1, &TDATA1_TYPE), 0 | TRIGGER_TYPE_DISABLED))
+    }
+
+    /// Returns true when the selected trigger is reserved for debug mode.
+    pub fn is_debug_only(&self) -> bool {
+        field(self.tdata1[self.tselect], &TDATA1_DMODE) == 1
+    }
+}
+
+impl Csr for TriggerModule {
+    fn contains(&self, address: u64) -> bool {
+        matches!(address, TSELECT | TDATA1 | TDATA2 | TDATA3 | TINFO)
+    }
+
+    fn addresses(&self) -> Vec<u64> {
+        vec![TSELECT, TDATA1, TDATA2, TDATA3, TINFO]
+    }
+
+    fn read(&self, address: u64) -> u64 {
+        match address {
+            TSELECT => self.tselect as u64,
+            TDATA1 => self.tdata1[self.tselect],
+            TDATA2 => self.tdata2[self.tselect],
+            TINFO => [
+                TRIGGER_TYPE_MCONTROL,
+                TRIGGER_TYPE_ICOUNT,
+                TRIGGER_TYPE_MCONTROL6,
+                TRIGGER_TYPE_DISABLED,
+            ]
+            .iter()
+            .fold(TINFO_VERSION_1_0, |acc, kind| acc | 1 << kind),
+            _ => 0,
+        }
+    }
+
+    fn write(&mut self, address: u64, value: u64) {
+        match address {
+            TSELECT if value < TRIGGERS as u64 => self.tselect = value as usize,
+            TDATA1 => self.tdata1[self.tselect] = value,
+            TDATA2 => self.tdata2[self.tselect] = value,
+            _ => {}
+        }
+    }
+
+    fn csrrw(&mut self, address: u64, value: u64) -> u64 {
+        let t = self.read(address);
+        self.write(address, value);
+        t
+    }
+
+    fn csrrs(&mut self, address: u64, value: u64) -> u64 {
+        let t = self.read(address);
+        self.write(address, t | value);
+        t
+    }
+
+    fn csrrc(&mut self, address: u64, value: u64) -> u64 {
+        let t = self.read(address);
+        self.write(address, t & !value);
+        t
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MCONTROL: u64 = TRIGGER_TYPE_MCONTROL << 60;
+    const MCONTROL6: u64 = TRIGGER_TYPE_MCONTROL6 << 60;
+    const ICOUNT: u64 = TRIGGER_TYPE_ICOUNT << 60;
+    const M: u64 = 1 << 6;
+    const U: u64 = 1 << 3;
+    const EXECUTE: u64 = 1 << 2;
+    const STORE: u64 = 1 << 1;
+    const LOAD: u64 = 1;
+    const CHAIN: u64 = 1 << 11;
+
+    fn set(triggers: &mut TriggerModule, index: u64, tdata1: u64, tdata2: u64) {
+        triggers.write(TSELECT, index);
+        triggers.write(TDATA1, triggers.legalize(tdata1, false));
+        triggers.write(TDATA2, tdata2);
+    }
+
+    #[test]
+    fn legalize_ok() {
+        let mut triggers = TriggerModule::default();
+        let value = MCONTROL | M | EXECUTE;
+        assert_eq!(triggers.legalize(value, false), value);
+        // unsupported actions, match conditions and types
+        assert_eq!(triggers.legalize(value | 5 << 12, false), value);
+        assert_eq!(triggers.legalize(value | MATCH_NAPOT << 7, false), value);
+        assert_eq!(
+            triggers.legalize(MCONTROL6 | MATCH_NAPOT << 7, false),
+            MCONTROL6 | MATCH_NAPOT << 7
+        );
+        assert_eq!(
+            triggers.legalize(1 << 60 | M, false),
+            TRIGGER_TYPE_DISABLED << 60
+        );
+        assert_eq!(triggers.legalize(0, false), TRIGGER_TYPE_DISABLED << 60);
+        // the last trigger cannot be chained
+        assert_eq!(triggers.legalize(value | CHAIN, false), value | CHAIN);
+        triggers.write(TSELECT, TRIGGERS as u64 - 1);
+        assert_eq!(triggers.legalize(value | CHAIN, false), value);
+        // only the debugger can reserve a trigger to enter debug mode
+        let dmode = 1 << 59 | ACTION_DEBUG_MODE << 12;
+        assert_eq!(triggers.legalize(value | dmode, false), value);
+        assert_eq!(triggers.legalize(value | dmode, true), value | dmode);
+        assert_eq!(
+            triggers.legalize(value | ACTION_DEBUG_MODE << 12, true),
+            value
+        );
+        // out of range
+        triggers.write(TSELECT, TRIGGERS as u64);
+        assert_eq!(triggers.read(TSELECT), TRIGGERS as u64 - 1);
+    }
+
+    #[test]
+    fn address_match_ok() {
+        let cases = [
+            (MATCH_EQUAL, 0x1000, 0x1000, true),
+            (MATCH_EQUAL, 0x1000, 0x1004, false),
+            (MATCH_NOT | MATCH_EQUAL, 0x1000, 0x1004, true),
+            (MATCH_NAPOT, 0x1007, 0x100c, true),
+            (MATCH_NAPOT, 0x1007, 0x1010, false),
+            (MATCH_GE, 0x1000, 0x1000, true),
+            (MATCH_GE, 0x1000, 0xfff, false),
+            (MATCH_LT, 0x1000, 0xfff, true),
+            (MATCH_LT, 0x1000, 0x1000, false),
+            (MATCH_MASK_LOW, 0xff00_0000_1200, 0x1234, true),
+            (MATCH_MASK_LOW, 0xff00_0000_1200, 0x1334, false),
+            (MATCH_MASK_HIGH, 0xffff_0000_1234, 0x1234_0000_0000, true),
+        ];
+        for (condition, tdata2, address, expected) in cases {
+            let mut triggers = TriggerModule::default();
+            set(
+                &mut triggers,
+                0,
+                MCONTROL6 | condition << 7 | M | LOAD,
+                tdata2,
+            );
+            assert_eq!(
+                triggers
+                    .fire(PrivilegeMode::Machine, TriggerAccess::Load, address, 0)
+                    .is_some(),
+                expected,
+                "{condition} {tdata2:x} {address:x}"
+            );
+            assert_eq!(triggers.read(TDATA1) & 1 << 22 != 0, expected);
+        }
+    }
+
+    #[test]
+    fn access_and_mode_ok() {
+        let mut triggers = TriggerModule::default();
+        set(&mut triggers, 0, MCONTROL | U | STORE, 0x1000);
+        assert_eq!(
+            triggers.fire(PrivilegeMode::User, TriggerAccess::Load, 0x1000, 0),
+            None
+        );
+        assert_eq!(
+            triggers.fire(PrivilegeMode::Machine, TriggerAccess::Store, 0x1000, 0),
+            None
+        );
+        assert_eq!(
+            triggers.fire(PrivilegeMode::User, TriggerAccess::Store, 0x1000, 0),
+            Some(ACTION_BREAKPOINT)
+        );
+        // data match
+        set(&mut triggers, 1, MCONTROL | 1 << 19 | M | EXECUTE, 0x13);
+        assert!(triggers
+            .fire(
+                PrivilegeMode::Machine,
+                TriggerAccess::Execute,
+                0x8000_0000,
+                0x13
+            )
+            .is_some());
+        assert!(triggers
+            .fire(PrivilegeMode::Machine, TriggerAccess::Execute, 0x13, 0x73)
+            .is_none());
+    }
+
+    #[test]
+    fn chain_ok() {
+        let mut triggers = TriggerModule::default();
+        set(
+            &mut triggers,
+            0,
+            MCONTROL | MATCH_GE << 7 | CHAIN | M | LOAD,
+            0x1000,
+        );
+        set(
+            &mut triggers,
+            1,
+            MCONTROL | MATCH_LT << 7 | M | LOAD,
+            0x2000,
+        );
+        for address in [0x800, 0x2000] {
+            assert_eq!(
+                triggers.fire(PrivilegeMode::Machine, TriggerAccess::Load, address, 0),
+                None
+            );
+        }
+        assert_eq!(triggers.read(TDATA1) & 1 << 20, 0);
+        assert!(triggers
+            .fire(PrivilegeMode::Machine, TriggerAccess::Load, 0x1800, 0)
+            .is_some());
+        triggers.write(TSELECT, 0);
+        assert_ne!(triggers.read(TDATA1) & 1 << 20, 0);
+    }
+
+    #[test]
+    fn icount_ok() {
+        let mut triggers = TriggerModule::default();
+        set(&mut triggers, 2, ICOUNT | 2 << 10 | 1 << 9, 0);
+        triggers.retire(PrivilegeMode::User);
+        triggers.retire(PrivilegeMode::Machine);
+        assert_eq!(triggers.fire_pending(PrivilegeMode::Machine), None);
+        triggers.retire(PrivilegeMode::Machine);
+        assert_eq!(triggers.fire_pending(PrivilegeMode::User), None);
+        assert_eq!(
+            triggers.fire_pending(PrivilegeMode::Machine),
+            Some(ACTION_BREAKPOINT)
+        );
+        assert_eq!(triggers.fire_pending(PrivilegeMode::Machine), None);
+        let tdata1 = triggers.read(TDATA1);
+        assert_eq!(field(tdata1, &ICOUNT_COUNT), 0);
+        assert_eq!(field(tdata1, &ICOUNT_HIT), 1);
+    }
+}
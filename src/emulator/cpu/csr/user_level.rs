@@ -14,6 +14,10 @@ impl Csr for UserLevelCsr {
         self.csr.contains_key(&address)
     }
 
+    fn addresses(&self) -> Vec<u64> {
+        self.csr.keys().cloned().collect()
+    }
+
     fn read(&self, address: u64) -> u64 {
         self.csr[&address]
     }
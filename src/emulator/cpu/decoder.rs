@@ -1,7 +1,9 @@
 pub mod privileged;
+pub mod rv32a;
 pub mod rv32f;
 pub mod rv32i;
 pub mod rv32m;
+pub mod rv64a;
 pub mod rv64f;
 pub mod rv64i;
 pub mod rv64m;
@@ -15,6 +17,9 @@ const MASK_5BIT: u32 = 0b11111;
 const MASK_7BIT: u32 = 0b1111111;
 
 pub trait Decoder {
+    /// The name of the extension that prefixes the names of the opcode enums, e.g. `Rv32i`.
+    const NAME: &'static str;
+
     type OpcodeR;
     type OpcodeI;
     type OpcodeS;
@@ -12,6 +12,7 @@ use crate::{
 pub struct PrivilegedDecoder;
 
 impl Decoder for PrivilegedDecoder {
+    const NAME: &'static str = "Privileged";
     type OpcodeR = PrivilegedOpcodeR;
     type OpcodeI = PrivilegedOpcodeI;
     type OpcodeS = PrivilegedOpcodeS;
@@ -53,6 +54,10 @@ impl Decoder for PrivilegedDecoder {
                             0b00010 => Some(PrivilegedOpcodeR::Mret),
                             _ => None,
                         },
+                        0b0111101 => match rs2 {
+                            0b10010 => Some(PrivilegedOpcodeR::Dret),
+                            _ => None,
+                        },
                         0b0001001 => Some(PrivilegedOpcodeR::SfenceVma),
                         _ => None,
                     },
@@ -0,0 +1,64 @@
+use crate::{
+    emulator::cpu::decoder::{Decoder, MASK_3BIT, MASK_5BIT, MASK_7BIT},
+    isa::instruction::{
+        rv32a::{
+            Rv32aOpcodeB, Rv32aOpcodeI, Rv32aOpcodeJ, Rv32aOpcodeR, Rv32aOpcodeS, Rv32aOpcodeU,
+        },
+        Instruction,
+    },
+};
+
+pub struct Rv32aDecoder;
+
+impl Decoder for Rv32aDecoder {
+    const NAME: &'static str = "Rv32a";
+    type OpcodeR = Rv32aOpcodeR;
+    type OpcodeI = Rv32aOpcodeI;
+    type OpcodeS = Rv32aOpcodeS;
+    type OpcodeB = Rv32aOpcodeB;
+    type OpcodeU = Rv32aOpcodeU;
+    type OpcodeJ = Rv32aOpcodeJ;
+
+    #[allow(clippy::type_complexity)]
+    fn decode(
+        instruction: u32,
+    ) -> Option<
+        Instruction<
+            Self::OpcodeR,
+            Self::OpcodeI,
+            Self::OpcodeS,
+            Self::OpcodeB,
+            Self::OpcodeU,
+            Self::OpcodeJ,
+        >,
+    > {
+        let opcode = instruction & MASK_7BIT;
+        let funct3 = (instruction >> 12) & MASK_3BIT;
+        let rs2 = (instruction >> 20) & MASK_5BIT;
+        // the two low bits of funct7 are the aq and rl bits
+        let funct5 = (instruction >> 27) & MASK_5BIT;
+        match opcode {
+            0b0101111 => Self::decode_r(
+                match funct3 {
+                    0b010 => match funct5 {
+                        0b00010 if rs2 == 0 => Some(Rv32aOpcodeR::LrW),
+                        0b00011 => Some(Rv32aOpcodeR::ScW),
+                        0b00001 => Some(Rv32aOpcodeR::AmoswapW),
+                        0b00000 => Some(Rv32aOpcodeR::AmoaddW),
+                        0b00100 => Some(Rv32aOpcodeR::AmoxorW),
+                        0b01100 => Some(Rv32aOpcodeR::AmoandW),
+                        0b01000 => Some(Rv32aOpcodeR::AmoorW),
+                        0b10000 => Some(Rv32aOpcodeR::AmominW),
+                        0b10100 => Some(Rv32aOpcodeR::AmomaxW),
+                        0b11000 => Some(Rv32aOpcodeR::AmominuW),
+                        0b11100 => Some(Rv32aOpcodeR::AmomaxuW),
+                        _ => None,
+                    },
+                    _ => None,
+                },
+                instruction,
+            ),
+            _ => None,
+        }
+    }
+}
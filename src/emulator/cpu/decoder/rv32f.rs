@@ -11,6 +11,7 @@ use crate::{
 pub struct Rv32fDecoder;
 
 impl Decoder for Rv32fDecoder {
+    const NAME: &'static str = "Rv32f";
     type OpcodeR = Rv32fOpcodeR;
     type OpcodeI = Rv32fOpcodeI;
     type OpcodeS = Rv32fOpcodeS;
@@ -11,6 +11,7 @@ use crate::{
 pub struct Rv32iDecoder;
 
 impl Decoder for Rv32iDecoder {
+    const NAME: &'static str = "Rv32i";
     type OpcodeR = Rv32iOpcodeR;
     type OpcodeI = Rv32iOpcodeI;
     type OpcodeS = Rv32iOpcodeS;
@@ -80,9 +81,10 @@ impl Decoder for Rv32iDecoder {
                     0b110 => Some(Rv32iOpcodeI::Ori),
                     0b111 => Some(Rv32iOpcodeI::Andi),
                     0b001 => Some(Rv32iOpcodeI::Slli),
-                    0b101 => match funct7 {
-                        0b0000000 => Some(Rv32iOpcodeI::Srli),
-                        0b0100000 => Some(Rv32iOpcodeI::Srai),
+                    // the low bit of funct7 is the high bit of the shift amount in RV64
+                    0b101 => match funct7 >> 1 {
+                        0b000000 => Some(Rv32iOpcodeI::Srli),
+                        0b010000 => Some(Rv32iOpcodeI::Srai),
                         _ => None,
                     },
                     _ => None,
@@ -11,6 +11,7 @@ use crate::{
 pub struct Rv32mDecoder;
 
 impl Decoder for Rv32mDecoder {
+    const NAME: &'static str = "Rv32m";
     type OpcodeR = Rv32mOpcodeR;
     type OpcodeI = Rv32mOpcodeI;
     type OpcodeS = Rv32mOpcodeS;
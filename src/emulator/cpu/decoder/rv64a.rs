@@ -0,0 +1,64 @@
+use crate::{
+    emulator::cpu::decoder::{Decoder, MASK_3BIT, MASK_5BIT, MASK_7BIT},
+    isa::instruction::{
+        rv64a::{
+            Rv64aOpcodeB, Rv64aOpcodeI, Rv64aOpcodeJ, Rv64aOpcodeR, Rv64aOpcodeS, Rv64aOpcodeU,
+        },
+        Instruction,
+    },
+};
+
+pub struct Rv64aDecoder;
+
+impl Decoder for Rv64aDecoder {
+    const NAME: &'static str = "Rv64a";
+    type OpcodeR = Rv64aOpcodeR;
+    type OpcodeI = Rv64aOpcodeI;
+    type OpcodeS = Rv64aOpcodeS;
+    type OpcodeB = Rv64aOpcodeB;
+    type OpcodeU = Rv64aOpcodeU;
+    type OpcodeJ = Rv64aOpcodeJ;
+
+    #[allow(clippy::type_complexity)]
+    fn decode(
+        instruction: u32,
+    ) -> Option<
+        Instruction<
+            Self::OpcodeR,
+            Self::OpcodeI,
+            Self::OpcodeS,
+            Self::OpcodeB,
+            Self::OpcodeU,
+            Self::OpcodeJ,
+        >,
+    > {
+        let opcode = instruction & MASK_7BIT;
+        let funct3 = (instruction >> 12) & MASK_3BIT;
+        let rs2 = (instruction >> 20) & MASK_5BIT;
+        // the two low bits of funct7 are the aq and rl bits
+        let funct5 = (instruction >> 27) & MASK_5BIT;
+        match opcode {
+            0b0101111 => Self::decode_r(
+                match funct3 {
+                    0b011 => match funct5 {
+                        0b00010 if rs2 == 0 => Some(Rv64aOpcodeR::LrD),
+                        0b00011 => Some(Rv64aOpcodeR::ScD),
+                        0b00001 => Some(Rv64aOpcodeR::AmoswapD),
+                        0b00000 => Some(Rv64aOpcodeR::AmoaddD),
+                        0b00100 => Some(Rv64aOpcodeR::AmoxorD),
+                        0b01100 => Some(Rv64aOpcodeR::AmoandD),
+                        0b01000 => Some(Rv64aOpcodeR::AmoorD),
+                        0b10000 => Some(Rv64aOpcodeR::AmominD),
+                        0b10100 => Some(Rv64aOpcodeR::AmomaxD),
+                        0b11000 => Some(Rv64aOpcodeR::AmominuD),
+                        0b11100 => Some(Rv64aOpcodeR::AmomaxuD),
+                        _ => None,
+                    },
+                    _ => None,
+                },
+                instruction,
+            ),
+            _ => None,
+        }
+    }
+}
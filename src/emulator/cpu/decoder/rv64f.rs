@@ -11,6 +11,7 @@ use crate::{
 pub struct Rv64fDecoder;
 
 impl Decoder for Rv64fDecoder {
+    const NAME: &'static str = "Rv64f";
     type OpcodeR = Rv64fOpcodeR;
     type OpcodeI = Rv64fOpcodeI;
     type OpcodeS = Rv64fOpcodeS;
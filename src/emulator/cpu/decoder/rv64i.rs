@@ -11,6 +11,7 @@ use crate::{
 pub struct Rv64iDecoder;
 
 impl Decoder for Rv64iDecoder {
+    const NAME: &'static str = "Rv64i";
     type OpcodeR = Rv64iOpcodeR;
     type OpcodeI = Rv64iOpcodeI;
     type OpcodeS = Rv64iOpcodeS;
@@ -11,6 +11,7 @@ use crate::{
 pub struct Rv64mDecoder;
 
 impl Decoder for Rv64mDecoder {
+    const NAME: &'static str = "Rv64m";
     type OpcodeR = Rv64mOpcodeR;
     type OpcodeI = Rv64mOpcodeI;
     type OpcodeS = Rv64mOpcodeS;
@@ -11,6 +11,7 @@ use crate::{
 pub struct ZicsrDecoder;
 
 impl Decoder for ZicsrDecoder {
+    const NAME: &'static str = "Zicsr";
     type OpcodeR = ZicsrOpcodeR;
     type OpcodeI = ZicsrOpcodeI;
     type OpcodeS = ZicsrOpcodeS;
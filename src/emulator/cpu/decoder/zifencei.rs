@@ -12,6 +12,7 @@ use crate::{
 pub struct ZifenceiDecoder;
 
 impl Decoder for ZifenceiDecoder {
+    const NAME: &'static str = "Zifencei";
     type OpcodeR = ZifenceiOpcodeR;
     type OpcodeI = ZifenceiOpcodeI;
     type OpcodeS = ZifenceiOpcodeS;
@@ -1,7 +1,9 @@
 pub mod privileged;
+pub mod rv32a;
 pub mod rv32f;
 pub mod rv32i;
 pub mod rv32m;
+pub mod rv64a;
 pub mod rv64f;
 pub mod rv64i;
 pub mod rv64m;
@@ -7,6 +7,10 @@ use crate::{
         },
     },
     isa::{
+        csr::{
+            machine_level::MSTATUS,
+            status::{STATUS_TSR, STATUS_TVM, STATUS_TW},
+        },
         instruction::{
             privileged::{
                 PrivilegedOpcodeB, PrivilegedOpcodeI, PrivilegedOpcodeJ, PrivilegedOpcodeR,
@@ -44,45 +48,130 @@ impl Executor for PrivilegedExecutor {
         _: &mut ProgramCounter,
         _: &mut IntegerRegister,
         _: &mut FloatingPointRegister,
-        _: &mut ControlAndStatusRegister,
+        csr: &mut ControlAndStatusRegister,
         _: &mut SystemBus,
     ) -> Result<(), Cause> {
-        if let Instruction::TypeR {
+        let Instruction::TypeR {
             opcode,
             rd: _,
             funct3: _,
             rs1: _,
             rs2: _,
             funct7: _,
-        } = instruction
-        {
-            match opcode {
-                PrivilegedOpcodeR::Uret => {
-                    if prv == &PrivilegeMode::User {
-                        Err(Cause::ExceptionReturn(ExceptionReturn::User))
-                    } else {
-                        Err(Cause::Exception(Exception::IllegalInstruction))
+        } = instruction;
+        match opcode {
+            // xRET is legal in the privilege mode x or any higher mode
+            PrivilegedOpcodeR::Uret => Err(Cause::ExceptionReturn(ExceptionReturn::User)),
+            PrivilegedOpcodeR::Sret => {
+                let tsr = csr.read_field(MSTATUS, &STATUS_TSR) == 1;
+                match prv {
+                    PrivilegeMode::Supervisor if !tsr => {
+                        Err(Cause::ExceptionReturn(ExceptionReturn::Supervisor))
                     }
-                }
-                PrivilegedOpcodeR::Sret => {
-                    if prv == &PrivilegeMode::Supervisor {
+                    PrivilegeMode::Machine => {
                         Err(Cause::ExceptionReturn(ExceptionReturn::Supervisor))
-                    } else {
-                        Err(Cause::Exception(Exception::IllegalInstruction))
                     }
+                    _ => Err(Cause::Exception(Exception::IllegalInstruction)),
                 }
-                PrivilegedOpcodeR::Mret => {
-                    if prv == &PrivilegeMode::Machine {
-                        Err(Cause::ExceptionReturn(ExceptionReturn::Machine))
-                    } else {
-                        Err(Cause::Exception(Exception::IllegalInstruction))
-                    }
+            }
+            PrivilegedOpcodeR::Mret => {
+                if prv == &PrivilegeMode::Machine {
+                    Err(Cause::ExceptionReturn(ExceptionReturn::Machine))
+                } else {
+                    Err(Cause::Exception(Exception::IllegalInstruction))
+                }
+            }
+            // DRET is only legal in debug mode
+            PrivilegedOpcodeR::Dret => {
+                if csr.is_debug_mode() {
+                    Err(Cause::DebugReturn)
+                } else {
+                    Err(Cause::Exception(Exception::IllegalInstruction))
+                }
+            }
+            PrivilegedOpcodeR::Wfi => {
+                let tw = csr.read_field(MSTATUS, &STATUS_TW) == 1;
+                if prv != &PrivilegeMode::Machine && tw {
+                    Err(Cause::Exception(Exception::IllegalInstruction))
+                } else {
+                    Err(Cause::WaitForInterrupt)
+                }
+            }
+            PrivilegedOpcodeR::SfenceVma => {
+                let tvm = csr.read_field(MSTATUS, &STATUS_TVM) == 1;
+                match prv {
+                    PrivilegeMode::Machine => Ok(()),
+                    PrivilegeMode::Supervisor if !tvm => Ok(()),
+                    _ => Err(Cause::Exception(Exception::IllegalInstruction)),
                 }
-                PrivilegedOpcodeR::Wfi => Ok(()), // not yet supported
-                PrivilegedOpcodeR::SfenceVma => Ok(()), // not yet supported
             }
-        } else {
-            Ok(())
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{emulator::cpu::csr::Csr, isa::csr::status::field_mask};
+
+    fn execute(opcode: PrivilegedOpcodeR, prv: PrivilegeMode, status: u64) -> Result<(), Cause> {
+        let mut csr = ControlAndStatusRegister::default();
+        csr.csrrs(MSTATUS, status);
+        PrivilegedExecutor::execute(
+            Instruction::TypeR {
+                opcode,
+                rd: 0,
+                funct3: 0,
+                rs1: 0,
+                rs2: 0,
+                funct7: 0,
+            },
+            &prv,
+            &mut ProgramCounter::default(),
+            &mut IntegerRegister::default(),
+            &mut FloatingPointRegister::default(),
+            &mut csr,
+            &mut SystemBus::default(),
+        )
+    }
+
+    fn is_illegal(result: Result<(), Cause>) -> bool {
+        matches!(result, Err(Cause::Exception(Exception::IllegalInstruction)))
+    }
+
+    #[test]
+    fn sret_ok() {
+        let sret = PrivilegedOpcodeR::Sret;
+        for prv in [PrivilegeMode::Supervisor, PrivilegeMode::Machine] {
+            assert!(matches!(
+                execute(sret, prv, 0),
+                Err(Cause::ExceptionReturn(ExceptionReturn::Supervisor))
+            ));
+        }
+        assert!(is_illegal(execute(sret, PrivilegeMode::User, 0)));
+        let tsr = field_mask(STATUS_TSR);
+        assert!(is_illegal(execute(sret, PrivilegeMode::Supervisor, tsr)));
+        assert!(!is_illegal(execute(sret, PrivilegeMode::Machine, tsr)));
+    }
+
+    #[test]
+    fn mret_ok() {
+        let mret = PrivilegedOpcodeR::Mret;
+        assert!(matches!(
+            execute(mret, PrivilegeMode::Machine, 0),
+            Err(Cause::ExceptionReturn(ExceptionReturn::Machine))
+        ));
+        assert!(is_illegal(execute(mret, PrivilegeMode::Supervisor, 0)));
+        assert!(is_illegal(execute(mret, PrivilegeMode::User, 0)));
+    }
+
+    #[test]
+    fn sfence_vma_ok() {
+        let sfence = PrivilegedOpcodeR::SfenceVma;
+        let tvm = field_mask(STATUS_TVM);
+        assert!(execute(sfence, PrivilegeMode::Supervisor, 0).is_ok());
+        assert!(execute(sfence, PrivilegeMode::Machine, tvm).is_ok());
+        assert!(is_illegal(execute(sfence, PrivilegeMode::Supervisor, tvm)));
+        assert!(is_illegal(execute(sfence, PrivilegeMode::User, 0)));
+    }
+}
@@ -0,0 +1,105 @@
+use crate::{
+    emulator::{
+        bus::SystemBus,
+        cpu::{
+            csr::{ControlAndStatusRegister, Csr},
+            executor::Executor,
+            f::FloatingPointRegister,
+            pc::ProgramCounter,
+            x::IntegerRegister,
+        },
+    },
+    isa::{
+        csr::machine_level::MHARTID,
+        instruction::{
+            rv32a::{
+                Rv32aOpcodeB, Rv32aOpcodeI, Rv32aOpcodeJ, Rv32aOpcodeR, Rv32aOpcodeS, Rv32aOpcodeU,
+            },
+            Instruction,
+        },
+        privileged::{
+            cause::{Cause, Exception},
+            mode::PrivilegeMode,
+        },
+    },
+};
+
+pub struct Rv32aExecutor;
+
+impl Executor for Rv32aExecutor {
+    type OpcodeR = Rv32aOpcodeR;
+    type OpcodeI = Rv32aOpcodeI;
+    type OpcodeS = Rv32aOpcodeS;
+    type OpcodeB = Rv32aOpcodeB;
+    type OpcodeU = Rv32aOpcodeU;
+    type OpcodeJ = Rv32aOpcodeJ;
+
+    fn execute(
+        instruction: Instruction<
+            Rv32aOpcodeR,
+            Rv32aOpcodeI,
+            Rv32aOpcodeS,
+            Rv32aOpcodeB,
+            Rv32aOpcodeU,
+            Rv32aOpcodeJ,
+        >,
+        _: &PrivilegeMode,
+        _: &mut ProgramCounter,
+        x: &mut IntegerRegister,
+        _: &mut FloatingPointRegister,
+        csr: &mut ControlAndStatusRegister,
+        bus: &mut SystemBus,
+    ) -> Result<(), Cause> {
+        let Instruction::TypeR {
+            opcode,
+            rd,
+            funct3: _,
+            rs1,
+            rs2,
+            funct7: _,
+        } = instruction;
+        let address = x.readu(rs1);
+        let hart = csr.read(MHARTID) as usize;
+        // the atomics access naturally aligned addresses only
+        if !address.is_multiple_of(4) {
+            return Err(Cause::Exception(match opcode {
+                Rv32aOpcodeR::LrW => Exception::LoadAddressMisaligned,
+                _ => Exception::StoreAddressMisaligned,
+            }));
+        }
+        match opcode {
+            Rv32aOpcodeR::LrW => {
+                let value = bus.load32(address);
+                bus.reserve(hart, address);
+                x.writei(rd, value as i32 as i64);
+            }
+            Rv32aOpcodeR::ScW => {
+                if bus.release(hart, address) {
+                    bus.try_store32(address, x.readu(rs2) as u32)?;
+                    x.writeu(rd, 0);
+                } else {
+                    x.writeu(rd, 1);
+                }
+            }
+            _ => {
+                let value = bus.load32(address);
+                let operand = x.readu(rs2) as u32;
+                let result = match opcode {
+                    Rv32aOpcodeR::AmoswapW => operand,
+                    Rv32aOpcodeR::AmoaddW => value.wrapping_add(operand),
+                    Rv32aOpcodeR::AmoxorW => value ^ operand,
+                    Rv32aOpcodeR::AmoandW => value & operand,
+                    Rv32aOpcodeR::AmoorW => value | operand,
+                    Rv32aOpcodeR::AmominW => (value as i32).min(operand as i32) as u32,
+                    Rv32aOpcodeR::AmomaxW => (value as i32).max(operand as i32) as u32,
+                    Rv32aOpcodeR::AmominuW => value.min(operand),
+                    Rv32aOpcodeR::AmomaxuW => value.max(operand),
+                    Rv32aOpcodeR::LrW | Rv32aOpcodeR::ScW => unreachable!(),
+                };
+                bus.try_store32(address, result)?;
+                x.writei(rd, value as i32 as i64);
+            }
+        }
+        Ok(())
+    }
+}
@@ -139,6 +139,9 @@ impl Executor for Rv32fExecutor {
         csr: &mut ControlAndStatusRegister,
         bus: &mut SystemBus,
     ) -> Result<(), Cause> {
+        if !csr.is_fp_enabled() {
+            return Err(Cause::Exception(Exception::IllegalInstruction));
+        }
         match instruction {
             Instruction::TypeR {
                 opcode,
@@ -428,6 +431,10 @@ impl Executor for Rv32fExecutor {
                     let fcsr = (frm << 5) | fflags;
                     csr.write(FCSR, fcsr);
                 }
+                // every other instruction writes either f[rd] or fcsr
+                if !matches!(opcode, Rv32fOpcodeR::FmvXW | Rv32fOpcodeR::FclassS) {
+                    csr.set_fp_dirty();
+                }
                 Ok(())
             }
             Instruction::TypeI {
@@ -443,6 +450,7 @@ impl Executor for Rv32fExecutor {
                         rd,
                         bus.load32(x.readi(rs1).wrapping_add(extend_sign(imm, 12)) as u64),
                     );
+                    csr.set_fp_dirty();
                     Ok(())
                 }
             },
@@ -455,8 +463,7 @@ impl Executor for Rv32fExecutor {
             } => match opcode {
                 Rv32fOpcodeS::Fsw => {
                     // Accumulating CSRs: None
-                    bus.store32(x.readi(rs1).wrapping_add(imm as i64) as u64, f.reads(rs2));
-                    Ok(())
+                    bus.try_store32(x.readi(rs1).wrapping_add(imm as i64) as u64, f.reads(rs2))
                 }
             },
             _ => Ok(()),
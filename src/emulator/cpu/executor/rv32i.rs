@@ -158,7 +158,7 @@ impl Executor for Rv32iExecutor {
                         Err(Cause::Exception(Exception::EnvironmentCallFromMachineMode))
                     }
                 },
-                Rv32iOpcodeI::Ebreak => Ok(()), // not yet supported
+                Rv32iOpcodeI::Ebreak => Err(Cause::Exception(Exception::Breakpoint)),
                 Rv32iOpcodeI::Lb => {
                     x.writei(
                         rd,
@@ -214,27 +214,18 @@ impl Executor for Rv32iExecutor {
                 rs2,
                 imm,
             } => match opcode {
-                Rv32iOpcodeS::Sb => {
-                    bus.store8(
-                        x.readi(rs1).wrapping_add(extend_sign(imm, 12)) as u64,
-                        x.readu(rs2) as u8,
-                    );
-                    Ok(())
-                }
-                Rv32iOpcodeS::Sh => {
-                    bus.store16(
-                        x.readi(rs1).wrapping_add(extend_sign(imm, 12)) as u64,
-                        x.readu(rs2) as u16,
-                    );
-                    Ok(())
-                }
-                Rv32iOpcodeS::Sw => {
-                    bus.store32(
-                        x.readi(rs1).wrapping_add(extend_sign(imm, 12)) as u64,
-                        x.readu(rs2) as u32,
-                    );
-                    Ok(())
-                }
+                Rv32iOpcodeS::Sb => bus.try_store8(
+                    x.readi(rs1).wrapping_add(extend_sign(imm, 12)) as u64,
+                    x.readu(rs2) as u8,
+                ),
+                Rv32iOpcodeS::Sh => bus.try_store16(
+                    x.readi(rs1).wrapping_add(extend_sign(imm, 12)) as u64,
+                    x.readu(rs2) as u16,
+                ),
+                Rv32iOpcodeS::Sw => bus.try_store32(
+                    x.readi(rs1).wrapping_add(extend_sign(imm, 12)) as u64,
+                    x.readu(rs2) as u32,
+                ),
             },
             Instruction::TypeB {
                 opcode,
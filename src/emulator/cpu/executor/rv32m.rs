@@ -43,77 +43,75 @@ impl Executor for Rv32mExecutor {
         _: &mut ControlAndStatusRegister,
         _: &mut SystemBus,
     ) -> Result<(), Cause> {
-        if let Instruction::TypeR {
+        let Instruction::TypeR {
             opcode,
             rd,
             funct3: _,
             rs1,
             rs2,
             funct7: _,
-        } = instruction
-        {
-            match opcode {
-                Rv32mOpcodeR::Mul => x.writeu(rd, x.readu(rs1).wrapping_mul(x.readu(rs2))),
-                Rv32mOpcodeR::Mulh => x.writeu(
+        } = instruction;
+        match opcode {
+            Rv32mOpcodeR::Mul => x.writeu(rd, x.readu(rs1).wrapping_mul(x.readu(rs2))),
+            Rv32mOpcodeR::Mulh => x.writeu(
+                rd,
+                ((x.readi(rs1) as i128).wrapping_mul(x.readi(rs2) as i128) >> 64) as u64,
+            ),
+            Rv32mOpcodeR::Mulhsu => x.writeu(
+                rd,
+                ((x.readi(rs1) as i128).wrapping_mul(x.readu(rs2) as i128) >> 64) as u64,
+            ),
+            Rv32mOpcodeR::Mulhu => x.writeu(
+                rd,
+                ((x.readu(rs1) as u128).wrapping_mul(x.readu(rs2) as u128) >> 64) as u64,
+            ),
+            Rv32mOpcodeR::Div => {
+                let dividend = x.readi(rs1);
+                let divisor = x.readi(rs2);
+                x.writei(
                     rd,
-                    ((x.readi(rs1) as i128).wrapping_mul(x.readi(rs2) as i128) >> 64) as u64,
-                ),
-                Rv32mOpcodeR::Mulhsu => x.writeu(
+                    if divisor == 0 {
+                        u64::MAX as i64
+                    } else {
+                        dividend.wrapping_div(divisor)
+                    },
+                )
+            }
+            Rv32mOpcodeR::Divu => {
+                let dividend = x.readu(rs1);
+                let divisor = x.readu(rs2);
+                x.writeu(
+                    rd,
+                    if divisor == 0 {
+                        u64::MAX
+                    } else {
+                        dividend.wrapping_div(divisor)
+                    },
+                )
+            }
+            Rv32mOpcodeR::Rem => {
+                let dividend = x.readi(rs1);
+                let divisor = x.readi(rs2);
+                x.writei(
                     rd,
-                    ((x.readi(rs1) as i128).wrapping_mul(x.readu(rs2) as i128) >> 64) as u64,
-                ),
-                Rv32mOpcodeR::Mulhu => x.writeu(
+                    if divisor == 0 {
+                        dividend
+                    } else {
+                        dividend.wrapping_rem(divisor)
+                    },
+                )
+            }
+            Rv32mOpcodeR::Remu => {
+                let dividend = x.readu(rs1);
+                let divisor = x.readu(rs2);
+                x.writeu(
                     rd,
-                    ((x.readu(rs1) as u128).wrapping_mul(x.readu(rs2) as u128) >> 64) as u64,
-                ),
-                Rv32mOpcodeR::Div => {
-                    let dividend = x.readi(rs1);
-                    let divisor = x.readi(rs2);
-                    x.writei(
-                        rd,
-                        if divisor == 0 {
-                            u64::MAX as i64
-                        } else {
-                            dividend.wrapping_div(divisor)
-                        },
-                    )
-                }
-                Rv32mOpcodeR::Divu => {
-                    let dividend = x.readu(rs1);
-                    let divisor = x.readu(rs2);
-                    x.writeu(
-                        rd,
-                        if divisor == 0 {
-                            u64::MAX
-                        } else {
-                            dividend.wrapping_div(divisor)
-                        },
-                    )
-                }
-                Rv32mOpcodeR::Rem => {
-                    let dividend = x.readi(rs1);
-                    let divisor = x.readi(rs2);
-                    x.writei(
-                        rd,
-                        if divisor == 0 {
-                            dividend
-                        } else {
-                            dividend.wrapping_rem(divisor)
-                        },
-                    )
-                }
-                Rv32mOpcodeR::Remu => {
-                    let dividend = x.readu(rs1);
-                    let divisor = x.readu(rs2);
-                    x.writeu(
-                        rd,
-                        if divisor == 0 {
-                            dividend
-                        } else {
-                            dividend.wrapping_rem(divisor)
-                        },
-                    )
-                }
+                    if divisor == 0 {
+                        dividend
+                    } else {
+                        dividend.wrapping_rem(divisor)
+                    },
+                )
             }
         }
         Ok(())
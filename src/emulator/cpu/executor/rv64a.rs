@@ -0,0 +1,105 @@
+use crate::{
+    emulator::{
+        bus::SystemBus,
+        cpu::{
+            csr::{ControlAndStatusRegister, Csr},
+            executor::Executor,
+            f::FloatingPointRegister,
+            pc::ProgramCounter,
+            x::IntegerRegister,
+        },
+    },
+    isa::{
+        csr::machine_level::MHARTID,
+        instruction::{
+            rv64a::{
+                Rv64aOpcodeB, Rv64aOpcodeI, Rv64aOpcodeJ, Rv64aOpcodeR, Rv64aOpcodeS, Rv64aOpcodeU,
+            },
+            Instruction,
+        },
+        privileged::{
+            cause::{Cause, Exception},
+            mode::PrivilegeMode,
+        },
+    },
+};
+
+pub struct Rv64aExecutor;
+
+impl Executor for Rv64aExecutor {
+    type OpcodeR = Rv64aOpcodeR;
+    type OpcodeI = Rv64aOpcodeI;
+    type OpcodeS = Rv64aOpcodeS;
+    type OpcodeB = Rv64aOpcodeB;
+    type OpcodeU = Rv64aOpcodeU;
+    type OpcodeJ = Rv64aOpcodeJ;
+
+    fn execute(
+        instruction: Instruction<
+            Rv64aOpcodeR,
+            Rv64aOpcodeI,
+            Rv64aOpcodeS,
+            Rv64aOpcodeB,
+            Rv64aOpcodeU,
+            Rv64aOpcodeJ,
+        >,
+        _: &PrivilegeMode,
+        _: &mut ProgramCounter,
+        x: &mut IntegerRegister,
+        _: &mut FloatingPointRegister,
+        csr: &mut ControlAndStatusRegister,
+        bus: &mut SystemBus,
+    ) -> Result<(), Cause> {
+        let Instruction::TypeR {
+            opcode,
+            rd,
+            funct3: _,
+            rs1,
+            rs2,
+            funct7: _,
+        } = instruction;
+        let address = x.readu(rs1);
+        let hart = csr.read(MHARTID) as usize;
+        // the atomics access naturally aligned addresses only
+        if !address.is_multiple_of(8) {
+            return Err(Cause::Exception(match opcode {
+                Rv64aOpcodeR::LrD => Exception::LoadAddressMisaligned,
+                _ => Exception::StoreAddressMisaligned,
+            }));
+        }
+        match opcode {
+            Rv64aOpcodeR::LrD => {
+                let value = bus.load64(address);
+                bus.reserve(hart, address);
+                x.writei(rd, value as i64);
+            }
+            Rv64aOpcodeR::ScD => {
+                if bus.release(hart, address) {
+                    bus.try_store64(address, x.readu(rs2))?;
+                    x.writeu(rd, 0);
+                } else {
+                    x.writeu(rd, 1);
+                }
+            }
+            _ => {
+                let value = bus.load64(address);
+                let operand = x.readu(rs2);
+                let result = match opcode {
+                    Rv64aOpcodeR::AmoswapD => operand,
+                    Rv64aOpcodeR::AmoaddD => value.wrapping_add(operand),
+                    Rv64aOpcodeR::AmoxorD => value ^ operand,
+                    Rv64aOpcodeR::AmoandD => value & operand,
+                    Rv64aOpcodeR::AmoorD => value | operand,
+                    Rv64aOpcodeR::AmominD => (value as i64).min(operand as i64) as u64,
+                    Rv64aOpcodeR::AmomaxD => (value as i64).max(operand as i64) as u64,
+                    Rv64aOpcodeR::AmominuD => value.min(operand),
+                    Rv64aOpcodeR::AmomaxuD => value.max(operand),
+                    Rv64aOpcodeR::LrD | Rv64aOpcodeR::ScD => unreachable!(),
+                };
+                bus.try_store64(address, result)?;
+                x.writei(rd, value as i64);
+            }
+        }
+        Ok(())
+    }
+}
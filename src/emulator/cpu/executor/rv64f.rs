@@ -128,6 +128,9 @@ impl Executor for Rv64fExecutor {
         csr: &mut ControlAndStatusRegister,
         _: &mut SystemBus,
     ) -> Result<(), Cause> {
+        if !csr.is_fp_enabled() {
+            return Err(Cause::Exception(Exception::IllegalInstruction));
+        }
         match instruction {
             Instruction::TypeR {
                 opcode,
@@ -185,6 +188,7 @@ impl Executor for Rv64fExecutor {
                     let fcsr = (frm << 5) | fflags;
                     csr.write(FCSR, fcsr);
                 }
+                csr.set_fp_dirty();
                 Ok(())
             }
             _ => Ok(()),
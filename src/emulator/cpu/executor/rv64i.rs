@@ -111,7 +111,8 @@ impl Executor for Rv64iExecutor {
                 imm,
             } => match opcode {
                 Rv64iOpcodeS::Sd => {
-                    bus.store64(x.readi(rs1).wrapping_add(imm as i64) as u64, x.readu(rs2))
+                    return bus
+                        .try_store64(x.readi(rs1).wrapping_add(imm as i64) as u64, x.readu(rs2));
                 }
             },
             _ => (),
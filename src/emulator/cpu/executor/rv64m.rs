@@ -43,68 +43,66 @@ impl Executor for Rv64mExecutor {
         _: &mut ControlAndStatusRegister,
         _: &mut SystemBus,
     ) -> Result<(), Cause> {
-        if let Instruction::TypeR {
+        let Instruction::TypeR {
             opcode,
             rd,
             funct3: _,
             rs1,
             rs2,
             funct7: _,
-        } = instruction
-        {
-            match opcode {
-                Rv64mOpcodeR::Mulw => x.writei(
+        } = instruction;
+        match opcode {
+            Rv64mOpcodeR::Mulw => x.writei(
+                rd,
+                x.readu(rs1).wrapping_mul(x.readu(rs2)) as u32 as i32 as i64,
+            ),
+            Rv64mOpcodeR::Divw => {
+                let dividend = x.readi(rs1) as i32;
+                let divisor = x.readi(rs2) as i32;
+                x.writei(
                     rd,
-                    x.readu(rs1).wrapping_mul(x.readu(rs2)) as u32 as i32 as i64,
-                ),
-                Rv64mOpcodeR::Divw => {
-                    let dividend = x.readi(rs1) as i32;
-                    let divisor = x.readi(rs2) as i32;
-                    x.writei(
-                        rd,
-                        if divisor == 0 {
-                            u64::MAX as i64
-                        } else {
-                            dividend.wrapping_div(divisor) as i64
-                        },
-                    )
-                }
-                Rv64mOpcodeR::Divuw => {
-                    let dividend = x.readu(rs1) as u32;
-                    let divisor = x.readu(rs2) as u32;
-                    x.writei(
-                        rd,
-                        if divisor == 0 {
-                            u64::MAX as i64
-                        } else {
-                            dividend.wrapping_div(divisor) as i32 as i64
-                        },
-                    )
-                }
-                Rv64mOpcodeR::Remw => {
-                    let dividend = x.readi(rs1);
-                    let divisor = x.readi(rs2);
-                    x.writei(
-                        rd,
-                        if divisor == 0 {
-                            dividend
-                        } else {
-                            (dividend as i32).wrapping_rem(divisor as i32) as i64
-                        },
-                    )
-                }
-                Rv64mOpcodeR::Remuw => {
-                    let dividend = x.readu(rs1);
-                    let divisor = x.readu(rs2);
-                    x.writei(
-                        rd,
-                        if divisor == 0 {
-                            dividend as i64
-                        } else {
-                            (dividend as u32).wrapping_rem(divisor as u32) as i32 as i64
-                        },
-                    )
-                }
+                    if divisor == 0 {
+                        u64::MAX as i64
+                    } else {
+                        dividend.wrapping_div(divisor) as i64
+                    },
+                )
+            }
+            Rv64mOpcodeR::Divuw => {
+                let dividend = x.readu(rs1) as u32;
+                let divisor = x.readu(rs2) as u32;
+                x.writei(
+                    rd,
+                    if divisor == 0 {
+                        u64::MAX as i64
+                    } else {
+                        dividend.wrapping_div(divisor) as i32 as i64
+                    },
+                )
+            }
+            Rv64mOpcodeR::Remw => {
+                let dividend = x.readi(rs1) as i32;
+                let divisor = x.readi(rs2) as i32;
+                x.writei(
+                    rd,
+                    if divisor == 0 {
+                        dividend as i64
+                    } else {
+                        dividend.wrapping_rem(divisor) as i64
+                    },
+                )
+            }
+            Rv64mOpcodeR::Remuw => {
+                let dividend = x.readu(rs1) as u32;
+                let divisor = x.readu(rs2) as u32;
+                x.writei(
+                    rd,
+                    if divisor == 0 {
+                        dividend as i32 as i64
+                    } else {
+                        dividend.wrapping_rem(divisor) as i32 as i64
+                    },
+                )
             }
         }
         Ok(())
@@ -11,13 +11,22 @@ use crate::{
         },
     },
     isa::{
+        csr::{
+            machine_level::{DCSR, DSCRATCH1, MSTATUS},
+            status::STATUS_TVM,
+            supervisor_level::SATP,
+            user_level::{FCSR, FFLAGS, FRM},
+        },
         instruction::{
             zicsr::{
                 ZicsrOpcodeB, ZicsrOpcodeI, ZicsrOpcodeJ, ZicsrOpcodeR, ZicsrOpcodeS, ZicsrOpcodeU,
             },
             Instruction,
         },
-        privileged::{cause::Cause, mode::PrivilegeMode},
+        privileged::{
+            cause::{Cause, Exception},
+            mode::PrivilegeMode,
+        },
     },
 };
 
@@ -40,29 +49,47 @@ impl Executor for ZicsrExecutor {
             ZicsrOpcodeU,
             ZicsrOpcodeJ,
         >,
-        _: &PrivilegeMode,
+        prv: &PrivilegeMode,
         _: &mut ProgramCounter,
         x: &mut IntegerRegister,
         _: &mut FloatingPointRegister,
         csr: &mut ControlAndStatusRegister,
         _: &mut SystemBus,
     ) -> Result<(), Cause> {
-        if let Instruction::TypeI {
+        let Instruction::TypeI {
             opcode,
             rd,
             funct3: _,
             rs1,
             imm,
-        } = instruction
-        {
-            match opcode {
-                ZicsrOpcodeI::Csrrw => x.writeu(rd, csr.csrrw(imm & MASK_12BIT, x.readu(rs1))),
-                ZicsrOpcodeI::Csrrs => x.writeu(rd, csr.csrrs(imm & MASK_12BIT, x.readu(rs1))),
-                ZicsrOpcodeI::Csrrc => x.writeu(rd, csr.csrrc(imm & MASK_12BIT, x.readu(rs1))),
-                ZicsrOpcodeI::Csrrwi => x.writeu(rd, csr.csrrw(imm & MASK_12BIT, rs1 as u64)),
-                ZicsrOpcodeI::Csrrsi => x.writeu(rd, csr.csrrs(imm & MASK_12BIT, rs1 as u64)),
-                ZicsrOpcodeI::Csrrci => x.writeu(rd, csr.csrrc(imm & MASK_12BIT, rs1 as u64)),
-            }
+        } = instruction;
+        let address = imm & MASK_12BIT;
+        let is_fp = matches!(address, FFLAGS | FRM | FCSR);
+        if is_fp && !csr.is_fp_enabled() {
+            return Err(Cause::Exception(Exception::IllegalInstruction));
+        }
+        // the debug mode registers are only accessible in debug mode
+        if (DCSR..=DSCRATCH1).contains(&address) && !csr.is_debug_mode() {
+            return Err(Cause::Exception(Exception::IllegalInstruction));
+        }
+        let tvm = csr.read_field(MSTATUS, &STATUS_TVM) == 1;
+        if address == SATP && prv == &PrivilegeMode::Supervisor && tvm {
+            return Err(Cause::Exception(Exception::IllegalInstruction));
+        }
+        // csrrs and csrrc do not write the csr when rs1 or uimm is zero
+        let writes = matches!(opcode, ZicsrOpcodeI::Csrrw | ZicsrOpcodeI::Csrrwi) || rs1 != 0;
+        let t = match opcode {
+            _ if !writes => csr.read(address),
+            ZicsrOpcodeI::Csrrw => csr.csrrw(address, x.readu(rs1)),
+            ZicsrOpcodeI::Csrrs => csr.csrrs(address, x.readu(rs1)),
+            ZicsrOpcodeI::Csrrc => csr.csrrc(address, x.readu(rs1)),
+            ZicsrOpcodeI::Csrrwi => csr.csrrw(address, rs1 as u64),
+            ZicsrOpcodeI::Csrrsi => csr.csrrs(address, rs1 as u64),
+            ZicsrOpcodeI::Csrrci => csr.csrrc(address, rs1 as u64),
+        };
+        x.writeu(rd, t);
+        if is_fp && writes {
+            csr.set_fp_dirty();
         }
         Ok(())
     }
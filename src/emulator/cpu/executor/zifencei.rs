@@ -44,19 +44,15 @@ impl Executor for ZifenceiExecutor {
         _: &mut ControlAndStatusRegister,
         _: &mut SystemBus,
     ) -> Result<(), Cause> {
-        if let Instruction::TypeI {
+        let Instruction::TypeI {
             opcode,
             rd: _,
             funct3: _,
             rs1: _,
             imm: _,
-        } = instruction
-        {
-            match opcode {
-                ZifenceiOpcodeI::FenceI => Ok(()), // not yet supported
-            }
-        } else {
-            Ok(())
+        } = instruction;
+        match opcode {
+            ZifenceiOpcodeI::FenceI => Ok(()), // not yet supported
         }
     }
 }
@@ -4,6 +4,8 @@ use std::fmt;
 #[derive(Default)]
 pub struct FloatingPointRegister {
     f: [u64; 32],
+    observing: bool,
+    writes: Vec<(usize, u64)>,
 }
 
 impl FloatingPointRegister {
@@ -17,12 +19,30 @@ impl FloatingPointRegister {
 
     pub fn writes(&mut self, register: usize, value: u32) {
         self.f[register] = value as u64;
+        if self.observing {
+            self.writes.push((register, value as u64));
+        }
+    }
+
+    /// Starts or stops recording the writes to the registers.
+    pub fn set_observing(&mut self, observing: bool) {
+        self.observing = observing;
+        self.writes.clear();
+    }
+
+    /// Returns the registers and the values written since the last call.
+    pub fn take_writes(&mut self) -> Vec<(usize, u64)> {
+        std::mem::take(&mut self.writes)
     }
 
     pub fn snapshot(&self) -> [u64; 32] {
         self.f
     }
 
+    pub fn restore(&mut self, snapshot: [u64; 32]) {
+        self.f = snapshot;
+    }
+
     pub fn diff(&self, other: [u64; 32]) -> Vec<(usize, u64, u64)> {
         let mut result = vec![];
         for (i, x) in self.f.iter().enumerate() {
@@ -0,0 +1,908 @@
+use crate::{
+    bitops::{extend_sign, shift_amount, MASK_6BIT},
+    emulator::{
+        bus::Size,
+        cpu::{block::Op, Cpu},
+    },
+    isa::instruction::{
+        rv32i::{
+            Rv32iOpcodeB, Rv32iOpcodeI, Rv32iOpcodeJ, Rv32iOpcodeR, Rv32iOpcodeS, Rv32iOpcodeU,
+        },
+        rv32m::Rv32mOpcodeR,
+        rv64i::{Rv64iOpcodeI, Rv64iOpcodeR, Rv64iOpcodeS},
+        rv64m::Rv64mOpcodeR,
+        Instruction,
+    },
+};
+use std::cell::{Cell, OnceCell};
+use std::ptr::{self, NonNull};
+use std::rc::Rc;
+
+#[cfg(not(all(target_arch = "x86_64", target_os = "linux")))]
+compile_error!("the jit feature needs an x86-64 Linux host");
+
+// The number of times that a block runs in the interpreter before it is compiled.
+pub(crate) const THRESHOLD: u32 = 16;
+
+// The registers of the host. The compiled code keeps the pointer to the integer registers of
+// the hart in rbx and the context in r12, which the calls preserve. rbx is loaded from the
+// context again after every call, since the helpers borrow the whole hart.
+const RAX: u8 = 0;
+const RCX: u8 = 1;
+const RDX: u8 = 2;
+const RSI: u8 = 6;
+
+// The condition codes of jcc, setcc and cmovcc.
+const CC_B: u8 = 0x2;
+const CC_AE: u8 = 0x3;
+const CC_E: u8 = 0x4;
+const CC_NE: u8 = 0x5;
+const CC_L: u8 = 0xc;
+const CC_GE: u8 = 0xd;
+
+// The /digit of the group 1 and group 2 instructions.
+const ADD: u8 = 0;
+const OR: u8 = 1;
+const AND: u8 = 4;
+const XOR: u8 = 6;
+const CMP: u8 = 7;
+const SHL: u8 = 4;
+const SHR: u8 = 5;
+const SAR: u8 = 7;
+
+/// The state that the compiled code and the helpers that it calls share. Every access to the
+/// hart goes through the pointer to it, and the pointer to its integer registers is derived
+/// from it again whenever a helper has borrowed the hart.
+#[repr(C)]
+struct Context {
+    cpu: *mut Cpu,
+    // the address of the first instruction of the block
+    start: u64,
+    // the address of the next instruction, or of the instruction that faulted
+    pc: u64,
+    // the number of instructions that the clint has ticked for
+    ticked: u64,
+    // the integer registers of the hart
+    x: *mut u64,
+}
+
+// The offsets of `Context::pc` and `Context::x`.
+const CONTEXT_PC: u8 = 16;
+const CONTEXT_X: u8 = 32;
+
+impl Context {
+    /// Ticks the clint up to the instruction, so that the devices see the time that they would
+    /// in the interpreter, and runs the function on the hart.
+    ///
+    /// # Safety
+    ///
+    /// The hart must not be borrowed elsewhere.
+    unsafe fn sync<T>(&mut self, index: u64, f: impl FnOnce(&mut Cpu) -> T) -> T {
+        let cpu = &mut *self.cpu;
+        let clint = &mut cpu.bus.clint;
+        clint.advance_to(clint.mtime().wrapping_add(index - self.ticked));
+        self.ticked = index;
+        let result = f(cpu);
+        // the borrow of the hart has invalidated the pointer to its registers
+        self.x = (*self.cpu).x.as_mut_ptr();
+        result
+    }
+}
+
+fn size(bytes: u64) -> Size {
+    match bytes {
+        1 => Size::Byte,
+        2 => Size::Halfword,
+        4 => Size::Word,
+        _ => Size::Doubleword,
+    }
+}
+
+// The operand of the load and store helpers: the index of the instruction, the size in bytes
+// and whether the value is sign-extended.
+fn operand(index: usize, bytes: u64, signed: bool) -> u64 {
+    (index as u64) << 8 | u64::from(signed) << 7 | bytes
+}
+
+unsafe extern "sysv64" fn load(context: *mut Context, address: u64, operand: u64) -> u64 {
+    let bytes = operand & 0xf;
+    let value = (*context).sync(operand >> 8, |cpu| cpu.bus.load(address, size(bytes)));
+    if operand & 0x80 != 0 {
+        extend_sign(value, 8 * bytes as u32) as u64
+    } else {
+        value
+    }
+}
+
+unsafe extern "sysv64" fn store(
+    context: *mut Context,
+    address: u64,
+    value: u64,
+    operand: u64,
+) -> u64 {
+    (*context).sync(operand >> 8, |cpu| {
+        u64::from(
+            cpu.bus
+                .try_store(address, value, size(operand & 0xf))
+                .is_err(),
+        )
+    })
+}
+
+unsafe extern "sysv64" fn execute(context: *mut Context, op: *const Op, index: u64) -> u64 {
+    let start = (*context).start;
+    (*context).sync(index, |cpu| {
+        cpu.pc.jump(start + 4 * index);
+        u64::from((*op).execute(cpu).is_err())
+    })
+}
+
+/// Machine code in executable memory.
+pub(crate) struct Code {
+    memory: NonNull<u8>,
+    len: usize,
+    // the instructions that the code executes through the interpreter
+    _ops: Rc<[Op]>,
+}
+
+impl Code {
+    fn new(bytes: &[u8], ops: Rc<[Op]>) -> Option<Self> {
+        unsafe {
+            let memory = libc::mmap(
+                ptr::null_mut(),
+                bytes.len(),
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            );
+            if memory == libc::MAP_FAILED {
+                return None;
+            }
+            ptr::copy_nonoverlapping(bytes.as_ptr(), memory.cast(), bytes.len());
+            let code = Self {
+                memory: NonNull::new(memory.cast())?,
+                len: bytes.len(),
+                _ops: ops,
+            };
+            (libc::mprotect(memory, bytes.len(), libc::PROT_READ | libc::PROT_EXEC) == 0)
+                .then_some(code)
+        }
+    }
+
+    /// Runs the block from the start and returns the number of instructions retired. The pc
+    /// is at the next instruction, or at the instruction that faulted, which has left the state
+    /// as it was so that `Cpu::step` can take the trap.
+    pub(crate) fn run(&self, cpu: &mut Cpu, start: u64) -> u64 {
+        let cpu: *mut Cpu = cpu;
+        unsafe {
+            let mut context = Context {
+                cpu,
+                start,
+                pc: start,
+                ticked: 0,
+                x: (*cpu).x.as_mut_ptr(),
+            };
+            let entry: unsafe extern "sysv64" fn(*mut Context) -> u64 =
+                std::mem::transmute(self.memory.as_ptr());
+            let retired = entry(&mut context);
+            let pc = context.pc;
+            context.sync(retired, |cpu| cpu.pc.jump(pc));
+            retired
+        }
+    }
+}
+
+impl Drop for Code {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(self.memory.as_ptr().cast(), self.len);
+        }
+    }
+}
+
+/// The code of a block, which is compiled once the block has run often enough.
+#[derive(Default)]
+pub(crate) struct Compiled {
+    runs: Cell<u32>,
+    code: OnceCell<Option<Code>>,
+}
+
+impl Compiled {
+    /// Returns the code of the block at the address, or None while the block is cold or if it
+    /// cannot be compiled.
+    pub(crate) fn get(&self, ops: &Rc<[Op]>, start: u64, threshold: u32) -> Option<&Code> {
+        if let Some(code) = self.code.get() {
+            return code.as_ref();
+        }
+        if self.runs.get() < threshold {
+            self.runs.set(self.runs.get() + 1);
+            return None;
+        }
+        self.code
+            .get_or_init(|| Code::new(&compile(ops, start), ops.clone()))
+            .as_ref()
+    }
+}
+
+/// An x86-64 encoder for the few forms that the compiled code uses.
+#[derive(Default)]
+struct Assembler {
+    code: Vec<u8>,
+    // the offsets of the jumps to the epilogue
+    exits: Vec<usize>,
+}
+
+impl Assembler {
+    fn emit(&mut self, bytes: &[u8]) {
+        self.code.extend_from_slice(bytes);
+    }
+
+    /// mov reg, x[register]
+    fn load(&mut self, reg: u8, register: usize) {
+        if register == 0 {
+            self.emit(&[0x31, 0xc0 | reg << 3 | reg]);
+        } else {
+            self.emit(&[0x48, 0x8b, 0x83 | reg << 3]);
+            self.emit(&(8 * register as u32).to_le_bytes());
+        }
+    }
+
+    /// mov x[register], reg
+    fn store(&mut self, register: usize, reg: u8) {
+        if register != 0 {
+            self.emit(&[0x48, 0x89, 0x83 | reg << 3]);
+            self.emit(&(8 * register as u32).to_le_bytes());
+        }
+    }
+
+    /// mov reg, value
+    fn mov_imm(&mut self, reg: u8, value: u64) {
+        self.emit(&[0x48, 0xb8 | reg]);
+        self.emit(&value.to_le_bytes());
+    }
+
+    /// mov to, from
+    fn mov(&mut self, to: u8, from: u8) {
+        self.emit(&[0x48, 0x89, 0xc0 | from << 3 | to]);
+    }
+
+    /// op rax, rcx
+    fn alu(&mut self, opcode: u8) {
+        self.emit(&[0x48, opcode, 0xc8]);
+    }
+
+    /// op rax, imm
+    fn alu_imm(&mut self, digit: u8, imm: i64) {
+        self.emit(&[0x48, 0x81, 0xc0 | digit << 3]);
+        self.emit(&(imm as i32).to_le_bytes());
+    }
+
+    /// op rax, cl, or op eax, cl if not wide
+    fn shift(&mut self, digit: u8, wide: bool) {
+        if wide {
+            self.emit(&[0x48]);
+        }
+        self.emit(&[0xd3, 0xc0 | digit << 3]);
+    }
+
+    /// op rax, amount, or op eax, amount if not wide
+    fn shift_imm(&mut self, digit: u8, amount: u64, wide: bool) {
+        if wide {
+            self.emit(&[0x48]);
+        }
+        self.emit(&[0xc1, 0xc0 | digit << 3, amount as u8]);
+    }
+
+    /// setcc al; movzx eax, al
+    fn set(&mut self, cc: u8) {
+        self.emit(&[0x0f, 0x90 | cc, 0xc0, 0x0f, 0xb6, 0xc0]);
+    }
+
+    /// movsxd rax, eax
+    fn extend32(&mut self) {
+        self.emit(&[0x48, 0x63, 0xc0]);
+    }
+
+    /// mov [r12 + CONTEXT_PC], rax
+    fn store_pc(&mut self) {
+        self.emit(&[0x49, 0x89, 0x44, 0x24, CONTEXT_PC]);
+    }
+
+    /// mov rbx, [r12 + CONTEXT_X]
+    fn load_x(&mut self) {
+        self.emit(&[0x49, 0x8b, 0x5c, 0x24, CONTEXT_X]);
+    }
+
+    /// Calls the helper with the context as the first argument.
+    fn call(&mut self, function: *const ()) {
+        // mov rdi, r12
+        self.emit(&[0x4c, 0x89, 0xe7]);
+        self.mov_imm(RAX, function as u64);
+        self.emit(&[0xff, 0xd0]);
+        self.load_x();
+    }
+
+    /// Returns from the block at the instruction if the helper has returned nonzero.
+    fn exit_on_fault(&mut self, address: u64, index: usize) {
+        // test rax, rax; jz over the exit
+        self.emit(&[0x48, 0x85, 0xc0, 0x74, 0]);
+        let skip = self.code.len();
+        self.mov_imm(RAX, address);
+        self.store_pc();
+        self.emit(&[0xb8]);
+        self.emit(&(index as u32).to_le_bytes());
+        self.emit(&[0xe9, 0, 0, 0, 0]);
+        self.exits.push(self.code.len() - 4);
+        self.code[skip - 1] = (self.code.len() - skip) as u8;
+    }
+
+    fn finish(mut self, retired: usize) -> Vec<u8> {
+        self.emit(&[0xb8]);
+        self.emit(&(retired as u32).to_le_bytes());
+        let epilogue = self.code.len();
+        for exit in &self.exits {
+            let offset = (epilogue - (exit + 4)) as u32;
+            self.code[*exit..*exit + 4].copy_from_slice(&offset.to_le_bytes());
+        }
+        // pop rbp; pop r12; pop rbx; ret
+        self.emit(&[0x5d, 0x41, 0x5c, 0x5b, 0xc3]);
+        self.code
+    }
+}
+
+/// The target of a control transfer. A transfer to itself moves on to the next instruction, as
+/// `Cpu::step` does when an instruction leaves the pc as it was.
+fn target(address: u64, target: u64) -> u64 {
+    if target == address {
+        address.wrapping_add(4)
+    } else {
+        target
+    }
+}
+
+/// Compiles the block at the address to a function of the context that returns the number of
+/// instructions retired.
+fn compile(ops: &[Op], start: u64) -> Vec<u8> {
+    let mut asm = Assembler::default();
+    // push rbx; push r12; push rbp to align the stack; mov r12, rdi
+    asm.emit(&[0x53, 0x41, 0x54, 0x55, 0x49, 0x89, 0xfc]);
+    asm.load_x();
+    let mut transferred = false;
+    for (index, op) in ops.iter().enumerate() {
+        let address = start + 4 * index as u64;
+        transferred = false;
+        match *op {
+            Op::Rv32i(Instruction::TypeR {
+                opcode,
+                rd,
+                rs1,
+                rs2,
+                ..
+            }) => {
+                if rd == 0 {
+                    continue;
+                }
+                asm.load(RAX, rs1);
+                asm.load(RCX, rs2);
+                match opcode {
+                    Rv32iOpcodeR::Add => asm.alu(0x01),
+                    Rv32iOpcodeR::Sub => asm.alu(0x29),
+                    Rv32iOpcodeR::Xor => asm.alu(0x31),
+                    Rv32iOpcodeR::Or => asm.alu(0x09),
+                    Rv32iOpcodeR::And => asm.alu(0x21),
+                    Rv32iOpcodeR::Sll => asm.shift(SHL, true),
+                    Rv32iOpcodeR::Srl => asm.shift(SHR, true),
+                    Rv32iOpcodeR::Sra => asm.shift(SAR, true),
+                    Rv32iOpcodeR::Slt => {
+                        asm.alu(0x39);
+                        asm.set(CC_L);
+                    }
+                    Rv32iOpcodeR::Sltu => {
+                        asm.alu(0x39);
+                        asm.set(CC_B);
+                    }
+                }
+                asm.store(rd, RAX);
+            }
+            Op::Rv32i(Instruction::TypeI {
+                opcode,
+                rd,
+                rs1,
+                imm,
+                ..
+            }) => {
+                let imm12 = extend_sign(imm, 12);
+                match opcode {
+                    Rv32iOpcodeI::Lb => compile_load(&mut asm, index, rd, rs1, imm12, 1, true),
+                    Rv32iOpcodeI::Lh => compile_load(&mut asm, index, rd, rs1, imm12, 2, true),
+                    Rv32iOpcodeI::Lw => compile_load(&mut asm, index, rd, rs1, imm12, 4, true),
+                    Rv32iOpcodeI::Lbu => compile_load(&mut asm, index, rd, rs1, imm12, 1, false),
+                    Rv32iOpcodeI::Lhu => compile_load(&mut asm, index, rd, rs1, imm12, 2, false),
+                    Rv32iOpcodeI::Jalr => {
+                        asm.load(RAX, rs1);
+                        asm.alu_imm(ADD, imm12);
+                        asm.alu_imm(AND, !1);
+                        // cmp rax, address; jne over add rax, 4
+                        asm.mov_imm(RCX, address);
+                        asm.alu(0x39);
+                        asm.emit(&[0x75, 0x04, 0x48, 0x83, 0xc0, 0x04]);
+                        asm.store_pc();
+                        asm.mov_imm(RAX, address.wrapping_add(4));
+                        asm.store(rd, RAX);
+                        transferred = true;
+                    }
+                    Rv32iOpcodeI::Fence | Rv32iOpcodeI::Ecall | Rv32iOpcodeI::Ebreak => {
+                        compile_op(&mut asm, op, address, index)
+                    }
+                    _ if rd == 0 => {}
+                    _ => {
+                        asm.load(RAX, rs1);
+                        match opcode {
+                            Rv32iOpcodeI::Addi => asm.alu_imm(ADD, imm12),
+                            Rv32iOpcodeI::Xori => asm.alu_imm(XOR, imm12),
+                            Rv32iOpcodeI::Ori => asm.alu_imm(OR, imm12),
+                            Rv32iOpcodeI::Andi => asm.alu_imm(AND, imm12),
+                            Rv32iOpcodeI::Slti => {
+                                asm.alu_imm(CMP, imm12);
+                                asm.set(CC_L);
+                            }
+                            Rv32iOpcodeI::Sltiu => {
+                                asm.alu_imm(CMP, imm12);
+                                asm.set(CC_B);
+                            }
+                            Rv32iOpcodeI::Slli => asm.shift_imm(SHL, shift_amount(imm), true),
+                            Rv32iOpcodeI::Srli => asm.shift_imm(SHR, shift_amount(imm), true),
+                            _ => asm.shift_imm(SAR, shift_amount(imm), true),
+                        }
+                        asm.store(rd, RAX);
+                    }
+                }
+            }
+            Op::Rv32i(Instruction::TypeS {
+                opcode,
+                rs1,
+                rs2,
+                imm,
+                ..
+            }) => {
+                let bytes = match opcode {
+                    Rv32iOpcodeS::Sb => 1,
+                    Rv32iOpcodeS::Sh => 2,
+                    Rv32iOpcodeS::Sw => 4,
+                };
+                compile_store(
+                    &mut asm,
+                    address,
+                    index,
+                    rs1,
+                    rs2,
+                    extend_sign(imm, 12),
+                    bytes,
+                );
+            }
+            Op::Rv32i(Instruction::TypeB {
+                opcode,
+                rs1,
+                rs2,
+                imm,
+                ..
+            }) => {
+                let cc = match opcode {
+                    Rv32iOpcodeB::Beq => CC_E,
+                    Rv32iOpcodeB::Bne => CC_NE,
+                    Rv32iOpcodeB::Blt => CC_L,
+                    Rv32iOpcodeB::Bge => CC_GE,
+                    Rv32iOpcodeB::Bltu => CC_B,
+                    Rv32iOpcodeB::Bgeu => CC_AE,
+                };
+                asm.load(RAX, rs1);
+                asm.load(RCX, rs2);
+                asm.alu(0x39);
+                asm.mov_imm(RAX, address.wrapping_add(4));
+                asm.mov_imm(RDX, target(address, address.wrapping_add(imm)));
+                // cmovcc rax, rdx
+                asm.emit(&[0x48, 0x0f, 0x40 | cc, 0xc2]);
+                asm.store_pc();
+                transferred = true;
+            }
+            Op::Rv32i(Instruction::TypeU { opcode, rd, imm }) => {
+                let value = match opcode {
+                    Rv32iOpcodeU::Lui => (extend_sign(imm, 20) << 12) as u64,
+                    Rv32iOpcodeU::Auipc => {
+                        address.wrapping_add((extend_sign(imm, 20) << 12) as u64)
+                    }
+                };
+                if rd != 0 {
+                    asm.mov_imm(RAX, value);
+                    asm.store(rd, RAX);
+                }
+            }
+            Op::Rv32i(Instruction::TypeJ {
+                opcode: Rv32iOpcodeJ::Jal,
+                rd,
+                imm,
+            }) => {
+                asm.mov_imm(RAX, address.wrapping_add(4));
+                asm.store(rd, RAX);
+                asm.mov_imm(RAX, target(address, address.wrapping_add(imm)));
+                asm.store_pc();
+                transferred = true;
+            }
+            Op::Rv64i(Instruction::TypeR {
+                opcode,
+                rd,
+                rs1,
+                rs2,
+                ..
+            }) => {
+                if rd == 0 {
+                    continue;
+                }
+                asm.load(RAX, rs1);
+                asm.load(RCX, rs2);
+                match opcode {
+                    Rv64iOpcodeR::Addw => asm.alu(0x01),
+                    Rv64iOpcodeR::Subw => asm.alu(0x29),
+                    Rv64iOpcodeR::Sllw => asm.shift(SHL, false),
+                    Rv64iOpcodeR::Srlw => asm.shift(SHR, false),
+                    Rv64iOpcodeR::Sraw => asm.shift(SAR, false),
+                }
+                asm.extend32();
+                asm.store(rd, RAX);
+            }
+            Op::Rv64i(Instruction::TypeI {
+                opcode,
+                rd,
+                rs1,
+                imm,
+                ..
+            }) => {
+                let imm12 = extend_sign(imm, 12);
+                match opcode {
+                    Rv64iOpcodeI::Lwu => compile_load(&mut asm, index, rd, rs1, imm12, 4, false),
+                    Rv64iOpcodeI::Ld => compile_load(&mut asm, index, rd, rs1, imm12, 8, false),
+                    _ if rd == 0 => {}
+                    _ => {
+                        asm.load(RAX, rs1);
+                        match opcode {
+                            Rv64iOpcodeI::Addiw => asm.alu_imm(ADD, imm12),
+                            Rv64iOpcodeI::Slliw => asm.shift_imm(SHL, imm & MASK_6BIT, false),
+                            Rv64iOpcodeI::Srliw => asm.shift_imm(SHR, imm & MASK_6BIT, false),
+                            _ => asm.shift_imm(SAR, imm & MASK_6BIT, false),
+                        }
+                        asm.extend32();
+                        asm.store(rd, RAX);
+                    }
+                }
+            }
+            Op::Rv64i(Instruction::TypeS {
+                opcode: Rv64iOpcodeS::Sd,
+                rs1,
+                rs2,
+                imm,
+                ..
+            }) => compile_store(&mut asm, address, index, rs1, rs2, imm as i64, 8),
+            Op::Rv32m(Instruction::TypeR {
+                opcode: opcode @ (Rv32mOpcodeR::Mul | Rv32mOpcodeR::Mulh | Rv32mOpcodeR::Mulhu),
+                rd,
+                rs1,
+                rs2,
+                ..
+            }) => {
+                if rd == 0 {
+                    continue;
+                }
+                asm.load(RAX, rs1);
+                asm.load(RCX, rs2);
+                match opcode {
+                    // imul rax, rcx
+                    Rv32mOpcodeR::Mul => asm.emit(&[0x48, 0x0f, 0xaf, 0xc1]),
+                    // imul rcx; mov rax, rdx
+                    Rv32mOpcodeR::Mulh => {
+                        asm.emit(&[0x48, 0xf7, 0xe9]);
+                        asm.mov(RAX, RDX);
+                    }
+                    // mul rcx; mov rax, rdx
+                    _ => {
+                        asm.emit(&[0x48, 0xf7, 0xe1]);
+                        asm.mov(RAX, RDX);
+                    }
+                }
+                asm.store(rd, RAX);
+            }
+            Op::Rv64m(Instruction::TypeR {
+                opcode: Rv64mOpcodeR::Mulw,
+                rd,
+                rs1,
+                rs2,
+                ..
+            }) => {
+                if rd == 0 {
+                    continue;
+                }
+                asm.load(RAX, rs1);
+                asm.load(RCX, rs2);
+                asm.emit(&[0x48, 0x0f, 0xaf, 0xc1]);
+                asm.extend32();
+                asm.store(rd, RAX);
+            }
+            // the division, the floating point and the rest go through the interpreter
+            _ => compile_op(&mut asm, op, address, index),
+        }
+    }
+    if !transferred {
+        asm.mov_imm(RAX, start + 4 * ops.len() as u64);
+        asm.store_pc();
+    }
+    asm.finish(ops.len())
+}
+
+fn compile_load(
+    asm: &mut Assembler,
+    index: usize,
+    rd: usize,
+    rs1: usize,
+    imm: i64,
+    bytes: u64,
+    signed: bool,
+) {
+    asm.load(RAX, rs1);
+    asm.alu_imm(ADD, imm);
+    asm.mov(RSI, RAX);
+    asm.mov_imm(RDX, operand(index, bytes, signed));
+    asm.call(load as *const ());
+    asm.store(rd, RAX);
+}
+
+fn compile_store(
+    asm: &mut Assembler,
+    address: u64,
+    index: usize,
+    rs1: usize,
+    rs2: usize,
+    imm: i64,
+    bytes: u64,
+) {
+    asm.load(RAX, rs1);
+    asm.alu_imm(ADD, imm);
+    asm.mov(RSI, RAX);
+    asm.load(RDX, rs2);
+    asm.mov_imm(RCX, operand(index, bytes, false));
+    asm.call(store as *const ());
+    asm.exit_on_fault(address, index);
+}
+
+/// Calls the interpreter for the instruction.
+fn compile_op(asm: &mut Assembler, op: &Op, address: u64, index: usize) {
+    asm.mov_imm(RSI, op as *const Op as u64);
+    asm.mov_imm(RDX, index as u64);
+    asm.call(execute as *const ());
+    asm.exit_on_fault(address, index);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::bus::{memory::MEMORY_BASE_ADDRESS, region::Region};
+    use crate::emulator::cpu::Step;
+    use crate::isa::privileged::cause::{Cause, Exception};
+
+    const LOOP: [u32; 10] = [
+        // addi a0, a0, 1
+        0x0015_0513,
+        // slli a1, a0, 3
+        0x0035_1593,
+        // mul a2, a1, a0
+        0x02a5_8633,
+        // sltu a3, a2, a1
+        0x00b6_36b3,
+        // addiw a4, a2, -5
+        0xffb6_071b,
+        // ld a5, 0(t0)
+        0x0002_b783,
+        // divu a6, a2, a0
+        0x02a6_5833,
+        // xor a5, a5, a6
+        0x0107_c7b3,
+        // sd a5, 0(t0)
+        0x00f2_b023,
+        // blt a0, s0, -36
+        0xfc85_4ee3,
+    ];
+
+    fn cpu() -> Cpu {
+        let mut cpu = Cpu::default();
+        for (i, instruction) in LOOP.iter().enumerate() {
+            cpu.bus
+                .store32(MEMORY_BASE_ADDRESS + 4 * i as u64, *instruction);
+        }
+        cpu.x.writeu(5, MEMORY_BASE_ADDRESS + 0x1000);
+        cpu.x.writeu(8, 3 * THRESHOLD as u64);
+        cpu
+    }
+
+    #[test]
+    fn compile_ok() {
+        let mut compiled = cpu();
+        let mut retired = 0;
+        while compiled.pc.read() != MEMORY_BASE_ADDRESS + 40 {
+            retired += compiled.run_blocks(1000, None);
+        }
+        let mut stepped = cpu();
+        for _ in 0..retired {
+            assert_eq!(stepped.step(false), Step::Retired);
+        }
+        assert_eq!(compiled.x.snapshot(), stepped.x.snapshot());
+        assert_eq!(compiled.pc.read(), stepped.pc.read());
+        assert_eq!(compiled.bus.clint.mtime(), stepped.bus.clint.mtime());
+        let scratch = MEMORY_BASE_ADDRESS + 0x1000;
+        assert_eq!(compiled.bus.load64(scratch), stepped.bus.load64(scratch));
+
+        // the store to ROM stops the compiled block at the store, which traps in `step`
+        let rom = 0x2000_0000;
+        compiled
+            .bus
+            .map(Region::from_bytes(rom, vec![0; 8], false).unwrap())
+            .unwrap();
+        compiled.x.writeu(5, rom);
+        compiled.pc.jump(MEMORY_BASE_ADDRESS);
+        assert_eq!(compiled.run_blocks(1000, None), 8);
+        assert_eq!(compiled.pc.read(), MEMORY_BASE_ADDRESS + 32);
+        assert_eq!(
+            compiled.step(false),
+            Step::Trap(Cause::Exception(Exception::StoreAccessFault))
+        );
+        assert_eq!(compiled.bus.load64(rom), 0);
+    }
+
+    // The registers that the instructions of `differential_ok` use.
+    const A0: u32 = 10;
+    const A1: u32 = 11;
+    const A2: u32 = 12;
+    const T0: u32 = 5;
+
+    fn r(funct7: u32, funct3: u32, opcode: u32, rd: u32) -> u32 {
+        funct7 << 25 | A2 << 20 | A1 << 15 | funct3 << 12 | rd << 7 | opcode
+    }
+
+    fn i(imm: i32, rs1: u32, funct3: u32, opcode: u32, rd: u32) -> u32 {
+        (imm as u32) << 20 | rs1 << 15 | funct3 << 12 | rd << 7 | opcode
+    }
+
+    fn s(imm: i32, funct3: u32) -> u32 {
+        let imm = imm as u32;
+        (imm >> 5 & 0x7f) << 25 | A2 << 20 | T0 << 15 | funct3 << 12 | (imm & 0x1f) << 7 | 0x23
+    }
+
+    // a branch forward by 8
+    fn b(funct3: u32) -> u32 {
+        A2 << 20 | A1 << 15 | funct3 << 12 | 8 << 7 | 0x63
+    }
+
+    /// Returns the instructions of RV64IM, and AMOs that the compiled code runs through
+    /// `Op::execute`, with the destination register.
+    fn instructions(rd: u32) -> Vec<u32> {
+        let mut instructions = vec![];
+        for (funct7, funct3) in [
+            (0, 0),
+            (0x20, 0),
+            (0, 1),
+            (0, 2),
+            (0, 3),
+            (0, 4),
+            (0, 5),
+            (0x20, 5),
+            (0, 6),
+            (0, 7),
+        ] {
+            instructions.push(r(funct7, funct3, 0x33, rd));
+        }
+        for (funct7, funct3) in [(0, 0), (0x20, 0), (0, 1), (0, 5), (0x20, 5)] {
+            instructions.push(r(funct7, funct3, 0x3b, rd));
+        }
+        for funct3 in 0..8 {
+            instructions.push(r(1, funct3, 0x33, rd));
+        }
+        for funct3 in [0, 4, 5, 6, 7] {
+            instructions.push(r(1, funct3, 0x3b, rd));
+        }
+        for imm in [0, 1, -1, 0x7ff, -0x800] {
+            for funct3 in [0, 2, 3, 4, 6, 7] {
+                instructions.push(i(imm, A1, funct3, 0x13, rd));
+            }
+            instructions.push(i(imm, A1, 0, 0x1b, rd));
+        }
+        for shamt in [0, 1, 31, 32, 63] {
+            instructions.push(i(shamt, A1, 1, 0x13, rd));
+            instructions.push(i(shamt, A1, 5, 0x13, rd));
+            instructions.push(i(0x400 | shamt, A1, 5, 0x13, rd));
+        }
+        for shamt in [0, 1, 31] {
+            instructions.push(i(shamt, A1, 1, 0x1b, rd));
+            instructions.push(i(shamt, A1, 5, 0x1b, rd));
+            instructions.push(i(0x400 | shamt, A1, 5, 0x1b, rd));
+        }
+        for imm in [-8, 0, 8] {
+            for funct3 in 0..7 {
+                instructions.push(i(imm, T0, funct3, 0x03, rd));
+            }
+        }
+        for imm in [-8, 8] {
+            for funct3 in 0..4 {
+                instructions.push(s(imm, funct3));
+            }
+        }
+        instructions.push(i(4, T0, 0, 0x67, rd));
+        instructions.push(i(-3, T0, 0, 0x67, rd));
+        for funct3 in [0, 1, 4, 5, 6, 7] {
+            instructions.push(b(funct3));
+        }
+        for imm in [0, 1, 0x80000, 0xfffff] {
+            instructions.push(imm << 12 | rd << 7 | 0x37);
+            instructions.push(imm << 12 | rd << 7 | 0x17);
+        }
+        // jal rd, 8
+        instructions.push(8 << 21 | rd << 7 | 0x6f);
+        // amoadd.w, amoadd.d
+        instructions.push(A2 << 20 | T0 << 15 | 2 << 12 | rd << 7 | 0x2f);
+        instructions.push(A2 << 20 | T0 << 15 | 3 << 12 | rd << 7 | 0x2f);
+        instructions
+    }
+
+    #[test]
+    fn differential_ok() {
+        let values = [
+            0,
+            1,
+            u64::MAX,
+            i64::MIN as u64,
+            i64::MAX as u64,
+            0x8000_0000,
+            0xffff_ffff,
+            63,
+            64,
+        ];
+        let scratch = MEMORY_BASE_ADDRESS + 0x1000;
+        let reset = |cpu: &mut Cpu, a1: u64, a2: u64| {
+            cpu.pc.jump(MEMORY_BASE_ADDRESS);
+            cpu.bus.store64(scratch, a2);
+            cpu.bus.store64(scratch + 8, a1);
+            cpu.bus.store64(scratch + 16, !a1);
+            cpu.x.writeu(A0 as usize, 0x5555);
+            cpu.x.writeu(A1 as usize, a1);
+            cpu.x.writeu(A2 as usize, a2);
+            cpu.x.writeu(T0 as usize, scratch + 8);
+        };
+        for rd in [A0, 0] {
+            for instruction in instructions(rd) {
+                // the block is compiled once and runs for every pair of operands
+                let mut compiled = Cpu {
+                    jit_threshold: 0,
+                    ..Cpu::default()
+                };
+                compiled.bus.store32(MEMORY_BASE_ADDRESS, instruction);
+                let mut stepped = Cpu::default();
+                stepped.bus.store32(MEMORY_BASE_ADDRESS, instruction);
+                for a1 in values {
+                    for a2 in values {
+                        let message = format!("{instruction:#010x} a1={a1:#x} a2={a2:#x}");
+                        reset(&mut compiled, a1, a2);
+                        assert_eq!(compiled.run_blocks(1, None), 1, "{message}");
+                        reset(&mut stepped, a1, a2);
+                        assert_eq!(stepped.step(false), Step::Retired, "{message}");
+                        assert_eq!(compiled.x.snapshot(), stepped.x.snapshot(), "{message}");
+                        assert_eq!(compiled.pc.read(), stepped.pc.read(), "{message}");
+                        for address in (scratch..scratch + 24).step_by(8) {
+                            assert_eq!(
+                                compiled.bus.load64(address),
+                                stepped.bus.load64(address),
+                                "{message}"
+                            );
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
@@ -3,14 +3,18 @@ use crate::{
     isa::{
         csr::{machine_level::*, status::*, supervisor_level::*, user_level::*},
         privileged::{
-            cause::{Cause, Exception},
+            cause::{Cause, Exception, ExceptionReturn},
             mode::PrivilegeMode,
         },
     },
 };
 use std::ops::Range;
 
-fn delegated_privilege_mode(csr: &mut ControlAndStatusRegister, cause: &Cause) -> PrivilegeMode {
+fn delegated_privilege_mode(
+    csr: &ControlAndStatusRegister,
+    cause: &Cause,
+    current_privilege_mode: PrivilegeMode,
+) -> PrivilegeMode {
     let m_addr = if cause.is_interrupt() {
         MIDELEG
     } else {
@@ -22,9 +26,12 @@ fn delegated_privilege_mode(csr: &mut ControlAndStatusRegister, cause: &Cause) -
         SEDELEG
     };
     let code = cause.exception_code();
-    if ((csr.csrrs(m_addr, 0) >> code) & 1) == 0 {
+    // traps never transition to a less privileged mode
+    if ((csr.read(m_addr) >> code) & 1) == 0 || current_privilege_mode == PrivilegeMode::Machine {
         PrivilegeMode::Machine
-    } else if ((csr.csrrs(s_addr, 0) >> code) & 1) == 0 {
+    } else if ((csr.read(s_addr) >> code) & 1) == 0
+        || current_privilege_mode == PrivilegeMode::Supervisor
+    {
         PrivilegeMode::Supervisor
     } else {
         PrivilegeMode::User
@@ -57,43 +64,15 @@ fn select_status_field(
     }
 }
 
-fn read_status_field(
-    csr: &mut ControlAndStatusRegister,
-    address: u64,
-    field: &Range<usize>,
-) -> u64 {
-    let status = csr.csrrs(address, 0);
-    let length = (field.end - field.start) as u64;
-    let mask = !((2 ^ length) - 1);
-    (status >> field.start) & mask
-}
-
-fn update_status_field(
-    csr: &mut ControlAndStatusRegister,
-    address: u64,
-    field: &Range<usize>,
-    value: u64,
-) {
-    let length = (field.end - field.start) as u64;
-    let mask = !((2 ^ length) - 1);
-    let shifted_value = value << field.start;
-    csr.csrrc(address, mask);
-    csr.csrrs(address, shifted_value);
-}
-
+/// Returns the value of the trap value register. The faults of loads and stores write 0, since
+/// the cause does not carry the address of the data.
 fn select_tval(cause: &Cause, faulting_address: u64, faulting_instruction: u32) -> u64 {
     if let Cause::Exception(exception) = cause {
         match exception {
             Exception::InstructionAddressMisaligned
-            | Exception::LoadAddressMisaligned
-            | Exception::StoreAddressMisaligned
             | Exception::Breakpoint
             | Exception::InstructionAccessFault
-            | Exception::LoadAccessFault
-            | Exception::StoreAccessFault
-            | Exception::InstructionPageFault
-            | Exception::LoadPageFault
-            | Exception::StorePageFault => faulting_address,
+            | Exception::InstructionPageFault => faulting_address,
             Exception::IllegalInstruction => faulting_instruction as u64,
             _ => 0,
         }
@@ -109,58 +88,60 @@ fn handle_trap(
     current_privilege_mode: PrivilegeMode,
     csr: &mut ControlAndStatusRegister,
 ) -> (PrivilegeMode, u64) {
-    let next_privilege_mode = delegated_privilege_mode(csr, cause);
+    let next_privilege_mode = delegated_privilege_mode(csr, cause, current_privilege_mode);
     // set cause register
     let cause_address = select_address(&next_privilege_mode, MCAUSE, SCAUSE, UCAUSE);
-    csr.csrrw(cause_address, cause.to_primitive());
+    csr.write(cause_address, cause.to_primitive());
 
     // set exception program counter
     let epc_address = select_address(&next_privilege_mode, MEPC, SEPC, UEPC);
-    csr.csrrw(epc_address, pc_address);
+    csr.write(epc_address, pc_address);
 
     // set trap value register
     let tval_address = select_address(&next_privilege_mode, MTVAL, STVAL, UTVAL);
     let tval = select_tval(cause, pc_address, instruction);
-    csr.csrrw(tval_address, tval);
+    csr.write(tval_address, tval);
 
     // set previous privilege
     let status_address = select_address(&next_privilege_mode, MSTATUS, SSTATUS, USTATUS);
     match next_privilege_mode {
-        PrivilegeMode::Machine => update_status_field(
-            csr,
-            status_address,
-            &STATUS_MPP,
-            current_privilege_mode as u64,
-        ),
-        PrivilegeMode::Supervisor => update_status_field(
-            csr,
-            status_address,
-            &STATUS_SPP,
-            current_privilege_mode as u64,
-        ),
+        PrivilegeMode::Machine => {
+            csr.write_field(status_address, &STATUS_MPP, current_privilege_mode as u64)
+        }
+        PrivilegeMode::Supervisor => {
+            csr.write_field(status_address, &STATUS_SPP, current_privilege_mode as u64)
+        }
         PrivilegeMode::User => {}
     }
 
     // set previous interrupt enable
     let ie_field = select_status_field(&next_privilege_mode, STATUS_MIE, STATUS_SIE, STATUS_UIE);
-    let ie = read_status_field(csr, status_address, &ie_field);
+    let ie = csr.read_field(status_address, &ie_field);
     let pie_field =
         select_status_field(&next_privilege_mode, STATUS_MPIE, STATUS_SPIE, STATUS_UPIE);
-    update_status_field(csr, status_address, &pie_field, ie);
+    csr.write_field(status_address, &pie_field, ie);
 
     // disable interrupt enable
-    update_status_field(csr, status_address, &ie_field, 0);
+    csr.write_field(status_address, &ie_field, 0);
 
     // set pc to trap-vector base-address register
     let tvec_address = select_address(&next_privilege_mode, MTVEC, STVEC, UTVEC);
-    let tvec = csr.csrrs(tvec_address, 0);
-    (next_privilege_mode, tvec)
+    let tvec = csr.read(tvec_address);
+    let base = tvec & !0b11;
+    if cause.is_interrupt() && tvec & 0b11 == 1 {
+        // vectored mode
+        (next_privilege_mode, base + 4 * cause.exception_code())
+    } else {
+        (next_privilege_mode, base)
+    }
 }
 
 fn handle_exception_return(
-    current_privilege_mode: PrivilegeMode,
+    exception_return: &ExceptionReturn,
     csr: &mut ControlAndStatusRegister,
 ) -> (PrivilegeMode, u64) {
+    // the fields of the returning privilege mode are restored
+    let current_privilege_mode = exception_return.privilege_mode();
     let status_address = select_address(&current_privilege_mode, MSTATUS, SSTATUS, USTATUS);
 
     // restore interrupt enable
@@ -171,33 +152,38 @@ fn handle_exception_return(
         STATUS_UPIE,
     );
     let ie_field = select_status_field(&current_privilege_mode, STATUS_MIE, STATUS_SIE, STATUS_UIE);
-    let pie = read_status_field(csr, status_address, &pie_field);
-    update_status_field(csr, status_address, &ie_field, pie);
+    let pie = csr.read_field(status_address, &pie_field);
+    csr.write_field(status_address, &ie_field, pie);
 
     // set 1 to previous interrupt enable
-    update_status_field(csr, status_address, &pie_field, 1);
+    csr.write_field(status_address, &pie_field, 1);
 
     // read previous privilege
     let pp = match current_privilege_mode {
         PrivilegeMode::Machine => {
-            PrivilegeMode::from_primitive(read_status_field(csr, status_address, &STATUS_MPP))
+            PrivilegeMode::from_primitive(csr.read_field(status_address, &STATUS_MPP))
         }
         PrivilegeMode::Supervisor => {
-            PrivilegeMode::from_primitive(read_status_field(csr, status_address, &STATUS_SPP))
+            PrivilegeMode::from_primitive(csr.read_field(status_address, &STATUS_SPP))
         }
         PrivilegeMode::User => PrivilegeMode::User,
     };
 
+    // returning to a mode less privileged than machine mode clears mprv
+    if pp != PrivilegeMode::Machine {
+        csr.write_field(MSTATUS, &STATUS_MPRV, 0);
+    }
+
     // set 0 to previous privilege
     match current_privilege_mode {
-        PrivilegeMode::Machine => update_status_field(csr, status_address, &STATUS_MPP, 0),
-        PrivilegeMode::Supervisor => update_status_field(csr, status_address, &STATUS_SPP, 0),
+        PrivilegeMode::Machine => csr.write_field(status_address, &STATUS_MPP, 0),
+        PrivilegeMode::Supervisor => csr.write_field(status_address, &STATUS_SPP, 0),
         PrivilegeMode::User => {}
     };
 
     // read exception program counter
     let epc_address = select_address(&current_privilege_mode, MEPC, SEPC, UEPC);
-    let epc = csr.csrrs(epc_address, 0);
+    let epc = csr.read(epc_address);
 
     (pp, epc)
 }
@@ -210,7 +196,225 @@ pub fn handle_cause(
     csr: &mut ControlAndStatusRegister,
 ) -> (PrivilegeMode, u64) {
     match cause {
-        Cause::ExceptionReturn(_) => handle_exception_return(current_privilege_mode, csr),
+        Cause::ExceptionReturn(exception_return) => handle_exception_return(exception_return, csr),
         _ => handle_trap(cause, pc_address, instruction, current_privilege_mode, csr),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::isa::privileged::cause::Interrupt;
+
+    const MODES: [PrivilegeMode; 3] = [
+        PrivilegeMode::User,
+        PrivilegeMode::Supervisor,
+        PrivilegeMode::Machine,
+    ];
+    const PC: u64 = 0x8000_0010;
+    const INSTRUCTION: u32 = 0xffff_ffff;
+
+    fn setup(medeleg: u64, sedeleg: u64) -> ControlAndStatusRegister {
+        let mut csr = ControlAndStatusRegister::default();
+        csr.csrrw(MTVEC, 0x100);
+        csr.csrrw(STVEC, 0x200);
+        csr.csrrw(UTVEC, 0x300);
+        csr.csrrw(MEDELEG, medeleg);
+        csr.csrrw(SEDELEG, sedeleg);
+        csr
+    }
+
+    fn select_ie(mode: &PrivilegeMode) -> (u64, Range<usize>, Range<usize>) {
+        let status = select_address(mode, MSTATUS, SSTATUS, USTATUS);
+        let ie = select_status_field(mode, STATUS_MIE, STATUS_SIE, STATUS_UIE);
+        let pie = select_status_field(mode, STATUS_MPIE, STATUS_SPIE, STATUS_UPIE);
+        (status, ie, pie)
+    }
+
+    fn exception_return(mode: &PrivilegeMode) -> ExceptionReturn {
+        match mode {
+            PrivilegeMode::User => ExceptionReturn::User,
+            PrivilegeMode::Supervisor => ExceptionReturn::Supervisor,
+            PrivilegeMode::Machine => ExceptionReturn::Machine,
+        }
+    }
+
+    #[test]
+    fn trap_entry_ok() {
+        let illegal = 1 << Exception::IllegalInstruction.to_primitive();
+        let delegations = [
+            (0, 0, PrivilegeMode::Machine),
+            (illegal, 0, PrivilegeMode::Supervisor),
+            (illegal, illegal, PrivilegeMode::User),
+        ];
+        for current in MODES {
+            for (medeleg, sedeleg, delegated) in delegations {
+                for ie in [0, 1] {
+                    // traps never transition to a less privileged mode
+                    let expected = if (current as u64) > (delegated as u64) {
+                        current
+                    } else {
+                        delegated
+                    };
+                    let mut csr = setup(medeleg, sedeleg);
+                    let (status, ie_field, pie_field) = select_ie(&expected);
+                    csr.write_field(status, &ie_field, ie);
+                    csr.write_field(status, &pie_field, ie ^ 1);
+
+                    let (prv, pc) = handle_cause(
+                        &Cause::Exception(Exception::IllegalInstruction),
+                        PC,
+                        INSTRUCTION,
+                        current,
+                        &mut csr,
+                    );
+
+                    assert!(prv == expected);
+                    assert_eq!(pc, select_address(&expected, 0x100, 0x200, 0x300));
+                    let cause = select_address(&expected, MCAUSE, SCAUSE, UCAUSE);
+                    assert_eq!(csr.read(cause), 2);
+                    let epc = select_address(&expected, MEPC, SEPC, UEPC);
+                    assert_eq!(csr.read(epc), PC);
+                    let tval = select_address(&expected, MTVAL, STVAL, UTVAL);
+                    assert_eq!(csr.read(tval), INSTRUCTION as u64);
+                    assert_eq!(csr.read_field(status, &pie_field), ie);
+                    assert_eq!(csr.read_field(status, &ie_field), 0);
+                    match expected {
+                        PrivilegeMode::Machine => {
+                            assert_eq!(csr.read_field(MSTATUS, &STATUS_MPP), current as u64)
+                        }
+                        PrivilegeMode::Supervisor => {
+                            assert_eq!(csr.read_field(MSTATUS, &STATUS_SPP), current as u64)
+                        }
+                        PrivilegeMode::User => {}
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn trap_entry_preserves_other_fields_ok() {
+        let mut csr = setup(0, 0);
+        csr.write_field(MSTATUS, &STATUS_FS, 0b01);
+        csr.write_field(MSTATUS, &STATUS_SIE, 1);
+        csr.write_field(MSTATUS, &STATUS_MIE, 1);
+        handle_cause(
+            &Cause::Exception(Exception::EnvironmentCallFromSupervisorMode),
+            PC,
+            INSTRUCTION,
+            PrivilegeMode::Supervisor,
+            &mut csr,
+        );
+        assert_eq!(csr.read_field(MSTATUS, &STATUS_FS), 0b01);
+        assert_eq!(csr.read_field(MSTATUS, &STATUS_SIE), 1);
+        assert_eq!(csr.read_field(MSTATUS, &STATUS_UXL), XLEN_64);
+        assert_eq!(csr.read(MTVAL), 0);
+    }
+
+    #[test]
+    fn interrupt_entry_ok() {
+        let mut csr = setup(0, 0);
+        csr.csrrw(MTVEC, 0x101);
+        let (prv, pc) = handle_cause(
+            &Cause::Interrupt(Interrupt::MachineTimer),
+            PC,
+            0,
+            PrivilegeMode::User,
+            &mut csr,
+        );
+        assert!(prv == PrivilegeMode::Machine);
+        assert_eq!(pc, 0x100 + 4 * 7);
+        assert_eq!(csr.read(MCAUSE), 1 << 63 | 7);
+        assert_eq!(csr.read(MEPC), PC);
+
+        csr.csrrw(MTVEC, 0x100);
+        let (_, pc) = handle_cause(
+            &Cause::Interrupt(Interrupt::MachineSoftware),
+            PC,
+            0,
+            PrivilegeMode::User,
+            &mut csr,
+        );
+        assert_eq!(pc, 0x100);
+    }
+
+    #[test]
+    fn exception_return_ok() {
+        for mode in MODES {
+            let previous_modes: &[PrivilegeMode] = match mode {
+                PrivilegeMode::Machine => &MODES,
+                PrivilegeMode::Supervisor => &MODES[..2],
+                PrivilegeMode::User => &MODES[..1],
+            };
+            for pp in previous_modes {
+                for pie in [0, 1] {
+                    for current in MODES.iter().filter(|m| (**m as u64) >= (mode as u64)) {
+                        let mut csr = ControlAndStatusRegister::default();
+                        let (status, ie_field, pie_field) = select_ie(&mode);
+                        csr.write_field(status, &pie_field, pie);
+                        csr.write_field(status, &ie_field, pie ^ 1);
+                        csr.write_field(MSTATUS, &STATUS_MPRV, 1);
+                        match mode {
+                            PrivilegeMode::Machine => {
+                                csr.write_field(MSTATUS, &STATUS_MPP, *pp as u64)
+                            }
+                            PrivilegeMode::Supervisor => {
+                                csr.write_field(MSTATUS, &STATUS_SPP, *pp as u64)
+                            }
+                            PrivilegeMode::User => {}
+                        }
+                        csr.write(select_address(&mode, MEPC, SEPC, UEPC), 0x8000_0040);
+
+                        let (prv, pc) = handle_cause(
+                            &Cause::ExceptionReturn(exception_return(&mode)),
+                            PC,
+                            INSTRUCTION,
+                            *current,
+                            &mut csr,
+                        );
+
+                        assert!(prv == *pp);
+                        assert_eq!(pc, 0x8000_0040);
+                        assert_eq!(csr.read_field(status, &ie_field), pie);
+                        assert_eq!(csr.read_field(status, &pie_field), 1);
+                        assert_eq!(csr.read_field(MSTATUS, &STATUS_MPP), 0);
+                        assert_eq!(csr.read_field(MSTATUS, &STATUS_SPP), 0);
+                        let mprv = u64::from(*pp == PrivilegeMode::Machine);
+                        assert_eq!(csr.read_field(MSTATUS, &STATUS_MPRV), mprv);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn trap_and_return_round_trip_ok() {
+        let ecall = 1 << Exception::EnvironmentCallFromUserMode.to_primitive();
+        let mut csr = setup(ecall, 0);
+        csr.write_field(MSTATUS, &STATUS_SIE, 1);
+        let (prv, pc) = handle_cause(
+            &Cause::Exception(Exception::EnvironmentCallFromUserMode),
+            PC,
+            INSTRUCTION,
+            PrivilegeMode::User,
+            &mut csr,
+        );
+        assert!(prv == PrivilegeMode::Supervisor);
+        assert_eq!(pc, 0x200);
+        assert_eq!(csr.read_field(SSTATUS, &STATUS_SIE), 0);
+        assert_eq!(csr.read(SCAUSE), 8);
+
+        csr.write(SEPC, csr.read(SEPC) + 4);
+        let (prv, pc) = handle_cause(
+            &Cause::ExceptionReturn(ExceptionReturn::Supervisor),
+            pc,
+            INSTRUCTION,
+            prv,
+            &mut csr,
+        );
+        assert!(prv == PrivilegeMode::User);
+        assert_eq!(pc, PC + 4);
+        assert_eq!(csr.read_field(MSTATUS, &STATUS_SIE), 1);
+    }
+}
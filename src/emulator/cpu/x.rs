@@ -1,17 +1,11 @@
-use crate::isa::register::{SP, ZERO};
-use crate::{emulator::bus::memory::MEMORY_SIZE, isa::register::xname};
+use crate::isa::register::{xname, ZERO};
 use std::fmt;
 
+#[derive(Default)]
 pub struct IntegerRegister {
     x: [u64; 32],
-}
-
-impl Default for IntegerRegister {
-    fn default() -> Self {
-        let mut x = [0; 32];
-        x[SP] = MEMORY_SIZE;
-        Self { x }
-    }
+    observing: bool,
+    writes: Vec<(usize, u64)>,
 }
 
 impl IntegerRegister {
@@ -24,21 +18,43 @@ impl IntegerRegister {
     }
 
     pub fn writei(&mut self, register: usize, value: i64) {
-        if register != ZERO {
-            self.x[register] = value as u64;
-        }
+        self.writeu(register, value as u64);
     }
 
     pub fn writeu(&mut self, register: usize, value: u64) {
         if register != ZERO {
             self.x[register] = value;
+            if self.observing {
+                self.writes.push((register, value));
+            }
         }
     }
 
+    /// Starts or stops recording the writes to the registers.
+    pub fn set_observing(&mut self, observing: bool) {
+        self.observing = observing;
+        self.writes.clear();
+    }
+
+    /// Returns the registers and the values written since the last call.
+    pub fn take_writes(&mut self) -> Vec<(usize, u64)> {
+        std::mem::take(&mut self.writes)
+    }
+
+    /// Returns the registers for the compiled code, which writes them without recording.
+    #[cfg(feature = "jit")]
+    pub(crate) fn as_mut_ptr(&mut self) -> *mut u64 {
+        self.x.as_mut_ptr()
+    }
+
     pub fn snapshot(&self) -> [u64; 32] {
         self.x
     }
 
+    pub fn restore(&mut self, snapshot: [u64; 32]) {
+        self.x = snapshot;
+    }
+
     pub fn diff(&self, other: [u64; 32]) -> Vec<(usize, u64, u64)> {
         let mut result = vec![];
         for (i, x) in self.x.iter().enumerate() {
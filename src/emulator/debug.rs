@@ -0,0 +1,253 @@
+use crate::{
+    emulator::cpu::{csr::Csr, Cpu, Step},
+    isa::{
+        csr::{
+            debug::{DCSR_CAUSE, DCSR_STEP},
+            machine_level::DCSR,
+        },
+        privileged::cause::{Cause, Exception},
+    },
+};
+
+/// The error of an abstract command of the debug module.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CommandError {
+    /// The command is not supported, e.g. an unknown register.
+    NotSupported,
+    /// The command has raised an exception.
+    Exception(Exception),
+    /// The hart is not in the state that the command requires.
+    HaltResume,
+}
+
+type Result<T> = std::result::Result<T, CommandError>;
+
+/// An in-process debug module that halts, resumes and inspects a hart through debug mode.
+pub struct DebugModule<'a> {
+    cpu: &'a mut Cpu,
+}
+
+impl<'a> DebugModule<'a> {
+    pub fn new(cpu: &'a mut Cpu) -> Self {
+        Self { cpu }
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.cpu.is_debug_mode()
+    }
+
+    /// Returns the reason why the hart entered debug mode in the encoding of dcsr.cause.
+    pub fn cause(&self) -> Option<u64> {
+        self.is_halted()
+            .then(|| self.cpu.csr.read_field(DCSR, &DCSR_CAUSE))
+    }
+
+    /// Halts the hart before its next instruction.
+    pub fn halt(&mut self) {
+        self.cpu.halt();
+    }
+
+    pub fn resume(&mut self) -> Result<()> {
+        self.require_halted()?;
+        self.cpu.resume();
+        Ok(())
+    }
+
+    /// Executes a single instruction and halts again.
+    pub fn step(&mut self) -> Result<Step> {
+        self.require_halted()?;
+        self.cpu.csr.write_field(DCSR, &DCSR_STEP, 1);
+        self.cpu.resume();
+        let step = self.cpu.step(false);
+        self.cpu.csr.write_field(DCSR, &DCSR_STEP, 0);
+        Ok(step)
+    }
+
+    /// Returns the pc the hart resumes from, i.e. dpc.
+    pub fn pc(&self) -> Result<u64> {
+        self.require_halted()?;
+        Ok(self.cpu.pc())
+    }
+
+    pub fn set_pc(&mut self, address: u64) -> Result<()> {
+        self.require_halted()?;
+        self.cpu.set_pc(address);
+        Ok(())
+    }
+
+    pub fn read_register(&self, register: usize) -> Result<u64> {
+        self.require_halted()?;
+        require_register(register)?;
+        Ok(self.cpu.x.readu(register))
+    }
+
+    pub fn write_register(&mut self, register: usize, value: u64) -> Result<()> {
+        self.require_halted()?;
+        require_register(register)?;
+        self.cpu.x.writeu(register, value);
+        Ok(())
+    }
+
+    pub fn read_fregister(&self, register: usize) -> Result<u32> {
+        self.require_halted()?;
+        require_register(register)?;
+        Ok(self.cpu.f.reads(register))
+    }
+
+    pub fn write_fregister(&mut self, register: usize, value: u32) -> Result<()> {
+        self.require_halted()?;
+        require_register(register)?;
+        self.cpu.f.writes(register, value);
+        Ok(())
+    }
+
+    pub fn read_csr(&self, address: u64) -> Result<u64> {
+        self.require_halted()?;
+        if !self.cpu.csr.contains(address) {
+            return Err(CommandError::NotSupported);
+        }
+        Ok(self.cpu.csr.read(address))
+    }
+
+    /// Writes a CSR as CSRRW does, so that only the legal values are written.
+    pub fn write_csr(&mut self, address: u64, value: u64) -> Result<()> {
+        self.require_halted()?;
+        if !self.cpu.csr.contains(address) {
+            return Err(CommandError::NotSupported);
+        }
+        self.cpu.csr.csrrw(address, value);
+        Ok(())
+    }
+
+    /// Reads 1, 2, 4 or 8 bytes of memory.
+    pub fn read_memory(&self, address: u64, size: u64) -> Result<u64> {
+        self.require_halted()?;
+        self.require_mapped(address, size, Exception::LoadAccessFault)?;
+        let bus = &self.cpu.bus;
+        Ok(match size {
+            1 => bus.load8(address) as u64,
+            2 => bus.load16(address) as u64,
+            4 => bus.load32(address) as u64,
+            _ => bus.load64(address),
+        })
+    }
+
+    /// Writes 1, 2, 4 or 8 bytes of memory.
+    pub fn write_memory(&mut self, address: u64, size: u64, value: u64) -> Result<()> {
+        self.require_halted()?;
+        self.require_mapped(address, size, Exception::StoreAccessFault)?;
+        let bus = &mut self.cpu.bus;
+        match size {
+            1 => bus.store8(address, value as u8),
+            2 => bus.store16(address, value as u16),
+            4 => bus.store32(address, value as u32),
+            _ => bus.store64(address, value),
+        }
+        Ok(())
+    }
+
+    /// Executes an instruction as if it were in the program buffer. DRET resumes the hart.
+    pub fn execute(&mut self, instruction: u32) -> Result<()> {
+        self.require_halted()?;
+        match self.cpu.execute_in_debug_mode(instruction) {
+            Ok(()) => Ok(()),
+            Err(Cause::Exception(exception)) => Err(CommandError::Exception(exception)),
+            Err(_) => Err(CommandError::NotSupported),
+        }
+    }
+
+    fn require_halted(&self) -> Result<()> {
+        if self.is_halted() {
+            Ok(())
+        } else {
+            Err(CommandError::HaltResume)
+        }
+    }
+
+    fn require_mapped(&self, address: u64, size: u64, fault: Exception) -> Result<()> {
+        if !matches!(size, 1 | 2 | 4 | 8) {
+            return Err(CommandError::NotSupported);
+        }
+        let end = address
+            .checked_add(size)
+            .ok_or(CommandError::Exception(fault))?;
+        if !(address..end).all(|a| self.cpu.bus.is_mapped(a)) {
+            return Err(CommandError::Exception(fault));
+        }
+        Ok(())
+    }
+}
+
+fn require_register(register: usize) -> Result<()> {
+    if register < 32 {
+        Ok(())
+    } else {
+        Err(CommandError::NotSupported)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        emulator::bus::memory::MEMORY_BASE_ADDRESS,
+        isa::csr::{debug::DEBUG_CAUSE_HALTREQ, machine_level::DPC},
+    };
+
+    // addi a0, a0, 1
+    const ADDI: u32 = 0x0015_0513;
+    // csrr a1, dpc
+    const CSRR_DPC: u32 = 0x7b10_25f3;
+    const DRET: u32 = 0x7b20_0073;
+
+    #[test]
+    fn halt_inspect_resume_ok() {
+        let mut cpu = Cpu::default();
+        for i in 0..4 {
+            cpu.bus.store32(MEMORY_BASE_ADDRESS + i * 4, ADDI);
+        }
+        let mut module = DebugModule::new(&mut cpu);
+        assert_eq!(module.read_register(10), Err(CommandError::HaltResume));
+        module.halt();
+        assert!(module.is_halted());
+        assert_eq!(module.cause(), Some(DEBUG_CAUSE_HALTREQ));
+        assert_eq!(module.pc(), Ok(MEMORY_BASE_ADDRESS));
+
+        // inspect and modify the hart
+        module.write_register(10, 41).unwrap();
+        assert_eq!(module.read_register(32), Err(CommandError::NotSupported));
+        assert_eq!(module.read_csr(DPC), Ok(MEMORY_BASE_ADDRESS));
+        module
+            .write_memory(MEMORY_BASE_ADDRESS + 0x100, 4, 0xdead)
+            .unwrap();
+        assert_eq!(
+            module.read_memory(MEMORY_BASE_ADDRESS + 0x100, 4),
+            Ok(0xdead)
+        );
+        assert_eq!(
+            module.read_memory(0, 8),
+            Err(CommandError::Exception(Exception::LoadAccessFault))
+        );
+        assert_eq!(
+            module.write_memory(u64::MAX - 3, 8, 0),
+            Err(CommandError::Exception(Exception::StoreAccessFault))
+        );
+
+        // the program buffer runs in debug mode without moving the pc
+        module.execute(CSRR_DPC).unwrap();
+        assert_eq!(module.read_register(11), Ok(MEMORY_BASE_ADDRESS));
+        assert_eq!(module.pc(), Ok(MEMORY_BASE_ADDRESS));
+
+        assert_eq!(module.step(), Ok(Step::Retired));
+        assert_eq!(module.read_register(10), Ok(42));
+        assert_eq!(module.pc(), Ok(MEMORY_BASE_ADDRESS + 4));
+
+        module.set_pc(MEMORY_BASE_ADDRESS + 8).unwrap();
+        module.execute(DRET).unwrap();
+        assert!(!module.is_halted());
+        assert_eq!(module.resume(), Err(CommandError::HaltResume));
+        assert_eq!(cpu.step(false), Step::Retired);
+        assert_eq!(cpu.x().readu(10), 43);
+        assert_eq!(cpu.pc(), MEMORY_BASE_ADDRESS + 12);
+    }
+}
@@ -0,0 +1,301 @@
+use std::io::{self, Error, ErrorKind};
+
+// The fields of the ELF header that the loader checks.
+const MAGIC: &[u8] = b"\x7fELF";
+const CLASS_64: u8 = 2;
+const DATA_LITTLE_ENDIAN: u8 = 1;
+const TYPE_EXECUTABLE: u16 = 2;
+const MACHINE_RISCV: u16 = 243;
+const HEADER_SIZE: usize = 64;
+// The type of a program header that maps a segment into memory.
+const PT_LOAD: u32 = 1;
+// The type of the section that holds the symbol table, and the type of a function symbol.
+const SHT_SYMTAB: u32 = 2;
+const STT_FUNC: u8 = 2;
+const SYMBOL_SIZE: u64 = 24;
+
+/// A segment that the program header maps into memory. The bytes past the data up to the size
+/// in memory are zeros.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Segment {
+    pub address: u64,
+    pub data: Vec<u8>,
+    pub size: u64,
+}
+
+/// A function that the symbol table names.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Symbol {
+    pub name: String,
+    pub address: u64,
+    pub size: u64,
+}
+
+/// A statically linked RV64 executable in the little-endian ELF format.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Elf {
+    pub entry: u64,
+    pub segments: Vec<Segment>,
+    /// The address of the program headers in memory, if a segment maps them.
+    pub phdr: Option<u64>,
+    pub phent: u16,
+    pub phnum: u16,
+}
+
+impl Elf {
+    pub fn parse(bytes: &[u8]) -> io::Result<Self> {
+        let file = Bytes(bytes);
+        if bytes.len() < HEADER_SIZE || !bytes.starts_with(MAGIC) {
+            return Err(invalid("magic"));
+        }
+        if bytes[4] != CLASS_64 || bytes[5] != DATA_LITTLE_ENDIAN {
+            return Err(invalid("not a little-endian 64-bit ELF"));
+        }
+        if file.u16_at(16)? != TYPE_EXECUTABLE {
+            return Err(invalid("not a static executable"));
+        }
+        if file.u16_at(18)? != MACHINE_RISCV {
+            return Err(invalid("not a RISC-V ELF"));
+        }
+        let entry = file.u64_at(24)?;
+        let phoff = file.u64_at(32)?;
+        let phent = file.u16_at(54)?;
+        let phnum = file.u16_at(56)?;
+        let mut segments = vec![];
+        let mut phdr = None;
+        for i in 0..phnum as u64 {
+            let header = file.offset(phoff, i * phent as u64)?;
+            if file.u32_at(header)? != PT_LOAD {
+                continue;
+            }
+            let offset = file.u64_at(header + 8)?;
+            let address = file.u64_at(header + 16)?;
+            let file_size = file.u64_at(header + 32)?;
+            let size = file.u64_at(header + 40)?.max(file_size);
+            let data = file.get(offset, file_size)?;
+            if address.checked_add(size).is_none() {
+                return Err(invalid("segment"));
+            }
+            if (offset..offset + file_size).contains(&phoff) {
+                phdr = Some(address.wrapping_add(phoff - offset));
+            }
+            segments.push(Segment {
+                address,
+                data: data.to_vec(),
+                size,
+            });
+        }
+        Ok(Self {
+            entry,
+            segments,
+            phdr,
+            phent,
+            phnum,
+        })
+    }
+
+    /// Returns the functions of the symbol table of the file, which are none when the
+    /// executable has been stripped.
+    pub fn symbols(bytes: &[u8]) -> io::Result<Vec<Symbol>> {
+        let file = Bytes(bytes);
+        let shoff = file.u64_at(40)?;
+        let shent = file.u16_at(58)? as u64;
+        let shnum = file.u16_at(60)? as u64;
+        let mut symbols = vec![];
+        for i in 0..shnum {
+            let header = file.offset(shoff, i * shent)?;
+            if file.u32_at(header + 4)? != SHT_SYMTAB {
+                continue;
+            }
+            let offset = file.u64_at(header + 24)?;
+            let size = file.u64_at(header + 32)?;
+            // the linked section holds the names of the symbols
+            let strings = file.offset(shoff, file.u32_at(header + 40)? as u64 * shent)?;
+            let strings = file.u64_at(strings + 24)?;
+            for symbol in file.get(offset, size)?.chunks_exact(SYMBOL_SIZE as usize) {
+                let symbol = Bytes(symbol);
+                if symbol.0[4] & 0xf != STT_FUNC {
+                    continue;
+                }
+                let name = file.offset(strings, symbol.u32_at(0)? as u64)?;
+                let name = bytes[name as usize..]
+                    .split(|c| *c == 0)
+                    .next()
+                    .unwrap_or_default();
+                symbols.push(Symbol {
+                    name: String::from_utf8_lossy(name).into_owned(),
+                    address: symbol.u64_at(8)?,
+                    size: symbol.u64_at(16)?,
+                });
+            }
+        }
+        Ok(symbols)
+    }
+
+    /// Returns the address past the last segment.
+    pub fn end(&self) -> u64 {
+        self.segments
+            .iter()
+            .map(|segment| segment.address + segment.size)
+            .max()
+            .unwrap_or_default()
+    }
+}
+
+fn invalid(what: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, format!("invalid: {}", what))
+}
+
+/// The bytes of the file, read at offsets that may point past its end.
+struct Bytes<'a>(&'a [u8]);
+
+impl Bytes<'_> {
+    fn get(&self, offset: u64, size: u64) -> io::Result<&[u8]> {
+        let end = offset.checked_add(size).ok_or_else(|| invalid("offset"))?;
+        usize::try_from(offset)
+            .ok()
+            .zip(usize::try_from(end).ok())
+            .and_then(|(offset, end)| self.0.get(offset..end))
+            .ok_or_else(|| invalid("truncated"))
+    }
+
+    /// Returns the offset past the base, which has to be in the file.
+    fn offset(&self, base: u64, offset: u64) -> io::Result<u64> {
+        base.checked_add(offset)
+            .filter(|offset| *offset < self.0.len() as u64)
+            .ok_or_else(|| invalid("truncated"))
+    }
+
+    fn u16_at(&self, offset: u64) -> io::Result<u16> {
+        Ok(u16::from_le_bytes(self.get(offset, 2)?.try_into().unwrap()))
+    }
+
+    fn u32_at(&self, offset: u64) -> io::Result<u32> {
+        Ok(u32::from_le_bytes(self.get(offset, 4)?.try_into().unwrap()))
+    }
+
+    fn u64_at(&self, offset: u64) -> io::Result<u64> {
+        Ok(u64::from_le_bytes(self.get(offset, 8)?.try_into().unwrap()))
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Builds an executable with a single segment that holds the headers followed by the code.
+    pub(crate) fn executable(address: u64, code: &[u8]) -> Vec<u8> {
+        let headers = HEADER_SIZE + 56;
+        let mut elf = vec![0; headers];
+        elf[..4].copy_from_slice(MAGIC);
+        elf[4] = CLASS_64;
+        elf[5] = DATA_LITTLE_ENDIAN;
+        elf[6] = 1;
+        elf[16..18].copy_from_slice(&TYPE_EXECUTABLE.to_le_bytes());
+        elf[18..20].copy_from_slice(&MACHINE_RISCV.to_le_bytes());
+        elf[24..32].copy_from_slice(&(address + headers as u64).to_le_bytes());
+        elf[32..40].copy_from_slice(&(HEADER_SIZE as u64).to_le_bytes());
+        elf[52..54].copy_from_slice(&(HEADER_SIZE as u16).to_le_bytes());
+        elf[54..56].copy_from_slice(&56u16.to_le_bytes());
+        elf[56..58].copy_from_slice(&1u16.to_le_bytes());
+        let header = &mut elf[HEADER_SIZE..];
+        header[..4].copy_from_slice(&PT_LOAD.to_le_bytes());
+        header[16..24].copy_from_slice(&address.to_le_bytes());
+        let size = (headers + code.len()) as u64;
+        header[32..40].copy_from_slice(&size.to_le_bytes());
+        header[40..48].copy_from_slice(&(size + 0x100).to_le_bytes());
+        elf.extend_from_slice(code);
+        elf
+    }
+
+    /// Appends a symbol table of the functions with its string table and section headers.
+    pub(crate) fn with_symbols(mut elf: Vec<u8>, functions: &[(&str, u64, u64)]) -> Vec<u8> {
+        let strtab = elf.len() as u64;
+        let mut names = vec![0];
+        let mut symbols = vec![0; SYMBOL_SIZE as usize];
+        for (name, address, size) in functions {
+            let mut symbol = vec![0; SYMBOL_SIZE as usize];
+            symbol[..4].copy_from_slice(&(names.len() as u32).to_le_bytes());
+            symbol[4] = 0x10 | STT_FUNC;
+            symbol[8..16].copy_from_slice(&address.to_le_bytes());
+            symbol[16..24].copy_from_slice(&size.to_le_bytes());
+            symbols.extend_from_slice(&symbol);
+            names.extend_from_slice(name.as_bytes());
+            names.push(0);
+        }
+        elf.extend_from_slice(&names);
+        let symtab = elf.len() as u64;
+        elf.extend_from_slice(&symbols);
+        let shoff = elf.len() as u64;
+        // the null section, the string table and the symbol table
+        elf.extend_from_slice(&[0; 64]);
+        let mut header = vec![0; 64];
+        header[4..8].copy_from_slice(&3u32.to_le_bytes());
+        header[24..32].copy_from_slice(&strtab.to_le_bytes());
+        header[32..40].copy_from_slice(&(names.len() as u64).to_le_bytes());
+        elf.extend_from_slice(&header);
+        let mut header = vec![0; 64];
+        header[4..8].copy_from_slice(&SHT_SYMTAB.to_le_bytes());
+        header[24..32].copy_from_slice(&symtab.to_le_bytes());
+        header[32..40].copy_from_slice(&(symbols.len() as u64).to_le_bytes());
+        header[40..44].copy_from_slice(&1u32.to_le_bytes());
+        elf.extend_from_slice(&header);
+        elf[40..48].copy_from_slice(&shoff.to_le_bytes());
+        elf[58..60].copy_from_slice(&64u16.to_le_bytes());
+        elf[60..62].copy_from_slice(&3u16.to_le_bytes());
+        elf
+    }
+
+    #[test]
+    fn parse_ok() {
+        let elf = Elf::parse(&executable(0x8000_0000, &[0x13, 0, 0, 0])).unwrap();
+        assert_eq!(elf.entry, 0x8000_0078);
+        assert_eq!(elf.phdr, Some(0x8000_0040));
+        assert_eq!((elf.phent, elf.phnum), (56, 1));
+        assert_eq!(elf.segments.len(), 1);
+        assert_eq!(elf.segments[0].data.len(), 0x7c);
+        assert_eq!(elf.end(), 0x8000_017c);
+        assert_eq!(Elf::symbols(&executable(0x8000_0000, &[])).unwrap(), vec![]);
+
+        let elf = with_symbols(
+            executable(0x8000_0000, &[0x13, 0, 0, 0]),
+            &[("main", 0x8000_0078, 4)],
+        );
+        assert_eq!(
+            Elf::symbols(&elf).unwrap(),
+            vec![Symbol {
+                name: "main".to_string(),
+                address: 0x8000_0078,
+                size: 4,
+            }]
+        );
+
+        assert!(Elf::parse(b"\x7fELF").is_err());
+        let mut machine = executable(0x8000_0000, &[]);
+        machine[18] = 62;
+        assert!(Elf::parse(&machine).is_err());
+    }
+
+    #[test]
+    fn malformed_ok() {
+        let elf = with_symbols(executable(0x8000_0000, &[0x13, 0, 0, 0]), &[("main", 0, 4)]);
+        // a file truncated before the end of the segment or of the section headers, up to the
+        // link of the last one, is rejected
+        for length in 0..elf.len() - 20 {
+            assert!(length >= 0x7c || Elf::parse(&elf[..length]).is_err());
+            assert!(Elf::symbols(&elf[..length]).is_err());
+        }
+        // an offset, a size or an address at the end of the address space does not overflow
+        for offset in 0..elf.len() - 8 {
+            for value in [u64::MAX, u64::MAX - 0x40, 1 << 63] {
+                let mut elf = elf.clone();
+                elf[offset..offset + 8].copy_from_slice(&value.to_le_bytes());
+                let _ = Elf::parse(&elf);
+                let _ = Elf::symbols(&elf);
+            }
+        }
+        let mut segment = elf.clone();
+        segment[HEADER_SIZE + 16..HEADER_SIZE + 24].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert!(Elf::parse(&segment).is_err());
+    }
+}
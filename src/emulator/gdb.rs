@@ -0,0 +1,702 @@
+use crate::{
+    emulator::{
+        bus::{Access, MemoryAccess},
+        cpu::{csr::Csr, Cpu, Step},
+    },
+    isa::{
+        csr::{
+            csrname,
+            user_level::{FCSR, FFLAGS, FRM},
+        },
+        register::{fname, xname},
+    },
+};
+use std::{
+    collections::BTreeMap,
+    io::{self, ErrorKind, Read, Write},
+    net::TcpStream,
+};
+
+// Register numbers used by GDB for RISC-V.
+const REGISTER_PC: usize = 32;
+const REGISTER_F0: usize = 33;
+const REGISTER_CSR0: usize = 65;
+
+// Number of instructions executed between polls for an interrupt request.
+const INTERRUPT_POLL_INTERVAL: u64 = 4096;
+
+/// A byte stream to a debugger.
+pub trait Connection: Read + Write {
+    /// Returns true when the debugger has requested to stop the target (Ctrl-C). Must not block.
+    fn interrupted(&mut self) -> io::Result<bool>;
+}
+
+impl Connection for TcpStream {
+    fn interrupted(&mut self) -> io::Result<bool> {
+        self.set_nonblocking(true)?;
+        let mut byte = [0; 1];
+        let result = self.read(&mut byte);
+        self.set_nonblocking(false)?;
+        match result {
+            Ok(0) => Err(ErrorKind::UnexpectedEof.into()),
+            Ok(_) => Ok(byte[0] == 0x03),
+            Err(e) if e.kind() == ErrorKind::WouldBlock => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Breakpoint {
+    Software,
+    Hardware,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum WatchKind {
+    Write,
+    Read,
+    Access,
+}
+
+// The range of a watchpoint does not wrap around the address space.
+struct Watchpoint {
+    kind: WatchKind,
+    address: u64,
+    length: u64,
+}
+
+impl Watchpoint {
+    fn is_hit_by(&self, access: &MemoryAccess) -> bool {
+        let kind = matches!(
+            (self.kind, access.access),
+            (WatchKind::Write, Access::Store)
+                | (WatchKind::Read, Access::Load)
+                | (WatchKind::Access, _)
+        );
+        kind && access.address < self.address + self.length
+            && self.address < access.address.saturating_add(access.size)
+    }
+}
+
+enum Stop {
+    Step,
+    Breakpoint(Breakpoint),
+    Watchpoint(WatchKind, u64),
+    Interrupt,
+    DebugMode,
+    Halt,
+}
+
+/// A server of the GDB remote serial protocol that controls a hart.
+pub struct GdbStub<'a, C: Connection> {
+    cpu: &'a mut Cpu,
+    connection: C,
+    breakpoints: BTreeMap<u64, Breakpoint>,
+    watchpoints: Vec<Watchpoint>,
+}
+
+impl<'a, C: Connection> GdbStub<'a, C> {
+    pub fn new(cpu: &'a mut Cpu, connection: C) -> Self {
+        Self {
+            cpu,
+            connection,
+            breakpoints: BTreeMap::new(),
+            watchpoints: vec![],
+        }
+    }
+
+    /// Serves requests until the debugger kills the target, detaches or disconnects.
+    pub fn serve(&mut self) -> io::Result<()> {
+        while let Some(packet) = self.receive()? {
+            match self.handle(&packet)? {
+                Some(reply) => self.send(&reply)?,
+                None => break,
+            }
+        }
+        Ok(())
+    }
+
+    fn receive(&mut self) -> io::Result<Option<String>> {
+        loop {
+            // skip acknowledgements and interrupt requests while the target is stopped
+            match self.read_byte()? {
+                Some(b'$') => {}
+                Some(_) => continue,
+                None => return Ok(None),
+            }
+            let mut data = vec![];
+            loop {
+                match self.read_byte()? {
+                    Some(b'#') => break,
+                    Some(byte) => data.push(byte),
+                    None => return Ok(None),
+                }
+            }
+            let mut checksum = [0; 2];
+            self.connection.read_exact(&mut checksum)?;
+            let expected = std::str::from_utf8(&checksum)
+                .ok()
+                .and_then(|c| u8::from_str_radix(c, 16).ok());
+            if expected == Some(checksum_of(&data)) {
+                self.connection.write_all(b"+")?;
+                return Ok(Some(String::from_utf8_lossy(&data).into_owned()));
+            }
+            self.connection.write_all(b"-")?;
+        }
+    }
+
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        let mut byte = [0; 1];
+        match self.connection.read(&mut byte)? {
+            0 => Ok(None),
+            _ => Ok(Some(byte[0])),
+        }
+    }
+
+    fn send(&mut self, data: &str) -> io::Result<()> {
+        let packet = format!("${}#{:02x}", data, checksum_of(data.as_bytes()));
+        self.connection.write_all(packet.as_bytes())?;
+        self.connection.flush()
+    }
+
+    /// Handles a packet and returns the reply, or None when the session is over.
+    fn handle(&mut self, packet: &str) -> io::Result<Option<String>> {
+        let (command, arguments) = packet.split_at(packet.len().min(1));
+        let reply = match command {
+            "?" => "S05".to_string(),
+            "q" => self.query(arguments),
+            "H" => "OK".to_string(),
+            "v" => match arguments {
+                "Cont?" => "vCont;c;s".to_string(),
+                _ if arguments.starts_with("Cont;c") => self.resume(false)?,
+                _ if arguments.starts_with("Cont;s") => self.resume(true)?,
+                _ => String::new(),
+            },
+            "g" => self.read_registers(),
+            "G" => self.write_registers(arguments),
+            "p" => self.read_register(arguments),
+            "P" => self.write_register(arguments),
+            "m" => self.read_memory(arguments),
+            "M" => self.write_memory(arguments),
+            "c" | "s" => {
+                if let Some(address) = parse_hex(arguments) {
+                    self.cpu.set_pc(address);
+                }
+                self.resume(command == "s")?
+            }
+            "Z" => self.insert_point(arguments),
+            "z" => self.remove_point(arguments),
+            "k" => return Ok(None),
+            "D" => {
+                self.send("OK")?;
+                return Ok(None);
+            }
+            _ => String::new(),
+        };
+        Ok(Some(reply))
+    }
+
+    fn query(&self, query: &str) -> String {
+        if query.starts_with("Supported") {
+            return "PacketSize=4000;qXfer:features:read+;swbreak+;hwbreak+".to_string();
+        }
+        if let Some(range) = query.strip_prefix("Xfer:features:read:target.xml:") {
+            let description = self.target_description();
+            return match range.split_once(',').and_then(|(offset, length)| {
+                Some((parse_hex(offset)? as usize, parse_hex(length)? as usize))
+            }) {
+                Some((offset, length)) if offset < description.len() => {
+                    let end = description.len().min(offset + length);
+                    let prefix = if end == description.len() { "l" } else { "m" };
+                    format!("{}{}", prefix, &description[offset..end])
+                }
+                Some(_) => "l".to_string(),
+                None => "E01".to_string(),
+            };
+        }
+        match query {
+            "Attached" => "1".to_string(),
+            "C" => "QC1".to_string(),
+            "fThreadInfo" => "m1".to_string(),
+            "sThreadInfo" => "l".to_string(),
+            _ => String::new(),
+        }
+    }
+
+    fn target_description(&self) -> String {
+        let mut cpu = String::new();
+        for i in 0..32 {
+            cpu += &format!(
+                "<reg name=\"{}\" bitsize=\"64\" type=\"int\" regnum=\"{}\"/>",
+                xname(i),
+                i
+            );
+        }
+        cpu += &format!(
+            "<reg name=\"pc\" bitsize=\"64\" type=\"code_ptr\" regnum=\"{}\"/>",
+            REGISTER_PC
+        );
+        let mut fpu = String::new();
+        for i in 0..32 {
+            fpu += &format!(
+                "<reg name=\"{}\" bitsize=\"32\" type=\"ieee_single\" regnum=\"{}\"/>",
+                fname(i),
+                REGISTER_F0 + i
+            );
+        }
+        let mut csr = String::new();
+        for address in self.cpu.csr.addresses() {
+            let reg = format!(
+                "<reg name=\"{}\" bitsize=\"{}\" type=\"int\" regnum=\"{}\"/>",
+                csrname(address),
+                csr_bitsize(address),
+                REGISTER_CSR0 + address as usize
+            );
+            match address {
+                FFLAGS | FRM | FCSR => fpu += &reg,
+                _ => csr += &reg,
+            }
+        }
+        format!(
+            "<?xml version=\"1.0\"?><!DOCTYPE target SYSTEM \"gdb-target.dtd\">\
+            <target version=\"1.0\"><architecture>riscv:rv64</architecture>\
+            <feature name=\"org.gnu.gdb.riscv.cpu\">{}</feature>\
+            <feature name=\"org.gnu.gdb.riscv.fpu\">{}</feature>\
+            <feature name=\"org.gnu.gdb.riscv.csr\">{}</feature></target>",
+            cpu, fpu, csr
+        )
+    }
+
+    /// Returns the size in bytes of a register, or None if the register does not exist.
+    fn register_size(&self, register: usize) -> Option<usize> {
+        match register {
+            0..=REGISTER_PC => Some(8),
+            REGISTER_F0..=64 => Some(4),
+            _ => {
+                let address = (register - REGISTER_CSR0) as u64;
+                (register >= REGISTER_CSR0 && self.cpu.csr.contains(address))
+                    .then(|| csr_bitsize(address) / 8)
+            }
+        }
+    }
+
+    fn register(&self, register: usize) -> u64 {
+        match register {
+            0..=31 => self.cpu.x.readu(register),
+            REGISTER_PC => self.cpu.pc.read(),
+            REGISTER_F0..=64 => self.cpu.f.reads(register - REGISTER_F0) as u64,
+            _ => self.cpu.csr.read((register - REGISTER_CSR0) as u64),
+        }
+    }
+
+    fn set_register(&mut self, register: usize, value: u64) {
+        match register {
+            0..=31 => self.cpu.x.writeu(register, value),
+            REGISTER_PC => self.cpu.set_pc(value),
+            REGISTER_F0..=64 => self.cpu.f.writes(register - REGISTER_F0, value as u32),
+            _ => self.cpu.csr.write((register - REGISTER_CSR0) as u64, value),
+        }
+    }
+
+    fn read_registers(&self) -> String {
+        (0..=REGISTER_PC)
+            .map(|register| encode(self.register(register), 8))
+            .collect()
+    }
+
+    fn write_registers(&mut self, values: &str) -> String {
+        if values.len() < (REGISTER_PC + 1) * 16 {
+            return "E01".to_string();
+        }
+        for register in 0..=REGISTER_PC {
+            match decode(&values[register * 16..(register + 1) * 16]) {
+                Some(value) => self.set_register(register, value),
+                None => return "E01".to_string(),
+            }
+        }
+        "OK".to_string()
+    }
+
+    fn read_register(&self, register: &str) -> String {
+        match parse_hex(register).and_then(|r| Some((r as usize, self.register_size(r as usize)?)))
+        {
+            Some((register, size)) => encode(self.register(register), size),
+            None => "E01".to_string(),
+        }
+    }
+
+    fn write_register(&mut self, arguments: &str) -> String {
+        let parsed = arguments.split_once('=').and_then(|(register, value)| {
+            let register = parse_hex(register)? as usize;
+            self.register_size(register)?;
+            Some((register, decode(value)?))
+        });
+        match parsed {
+            Some((register, value)) => {
+                self.set_register(register, value);
+                "OK".to_string()
+            }
+            None => "E01".to_string(),
+        }
+    }
+
+    fn read_memory(&self, arguments: &str) -> String {
+        let Some((address, length)) = parse_range(arguments) else {
+            return "E01".to_string();
+        };
+        let Some(end) = address.checked_add(length) else {
+            return "E14".to_string();
+        };
+        let addresses = address..end;
+        if !addresses.clone().all(|a| self.cpu.bus.is_mapped(a)) {
+            return "E14".to_string();
+        }
+        addresses
+            .map(|a| format!("{:02x}", self.cpu.bus.load8(a)))
+            .collect()
+    }
+
+    fn write_memory(&mut self, arguments: &str) -> String {
+        let parsed = arguments.split_once(':').and_then(|(range, data)| {
+            let (address, length) = parse_range(range)?;
+            let bytes = (0..data.len() / 2)
+                .map(|i| u8::from_str_radix(data.get(i * 2..i * 2 + 2)?, 16).ok())
+                .collect::<Option<Vec<u8>>>()?;
+            (bytes.len() as u64 == length).then_some((address, bytes))
+        });
+        let Some((address, bytes)) = parsed else {
+            return "E01".to_string();
+        };
+        let Some(end) = address.checked_add(bytes.len() as u64) else {
+            return "E14".to_string();
+        };
+        let addresses = address..end;
+        if !addresses.clone().all(|a| self.cpu.bus.is_mapped(a)) {
+            return "E14".to_string();
+        }
+        for (a, byte) in addresses.zip(bytes) {
+            self.cpu.bus.store8(a, byte);
+        }
+        "OK".to_string()
+    }
+
+    fn insert_point(&mut self, arguments: &str) -> String {
+        let Some((kind, address, length)) = parse_point(arguments) else {
+            return "E01".to_string();
+        };
+        match kind {
+            0 => self.breakpoints.insert(address, Breakpoint::Software),
+            1 => self.breakpoints.insert(address, Breakpoint::Hardware),
+            _ => {
+                let kind = match kind {
+                    2 => WatchKind::Write,
+                    3 => WatchKind::Read,
+                    4 => WatchKind::Access,
+                    _ => return String::new(),
+                };
+                if address.checked_add(length).is_none() {
+                    return "E01".to_string();
+                }
+                self.watchpoints.push(Watchpoint {
+                    kind,
+                    address,
+                    length,
+                });
+                None
+            }
+        };
+        "OK".to_string()
+    }
+
+    fn remove_point(&mut self, arguments: &str) -> String {
+        let Some((kind, address, length)) = parse_point(arguments) else {
+            return "E01".to_string();
+        };
+        match kind {
+            0 | 1 => {
+                self.breakpoints.remove(&address);
+            }
+            2..=4 => {
+                let kind = [WatchKind::Write, WatchKind::Read, WatchKind::Access][kind - 2];
+                self.watchpoints
+                    .retain(|w| w.kind != kind || w.address != address || w.length != length);
+            }
+            _ => return String::new(),
+        }
+        "OK".to_string()
+    }
+
+    /// Resumes the hart and returns the stop reply.
+    fn resume(&mut self, step: bool) -> io::Result<String> {
+        self.cpu.resume();
+        self.cpu.bus.set_observing(!self.watchpoints.is_empty());
+        let stop = self.run(step);
+        self.cpu.bus.set_observing(false);
+        Ok(match stop? {
+            Stop::Step | Stop::DebugMode => "S05".to_string(),
+            Stop::Breakpoint(Breakpoint::Software) => "T05swbreak:;".to_string(),
+            Stop::Breakpoint(Breakpoint::Hardware) => "T05hwbreak:;".to_string(),
+            Stop::Watchpoint(kind, address) => {
+                let name = match kind {
+                    WatchKind::Write => "watch",
+                    WatchKind::Read => "rwatch",
+                    WatchKind::Access => "awatch",
+                };
+                format!("T05{}:{:x};", name, address)
+            }
+            Stop::Interrupt => "S02".to_string(),
+            Stop::Halt => "W00".to_string(),
+        })
+    }
+
+    fn run(&mut self, step: bool) -> io::Result<Stop> {
+        // a breakpoint at the current pc is stepped over since the hart stops after a step
+        let mut steps: u64 = 0;
+        loop {
+            steps += 1;
+            match self.cpu.step(false) {
+                Step::Halted => return Ok(Stop::Halt),
+                Step::Breakpoint => return Ok(Stop::Breakpoint(Breakpoint::Software)),
+                Step::Debug => return Ok(Stop::DebugMode),
+                _ => {}
+            }
+            let accesses = self.cpu.bus.take_accesses();
+            for watchpoint in &self.watchpoints {
+                if let Some(access) = accesses.iter().find(|a| watchpoint.is_hit_by(a)) {
+                    return Ok(Stop::Watchpoint(watchpoint.kind, access.address));
+                }
+            }
+            if step {
+                return Ok(Stop::Step);
+            }
+            if let Some(breakpoint) = self.breakpoints.get(&self.cpu.pc.read()) {
+                return Ok(Stop::Breakpoint(*breakpoint));
+            }
+            if steps.is_multiple_of(INTERRUPT_POLL_INTERVAL) && self.connection.interrupted()? {
+                return Ok(Stop::Interrupt);
+            }
+        }
+    }
+}
+
+fn checksum_of(data: &[u8]) -> u8 {
+    data.iter().fold(0, |acc, byte| acc.wrapping_add(*byte))
+}
+
+fn csr_bitsize(address: u64) -> usize {
+    match address {
+        FFLAGS | FRM | FCSR => 32,
+        _ => 64,
+    }
+}
+
+fn parse_hex(value: &str) -> Option<u64> {
+    u64::from_str_radix(value, 16).ok()
+}
+
+fn parse_range(arguments: &str) -> Option<(u64, u64)> {
+    let (address, length) = arguments.split_once(',')?;
+    Some((parse_hex(address)?, parse_hex(length)?))
+}
+
+fn parse_point(arguments: &str) -> Option<(usize, u64, u64)> {
+    let (kind, range) = arguments.split_once(',')?;
+    // ignore conditions and commands of the breakpoint
+    let range = range.split(';').next()?;
+    let (address, length) = parse_range(range)?;
+    Some((kind.parse().ok()?, address, length))
+}
+
+/// Encodes a register value as hex digits in target byte order.
+fn encode(value: u64, size: usize) -> String {
+    value.to_le_bytes()[..size]
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Decodes a register value from hex digits in target byte order.
+fn decode(value: &str) -> Option<u64> {
+    if value.is_empty() || value.len() > 16 || !value.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..value.len() / 2).try_fold(0, |acc, i| {
+        let byte = u8::from_str_radix(value.get(i * 2..i * 2 + 2)?, 16).ok()?;
+        Some(acc | (byte as u64) << (i * 8))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::bus::memory::MEMORY_BASE_ADDRESS;
+    use std::io::Cursor;
+
+    const NOP: u32 = 0x0000_0013;
+    // jal zero, -8
+    const JAL: u32 = 0xff9f_f06f;
+    // sd zero, 0(ra)
+    const SD: u32 = 0x0000_b023;
+
+    struct MockConnection {
+        input: Cursor<Vec<u8>>,
+        output: Vec<u8>,
+    }
+
+    impl Read for MockConnection {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.input.read(buf)
+        }
+    }
+
+    impl Write for MockConnection {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.output.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Connection for MockConnection {
+        fn interrupted(&mut self) -> io::Result<bool> {
+            Ok(false)
+        }
+    }
+
+    /// Sends the packets to a stub and returns the replies.
+    fn session(cpu: &mut Cpu, packets: &[&str]) -> Vec<String> {
+        let input = packets
+            .iter()
+            .map(|p| format!("${}#{:02x}", p, checksum_of(p.as_bytes())))
+            .collect::<String>();
+        let mut stub = GdbStub::new(
+            cpu,
+            MockConnection {
+                input: Cursor::new(input.into_bytes()),
+                output: vec![],
+            },
+        );
+        stub.serve().unwrap();
+        let output = String::from_utf8(stub.connection.output).unwrap();
+        output
+            .split('$')
+            .skip(1)
+            .map(|reply| reply.split('#').next().unwrap().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn packet_framing_ok() {
+        let mut cpu = Cpu::default();
+        let mut stub = GdbStub::new(
+            &mut cpu,
+            MockConnection {
+                input: Cursor::new(b"+$?#00$?#3f".to_vec()),
+                output: vec![],
+            },
+        );
+        stub.serve().unwrap();
+        assert_eq!(stub.connection.output, b"-+$S05#b8");
+    }
+
+    #[test]
+    fn registers_ok() {
+        let mut cpu = Cpu::default();
+        let replies = session(
+            &mut cpu,
+            &[
+                "P1=efcdab8967452301",
+                "p1",
+                "P20=0400008000000000",
+                "P21=0000803f",
+                "p21",
+                "P381=0800000000000000",
+                "p381",
+                "p2000",
+            ],
+        );
+        assert_eq!(
+            replies,
+            [
+                "OK",
+                "efcdab8967452301",
+                "OK",
+                "OK",
+                "0000803f",
+                "OK",
+                "0800000000000000",
+                "E01"
+            ]
+        );
+        assert_eq!(cpu.x.readu(1), 0x0123_4567_89ab_cdef);
+        assert_eq!(cpu.pc.read(), MEMORY_BASE_ADDRESS + 4);
+        assert_eq!(cpu.f.reads(0), 1.0f32.to_bits());
+        let registers = &session(&mut cpu, &["g"])[0];
+        assert_eq!(registers.len(), 33 * 16);
+        assert_eq!(&registers[16..32], "efcdab8967452301");
+    }
+
+    #[test]
+    fn memory_ok() {
+        let mut cpu = Cpu::default();
+        let replies = session(
+            &mut cpu,
+            &[
+                "M80000000,4:13000000",
+                "m80000000,4",
+                "m0,4",
+                "M0,1:00",
+                "mffffffffffffffff,2",
+                "Mffffffffffffffff,2:0000",
+            ],
+        );
+        assert_eq!(replies, ["OK", "13000000", "E14", "E14", "E14", "E14"]);
+        assert_eq!(cpu.bus.load32(MEMORY_BASE_ADDRESS), NOP);
+    }
+
+    #[test]
+    fn breakpoint_ok() {
+        let mut cpu = Cpu::default();
+        for i in 0..3 {
+            cpu.bus.store32(MEMORY_BASE_ADDRESS + i * 4, NOP);
+        }
+        cpu.bus.store32(MEMORY_BASE_ADDRESS + 12, JAL);
+        let replies = session(
+            &mut cpu,
+            &["Z0,80000008,4", "c", "c", "s", "z0,80000008,4", "p20"],
+        );
+        assert_eq!(
+            replies,
+            [
+                "OK",
+                "T05swbreak:;",
+                "T05swbreak:;",
+                "S05",
+                "OK",
+                "0c00008000000000"
+            ]
+        );
+    }
+
+    #[test]
+    fn watchpoint_ok() {
+        let mut cpu = Cpu::default();
+        cpu.bus.store32(MEMORY_BASE_ADDRESS, NOP);
+        cpu.bus.store32(MEMORY_BASE_ADDRESS + 4, SD);
+        cpu.x.writeu(1, MEMORY_BASE_ADDRESS + 0x1004);
+        let replies = session(
+            &mut cpu,
+            &[
+                "Z2,ffffffffffffffff,8",
+                "Z3,80001000,8",
+                "Z2,80001000,8",
+                "c",
+            ],
+        );
+        assert_eq!(replies, ["E01", "OK", "OK", "T05watch:80001004;"]);
+        assert_eq!(cpu.pc.read(), MEMORY_BASE_ADDRESS + 8);
+    }
+}
@@ -0,0 +1,778 @@
+use crate::{
+    emulator::{
+        bus::memory::PAGE_SIZE,
+        cpu::{csr::Csr, Cpu},
+        elf::Elf,
+    },
+    isa::{
+        csr::{
+            machine_level::{MISA, MSTATUS},
+            status::{EXTENSION_STATUS_INITIAL, STATUS_FS},
+        },
+        privileged::mode::PrivilegeMode,
+    },
+};
+use std::collections::BTreeMap;
+use std::fs::{self, File, Metadata, OpenOptions};
+use std::io::{self, Error, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::time::UNIX_EPOCH;
+
+// The system call numbers of RV64 Linux.
+const GETCWD: u64 = 17;
+const IOCTL: u64 = 29;
+const OPENAT: u64 = 56;
+const CLOSE: u64 = 57;
+const LSEEK: u64 = 62;
+const READ: u64 = 63;
+const WRITE: u64 = 64;
+const WRITEV: u64 = 66;
+const READLINKAT: u64 = 78;
+const NEWFSTATAT: u64 = 79;
+const FSTAT: u64 = 80;
+const EXIT: u64 = 93;
+const EXIT_GROUP: u64 = 94;
+const SET_TID_ADDRESS: u64 = 96;
+const SET_ROBUST_LIST: u64 = 99;
+const CLOCK_GETTIME: u64 = 113;
+const RT_SIGACTION: u64 = 134;
+const RT_SIGPROCMASK: u64 = 135;
+const UNAME: u64 = 160;
+const GETPID: u64 = 172;
+const GETPPID: u64 = 173;
+const GETUID: u64 = 174;
+const GETEUID: u64 = 175;
+const GETGID: u64 = 176;
+const GETEGID: u64 = 177;
+const GETTID: u64 = 178;
+const BRK: u64 = 214;
+const MUNMAP: u64 = 215;
+const MMAP: u64 = 222;
+const MPROTECT: u64 = 226;
+const PRLIMIT64: u64 = 261;
+const GETRANDOM: u64 = 278;
+
+// The error numbers that the system calls return negated.
+const ENOENT: i64 = 2;
+const EIO: i64 = 5;
+const EBADF: i64 = 9;
+const ENOMEM: i64 = 12;
+const EFAULT: i64 = 14;
+const EINVAL: i64 = 22;
+const ENOTTY: i64 = 25;
+const ESPIPE: i64 = 29;
+const ERANGE: i64 = 34;
+const ENOSYS: i64 = 38;
+
+// The flags of openat, newfstatat and mmap.
+const AT_FDCWD: i64 = -100;
+const AT_EMPTY_PATH: u64 = 0x1000;
+const O_ACCMODE: u64 = 0o3;
+const O_WRONLY: u64 = 0o1;
+const O_RDWR: u64 = 0o2;
+const O_CREAT: u64 = 0o100;
+const O_EXCL: u64 = 0o200;
+const O_TRUNC: u64 = 0o1000;
+const O_APPEND: u64 = 0o2000;
+const MAP_FIXED: u64 = 0x10;
+const MAP_ANONYMOUS: u64 = 0x20;
+
+// The file types in the mode of stat.
+const S_IFCHR: u32 = 0o020000;
+const S_IFDIR: u32 = 0o040000;
+const S_IFREG: u32 = 0o100000;
+// The size of struct stat.
+const STAT_SIZE: usize = 128;
+
+// The entries of the auxiliary vector.
+const AT_NULL: u64 = 0;
+const AT_PHDR: u64 = 3;
+const AT_PHENT: u64 = 4;
+const AT_PHNUM: u64 = 5;
+const AT_PAGESZ: u64 = 6;
+const AT_BASE: u64 = 7;
+const AT_FLAGS: u64 = 8;
+const AT_ENTRY: u64 = 9;
+const AT_UID: u64 = 11;
+const AT_EUID: u64 = 12;
+const AT_GID: u64 = 13;
+const AT_EGID: u64 = 14;
+const AT_HWCAP: u64 = 16;
+const AT_CLKTCK: u64 = 17;
+const AT_SECURE: u64 = 23;
+const AT_RANDOM: u64 = 25;
+
+// The stack takes the top of the memory and the mappings grow down from below it.
+pub(crate) const STACK_SIZE: u64 = 8 * 1024 * 1024;
+// The longest path that the guest passes.
+const PATH_MAX: u64 = 4096;
+// The most buffers that writev takes.
+const IOV_MAX: u64 = 1024;
+// The id of the only process and thread.
+const PID: u64 = 1;
+
+/// A file that the guest has opened.
+enum Descriptor {
+    Stdin,
+    Stdout,
+    Stderr,
+    File(File),
+}
+
+/// Serves the system calls of a static Linux executable that runs in user mode. The files are
+/// the files of the host, the time is the time CSR, and the random numbers are a fixed sequence
+/// so that runs are reproducible.
+pub struct Linux {
+    files: BTreeMap<u64, Descriptor>,
+    stdout: Box<dyn Write>,
+    stderr: Box<dyn Write>,
+    brk_start: u64,
+    brk: u64,
+    // the lowest address mapped by mmap so far
+    mmap_bottom: u64,
+    random: u64,
+}
+
+impl Default for Linux {
+    fn default() -> Self {
+        Self {
+            files: BTreeMap::from([
+                (0, Descriptor::Stdin),
+                (1, Descriptor::Stdout),
+                (2, Descriptor::Stderr),
+            ]),
+            stdout: Box::new(io::stdout()),
+            stderr: Box::new(io::stderr()),
+            brk_start: 0,
+            brk: 0,
+            mmap_bottom: 0,
+            random: 0x2545_f491_4f6c_dd1d,
+        }
+    }
+}
+
+impl Linux {
+    /// Sends what the guest writes to its standard output to the writer.
+    pub fn set_stdout(&mut self, stdout: impl Write + 'static) {
+        self.stdout = Box::new(stdout);
+    }
+
+    /// Sends what the guest writes to its standard error to the writer.
+    pub fn set_stderr(&mut self, stderr: impl Write + 'static) {
+        self.stderr = Box::new(stderr);
+    }
+
+    /// Loads the executable, builds the initial stack with the arguments, the environment and
+    /// the auxiliary vector, and makes the hart start from the entry in user mode. The segments
+    /// have to be linked into the memory.
+    pub(crate) fn load(
+        &mut self,
+        cpu: &mut Cpu,
+        elf: &Elf,
+        args: &[String],
+        env: &[String],
+    ) -> io::Result<()> {
+        let memory = &cpu.bus.memory;
+        self.mmap_bottom = memory
+            .end()
+            .checked_sub(STACK_SIZE)
+            .filter(|bottom| *bottom >= memory.base())
+            .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "memory smaller than the stack"))?;
+        for segment in &elf.segments {
+            let end = segment.address.checked_add(segment.size);
+            if segment.address < cpu.bus.memory.base()
+                || end.is_none_or(|end| end > self.mmap_bottom)
+            {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("segment outside of memory: 0x{:x}", segment.address),
+                ));
+            }
+            let mut data = segment.data.clone();
+            data.resize(segment.size as usize, 0);
+            write_bytes(cpu, segment.address, &data).map_err(errno_error)?;
+        }
+        // the segments end below the mappings, so the break does too
+        self.brk_start = align_up(elf.end()).unwrap_or(self.mmap_bottom);
+        self.brk = self.brk_start;
+        let sp = self.build_stack(cpu, elf, args, env)?;
+        cpu.x.writeu(2, sp);
+        cpu.set_pc(elf.entry);
+        cpu.set_prv(PrivilegeMode::User);
+        cpu.csr
+            .write_field(MSTATUS, &STATUS_FS, EXTENSION_STATUS_INITIAL);
+        Ok(())
+    }
+
+    /// Writes argc, argv, envp and the auxiliary vector below the strings they point to at the
+    /// top of the stack, and returns the stack pointer.
+    fn build_stack(
+        &mut self,
+        cpu: &mut Cpu,
+        elf: &Elf,
+        args: &[String],
+        env: &[String],
+    ) -> io::Result<u64> {
+        let bottom = self.mmap_bottom;
+        let mut sp = bottom + STACK_SIZE;
+        let mut push = |cpu: &mut Cpu, bytes: &[u8]| -> io::Result<u64> {
+            sp = sp
+                .checked_sub(bytes.len() as u64)
+                .filter(|sp| *sp >= bottom)
+                .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "arguments too long"))?;
+            write_bytes(cpu, sp, bytes).map_err(errno_error)?;
+            Ok(sp)
+        };
+        let mut string = |cpu: &mut Cpu, s: &String| push(cpu, &[s.as_bytes(), &[0]].concat());
+        let argv = args
+            .iter()
+            .map(|arg| string(cpu, arg))
+            .collect::<io::Result<Vec<_>>>()?;
+        let envp = env
+            .iter()
+            .map(|var| string(cpu, var))
+            .collect::<io::Result<Vec<_>>>()?;
+        let random = self.random_bytes(16);
+        let random = push(cpu, &random)?;
+
+        let mut auxv = vec![];
+        if let Some(phdr) = elf.phdr {
+            auxv.extend([
+                (AT_PHDR, phdr),
+                (AT_PHENT, elf.phent as u64),
+                (AT_PHNUM, elf.phnum as u64),
+            ]);
+        }
+        auxv.extend([
+            (AT_PAGESZ, PAGE_SIZE as u64),
+            (AT_BASE, 0),
+            (AT_FLAGS, 0),
+            (AT_ENTRY, elf.entry),
+            (AT_UID, 0),
+            (AT_EUID, 0),
+            (AT_GID, 0),
+            (AT_EGID, 0),
+            (AT_HWCAP, cpu.csr.read(MISA) & 0x3ff_ffff),
+            (AT_CLKTCK, 100),
+            (AT_SECURE, 0),
+            (AT_RANDOM, random),
+            (AT_NULL, 0),
+        ]);
+        let words = [args.len() as u64]
+            .into_iter()
+            .chain(argv)
+            .chain([0])
+            .chain(envp)
+            .chain([0])
+            .chain(auxv.into_iter().flat_map(|(key, value)| [key, value]))
+            .flat_map(u64::to_le_bytes)
+            .collect::<Vec<_>>();
+        // the stack pointer is aligned to 16 bytes at the entry
+        let sp = (sp - words.len() as u64) & !0xf;
+        if sp < bottom {
+            return Err(Error::new(ErrorKind::InvalidInput, "arguments too long"));
+        }
+        write_bytes(cpu, sp, &words).map_err(errno_error)?;
+        Ok(sp)
+    }
+
+    /// Serves the system call in a7 with the arguments in a0 to a5, and writes the result or the
+    /// negated error number to a0. Returns the exit code when the guest exits.
+    pub fn syscall(&mut self, cpu: &mut Cpu) -> Option<u64> {
+        let number = cpu.x.readu(17);
+        let a = [10, 11, 12, 13, 14, 15].map(|register| cpu.x.readu(register));
+        let result = match number {
+            EXIT | EXIT_GROUP => return Some(a[0] & 0xff),
+            READ => self.read(cpu, a[0], a[1], a[2]),
+            WRITE => read_bytes(cpu, a[1], a[2]).and_then(|bytes| self.write(a[0], &bytes)),
+            WRITEV => self.writev(cpu, a[0], a[1], a[2]),
+            OPENAT => self.openat(cpu, a[0] as i64, a[1], a[2]),
+            CLOSE => self.close(a[0]),
+            LSEEK => self.lseek(a[0], a[1] as i64, a[2]),
+            FSTAT => self
+                .fstat(a[0])
+                .and_then(|stat| write_bytes(cpu, a[1], &stat)),
+            NEWFSTATAT => self.newfstatat(cpu, a[0] as i64, a[1], a[2], a[3]),
+            BRK => Ok(self.set_brk(cpu, a[0])),
+            MMAP => self.mmap(cpu, a[0], a[1], a[3], a[4], a[5]),
+            CLOCK_GETTIME => {
+                let time = cpu.nanoseconds();
+                let timespec = [time / 1_000_000_000, time % 1_000_000_000]
+                    .map(u64::to_le_bytes)
+                    .concat();
+                write_bytes(cpu, a[1], &timespec)
+            }
+            GETRANDOM => buffer_size(cpu, a[0], a[1]).and_then(|size| {
+                let bytes = self.random_bytes(size);
+                write_bytes(cpu, a[0], &bytes).map(|_| size as u64)
+            }),
+            GETCWD => getcwd(cpu, a[0], a[1]),
+            UNAME => {
+                let fields = ["Linux", "five", "6.1.0", "#1", "riscv64", ""];
+                let mut utsname = vec![0; 65 * fields.len()];
+                for (i, field) in fields.iter().enumerate() {
+                    utsname[i * 65..i * 65 + field.len()].copy_from_slice(field.as_bytes());
+                }
+                write_bytes(cpu, a[0], &utsname)
+            }
+            PRLIMIT64 if a[3] != 0 => {
+                write_bytes(cpu, a[3], &[u64::MAX; 2].map(u64::to_le_bytes).concat())
+            }
+            IOCTL => Err(ENOTTY),
+            READLINKAT => Err(ENOENT),
+            SET_TID_ADDRESS | GETPID | GETTID => Ok(PID),
+            GETPPID | GETUID | GETEUID | GETGID | GETEGID => Ok(0),
+            // the memory is never reclaimed or protected, and signals are never delivered
+            MUNMAP | MPROTECT | SET_ROBUST_LIST | RT_SIGACTION | RT_SIGPROCMASK | PRLIMIT64 => {
+                Ok(0)
+            }
+            _ => Err(ENOSYS),
+        };
+        let value = match result {
+            Ok(value) => value,
+            Err(errno) => (-errno) as u64,
+        };
+        cpu.x.writeu(10, value);
+        None
+    }
+
+    fn read(&mut self, cpu: &mut Cpu, fd: u64, address: u64, count: u64) -> Result<u64, i64> {
+        let mut buffer = vec![0; buffer_size(cpu, address, count)?];
+        let read = match self.files.get_mut(&fd).ok_or(EBADF)? {
+            Descriptor::Stdin => cpu.read_stdin(&mut buffer),
+            Descriptor::File(file) => file.read(&mut buffer),
+            _ => return Err(EBADF),
+        }
+        .map_err(errno)?;
+        write_bytes(cpu, address, &buffer[..read])?;
+        Ok(read as u64)
+    }
+
+    fn write(&mut self, fd: u64, bytes: &[u8]) -> Result<u64, i64> {
+        match self.files.get_mut(&fd).ok_or(EBADF)? {
+            Descriptor::Stdout => self.stdout.write_all(bytes).and(self.stdout.flush()),
+            Descriptor::Stderr => self.stderr.write_all(bytes).and(self.stderr.flush()),
+            Descriptor::File(file) => file.write_all(bytes),
+            Descriptor::Stdin => return Err(EBADF),
+        }
+        .map_err(errno)?;
+        Ok(bytes.len() as u64)
+    }
+
+    fn writev(&mut self, cpu: &Cpu, fd: u64, iov: u64, count: u64) -> Result<u64, i64> {
+        if count > IOV_MAX {
+            return Err(EINVAL);
+        }
+        let mut bytes = vec![];
+        for i in 0..count {
+            let vector = read_bytes(cpu, iov.checked_add(i * 16).ok_or(EFAULT)?, 16)?;
+            let base = u64::from_le_bytes(vector[..8].try_into().unwrap());
+            let len = u64::from_le_bytes(vector[8..].try_into().unwrap());
+            bytes.extend(read_bytes(cpu, base, len)?);
+        }
+        self.write(fd, &bytes)
+    }
+
+    fn openat(&mut self, cpu: &Cpu, dirfd: i64, path: u64, flags: u64) -> Result<u64, i64> {
+        let path = read_string(cpu, path)?;
+        if dirfd != AT_FDCWD && !path.starts_with('/') {
+            return Err(EBADF);
+        }
+        let file = OpenOptions::new()
+            .read(flags & O_ACCMODE != O_WRONLY)
+            .write(flags & O_ACCMODE == O_WRONLY || flags & O_ACCMODE == O_RDWR)
+            .append(flags & O_APPEND != 0)
+            .truncate(flags & O_TRUNC != 0)
+            .create(flags & O_CREAT != 0 && flags & O_EXCL == 0)
+            .create_new(flags & O_CREAT != 0 && flags & O_EXCL != 0)
+            .open(path)
+            .map_err(errno)?;
+        let fd = (0..).find(|fd| !self.files.contains_key(fd)).unwrap();
+        self.files.insert(fd, Descriptor::File(file));
+        Ok(fd)
+    }
+
+    fn close(&mut self, fd: u64) -> Result<u64, i64> {
+        self.files.remove(&fd).map(|_| 0).ok_or(EBADF)
+    }
+
+    fn lseek(&mut self, fd: u64, offset: i64, whence: u64) -> Result<u64, i64> {
+        let Descriptor::File(file) = self.files.get_mut(&fd).ok_or(EBADF)? else {
+            return Err(ESPIPE);
+        };
+        let position = match whence {
+            0 => SeekFrom::Start(offset as u64),
+            1 => SeekFrom::Current(offset),
+            2 => SeekFrom::End(offset),
+            _ => return Err(EINVAL),
+        };
+        file.seek(position).map_err(errno)
+    }
+
+    fn fstat(&self, fd: u64) -> Result<Vec<u8>, i64> {
+        match self.files.get(&fd).ok_or(EBADF)? {
+            Descriptor::File(file) => file.metadata().map(|m| stat(&m)).map_err(errno),
+            _ => Ok(stat_of(S_IFCHR | 0o620, 0, 0)),
+        }
+    }
+
+    fn newfstatat(
+        &self,
+        cpu: &mut Cpu,
+        dirfd: i64,
+        path: u64,
+        address: u64,
+        flags: u64,
+    ) -> Result<u64, i64> {
+        let path = read_string(cpu, path)?;
+        let stat = if path.is_empty() && flags & AT_EMPTY_PATH != 0 {
+            self.fstat(dirfd as u64)?
+        } else if dirfd != AT_FDCWD && !path.starts_with('/') {
+            return Err(EBADF);
+        } else {
+            fs::metadata(path).map(|m| stat(&m)).map_err(errno)?
+        };
+        write_bytes(cpu, address, &stat)
+    }
+
+    /// Moves the break within the memory between the executable and the mappings, and returns
+    /// the break, which stays unchanged when the address is out of the range.
+    fn set_brk(&mut self, cpu: &mut Cpu, address: u64) -> u64 {
+        if (self.brk_start..=self.mmap_bottom).contains(&address) {
+            // the memory that the break grows over is zeroed
+            if address > self.brk {
+                write_bytes(cpu, self.brk, &vec![0; (address - self.brk) as usize]).ok();
+            }
+            self.brk = address;
+        }
+        self.brk
+    }
+
+    fn mmap(
+        &mut self,
+        cpu: &mut Cpu,
+        address: u64,
+        length: u64,
+        flags: u64,
+        fd: u64,
+        offset: u64,
+    ) -> Result<u64, i64> {
+        if length == 0 {
+            return Err(EINVAL);
+        }
+        // the mapping cannot be larger than the memory that holds it
+        let length = align_up(length)
+            .filter(|length| *length <= cpu.bus.memory.size())
+            .ok_or(ENOMEM)?;
+        let address = if flags & MAP_FIXED != 0 {
+            address
+        } else {
+            let address = self.mmap_bottom.checked_sub(length).ok_or(ENOMEM)?;
+            if address < self.brk {
+                return Err(ENOMEM);
+            }
+            self.mmap_bottom = address;
+            address
+        };
+        let mut data = vec![0; length as usize];
+        if flags & MAP_ANONYMOUS == 0 {
+            let Descriptor::File(file) = self.files.get_mut(&fd).ok_or(EBADF)? else {
+                return Err(EBADF);
+            };
+            // a mapping leaves the offset of the file unchanged
+            let position = file.stream_position().map_err(errno)?;
+            file.seek(SeekFrom::Start(offset)).map_err(errno)?;
+            let mut read = 0;
+            while read < data.len() {
+                match file.read(&mut data[read..]).map_err(errno)? {
+                    0 => break,
+                    n => read += n,
+                }
+            }
+            file.seek(SeekFrom::Start(position)).map_err(errno)?;
+        }
+        write_bytes(cpu, address, &data)?;
+        Ok(address)
+    }
+
+    /// Returns bytes from a xorshift generator with a fixed seed.
+    fn random_bytes(&mut self, count: usize) -> Vec<u8> {
+        (0..count)
+            .map(|_| {
+                self.random ^= self.random << 13;
+                self.random ^= self.random >> 7;
+                self.random ^= self.random << 17;
+                self.random as u8
+            })
+            .collect()
+    }
+}
+
+fn getcwd(cpu: &mut Cpu, address: u64, size: u64) -> Result<u64, i64> {
+    let cwd = std::env::current_dir().map_err(errno)?;
+    let cwd = [cwd.to_string_lossy().as_bytes(), &[0]].concat();
+    if cwd.len() as u64 > size {
+        return Err(ERANGE);
+    }
+    write_bytes(cpu, address, &cwd)?;
+    Ok(cwd.len() as u64)
+}
+
+fn stat(metadata: &Metadata) -> Vec<u8> {
+    let mode = if metadata.is_dir() {
+        S_IFDIR | 0o755
+    } else if metadata.permissions().readonly() {
+        S_IFREG | 0o444
+    } else {
+        S_IFREG | 0o644
+    };
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map(|time| time.as_secs())
+        .unwrap_or_default();
+    stat_of(mode, metadata.len(), modified)
+}
+
+/// Returns struct stat of a file of the mode with a single link owned by root.
+fn stat_of(mode: u32, size: u64, time: u64) -> Vec<u8> {
+    let mut stat = vec![0; STAT_SIZE];
+    stat[16..20].copy_from_slice(&mode.to_le_bytes());
+    stat[20..24].copy_from_slice(&1u32.to_le_bytes());
+    stat[48..56].copy_from_slice(&size.to_le_bytes());
+    stat[56..60].copy_from_slice(&(PAGE_SIZE as u32).to_le_bytes());
+    stat[64..72].copy_from_slice(&size.div_ceil(512).to_le_bytes());
+    for offset in [72, 88, 104] {
+        stat[offset..offset + 8].copy_from_slice(&time.to_le_bytes());
+    }
+    stat
+}
+
+fn align_up(address: u64) -> Option<u64> {
+    address.checked_next_multiple_of(PAGE_SIZE as u64)
+}
+
+fn errno(error: Error) -> i64 {
+    error.raw_os_error().map(i64::from).unwrap_or(EIO)
+}
+
+fn errno_error(errno: i64) -> Error {
+    Error::from_raw_os_error(errno as i32)
+}
+
+fn read_bytes(cpu: &Cpu, address: u64, count: u64) -> Result<Vec<u8>, i64> {
+    cpu.bus.memory.load_bytes(address, count).ok_or(EFAULT)
+}
+
+fn write_bytes(cpu: &mut Cpu, address: u64, bytes: &[u8]) -> Result<u64, i64> {
+    match cpu.bus.memory.store_bytes(address, bytes) {
+        true => Ok(0),
+        false => Err(EFAULT),
+    }
+}
+
+/// Returns the size of the buffer at the address that the guest passes with the count, which
+/// ends at the end of the memory at the latest.
+fn buffer_size(cpu: &Cpu, address: u64, count: u64) -> Result<usize, i64> {
+    let memory = &cpu.bus.memory;
+    if !memory.contains(address) {
+        return Err(EFAULT);
+    }
+    Ok(count.min(memory.end() - address) as usize)
+}
+
+fn read_string(cpu: &Cpu, address: u64) -> Result<String, i64> {
+    let mut bytes = vec![];
+    for a in address..address.saturating_add(PATH_MAX) {
+        match read_bytes(cpu, a, 1)?[0] {
+            0 => return String::from_utf8(bytes).map_err(|_| ENOENT),
+            byte => bytes.push(byte),
+        }
+    }
+    Err(EFAULT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        emulator::{
+            bus::{
+                memory::{MEMORY_BASE_ADDRESS, MEMORY_SIZE},
+                Size,
+            },
+            elf::tests::executable,
+            testing::Output,
+            Emulator, StopReason,
+        },
+        isa::csr::user_level::INSTRET,
+    };
+    use std::net::TcpListener;
+
+    // The address that the test executables are linked at.
+    const BASE: u64 = MEMORY_BASE_ADDRESS;
+    // The top of the stack in the default memory.
+    const STACK_TOP: u64 = MEMORY_BASE_ADDRESS + MEMORY_SIZE;
+
+    fn code(instructions: &[u32], data: &[u8]) -> Vec<u8> {
+        let mut code = instructions
+            .iter()
+            .flat_map(|i| i.to_le_bytes())
+            .collect::<Vec<_>>();
+        code.extend_from_slice(data);
+        code
+    }
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn hello_ok() {
+        let program = code(
+            &[
+                0x0000_0597, // auipc a1, 0
+                0x0245_8593, // addi a1, a1, 36
+                0x0010_0513, // li a0, 1
+                0x0060_0613, // li a2, 6
+                0x0400_0893, // li a7, 64
+                0x0000_0073, // ecall
+                0x02a0_0513, // li a0, 42
+                0x05e0_0893, // li a7, 94
+                0x0000_0073, // ecall
+            ],
+            b"hello\n",
+        );
+        let output = Output::default();
+        let mut linux = Linux::default();
+        linux.set_stdout(output.clone());
+        let mut emulator = Emulator::default();
+        emulator
+            .load_linux(linux, &executable(BASE, &program), &args(&["hello"]), &[])
+            .unwrap();
+        assert_eq!(emulator.cpu().prv(), PrivilegeMode::User);
+        assert_eq!(emulator.run(), StopReason::Exit(42));
+        assert_eq!(*output.0.borrow(), b"hello\n");
+        assert_eq!(emulator.cpu().csr.read(INSTRET), 9);
+    }
+
+    #[test]
+    fn low_address_ok() {
+        // a plain static executable is linked at 0x10000, below the devices
+        let program = code(
+            &[
+                0x0200_05b7, // lui a1, 0x2000
+                0x0010_0513, // li a0, 1
+                0x00a5_b023, // sd a0, 0(a1)
+                0x0005_b503, // ld a0, 0(a1)
+                0x05e0_0893, // li a7, 94
+                0x0000_0073, // ecall
+            ],
+            &[],
+        );
+        let mut emulator = Emulator::default();
+        emulator
+            .load_linux(
+                Linux::default(),
+                &executable(0x10000, &program),
+                &args(&["low"]),
+                &[],
+            )
+            .unwrap();
+        assert_eq!(emulator.cpu().bus.memory.base(), 0x10000);
+        assert_eq!(emulator.cpu().bus.memory.size(), MEMORY_SIZE);
+        // the store to the address of the CLINT goes to the memory
+        assert_eq!(emulator.run(), StopReason::Exit(1));
+        assert_eq!(emulator.cpu().bus.load64(0x0200_0000), 1);
+        assert_eq!(emulator.cpu().bus.clint.load(0x0200_0000, Size::Word), 0);
+        // the drivers that step the hart by themselves cannot serve the system calls
+        assert!(emulator.lockstep().is_err());
+        let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        assert!(emulator.gdb(listener).is_err());
+    }
+
+    #[test]
+    fn stack_ok() {
+        let elf = Elf::parse(&executable(BASE, &[0x13, 0, 0, 0])).unwrap();
+        let mut cpu = Cpu::default();
+        let mut linux = Linux::default();
+        linux
+            .load(&mut cpu, &elf, &args(&["prog", "arg"]), &args(&["HOME=/"]))
+            .unwrap();
+        let sp = cpu.x().readu(2);
+        assert_eq!(sp % 16, 0);
+        assert_eq!(cpu.pc(), elf.entry);
+        let word = |i: u64| cpu.bus.load64(sp + i * 8);
+        let string = |address| read_string(&cpu, address).unwrap();
+        assert_eq!(word(0), 2);
+        assert_eq!(string(word(1)), "prog");
+        assert_eq!(string(word(2)), "arg");
+        assert_eq!(word(3), 0);
+        assert_eq!(string(word(4)), "HOME=/");
+        assert_eq!(word(5), 0);
+        assert_eq!((word(6), word(7)), (AT_PHDR, BASE + 0x40));
+        let auxv = (6..).step_by(2).map(|i| (word(i), word(i + 1)));
+        let auxv = auxv
+            .take_while(|(key, _)| *key != AT_NULL)
+            .collect::<BTreeMap<_, _>>();
+        assert_eq!(auxv[&AT_ENTRY], elf.entry);
+        assert_eq!(auxv[&AT_PAGESZ], 4096);
+        assert!(read_bytes(&cpu, auxv[&AT_RANDOM], 16).is_ok());
+        assert_eq!(Some(linux.brk), align_up(elf.end()));
+    }
+
+    #[test]
+    fn memory_ok() {
+        let elf = Elf::parse(&executable(BASE, &[0x13, 0, 0, 0])).unwrap();
+        let mut cpu = Cpu::default();
+        let mut linux = Linux::default();
+        linux.load(&mut cpu, &elf, &args(&["prog"]), &[]).unwrap();
+        let mut syscall = |cpu: &mut Cpu, number: u64, a: &[u64]| {
+            cpu.x.writeu(17, number);
+            for (i, value) in a.iter().enumerate() {
+                cpu.x.writeu(10 + i, *value);
+            }
+            assert_eq!(linux.syscall(cpu), None);
+            cpu.x.readi(10)
+        };
+        let brk = syscall(&mut cpu, BRK, &[0]) as u64;
+        assert_eq!(brk, BASE + 0x1000);
+        assert_eq!(syscall(&mut cpu, BRK, &[brk + 0x2000]) as u64, brk + 0x2000);
+        assert_eq!(syscall(&mut cpu, BRK, &[BASE]) as u64, brk + 0x2000);
+
+        let mapped = syscall(&mut cpu, MMAP, &[0, 100, 3, 0x22, u64::MAX, 0]) as u64;
+        assert_eq!(mapped, STACK_TOP - STACK_SIZE - 0x1000);
+        assert_eq!(
+            syscall(&mut cpu, MMAP, &[0, 0, 3, 0x22, u64::MAX, 0]),
+            -EINVAL
+        );
+        assert_eq!(syscall(&mut cpu, WRITE, &[1, 0, 1]), -EFAULT);
+
+        // the pointers and the lengths near the end of the address space fail
+        assert_eq!(
+            syscall(&mut cpu, MMAP, &[0, u64::MAX, 3, 0x22, u64::MAX, 0]),
+            -ENOMEM
+        );
+        assert_eq!(
+            syscall(&mut cpu, MMAP, &[0, MEMORY_SIZE + 1, 3, 0x22, u64::MAX, 0]),
+            -ENOMEM
+        );
+        assert_eq!(
+            syscall(&mut cpu, OPENAT, &[AT_FDCWD as u64, u64::MAX]),
+            -EFAULT
+        );
+        assert_eq!(syscall(&mut cpu, WRITEV, &[1, u64::MAX - 8, 2]), -EFAULT);
+        assert_eq!(syscall(&mut cpu, WRITEV, &[1, mapped, u64::MAX]), -EINVAL);
+        assert_eq!(
+            syscall(&mut cpu, GETRANDOM, &[u64::MAX - 8, u64::MAX]),
+            -EFAULT
+        );
+        assert_eq!(syscall(&mut cpu, READ, &[0, u64::MAX, u64::MAX]), -EFAULT);
+        assert_eq!(syscall(&mut cpu, CLOSE, &[7]), -EBADF);
+        assert_eq!(syscall(&mut cpu, 1000, &[]), -ENOSYS);
+        assert_eq!(
+            syscall(&mut cpu, OPENAT, &[AT_FDCWD as u64, mapped]),
+            -ENOENT
+        );
+    }
+}
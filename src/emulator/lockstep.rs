@@ -0,0 +1,539 @@
+use crate::{
+    emulator::cpu::{csr::Csr, Cpu, Step},
+    isa::{
+        csr::csrname,
+        privileged::cause::{Cause, Exception},
+        register::{fname, xname},
+    },
+};
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{self, BufRead};
+
+// Number of instructions before a mismatch that are shown as its context.
+const CONTEXT: usize = 8;
+// Number of consecutive traps that five can take when the traps are skipped.
+const MAX_TRAPS: usize = 16;
+
+/// How the traps that five takes are checked against the reference.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum TrapPolicy {
+    /// Every trap must match an exception line, which Spike writes with `-l`.
+    #[default]
+    Compare,
+    /// The reference log has no exception lines, so five may take a few traps in a row before
+    /// each instruction it retires.
+    Skip,
+}
+
+/// A register written by an instruction of the reference.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum RegisterWrite {
+    X(usize, u64),
+    F(usize, u64),
+    Csr(u64, u64),
+}
+
+/// A line of a reference commit log.
+#[derive(Debug, PartialEq)]
+enum Event {
+    Commit {
+        pc: u64,
+        instruction: u32,
+        writes: Vec<RegisterWrite>,
+    },
+    Trap {
+        name: String,
+        epc: u64,
+    },
+}
+
+/// The first instruction where five diverges from the reference.
+#[derive(Debug)]
+pub struct Mismatch {
+    /// The line number of the reference log.
+    pub line: usize,
+    pub reference: String,
+    pub reason: String,
+    /// The number of instructions that matched before the mismatch.
+    pub instructions: u64,
+    /// The disassembly of the last instructions executed by five.
+    pub context: Vec<String>,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "mismatch at line {} after {} instructions: {}",
+            self.line, self.instructions, self.reason
+        )?;
+        writeln!(f, "reference: {}", self.reference)?;
+        for line in &self.context {
+            writeln!(f, "{}", line)?;
+        }
+        Ok(())
+    }
+}
+
+/// Steps a hart alongside a reference commit log in the format of Spike's `--log-commits` and
+/// stops at the first mismatch in the pc, a register written or a trap.
+pub struct Lockstep<'a> {
+    cpu: &'a mut Cpu,
+    history: VecDeque<u64>,
+    instructions: u64,
+    traps: TrapPolicy,
+}
+
+impl<'a> Lockstep<'a> {
+    pub fn new(cpu: &'a mut Cpu) -> Self {
+        Self {
+            cpu,
+            history: VecDeque::new(),
+            instructions: 0,
+            traps: TrapPolicy::default(),
+        }
+    }
+
+    pub fn set_trap_policy(&mut self, traps: TrapPolicy) {
+        self.traps = traps;
+    }
+
+    /// Returns the number of reference instructions that five has matched.
+    pub fn instructions(&self) -> u64 {
+        self.instructions
+    }
+
+    /// Runs until the end of the reference log or the first mismatch, reading the log a line at
+    /// a time.
+    pub fn run(&mut self, reference: impl BufRead) -> io::Result<Option<Mismatch>> {
+        for (index, line) in reference.lines().enumerate() {
+            let line = line?;
+            let result = match parse(&line) {
+                Some(Event::Commit {
+                    pc,
+                    instruction,
+                    writes,
+                }) => self.commit(pc, instruction, &writes),
+                Some(Event::Trap { name, epc }) => self.trap(&name, epc),
+                None => continue,
+            };
+            if let Err(reason) = result {
+                return Ok(Some(Mismatch {
+                    line: index + 1,
+                    reference: line,
+                    reason,
+                    instructions: self.instructions,
+                    context: self.context(),
+                }));
+            }
+        }
+        Ok(None)
+    }
+
+    fn step(&mut self) -> Step {
+        if self.history.len() == CONTEXT {
+            self.history.pop_front();
+        }
+        self.history.push_back(self.cpu.pc());
+        self.cpu.step(false)
+    }
+
+    fn commit(
+        &mut self,
+        pc: u64,
+        instruction: u32,
+        writes: &[RegisterWrite],
+    ) -> Result<(), String> {
+        let mut traps = 0;
+        let (actual, xsnapshot, fsnapshot) = loop {
+            let actual = self.cpu.pc();
+            let xsnapshot = self.cpu.x.snapshot();
+            let fsnapshot = self.cpu.f.snapshot();
+            match self.step() {
+                Step::Retired | Step::Syscall | Step::Semihosting => {
+                    break (actual, xsnapshot, fsnapshot)
+                }
+                // the trap is not in the reference log
+                Step::Trap(_) if self.traps == TrapPolicy::Skip && traps < MAX_TRAPS => traps += 1,
+                Step::Trap(cause) => {
+                    return Err(format!(
+                        "five took {:?} at 0x{:x}, the reference retired 0x{:x}",
+                        cause, actual, pc
+                    ))
+                }
+                step => return Err(format!("five stopped with {:?} at 0x{:x}", step, actual)),
+            }
+        };
+        if actual != pc {
+            return Err(format!("pc: expected 0x{:x}, five 0x{:x}", pc, actual));
+        }
+        let fetched = self.cpu.bus.fetch(pc);
+        if fetched != instruction {
+            return Err(format!(
+                "instruction: expected 0x{:08x}, five 0x{:08x}",
+                instruction, fetched
+            ));
+        }
+        for write in writes {
+            let (name, expected, value) = match *write {
+                RegisterWrite::X(register, value) => (
+                    xname(register).to_string(),
+                    value,
+                    self.cpu.x.readu(register),
+                ),
+                // the reference may NaN-box single-precision values
+                RegisterWrite::F(register, value) => (
+                    fname(register).to_string(),
+                    value as u32 as u64,
+                    self.cpu.f.reads(register) as u64,
+                ),
+                RegisterWrite::Csr(address, _) if !self.cpu.csr.contains(address) => {
+                    return Err(format!("{}: not implemented by five", csrname(address)));
+                }
+                RegisterWrite::Csr(address, value) => {
+                    (csrname(address), value, self.cpu.csr.read(address))
+                }
+            };
+            if expected != value {
+                return Err(format!(
+                    "{}: expected 0x{:x}, five 0x{:x}",
+                    name, expected, value
+                ));
+            }
+        }
+        for (register, _, value) in self.cpu.x.diff(xsnapshot) {
+            if !writes
+                .iter()
+                .any(|w| matches!(w, RegisterWrite::X(r, _) if *r == register))
+            {
+                return Err(format!(
+                    "{}: five wrote 0x{:x}, the reference did not",
+                    xname(register),
+                    value
+                ));
+            }
+        }
+        for (register, _, value) in self.cpu.f.diff(fsnapshot) {
+            if !writes
+                .iter()
+                .any(|w| matches!(w, RegisterWrite::F(r, _) if *r == register))
+            {
+                return Err(format!(
+                    "{}: five wrote 0x{:x}, the reference did not",
+                    fname(register),
+                    value
+                ));
+            }
+        }
+        self.instructions += 1;
+        Ok(())
+    }
+
+    fn trap(&mut self, name: &str, epc: u64) -> Result<(), String> {
+        if self.traps == TrapPolicy::Skip {
+            return Ok(());
+        }
+        let actual = self.cpu.pc();
+        match self.step() {
+            Step::Trap(cause) if is_same_trap(cause, name) && actual == epc => Ok(()),
+            Step::Trap(cause) => Err(format!(
+                "trap: expected {} at 0x{:x}, five took {:?} at 0x{:x}",
+                name, epc, cause, actual
+            )),
+            _ => Err(format!(
+                "trap: expected {} at 0x{:x}, five did not trap at 0x{:x}",
+                name, epc, actual
+            )),
+        }
+    }
+
+    fn context(&self) -> Vec<String> {
+        self.history
+            .iter()
+            .enumerate()
+            .map(|(i, pc)| {
+                let marker = if i + 1 == self.history.len() {
+                    "=>"
+                } else {
+                    "  "
+                };
+                match self.cpu.disassemble(*pc) {
+                    Some(description) => {
+                        format!("{} {:x}: {}", marker, pc, description.assembly())
+                    }
+                    None => format!("{} {:x}: unknown", marker, pc),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Returns the name of the exception in Spike's logs.
+fn spike_name(exception: Exception) -> &'static str {
+    match exception {
+        Exception::InstructionAddressMisaligned => "trap_instruction_address_misaligned",
+        Exception::InstructionAccessFault => "trap_instruction_access_fault",
+        Exception::IllegalInstruction => "trap_illegal_instruction",
+        Exception::Breakpoint => "trap_breakpoint",
+        Exception::LoadAddressMisaligned => "trap_load_address_misaligned",
+        Exception::LoadAccessFault => "trap_load_access_fault",
+        Exception::StoreAddressMisaligned => "trap_store_address_misaligned",
+        Exception::StoreAccessFault => "trap_store_access_fault",
+        Exception::EnvironmentCallFromUserMode => "trap_user_ecall",
+        Exception::EnvironmentCallFromSupervisorMode => "trap_supervisor_ecall",
+        Exception::EnvironmentCallFromMachineMode => "trap_machine_ecall",
+        Exception::InstructionPageFault => "trap_instruction_page_fault",
+        Exception::LoadPageFault => "trap_load_page_fault",
+        Exception::StorePageFault => "trap_store_page_fault",
+    }
+}
+
+fn is_same_trap(cause: Cause, name: &str) -> bool {
+    match cause {
+        Cause::Interrupt(interrupt) => name == format!("interrupt #{}", interrupt.exception_code()),
+        Cause::Exception(exception) => spike_name(exception) == name,
+        _ => false,
+    }
+}
+
+fn hex(value: &str) -> Option<u64> {
+    u64::from_str_radix(value.strip_prefix("0x")?, 16).ok()
+}
+
+/// Parses a line of a commit log. The lines that do not describe a commit or a trap are
+/// ignored.
+fn parse(line: &str) -> Option<Event> {
+    let (_, rest) = line.strip_prefix("core")?.split_once(':')?;
+    let mut tokens = rest.split_whitespace().peekable();
+    match tokens.next()? {
+        "exception" => {
+            let name = tokens.next()?.trim_end_matches(',');
+            // an interrupt is named by its code, e.g. `interrupt #7,`
+            let name = if name == "interrupt" {
+                format!("interrupt {}", tokens.next()?.trim_end_matches(','))
+            } else {
+                name.to_string()
+            };
+            if tokens.next()? != "epc" {
+                return None;
+            }
+            let epc = hex(tokens.next()?)?;
+            Some(Event::Trap { name, epc })
+        }
+        prv if prv.len() == 1 && prv.chars().all(|c| c.is_ascii_digit()) => {
+            let pc = hex(tokens.next()?)?;
+            let instruction = hex(tokens.next()?.trim_matches(['(', ')']))? as u32;
+            let mut writes = vec![];
+            while let Some(token) = tokens.next() {
+                if token == "mem" {
+                    // the address and the value of a store
+                    tokens.next();
+                    tokens.next_if(|t| t.starts_with("0x"));
+                } else if let Some(register) = token.strip_prefix('x') {
+                    writes.push(RegisterWrite::X(
+                        register.parse().ok()?,
+                        hex(tokens.next()?)?,
+                    ));
+                } else if let Some(register) = token.strip_prefix('f') {
+                    writes.push(RegisterWrite::F(
+                        register.parse().ok()?,
+                        hex(tokens.next()?)?,
+                    ));
+                } else if let Some(csr) = token.strip_prefix('c') {
+                    let (address, _) = csr.split_once('_')?;
+                    writes.push(RegisterWrite::Csr(
+                        address.parse().ok()?,
+                        hex(tokens.next()?)?,
+                    ));
+                }
+            }
+            Some(Event::Commit {
+                pc,
+                instruction,
+                writes,
+            })
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        emulator::{
+            bus::memory::MEMORY_BASE_ADDRESS,
+            testing::{emulator, Output},
+            trace::CommitLog,
+        },
+        isa::csr::machine_level::{MIE, MSTATUS, MTVEC},
+    };
+
+    // addi a0, a0, 1
+    const ADDI: u32 = 0x0015_0513;
+    // csrw mscratch, a0
+    const CSRW: u32 = 0x3405_1073;
+    const ECALL: u32 = 0x0000_0073;
+
+    fn reference(program: &[u32], instructions: u64) -> String {
+        let mut emulator = emulator(program);
+        let shared = Output::default();
+        emulator.set_commit_log(CommitLog::new(shared.clone()));
+        emulator.run_for(instructions);
+        emulator.finish_commit_log().unwrap();
+        let output = shared.0.borrow().clone();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn parse_ok() {
+        assert_eq!(
+            parse("core   0: 3 0x0000000080000008 (0x1002a583) x11 0xffffffff80000000 mem 0x0000000080000100"),
+            Some(Event::Commit {
+                pc: 0x8000_0008,
+                instruction: 0x1002_a583,
+                writes: vec![RegisterWrite::X(11, 0xffff_ffff_8000_0000)],
+            })
+        );
+        assert_eq!(
+            parse("core   0: 1 0x0000000080000004 (0x1052b023) mem 0x0000000080000100 0x01 c1_fflags 0x1 f1  0xffffffff3f800000"),
+            Some(Event::Commit {
+                pc: 0x8000_0004,
+                instruction: 0x1052_b023,
+                writes: vec![
+                    RegisterWrite::Csr(1, 1),
+                    RegisterWrite::F(1, 0xffff_ffff_3f80_0000)
+                ],
+            })
+        );
+        assert_eq!(
+            parse("core   0: exception trap_illegal_instruction, epc 0x0000000080000010"),
+            Some(Event::Trap {
+                name: "trap_illegal_instruction".to_string(),
+                epc: 0x8000_0010,
+            })
+        );
+        assert_eq!(
+            parse("core   0: exception interrupt #7, epc 0x0000000080000004"),
+            Some(Event::Trap {
+                name: "interrupt #7".to_string(),
+                epc: 0x8000_0004,
+            })
+        );
+        assert_eq!(parse("core   0:           tval 0x0000000000000000"), None);
+        assert_eq!(
+            parse("core   0: 0x0000000080000000 (0x04c0006f) j pc + 0x4c"),
+            None
+        );
+    }
+
+    #[test]
+    fn match_ok() {
+        let program = [ADDI, CSRW, ADDI, ADDI];
+        let log = reference(&program, 4);
+        let mut emulator = emulator(&program);
+        let mut lockstep = emulator.lockstep().unwrap();
+        assert!(lockstep.run(log.as_bytes()).unwrap().is_none());
+        assert_eq!(lockstep.instructions(), 4);
+    }
+
+    #[test]
+    fn register_mismatch_ok() {
+        let program = [ADDI, CSRW, ADDI, ADDI];
+        let log = reference(&program, 4);
+        let mut emulator = emulator(&program);
+        emulator.cpu_mut().x_mut().writeu(10, 5);
+        let mismatch = emulator
+            .lockstep()
+            .unwrap()
+            .run(log.as_bytes())
+            .unwrap()
+            .unwrap();
+        assert_eq!(mismatch.line, 1);
+        assert_eq!(mismatch.reason, "a0: expected 0x1, five 0x6");
+        assert_eq!(mismatch.context, ["=> 80000000: addi a0,a0,0x1(1)"]);
+    }
+
+    #[test]
+    fn trap_mismatch_ok() {
+        let handler = MEMORY_BASE_ADDRESS + 0x100;
+        let program = [ADDI, ECALL];
+        let log = format!(
+            "{}core   0: exception trap_user_ecall, epc 0x{:016x}\n",
+            reference(&program, 1),
+            MEMORY_BASE_ADDRESS + 4
+        );
+        let mut emulator = emulator(&program);
+        emulator.cpu_mut().csr.write(MTVEC, handler);
+        let mismatch = emulator
+            .lockstep()
+            .unwrap()
+            .run(log.as_bytes())
+            .unwrap()
+            .unwrap();
+        assert_eq!(mismatch.line, 2);
+        assert_eq!(mismatch.instructions, 1);
+        assert_eq!(
+            mismatch.reason,
+            "trap: expected trap_user_ecall at 0x80000004, five took \
+             Exception(EnvironmentCallFromMachineMode) at 0x80000004"
+        );
+    }
+
+    #[test]
+    fn interrupt_ok() {
+        let interrupted = || {
+            let mut emulator = emulator(&[ADDI; 4]);
+            let cpu = emulator.cpu_mut();
+            cpu.csr.write(MTVEC, MEMORY_BASE_ADDRESS + 8);
+            cpu.csr.write(MIE, 1 << 7);
+            cpu.csr.write(MSTATUS, 1 << 3);
+            cpu.bus.store64(0x0200_4000, 0);
+            emulator
+        };
+        let commits = "\
+            core   0: 3 0x0000000080000008 (0x00150513) x10 0x0000000000000001\n\
+            core   0: 3 0x000000008000000c (0x00150513) x10 0x0000000000000002\n";
+        let log = format!(
+            "core   0: exception interrupt #7, epc 0x0000000080000000\n{}",
+            commits
+        );
+        let mut emulator = interrupted();
+        assert!(emulator
+            .lockstep()
+            .unwrap()
+            .run(log.as_bytes())
+            .unwrap()
+            .is_none());
+
+        // the timer interrupt is not the software interrupt of the reference
+        let mut emulator = interrupted();
+        let log = log.replace("#7", "#3");
+        let mismatch = emulator
+            .lockstep()
+            .unwrap()
+            .run(log.as_bytes())
+            .unwrap()
+            .unwrap();
+        assert_eq!(mismatch.line, 1);
+
+        // the interrupt is skipped only when the policy says so
+        let mut emulator = interrupted();
+        let mismatch = emulator
+            .lockstep()
+            .unwrap()
+            .run(commits.as_bytes())
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            mismatch.reason,
+            "five took Interrupt(MachineTimer) at 0x80000000, the reference retired 0x80000008"
+        );
+        let mut emulator = interrupted();
+        let mut lockstep = emulator.lockstep().unwrap();
+        lockstep.set_trap_policy(TrapPolicy::Skip);
+        assert!(lockstep.run(commits.as_bytes()).unwrap().is_none());
+        assert_eq!(lockstep.instructions(), 2);
+    }
+}
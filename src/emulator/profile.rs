@@ -0,0 +1,311 @@
+use crate::{
+    emulator::{
+        cpu::{Cpu, Step},
+        elf::Symbol,
+    },
+    isa::{
+        privileged::cause::Cause,
+        register::{RA, ZERO},
+    },
+};
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, Write};
+
+// The major opcodes of JAL and JALR.
+const OPCODE_JAL: u32 = 0b1101111;
+const OPCODE_JALR: u32 = 0b1100111;
+// The number of the hottest pcs in the report.
+const HOT_SPOTS: usize = 32;
+
+/// A function on the call stack, which the frame of the caller has called.
+struct Frame {
+    caller: Option<usize>,
+    address: u64,
+    // the instructions retired in the function itself
+    count: u64,
+}
+
+/// A counter of the retired instructions by opcode, by pc and by function, and of the traps
+/// by cause. The calls and the returns through `ra` build the call stacks of the folded-stack
+/// file that flame graph tools read. The trap handlers show up under the function that the trap
+/// has interrupted.
+#[derive(Default)]
+pub struct Profiler {
+    symbols: BTreeMap<u64, Symbol>,
+    // the instruction at the pc and the number of times that it has retired
+    pcs: HashMap<u64, (u32, u64)>,
+    traps: Vec<(Cause, u64)>,
+    frames: Vec<Frame>,
+    callees: HashMap<(Option<usize>, u64), usize>,
+    // the frame that every hart runs in
+    stacks: HashMap<usize, usize>,
+    pending: Option<(u64, u32)>,
+}
+
+impl Profiler {
+    /// Names the function at the address. A function without a size extends to the next one.
+    pub fn add_symbol(&mut self, name: &str, address: u64, size: u64) {
+        let symbol = Symbol {
+            name: name.to_string(),
+            address,
+            size,
+        };
+        self.symbols.insert(address, symbol);
+    }
+
+    pub fn add_symbols(&mut self, symbols: &[Symbol]) {
+        for symbol in symbols {
+            self.symbols.insert(symbol.address, symbol.clone());
+        }
+    }
+
+    /// Remembers the instruction that the hart is about to execute.
+    pub(crate) fn begin(&mut self, cpu: &Cpu) {
+        let pc = cpu.pc();
+        self.pending = cpu.bus.is_mapped(pc).then(|| (pc, cpu.bus.fetch(pc)));
+    }
+
+    /// Counts the instruction if it has retired, or the trap that the hart has taken.
+    pub(crate) fn commit(&mut self, cpu: &Cpu, step: Step) {
+        let Some((pc, instruction)) = self.pending.take() else {
+            return;
+        };
+        match step {
+            Step::Retired | Step::Syscall | Step::Semihosting => {}
+            Step::Trap(cause) => {
+                match self.traps.iter_mut().find(|(c, _)| *c == cause) {
+                    Some((_, count)) => *count += 1,
+                    None => self.traps.push((cause, 1)),
+                }
+                return;
+            }
+            _ => return,
+        }
+        self.pcs.entry(pc).or_insert((instruction, 0)).1 += 1;
+        let hart = cpu.hartid();
+        let frame = match self.stacks.get(&hart) {
+            Some(frame) => *frame,
+            None => self.callee(None, pc),
+        };
+        self.frames[frame].count += 1;
+        let opcode = instruction & 0x7f;
+        let rd = (instruction >> 7) & 0x1f;
+        let rs1 = (instruction >> 15) & 0x1f;
+        let frame = if (opcode == OPCODE_JAL || opcode == OPCODE_JALR) && rd == RA as u32 {
+            self.callee(Some(frame), cpu.pc())
+        } else if opcode == OPCODE_JALR && rd == ZERO as u32 && rs1 == RA as u32 {
+            // a return from the function that the profile has seen the hart enter
+            self.frames[frame].caller.unwrap_or(frame)
+        } else {
+            frame
+        };
+        self.stacks.insert(hart, frame);
+    }
+
+    /// Returns the frame of the function at the address called from the caller.
+    fn callee(&mut self, caller: Option<usize>, address: u64) -> usize {
+        let frames = &mut self.frames;
+        *self.callees.entry((caller, address)).or_insert_with(|| {
+            frames.push(Frame {
+                caller,
+                address,
+                count: 0,
+            });
+            frames.len() - 1
+        })
+    }
+
+    /// Returns the function that contains the address.
+    fn function(&self, address: u64) -> Option<&Symbol> {
+        self.symbols
+            .range(..=address)
+            .next_back()
+            .map(|(_, symbol)| symbol)
+            .filter(|symbol| symbol.size == 0 || address - symbol.address < symbol.size)
+    }
+
+    fn name(&self, address: u64) -> String {
+        match self.function(address) {
+            Some(symbol) => symbol.name.clone(),
+            None => format!("0x{:x}", address),
+        }
+    }
+
+    /// Returns the number of instructions retired.
+    pub fn instructions(&self) -> u64 {
+        self.pcs.values().map(|(_, count)| count).sum()
+    }
+
+    /// Writes the counts from the largest to the smallest: the opcodes, the functions, the
+    /// hottest pcs and the traps.
+    pub fn report(&self, mut writer: impl Write) -> io::Result<()> {
+        let total = self.instructions();
+        let percent = |count: u64| count as f64 * 100.0 / total.max(1) as f64;
+        writeln!(writer, "instructions: {}", total)?;
+
+        let mut opcodes = HashMap::<_, u64>::new();
+        for (instruction, count) in self.pcs.values() {
+            let (group, mnemonic) = Cpu::opcode(*instruction)
+                .unwrap_or(("Unknown".to_string(), format!("0x{:08x}", instruction)));
+            *opcodes.entry((group, mnemonic)).or_default() += count;
+        }
+        writeln!(writer, "\nopcodes:")?;
+        for ((group, mnemonic), count) in sorted(opcodes) {
+            writeln!(
+                writer,
+                "{:>12} {:6.2}%  {:<20} {}",
+                count,
+                percent(count),
+                group,
+                mnemonic
+            )?;
+        }
+
+        let mut functions = HashMap::<_, u64>::new();
+        for (pc, (_, count)) in &self.pcs {
+            *functions.entry(self.name(*pc)).or_default() += count;
+        }
+        writeln!(writer, "\nfunctions:")?;
+        for (function, count) in sorted(functions) {
+            writeln!(
+                writer,
+                "{:>12} {:6.2}%  {}",
+                count,
+                percent(count),
+                function
+            )?;
+        }
+
+        writeln!(writer, "\nhot spots:")?;
+        let pcs = self.pcs.iter().map(|(pc, (_, count))| (*pc, *count));
+        for (pc, count) in sorted(pcs).into_iter().take(HOT_SPOTS) {
+            let (instruction, _) = self.pcs[&pc];
+            let mnemonic = Cpu::opcode(instruction).map_or("unknown".to_string(), |(_, m)| m);
+            let location = self.function(pc).map_or(String::new(), |symbol| {
+                format!("{}+0x{:x}", symbol.name, pc - symbol.address)
+            });
+            writeln!(
+                writer,
+                "{:>12} {:6.2}%  0x{:016x} {:<8} {}",
+                count,
+                percent(count),
+                pc,
+                mnemonic,
+                location
+            )?;
+        }
+
+        writeln!(writer, "\ntraps:")?;
+        let traps = self
+            .traps
+            .iter()
+            .map(|(cause, count)| (format!("{:?}", cause), *count));
+        for (cause, count) in sorted(traps) {
+            writeln!(writer, "{:>12}  {}", count, cause)?;
+        }
+        Ok(())
+    }
+
+    /// Writes a line of the functions on the call stack from the outermost, separated by
+    /// semicolons, and the instructions retired in the innermost for every call stack.
+    pub fn write_folded(&self, mut writer: impl Write) -> io::Result<()> {
+        let mut stacks = BTreeMap::<_, u64>::new();
+        for (index, frame) in self.frames.iter().enumerate() {
+            if frame.count == 0 {
+                continue;
+            }
+            let mut names = vec![];
+            let mut next = Some(index);
+            while let Some(index) = next {
+                names.push(self.name(self.frames[index].address));
+                next = self.frames[index].caller;
+            }
+            names.reverse();
+            // calls from different sites to the same function share a stack
+            *stacks.entry(names.join(";")).or_default() += frame.count;
+        }
+        for (stack, count) in stacks {
+            writeln!(writer, "{} {}", stack, count)?;
+        }
+        Ok(())
+    }
+}
+
+/// Sorts the counts from the largest to the smallest, and the keys of the same count.
+fn sorted<K: Ord>(counts: impl IntoIterator<Item = (K, u64)>) -> Vec<(K, u64)> {
+    let mut counts = counts.into_iter().collect::<Vec<_>>();
+    counts.sort_by(|(k1, c1), (k2, c2)| c2.cmp(c1).then_with(|| k1.cmp(k2)));
+    counts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::{bus::memory::MEMORY_BASE_ADDRESS, Emulator};
+
+    fn run(program: &[u32], instructions: u64) -> Profiler {
+        let mut emulator = Emulator::default();
+        for (i, instruction) in program.iter().enumerate() {
+            emulator
+                .cpu_mut()
+                .bus
+                .store32(MEMORY_BASE_ADDRESS + i as u64 * 4, *instruction);
+        }
+        let mut profiler = Profiler::default();
+        profiler.add_symbol("main", MEMORY_BASE_ADDRESS, 0);
+        profiler.add_symbol("leaf", MEMORY_BASE_ADDRESS + 0x10, 8);
+        emulator.set_profiler(profiler);
+        emulator.run_for(instructions);
+        emulator.take_profiler().unwrap()
+    }
+
+    #[test]
+    fn report_ok() {
+        let profiler = run(
+            &[
+                // addi a0, zero, 1
+                0x0010_0513,
+                // addi a0, a0, 1
+                0x0015_0513,
+                // mul a0, a0, a0
+                0x02a5_0533,
+                // unimp
+                0x0000_0000,
+            ],
+            4,
+        );
+        assert_eq!(profiler.instructions(), 3);
+        let mut report = vec![];
+        profiler.report(&mut report).unwrap();
+        let report = String::from_utf8(report).unwrap();
+        assert!(report.starts_with("instructions: 3\n\nopcodes:\n"));
+        assert!(report.contains("           2  66.67%  Rv32iOpcodeI         addi\n"));
+        assert!(report.contains("           1  33.33%  Rv32mOpcodeR         mul\n"));
+        assert!(report.contains("           3 100.00%  main\n"));
+        assert!(report.contains("0x0000000080000000 addi     main+0x0\n"));
+        assert!(report.ends_with("traps:\n           1  Exception(IllegalInstruction)\n"));
+    }
+
+    #[test]
+    fn folded_ok() {
+        let profiler = run(
+            &[
+                // jal ra, leaf
+                0x0100_00ef,
+                // jal ra, leaf
+                0x00c0_00ef,
+                // jal zero, 0
+                0x0000_006f,
+                0x0000_0013,
+                // leaf: addi a0, a0, 1
+                0x0015_0513,
+                // ret
+                0x0000_8067,
+            ],
+            8,
+        );
+        let mut folded = vec![];
+        profiler.write_folded(&mut folded).unwrap();
+        assert_eq!(String::from_utf8(folded).unwrap(), "main 4\nmain;leaf 4\n");
+    }
+}
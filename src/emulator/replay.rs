@@ -0,0 +1,357 @@
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Error, ErrorKind, Read, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// The first line of an input log.
+const HEADER: &str = "five-inputs 3";
+
+/// The source of the time CSR.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Clock {
+    /// The seconds since the Unix epoch.
+    #[default]
+    WallClock,
+    /// The mtime of the clint, which advances with the instructions, so that runs are
+    /// reproducible. It counts ticks of `MTIME_FREQUENCY`.
+    Mtime,
+}
+
+/// An input of the machine that does not derive from its state.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Input {
+    /// The value of the time CSR on the wall clock.
+    Time(u64),
+    /// The bytes that a read of the stdin of the host returned, which are none at its end.
+    Stdin(Vec<u8>),
+}
+
+/// The inputs of a run, each with the hart that received it and the value of its instret at
+/// that time. Only the changes of the time are recorded.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct InputLog {
+    inputs: Vec<(usize, u64, Input)>,
+}
+
+impl InputLog {
+    pub fn inputs(&self) -> &[(usize, u64, Input)] {
+        &self.inputs
+    }
+
+    pub fn push(&mut self, hart: usize, instret: u64, input: Input) {
+        self.inputs.push((hart, instret, input));
+    }
+
+    /// Discards the inputs that the hart received at or after the instret.
+    pub(crate) fn truncate(&mut self, hart: usize, instret: u64) {
+        self.inputs.retain(|(h, i, _)| *h != hart || *i < instret);
+    }
+
+    /// Returns the last time recorded for the hart.
+    fn last_time(&self, hart: usize) -> Option<u64> {
+        self.inputs
+            .iter()
+            .rev()
+            .find_map(|(h, _, input)| match input {
+                Input::Time(time) if *h == hart => Some(*time),
+                _ => None,
+            })
+    }
+
+    /// Writes a header and a line of `time <hart> <instret> <value>` or
+    /// `stdin <hart> <instret> <hex bytes>` for every input.
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writeln!(writer, "{}", HEADER)?;
+        for (hart, instret, input) in &self.inputs {
+            match input {
+                Input::Time(time) => writeln!(writer, "time {} {} {}", hart, instret, time)?,
+                Input::Stdin(bytes) => {
+                    write!(writer, "stdin {} {} ", hart, instret)?;
+                    for byte in bytes {
+                        write!(writer, "{:02x}", byte)?;
+                    }
+                    writeln!(writer)?;
+                }
+            }
+        }
+        writer.flush()
+    }
+
+    pub fn load(path: &str) -> io::Result<Self> {
+        fs::read_to_string(path)?.parse()
+    }
+}
+
+impl std::str::FromStr for InputLog {
+    type Err = Error;
+
+    fn from_str(text: &str) -> io::Result<Self> {
+        let invalid = |line: &str| Error::new(ErrorKind::InvalidData, format!("invalid: {}", line));
+        let mut lines = text.lines();
+        if lines.next() != Some(HEADER) {
+            return Err(invalid("header"));
+        }
+        let mut log = Self::default();
+        for line in lines {
+            match line.split_whitespace().collect::<Vec<_>>()[..] {
+                ["time", hart, instret, time] => log.push(
+                    hart.parse().map_err(|_| invalid(line))?,
+                    instret.parse().map_err(|_| invalid(line))?,
+                    Input::Time(time.parse().map_err(|_| invalid(line))?),
+                ),
+                ["stdin", hart, instret, ref bytes @ ..] if bytes.len() <= 1 => log.push(
+                    hart.parse().map_err(|_| invalid(line))?,
+                    instret.parse().map_err(|_| invalid(line))?,
+                    Input::Stdin(parse_hex(bytes.first().unwrap_or(&"")).ok_or(invalid(line))?),
+                ),
+                [] => {}
+                _ => return Err(invalid(line)),
+            }
+        }
+        Ok(log)
+    }
+}
+
+fn parse_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.is_ascii() || !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
+
+/// The position of a hart in a replayed log: the index of the first input that it has not
+/// looked at, and the last time before it.
+#[derive(Clone, Copy, Debug, Default)]
+struct Cursor {
+    index: usize,
+    instret: u64,
+    time: Option<u64>,
+}
+
+/// A log that is replayed, with the position of every hart in it, so that the inputs are
+/// looked up as the harts advance instead of searched for.
+#[derive(Debug, Default)]
+pub struct Replay {
+    log: InputLog,
+    cursors: Vec<Cursor>,
+    // the index after the last read of the stdin
+    stdin: usize,
+}
+
+impl Replay {
+    pub fn new(log: InputLog) -> Self {
+        Self {
+            log,
+            ..Self::default()
+        }
+    }
+
+    pub fn into_log(self) -> InputLog {
+        self.log
+    }
+
+    /// Returns the time that the hart read at the instret, i.e. the last time recorded for the
+    /// hart at or before it.
+    fn time(&mut self, hart: usize, instret: u64) -> Option<u64> {
+        if self.cursors.len() <= hart {
+            self.cursors.resize(hart + 1, Cursor::default());
+        }
+        let cursor = &mut self.cursors[hart];
+        // the hart has gone back to a checkpoint
+        if instret < cursor.instret {
+            *cursor = Cursor::default();
+        }
+        cursor.instret = instret;
+        for (h, i, input) in &self.log.inputs[cursor.index..] {
+            if *h == hart {
+                if *i > instret {
+                    break;
+                }
+                if let Input::Time(time) = input {
+                    cursor.time = Some(*time);
+                }
+            }
+            cursor.index += 1;
+        }
+        cursor.time
+    }
+
+    /// Returns the bytes of the next read of the stdin, which are none after the last one.
+    fn stdin(&mut self) -> &[u8] {
+        let inputs = &self.log.inputs;
+        while let Some((_, _, input)) = inputs.get(self.stdin) {
+            self.stdin += 1;
+            if let Input::Stdin(bytes) = input {
+                return bytes;
+            }
+        }
+        &[]
+    }
+}
+
+/// Whether the inputs come from the host, are recorded, or are replayed from a log.
+#[derive(Debug, Default)]
+pub enum Inputs {
+    #[default]
+    Live,
+    Record(InputLog),
+    Replay(Replay),
+}
+
+impl Inputs {
+    /// Returns the value of the time CSR of the hart at the instret on the wall clock.
+    pub(crate) fn time(&mut self, hart: usize, instret: u64) -> u64 {
+        match self {
+            Self::Replay(replay) => replay.time(hart, instret).unwrap_or_default(),
+            Self::Record(log) => {
+                let time = wall_clock();
+                if log.last_time(hart) != Some(time) {
+                    log.push(hart, instret, Input::Time(time));
+                }
+                time
+            }
+            Self::Live => wall_clock(),
+        }
+    }
+
+    /// Reads the stdin of the host into the buffer for the hart at the instret, and returns
+    /// the number of bytes read.
+    pub(crate) fn read_stdin(
+        &mut self,
+        hart: usize,
+        instret: u64,
+        buffer: &mut [u8],
+    ) -> io::Result<usize> {
+        match self {
+            Self::Replay(replay) => {
+                let bytes = replay.stdin();
+                let read = bytes.len().min(buffer.len());
+                buffer[..read].copy_from_slice(&bytes[..read]);
+                Ok(read)
+            }
+            Self::Record(log) => {
+                let read = io::stdin().read(buffer)?;
+                log.push(hart, instret, Input::Stdin(buffer[..read].to_vec()));
+                Ok(read)
+            }
+            Self::Live => io::stdin().read(buffer),
+        }
+    }
+}
+
+fn wall_clock() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|t| t.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::{testing, Emulator};
+
+    const MTIME: u64 = 0x0200_bff8;
+
+    // csrr a0, time
+    const CSRR_TIME: u32 = 0xc010_2573;
+    // jal zero, -4
+    const LOOP: u32 = 0xffdf_f06f;
+
+    fn emulator() -> Emulator {
+        testing::emulator(&[CSRR_TIME, LOOP])
+    }
+
+    #[test]
+    fn time_ok() {
+        let mut log = InputLog::default();
+        log.push(0, 0, Input::Time(100));
+        log.push(1, 5, Input::Time(102));
+        log.push(0, 7, Input::Stdin(vec![1]));
+        log.push(0, 10, Input::Time(101));
+        let mut inputs = Inputs::Replay(Replay::new(log));
+        assert_eq!(inputs.time(0, 9), 100);
+        assert_eq!(inputs.time(0, 10), 101);
+        assert_eq!(inputs.time(0, 1000), 101);
+        assert_eq!(inputs.time(1, 4), 0);
+        assert_eq!(inputs.time(1, 9), 102);
+        // back to a checkpoint
+        assert_eq!(inputs.time(0, 9), 100);
+
+        // only the changes are recorded
+        let mut inputs = Inputs::Record(InputLog::default());
+        let time = inputs.time(0, 0);
+        inputs.time(0, 1);
+        let Inputs::Record(recorded) = inputs else {
+            unreachable!()
+        };
+        assert!(recorded.inputs().len() <= 2);
+        assert_eq!(recorded.inputs()[0], (0, 0, Input::Time(time)));
+    }
+
+    #[test]
+    fn stdin_ok() {
+        let mut log = InputLog::default();
+        log.push(0, 3, Input::Stdin(b"ab".to_vec()));
+        log.push(0, 4, Input::Time(100));
+        log.push(0, 9, Input::Stdin(vec![]));
+        let mut inputs = Inputs::Replay(Replay::new(log));
+        let mut buffer = [0; 4];
+        assert_eq!(inputs.read_stdin(0, 3, &mut buffer).unwrap(), 2);
+        assert_eq!(&buffer[..2], b"ab");
+        assert_eq!(inputs.read_stdin(0, 9, &mut buffer).unwrap(), 0);
+        assert_eq!(inputs.read_stdin(0, 10, &mut buffer).unwrap(), 0);
+    }
+
+    #[test]
+    fn parse_ok() {
+        let log = "five-inputs 3\ntime 0 0 100\ntime 1 10 101\nstdin 0 12 0a7f\nstdin 0 13\n"
+            .parse::<InputLog>()
+            .unwrap();
+        assert_eq!(
+            log.inputs(),
+            [
+                (0, 0, Input::Time(100)),
+                (1, 10, Input::Time(101)),
+                (0, 12, Input::Stdin(vec![0x0a, 0x7f])),
+                (0, 13, Input::Stdin(vec![])),
+            ]
+        );
+        assert!("time 0 0 100".parse::<InputLog>().is_err());
+        assert!("five-inputs 3\ntime 0 0".parse::<InputLog>().is_err());
+        assert!("five-inputs 3\nstdin 0 0 abc".parse::<InputLog>().is_err());
+    }
+
+    #[test]
+    fn replay_ok() {
+        let mut deterministic = emulator();
+        deterministic.set_clock(Clock::Mtime);
+        deterministic.run_for(5);
+        assert_eq!(deterministic.cpu().x().readu(10), 4);
+        // the time follows mtime when the guest writes it
+        deterministic.cpu_mut().bus.store64(MTIME, 1000);
+        deterministic.run_for(2);
+        assert_eq!(deterministic.cpu().x().readu(10), 1001);
+
+        let mut recorded = emulator();
+        recorded.record_inputs();
+        recorded.run_for(5);
+        let log = recorded.take_input_log().unwrap();
+        assert!(recorded.take_input_log().is_none());
+
+        // the replayed run reads the recorded time
+        let mut replayed = emulator();
+        let mut constant = InputLog::default();
+        constant.push(0, 0, Input::Time(7));
+        replayed.replay_inputs(constant);
+        replayed.run_for(5);
+        assert_eq!(replayed.cpu().x().readu(10), 7);
+        let mut replayed = emulator();
+        replayed.replay_inputs(log);
+        replayed.run_for(5);
+        assert_eq!(replayed.cpu().x().readu(10), recorded.cpu().x().readu(10));
+    }
+}
@@ -0,0 +1,194 @@
+use crate::emulator::snapshot::Snapshot;
+
+// The most checkpoints that the history keeps.
+const MAX_CHECKPOINTS: usize = 64;
+
+/// The checkpoints that reverse execution re-executes from. A position is the number of steps
+/// the hart has taken since the history started. The checkpoints are thinned out as they get
+/// older, so the spacing between them grows about exponentially with their age.
+pub(crate) struct History {
+    interval: u64,
+    position: u64,
+    checkpoints: Vec<(u64, Snapshot)>,
+    // whether the machine has been changed by something else than the hart
+    modified: bool,
+}
+
+impl History {
+    pub(crate) fn new(interval: u64) -> Self {
+        Self {
+            interval: interval.max(1),
+            position: 0,
+            checkpoints: vec![],
+            modified: false,
+        }
+    }
+
+    pub(crate) fn position(&self) -> u64 {
+        self.position
+    }
+
+    pub(crate) fn advance(&mut self) {
+        self.position += 1;
+    }
+
+    /// Takes a checkpoint every interval steps, and whenever the machine has been modified from
+    /// outside since re-execution cannot reproduce the modification.
+    pub(crate) fn checkpoint(&mut self, snapshot: impl FnOnce() -> Snapshot) {
+        let due = match self.checkpoints.last() {
+            Some((position, _)) => self.position >= position + self.interval,
+            None => true,
+        };
+        if due || self.modified {
+            let position = self.position;
+            self.checkpoints.retain(|(p, _)| *p < position);
+            self.checkpoints.push((position, snapshot()));
+            self.modified = false;
+            if self.checkpoints.len() > MAX_CHECKPOINTS {
+                self.thin();
+            }
+        }
+    }
+
+    /// Drops the checkpoint that leaves the smallest gap for its age, keeping the oldest and
+    /// the newest.
+    fn thin(&mut self) {
+        let newest = self.position;
+        let positions = self.checkpoints.iter().map(|(p, _)| *p).collect::<Vec<_>>();
+        let cost = |i: usize| {
+            let gap = (positions[i + 1] - positions[i - 1]) as u128;
+            let age = (newest - positions[i]).max(1) as u128;
+            (gap, age)
+        };
+        let thinnest = (1..positions.len() - 1).min_by(|&i, &j| {
+            let ((gap_i, age_i), (gap_j, age_j)) = (cost(i), cost(j));
+            (gap_i * age_j).cmp(&(gap_j * age_i))
+        });
+        if let Some(i) = thinnest {
+            self.checkpoints.remove(i);
+        }
+    }
+
+    pub(crate) fn set_modified(&mut self) {
+        self.modified = true;
+    }
+
+    pub(crate) fn oldest(&self) -> Option<u64> {
+        self.checkpoints.first().map(|(position, _)| *position)
+    }
+
+    /// Returns the position of the last checkpoint at or before the position.
+    pub(crate) fn before(&self, position: u64) -> Option<u64> {
+        self.checkpoints
+            .iter()
+            .rev()
+            .map(|(p, _)| *p)
+            .find(|p| *p <= position)
+    }
+
+    /// Returns the last checkpoint at or before the position to restore, and discards the
+    /// checkpoints after it so that running forward again starts a new history.
+    pub(crate) fn rewind(&mut self, position: u64) -> Option<&Snapshot> {
+        self.checkpoints.retain(|(p, _)| *p <= position);
+        let (p, snapshot) = self.checkpoints.last()?;
+        self.position = *p;
+        self.modified = false;
+        Some(snapshot)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::{
+        bus::memory::MEMORY_BASE_ADDRESS, semihosting::Semihosting, testing, Emulator, StopReason,
+    };
+
+    fn emulator() -> Emulator {
+        let mut emulator = testing::counter();
+        emulator.enable_reverse(4).unwrap();
+        emulator
+    }
+
+    #[test]
+    fn reverse_step_ok() {
+        let mut emulator = emulator();
+        assert_eq!(emulator.reverse_step(), StopReason::StartOfHistory);
+        emulator.run_for(10);
+        assert_eq!(emulator.cpu().x().readu(10), 4);
+        assert_eq!(emulator.reverse_step(), StopReason::InstructionLimit);
+        assert_eq!(emulator.cpu().pc(), MEMORY_BASE_ADDRESS);
+        assert_eq!(emulator.cpu().x().readu(10), 3);
+        assert_eq!(emulator.cpu().bus.load64(MEMORY_BASE_ADDRESS + 0x100), 3);
+        assert_eq!(emulator.reverse_for(5), StopReason::InstructionLimit);
+        assert_eq!(emulator.cpu().x().readu(10), 2);
+        assert_eq!(emulator.reverse_for(100), StopReason::StartOfHistory);
+        assert_eq!(emulator.cpu().x().readu(10), 0);
+        assert_eq!(emulator.cpu().pc(), MEMORY_BASE_ADDRESS);
+
+        // running forward again reproduces the run
+        emulator.run_for(10);
+        assert_eq!(emulator.cpu().x().readu(10), 4);
+        assert_eq!(emulator.cpu().pc(), MEMORY_BASE_ADDRESS + 4);
+    }
+
+    #[test]
+    fn reverse_continue_ok() {
+        let mut emulator = emulator();
+        emulator.run_for(20);
+        emulator.add_breakpoint(MEMORY_BASE_ADDRESS + 4);
+        assert_eq!(
+            emulator.reverse_continue(),
+            StopReason::Breakpoint(MEMORY_BASE_ADDRESS + 4)
+        );
+        assert_eq!(emulator.cpu().x().readu(10), 7);
+        emulator.remove_breakpoint(MEMORY_BASE_ADDRESS + 4);
+        assert_eq!(
+            emulator.reverse_until(|cpu| cpu.x().readu(10) == 2),
+            StopReason::Condition
+        );
+        assert_eq!(emulator.cpu().x().readu(10), 2);
+        assert_eq!(emulator.cpu().pc(), MEMORY_BASE_ADDRESS);
+        assert_eq!(emulator.reverse_continue(), StopReason::StartOfHistory);
+        assert_eq!(emulator.cpu().x().readu(10), 0);
+
+        // a modification from outside is kept when going back to after it
+        emulator.run_for(3);
+        emulator.cpu_mut().x_mut().writeu(10, 100);
+        emulator.run_for(3);
+        assert_eq!(emulator.reverse_step(), StopReason::InstructionLimit);
+        assert_eq!(emulator.cpu().x().readu(10), 101);
+        assert_eq!(emulator.reverse_for(3), StopReason::InstructionLimit);
+        assert_eq!(emulator.cpu().x().readu(10), 1);
+    }
+
+    #[test]
+    fn hosted_ok() {
+        // re-execution cannot serve the semihosting calls again
+        let mut emulator = emulator();
+        emulator.set_semihosting(Semihosting::default());
+        assert_eq!(emulator.reverse_step(), StopReason::StartOfHistory);
+        assert!(emulator.enable_reverse(4).is_err());
+    }
+
+    #[test]
+    fn thin_ok() {
+        let snapshot = Emulator::default().snapshot();
+        let mut history = History::new(1);
+        for _ in 0..10_000 {
+            history.checkpoint(|| snapshot.clone());
+            history.advance();
+        }
+        let positions = history
+            .checkpoints
+            .iter()
+            .map(|(p, _)| *p)
+            .collect::<Vec<_>>();
+        assert_eq!(positions.len(), MAX_CHECKPOINTS);
+        assert_eq!(positions[0], 0);
+        assert_eq!(positions[MAX_CHECKPOINTS - 1], 9_999);
+        // the recent checkpoints are dense and the old ones sparse
+        assert_eq!(positions[MAX_CHECKPOINTS - 2], 9_998);
+        assert!(positions[2] - positions[1] > 1000);
+    }
+}
@@ -0,0 +1,496 @@
+use crate::emulator::cpu::Cpu;
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Error, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::{Component, Path, PathBuf};
+
+// The operations of the semihosting calls.
+const SYS_OPEN: u64 = 0x01;
+const SYS_CLOSE: u64 = 0x02;
+const SYS_WRITEC: u64 = 0x03;
+const SYS_WRITE0: u64 = 0x04;
+const SYS_WRITE: u64 = 0x05;
+const SYS_READ: u64 = 0x06;
+const SYS_READC: u64 = 0x07;
+const SYS_ISERROR: u64 = 0x08;
+const SYS_ISTTY: u64 = 0x09;
+const SYS_SEEK: u64 = 0x0a;
+const SYS_FLEN: u64 = 0x0c;
+const SYS_REMOVE: u64 = 0x0e;
+const SYS_RENAME: u64 = 0x0f;
+const SYS_CLOCK: u64 = 0x10;
+const SYS_TIME: u64 = 0x11;
+const SYS_ERRNO: u64 = 0x13;
+const SYS_GET_CMDLINE: u64 = 0x15;
+const SYS_HEAPINFO: u64 = 0x16;
+const SYS_EXIT: u64 = 0x18;
+const SYS_EXIT_EXTENDED: u64 = 0x20;
+
+// The reason of SYS_EXIT that comes with the exit code of the guest.
+const ADP_STOPPED_APPLICATION_EXIT: u64 = 0x20026;
+// The file name that opens the console.
+const CONSOLE: &str = ":tt";
+// The error numbers that SYS_ERRNO returns.
+const EIO: u64 = 5;
+const EBADF: u64 = 9;
+const EACCES: u64 = 13;
+const EFAULT: u64 = 14;
+const EINVAL: u64 = 22;
+
+/// A file that the guest has opened.
+enum Handle {
+    Stdin,
+    Stdout,
+    Stderr,
+    File(File),
+}
+
+/// Serves the semihosting calls of a bare-metal program. The program only reaches the files in
+/// the directory that it is given, and the console.
+pub struct Semihosting {
+    root: Option<PathBuf>,
+    cmdline: String,
+    handles: BTreeMap<u64, Handle>,
+    stdout: Box<dyn Write>,
+    stderr: Box<dyn Write>,
+    errno: u64,
+    // the time in nanoseconds at the first call of SYS_CLOCK
+    start: Option<u64>,
+}
+
+impl Default for Semihosting {
+    fn default() -> Self {
+        Self {
+            root: None,
+            cmdline: String::new(),
+            handles: BTreeMap::new(),
+            stdout: Box::new(io::stdout()),
+            stderr: Box::new(io::stderr()),
+            errno: 0,
+            start: None,
+        }
+    }
+}
+
+impl Semihosting {
+    /// Lets the program open the files in the directory. Without one, only the console opens.
+    pub fn set_root(&mut self, root: impl Into<PathBuf>) {
+        self.root = Some(root.into());
+    }
+
+    /// Sets the command line that SYS_GET_CMDLINE returns.
+    pub fn set_cmdline(&mut self, cmdline: impl Into<String>) {
+        self.cmdline = cmdline.into();
+    }
+
+    /// Sends what the program writes to the console to the writer.
+    pub fn set_stdout(&mut self, stdout: impl Write + 'static) {
+        self.stdout = Box::new(stdout);
+    }
+
+    /// Sends what the program writes to the console opened for appending to the writer.
+    pub fn set_stderr(&mut self, stderr: impl Write + 'static) {
+        self.stderr = Box::new(stderr);
+    }
+
+    /// Serves the operation in a0 with the parameter in a1, which usually points to a block of
+    /// doublewords, and writes the result to a0. Returns the exit code when the program exits.
+    pub fn call(&mut self, cpu: &mut Cpu) -> Option<u64> {
+        let operation = cpu.x.readu(10);
+        let parameter = cpu.x.readu(11);
+        let result = match operation {
+            SYS_EXIT | SYS_EXIT_EXTENDED => {
+                let code = match read_words(cpu, parameter, 2) {
+                    Ok(block) if block[0] == ADP_STOPPED_APPLICATION_EXIT => block[1],
+                    _ => 1,
+                };
+                return Some(code);
+            }
+            SYS_OPEN => read_words(cpu, parameter, 3).and_then(|block| {
+                let name = read_bytes(cpu, block[0], block[2])?;
+                self.open(&String::from_utf8_lossy(&name), block[1])
+            }),
+            SYS_CLOSE => read_words(cpu, parameter, 1)
+                .and_then(|block| self.handles.remove(&block[0]).ok_or(EBADF))
+                .map(|_| 0),
+            SYS_WRITEC => read_bytes(cpu, parameter, 1)
+                .and_then(|byte| write(&mut self.stdout, &byte))
+                .map(|_| 0),
+            SYS_WRITE0 => read_string(cpu, parameter)
+                .and_then(|bytes| write(&mut self.stdout, &bytes))
+                .map(|_| 0),
+            SYS_WRITE => read_words(cpu, parameter, 3).and_then(|block| {
+                let bytes = read_bytes(cpu, block[1], block[2])?;
+                self.write(block[0], &bytes)
+            }),
+            SYS_READ => read_words(cpu, parameter, 3).and_then(|block| {
+                // the buffer cannot be larger than the memory that it is written to
+                let bytes = self.read(cpu, block[0], block[2].min(cpu.bus.memory.size()))?;
+                write_bytes(cpu, block[1], &bytes)?;
+                // the result is the number of bytes that were not read
+                Ok(block[2] - bytes.len() as u64)
+            }),
+            SYS_READC => {
+                let mut byte = [0];
+                match cpu.read_stdin(&mut byte) {
+                    Ok(1) => Ok(byte[0] as u64),
+                    _ => Err(EIO),
+                }
+            }
+            SYS_ISERROR => {
+                read_words(cpu, parameter, 1).map(|block| ((block[0] as i64) < 0) as u64)
+            }
+            SYS_ISTTY => read_words(cpu, parameter, 1).and_then(|block| {
+                match self.handles.get(&block[0]).ok_or(EBADF)? {
+                    Handle::File(_) => Ok(0),
+                    _ => Ok(1),
+                }
+            }),
+            SYS_SEEK => read_words(cpu, parameter, 2).and_then(|block| {
+                let file = self.file(block[0])?;
+                file.seek(SeekFrom::Start(block[1])).map_err(errno)?;
+                Ok(0)
+            }),
+            SYS_FLEN => read_words(cpu, parameter, 1).and_then(|block| {
+                let file = self.file(block[0])?;
+                file.metadata().map(|m| m.len()).map_err(errno)
+            }),
+            SYS_REMOVE => read_words(cpu, parameter, 2).and_then(|block| {
+                let path = self.resolve(&read_bytes(cpu, block[0], block[1])?)?;
+                fs::remove_file(path).map(|_| 0).map_err(errno)
+            }),
+            SYS_RENAME => read_words(cpu, parameter, 4).and_then(|block| {
+                let from = self.resolve(&read_bytes(cpu, block[0], block[1])?)?;
+                let to = self.resolve(&read_bytes(cpu, block[2], block[3])?)?;
+                fs::rename(from, to).map(|_| 0).map_err(errno)
+            }),
+            SYS_CLOCK => {
+                let time = cpu.nanoseconds();
+                Ok(time.saturating_sub(*self.start.get_or_insert(time)) / 10_000_000)
+            }
+            SYS_TIME => Ok(cpu.nanoseconds() / 1_000_000_000),
+            SYS_ERRNO => Ok(self.errno),
+            SYS_GET_CMDLINE => read_words(cpu, parameter, 2).and_then(|block| {
+                let cmdline = [self.cmdline.as_bytes(), &[0]].concat();
+                if cmdline.len() as u64 > block[1] {
+                    return Err(EINVAL);
+                }
+                write_bytes(cpu, block[0], &cmdline)?;
+                let length = (cmdline.len() as u64 - 1).to_le_bytes();
+                write_bytes(cpu, parameter + 8, &length).map(|_| 0)
+            }),
+            // the program finds its heap and stack from its own symbols
+            SYS_HEAPINFO => read_words(cpu, parameter, 1)
+                .and_then(|block| write_bytes(cpu, block[0], &[0; 32]))
+                .map(|_| 0),
+            _ => Err(EINVAL),
+        };
+        let value = match result {
+            Ok(value) => value,
+            Err(errno) => {
+                self.errno = errno;
+                u64::MAX
+            }
+        };
+        cpu.x.writeu(10, value);
+        None
+    }
+
+    /// Opens the file in the mode of fopen, from "r" at 0 to "a+b" at 11.
+    fn open(&mut self, name: &str, mode: u64) -> Result<u64, u64> {
+        let handle = if name == CONSOLE {
+            match mode {
+                0..=3 => Handle::Stdin,
+                4..=7 => Handle::Stdout,
+                8..=11 => Handle::Stderr,
+                _ => return Err(EINVAL),
+            }
+        } else {
+            let path = self.resolve(name.as_bytes())?;
+            let mut options = OpenOptions::new();
+            match mode {
+                0 | 1 => options.read(true),
+                2 | 3 => options.read(true).write(true),
+                4 | 5 => options.write(true).create(true).truncate(true),
+                6 | 7 => options.read(true).write(true).create(true).truncate(true),
+                8 | 9 => options.append(true).create(true),
+                10 | 11 => options.read(true).append(true).create(true),
+                _ => return Err(EINVAL),
+            };
+            Handle::File(options.open(path).map_err(errno)?)
+        };
+        let handle_number = (1..).find(|h| !self.handles.contains_key(h)).unwrap();
+        self.handles.insert(handle_number, handle);
+        Ok(handle_number)
+    }
+
+    /// Returns the path of the name in the directory, unless the name is absolute or goes out
+    /// of the directory.
+    fn resolve(&self, name: &[u8]) -> Result<PathBuf, u64> {
+        let root = self.root.as_ref().ok_or(EACCES)?;
+        let name = String::from_utf8_lossy(name);
+        let path = Path::new(name.as_ref());
+        if !path
+            .components()
+            .all(|c| matches!(c, Component::Normal(_) | Component::CurDir))
+        {
+            return Err(EACCES);
+        }
+        Ok(root.join(path))
+    }
+
+    fn file(&mut self, handle: u64) -> Result<&mut File, u64> {
+        match self.handles.get_mut(&handle).ok_or(EBADF)? {
+            Handle::File(file) => Ok(file),
+            _ => Err(EBADF),
+        }
+    }
+
+    /// Writes the bytes and returns the number of bytes that were not written.
+    fn write(&mut self, handle: u64, bytes: &[u8]) -> Result<u64, u64> {
+        match self.handles.get_mut(&handle).ok_or(EBADF)? {
+            Handle::Stdout => write(&mut self.stdout, bytes)?,
+            Handle::Stderr => write(&mut self.stderr, bytes)?,
+            Handle::File(file) => file.write_all(bytes).map_err(errno)?,
+            Handle::Stdin => return Err(EBADF),
+        }
+        Ok(0)
+    }
+
+    fn read(&mut self, cpu: &mut Cpu, handle: u64, count: u64) -> Result<Vec<u8>, u64> {
+        let mut buffer = vec![0; count as usize];
+        let read = match self.handles.get_mut(&handle).ok_or(EBADF)? {
+            Handle::Stdin => cpu.read_stdin(&mut buffer),
+            Handle::File(file) => file.read(&mut buffer),
+            _ => return Err(EBADF),
+        }
+        .map_err(errno)?;
+        buffer.truncate(read);
+        Ok(buffer)
+    }
+}
+
+fn write(writer: &mut dyn Write, bytes: &[u8]) -> Result<(), u64> {
+    writer
+        .write_all(bytes)
+        .and_then(|_| writer.flush())
+        .map_err(errno)
+}
+
+fn errno(error: Error) -> u64 {
+    match error.raw_os_error() {
+        Some(errno) => errno as u64,
+        None if error.kind() == ErrorKind::PermissionDenied => EACCES,
+        None => EIO,
+    }
+}
+
+fn read_bytes(cpu: &Cpu, address: u64, count: u64) -> Result<Vec<u8>, u64> {
+    cpu.bus.memory.load_bytes(address, count).ok_or(EFAULT)
+}
+
+fn write_bytes(cpu: &mut Cpu, address: u64, bytes: &[u8]) -> Result<u64, u64> {
+    match cpu.bus.memory.store_bytes(address, bytes) {
+        true => Ok(0),
+        false => Err(EFAULT),
+    }
+}
+
+/// Reads the block of doublewords that the parameter points to.
+fn read_words(cpu: &Cpu, address: u64, count: u64) -> Result<Vec<u64>, u64> {
+    let bytes = read_bytes(cpu, address, count * 8)?;
+    Ok(bytes
+        .chunks_exact(8)
+        .map(|word| u64::from_le_bytes(word.try_into().unwrap()))
+        .collect())
+}
+
+fn read_string(cpu: &Cpu, address: u64) -> Result<Vec<u8>, u64> {
+    let mut bytes = vec![];
+    for a in address.. {
+        match read_bytes(cpu, a, 1)?[0] {
+            0 => break,
+            byte => bytes.push(byte),
+        }
+    }
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::{
+        bus::{clint::MTIME_FREQUENCY, memory::MEMORY_BASE_ADDRESS},
+        replay::{Clock, Input, InputLog},
+        testing::{self, Output},
+        Emulator, StopReason,
+    };
+    use std::net::TcpListener;
+
+    // The address of the parameter blocks and the strings.
+    const DATA: u64 = MEMORY_BASE_ADDRESS + 0x1000;
+    // The address of mtime in the clint.
+    const MTIME: u64 = 0x0200_bff8;
+
+    /// Returns an emulator that makes the semihosting call with a0 and a1 at every call.
+    fn emulator(semihosting: Semihosting) -> Emulator {
+        let mut emulator = testing::emulator(&[
+            0x01f0_1013, // slli zero, zero, 0x1f
+            0x0010_0073, // ebreak
+            0x4070_5013, // srai zero, zero, 7
+        ]);
+        emulator.set_semihosting(semihosting);
+        emulator
+    }
+
+    /// Makes a call from the start of the program and returns a0.
+    fn call(emulator: &mut Emulator, operation: u64, parameter: u64) -> u64 {
+        let cpu = emulator.cpu_mut();
+        cpu.set_pc(MEMORY_BASE_ADDRESS);
+        cpu.x_mut().writeu(10, operation);
+        cpu.x_mut().writeu(11, parameter);
+        emulator.run_for(3);
+        emulator.cpu().x().readu(10)
+    }
+
+    fn store(emulator: &mut Emulator, address: u64, bytes: &[u8]) {
+        assert!(emulator.cpu_mut().bus.memory.store_bytes(address, bytes));
+    }
+
+    fn store_words(emulator: &mut Emulator, address: u64, words: &[u64]) {
+        let bytes = words
+            .iter()
+            .flat_map(|w| w.to_le_bytes())
+            .collect::<Vec<_>>();
+        store(emulator, address, &bytes);
+    }
+
+    #[test]
+    fn console_ok() {
+        let output = Output::default();
+        let mut semihosting = Semihosting::default();
+        semihosting.set_stdout(output.clone());
+        semihosting.set_cmdline("prog arg");
+        let mut emulator = emulator(semihosting);
+
+        store(&mut emulator, DATA + 0x100, b":tt\0hi\0");
+        store_words(&mut emulator, DATA, &[DATA + 0x100, 4, 3]);
+        let handle = call(&mut emulator, SYS_OPEN, DATA);
+        assert_eq!(handle, 1);
+        assert_eq!(emulator.cpu().pc(), MEMORY_BASE_ADDRESS + 12);
+        store_words(&mut emulator, DATA, &[handle, DATA + 0x104, 2]);
+        assert_eq!(call(&mut emulator, SYS_WRITE, DATA), 0);
+        assert_eq!(call(&mut emulator, SYS_WRITE0, DATA + 0x104), 0);
+        assert_eq!(*output.0.borrow(), b"hihi");
+
+        store_words(&mut emulator, DATA, &[DATA + 0x200, 64]);
+        assert_eq!(call(&mut emulator, SYS_GET_CMDLINE, DATA), 0);
+        assert_eq!(
+            emulator.cpu().bus.memory.load_bytes(DATA + 0x200, 9),
+            Some(b"prog arg\0".to_vec())
+        );
+        assert_eq!(emulator.cpu().bus.load64(DATA + 8), 8);
+
+        assert_eq!(call(&mut emulator, 0x1234, 0), u64::MAX);
+        assert_eq!(call(&mut emulator, SYS_ERRNO, 0), EINVAL);
+
+        store_words(&mut emulator, DATA, &[ADP_STOPPED_APPLICATION_EXIT, 3]);
+        emulator.cpu_mut().set_pc(MEMORY_BASE_ADDRESS);
+        emulator.cpu_mut().x_mut().writeu(10, SYS_EXIT);
+        emulator.cpu_mut().x_mut().writeu(11, DATA);
+        assert_eq!(emulator.run(), StopReason::Exit(3));
+    }
+
+    #[test]
+    fn files_ok() {
+        let root = std::env::temp_dir().join(format!("five-semihosting-{}", std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+        let mut semihosting = Semihosting::default();
+        semihosting.set_root(&root);
+        let mut emulator = emulator(semihosting);
+
+        // a file in the directory is written and read back
+        store(&mut emulator, DATA + 0x100, b"out.txt\0data");
+        store_words(&mut emulator, DATA, &[DATA + 0x100, 4, 7]);
+        let handle = call(&mut emulator, SYS_OPEN, DATA);
+        store_words(&mut emulator, DATA, &[handle, DATA + 0x108, 4]);
+        assert_eq!(call(&mut emulator, SYS_WRITE, DATA), 0);
+        assert_eq!(call(&mut emulator, SYS_CLOSE, DATA), 0);
+        assert_eq!(fs::read(root.join("out.txt")).unwrap(), b"data");
+
+        store_words(&mut emulator, DATA, &[DATA + 0x100, 0, 7]);
+        let handle = call(&mut emulator, SYS_OPEN, DATA);
+        store_words(&mut emulator, DATA, &[handle]);
+        assert_eq!(call(&mut emulator, SYS_FLEN, DATA), 4);
+        store_words(&mut emulator, DATA, &[handle, DATA + 0x200, 10]);
+        assert_eq!(call(&mut emulator, SYS_READ, DATA), 6);
+        assert_eq!(emulator.cpu().bus.load32(DATA + 0x200), 0x6174_6164);
+        // a count larger than the memory reads what the file has
+        store_words(&mut emulator, DATA, &[handle, 0]);
+        assert_eq!(call(&mut emulator, SYS_SEEK, DATA), 0);
+        store_words(&mut emulator, DATA, &[handle, DATA + 0x200, u64::MAX]);
+        assert_eq!(call(&mut emulator, SYS_READ, DATA), u64::MAX - 4);
+
+        // the program cannot leave the directory
+        store(&mut emulator, DATA + 0x100, b"../x/etc");
+        store_words(&mut emulator, DATA, &[DATA + 0x100, 0, 4]);
+        assert_eq!(call(&mut emulator, SYS_OPEN, DATA), u64::MAX);
+        store_words(&mut emulator, DATA, &[DATA + 0x104, 0, 4]);
+        assert_eq!(call(&mut emulator, SYS_OPEN, DATA), u64::MAX);
+        assert_eq!(call(&mut emulator, SYS_ERRNO, 0), EACCES);
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn stdin_ok() {
+        let mut log = InputLog::default();
+        log.push(0, 0, Input::Stdin(b"a".to_vec()));
+        log.push(0, 5, Input::Stdin(b"bcd".to_vec()));
+        let mut emulator = emulator(Semihosting::default());
+        emulator.replay_inputs(log);
+
+        // the reads of the stdin return the recorded bytes
+        assert_eq!(call(&mut emulator, SYS_READC, 0), b'a' as u64);
+        store(&mut emulator, DATA + 0x100, b":tt\0");
+        store_words(&mut emulator, DATA, &[DATA + 0x100, 0, 3]);
+        let handle = call(&mut emulator, SYS_OPEN, DATA);
+        store_words(&mut emulator, DATA, &[handle, DATA + 0x200, 8]);
+        assert_eq!(call(&mut emulator, SYS_READ, DATA), 5);
+        assert_eq!(
+            emulator.cpu().bus.memory.load_bytes(DATA + 0x200, 3),
+            Some(b"bcd".to_vec())
+        );
+        assert_eq!(call(&mut emulator, SYS_READ, DATA), 8);
+    }
+
+    #[test]
+    fn clock_ok() {
+        let mut emulator = emulator(Semihosting::default());
+        emulator.set_clock(Clock::Mtime);
+
+        // the ticks of mtime are converted to centiseconds and seconds
+        emulator.cpu_mut().bus.store64(MTIME, 3 * MTIME_FREQUENCY);
+        assert_eq!(call(&mut emulator, SYS_CLOCK, 0), 0);
+        emulator.cpu_mut().bus.store64(MTIME, 5 * MTIME_FREQUENCY);
+        assert_eq!(call(&mut emulator, SYS_CLOCK, 0), 200);
+        assert_eq!(call(&mut emulator, SYS_TIME, 0), 5);
+
+        // the drivers that step the hart by themselves cannot serve the calls
+        assert!(emulator.lockstep().is_err());
+        let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        assert!(emulator.gdb(listener).is_err());
+    }
+
+    #[test]
+    fn plain_ebreak_ok() {
+        // an EBREAK without the markers raises a breakpoint exception as usual
+        let mut emulator = Emulator::default();
+        emulator
+            .cpu_mut()
+            .bus
+            .store32(MEMORY_BASE_ADDRESS, 0x0010_0073);
+        emulator.set_semihosting(Semihosting::default());
+        emulator.set_stop_on_trap(true);
+        assert!(matches!(emulator.step(), StopReason::Trap(_)));
+    }
+}
@@ -0,0 +1,413 @@
+use crate::{
+    emulator::{
+        bus::{memory::PAGE_SIZE, SystemBus},
+        cpu::Cpu,
+    },
+    isa::privileged::mode::PrivilegeMode,
+};
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Error, ErrorKind, Read, Write};
+
+// The first bytes of a snapshot file.
+const MAGIC: &[u8; 8] = b"FIVESNAP";
+// The version of the snapshot format. It is incremented when the format changes.
+pub const SNAPSHOT_VERSION: u32 = 3;
+
+/// The state of a hart: its registers, its CSRs and its triggers.
+#[derive(Clone, Debug, PartialEq)]
+struct Hart {
+    x: [u64; 32],
+    f: [u64; 32],
+    pc: u64,
+    prv: PrivilegeMode,
+    wfi: bool,
+    debug_mode: bool,
+    csr: Vec<(u64, u64)>,
+    triggers: Vec<u64>,
+}
+
+impl Hart {
+    fn new(cpu: &Cpu) -> Self {
+        Self {
+            x: cpu.x.snapshot(),
+            f: cpu.f.snapshot(),
+            pc: cpu.pc(),
+            prv: cpu.prv,
+            wfi: cpu.wfi,
+            debug_mode: cpu.csr.is_debug_mode(),
+            csr: cpu.csr.snapshot(),
+            triggers: cpu.csr.triggers.snapshot(),
+        }
+    }
+
+    fn restore(&self, cpu: &mut Cpu) {
+        cpu.x.restore(self.x);
+        cpu.f.restore(self.f);
+        cpu.pc.jump(self.pc);
+        cpu.prv = self.prv;
+        cpu.wfi = self.wfi;
+        cpu.csr.set_debug_mode(self.debug_mode);
+        cpu.csr.restore(&self.csr);
+        cpu.csr.triggers.restore(&self.triggers);
+    }
+}
+
+/// The state of the machine: the harts, the hart that runs next with the steps it has taken in
+/// its quantum, the CLINT, the UART, the reservations of LR/SC, the contents of the ROM and
+/// flash regions, and the range of the memory with its pages that are not filled with zeros.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Snapshot {
+    harts: Vec<Hart>,
+    current: usize,
+    slice: u64,
+    clint: Vec<u64>,
+    uart: [u64; 3],
+    reservations: Vec<(u64, u64)>,
+    regions: Vec<(u64, Vec<u8>)>,
+    memory: (u64, u64),
+    pages: Vec<(u64, Vec<u8>)>,
+}
+
+impl Snapshot {
+    /// Captures the harts in the order of their ids and the bus of the hart that runs.
+    pub(crate) fn new(harts: &[&Cpu], current: usize, slice: u64) -> Self {
+        let bus = &harts[current].bus;
+        Self {
+            harts: harts.iter().map(|cpu| Hart::new(cpu)).collect(),
+            current,
+            slice,
+            clint: bus.clint.snapshot(),
+            uart: bus.uart.snapshot(),
+            reservations: bus.reservations(),
+            regions: bus.region_contents(),
+            memory: (bus.memory.base(), bus.memory.size()),
+            pages: bus
+                .memory
+                .snapshot()
+                .into_iter()
+                .map(|(address, page)| (address, page.to_vec()))
+                .collect(),
+        }
+    }
+
+    pub(crate) fn restore_hart(&self, hart: usize, cpu: &mut Cpu) {
+        self.harts[hart].restore(cpu);
+    }
+
+    pub(crate) fn restore_bus(&self, bus: &mut SystemBus) {
+        bus.clint.restore(&self.clint);
+        bus.uart.restore(self.uart);
+        bus.restore_reservations(&self.reservations);
+        bus.restore_regions(&self.regions);
+        bus.memory.restore(
+            self.pages
+                .iter()
+                .map(|(address, page)| (*address, page.as_slice())),
+        );
+    }
+
+    pub fn harts(&self) -> usize {
+        self.harts.len()
+    }
+
+    /// Returns the base and the size of the memory.
+    pub fn memory(&self) -> (u64, u64) {
+        self.memory
+    }
+
+    /// Returns the hart that runs next.
+    pub fn current(&self) -> usize {
+        self.current
+    }
+
+    /// Returns the number of steps that the hart that runs next has taken in its quantum.
+    pub fn slice(&self) -> u64 {
+        self.slice
+    }
+
+    /// Returns the value of the CSR of the hart at the time of the snapshot.
+    pub fn csr(&self, hart: usize, address: u64) -> Option<u64> {
+        self.harts
+            .get(hart)?
+            .csr
+            .iter()
+            .find(|(a, _)| *a == address)
+            .map(|(_, value)| *value)
+    }
+
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_to(&mut writer)?;
+        writer.flush()
+    }
+
+    pub fn load(path: &str) -> io::Result<Self> {
+        Self::read_from(BufReader::new(File::open(path)?))
+    }
+
+    /// Writes the snapshot in little endian after the magic and the version.
+    pub fn write_to(&self, mut writer: impl Write) -> io::Result<()> {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&SNAPSHOT_VERSION.to_le_bytes())?;
+        let mut write = |value: u64| writer.write_all(&value.to_le_bytes());
+        write(self.harts.len() as u64)?;
+        write(self.current as u64)?;
+        write(self.slice)?;
+        for hart in &self.harts {
+            write(hart.pc)?;
+            write(hart.prv as u64)?;
+            write(hart.wfi as u64)?;
+            write(hart.debug_mode as u64)?;
+            for value in hart.x.iter().chain(hart.f.iter()) {
+                write(*value)?;
+            }
+            write(hart.csr.len() as u64)?;
+            for (address, value) in &hart.csr {
+                write(*address)?;
+                write(*value)?;
+            }
+            write(hart.triggers.len() as u64)?;
+            for value in &hart.triggers {
+                write(*value)?;
+            }
+        }
+        write(self.clint.len() as u64)?;
+        for value in &self.clint {
+            write(*value)?;
+        }
+        for value in self.uart {
+            write(value)?;
+        }
+        write(self.reservations.len() as u64)?;
+        for (hart, set) in &self.reservations {
+            write(*hart)?;
+            write(*set)?;
+        }
+        write(self.regions.len() as u64)?;
+        for (base, data) in &self.regions {
+            writer.write_all(&base.to_le_bytes())?;
+            writer.write_all(&(data.len() as u64).to_le_bytes())?;
+            writer.write_all(data)?;
+        }
+        let (base, size) = self.memory;
+        writer.write_all(&base.to_le_bytes())?;
+        writer.write_all(&size.to_le_bytes())?;
+        writer.write_all(&(self.pages.len() as u64).to_le_bytes())?;
+        for (address, page) in &self.pages {
+            writer.write_all(&address.to_le_bytes())?;
+            writer.write_all(page)?;
+        }
+        Ok(())
+    }
+
+    pub fn read_from(mut reader: impl Read) -> io::Result<Self> {
+        let invalid = |message: &str| Error::new(ErrorKind::InvalidData, message.to_string());
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid("not a snapshot"));
+        }
+        let mut version = [0; 4];
+        reader.read_exact(&mut version)?;
+        if u32::from_le_bytes(version) != SNAPSHOT_VERSION {
+            return Err(invalid("unsupported snapshot version"));
+        }
+        let mut read = || read_u64(&mut reader);
+        let count = read()?;
+        let current = read()?;
+        if current >= count {
+            return Err(invalid("invalid hart"));
+        }
+        let slice = read()?;
+        let mut harts = vec![];
+        for _ in 0..count {
+            let pc = read()?;
+            let prv = match read()? {
+                0b00 => PrivilegeMode::User,
+                0b01 => PrivilegeMode::Supervisor,
+                0b11 => PrivilegeMode::Machine,
+                _ => return Err(invalid("invalid privilege mode")),
+            };
+            let wfi = read()? != 0;
+            let debug_mode = read()? != 0;
+            let mut x = [0; 32];
+            for value in x.iter_mut() {
+                *value = read()?;
+            }
+            let mut f = [0; 32];
+            for value in f.iter_mut() {
+                *value = read()?;
+            }
+            let csr = (0..read()?)
+                .map(|_| Ok((read()?, read()?)))
+                .collect::<io::Result<Vec<_>>>()?;
+            let triggers = (0..read()?)
+                .map(|_| read())
+                .collect::<io::Result<Vec<_>>>()?;
+            harts.push(Hart {
+                x,
+                f,
+                pc,
+                prv,
+                wfi,
+                debug_mode,
+                csr,
+                triggers,
+            });
+        }
+        let clint = (0..read()?)
+            .map(|_| read())
+            .collect::<io::Result<Vec<_>>>()?;
+        if clint.len() != 1 + 2 * harts.len() {
+            return Err(invalid("invalid clint"));
+        }
+        let uart = [read()?, read()?, read()?];
+        let reservations = (0..read()?)
+            .map(|_| Ok((read()?, read()?)))
+            .collect::<io::Result<Vec<_>>>()?;
+        let mut regions = vec![];
+        for _ in 0..read_u64(&mut reader)? {
+            let base = read_u64(&mut reader)?;
+            let size = read_u64(&mut reader)?;
+            if base.checked_add(size).is_none() {
+                return Err(invalid("invalid region"));
+            }
+            // the size is not trusted with an allocation before the bytes are there
+            let mut data = vec![];
+            (&mut reader).take(size).read_to_end(&mut data)?;
+            if data.len() as u64 != size {
+                return Err(ErrorKind::UnexpectedEof.into());
+            }
+            regions.push((base, data));
+        }
+        let memory = (read_u64(&mut reader)?, read_u64(&mut reader)?);
+        let (base, size) = memory;
+        let end = base
+            .checked_add(size)
+            .ok_or_else(|| invalid("invalid memory"))?;
+        let pages = read_u64(&mut reader)?;
+        let mut snapshot = Self {
+            harts,
+            current: current as usize,
+            slice,
+            clint,
+            uart,
+            reservations,
+            regions,
+            memory,
+            pages: vec![],
+        };
+        for _ in 0..pages {
+            let address = read_u64(&mut reader)?;
+            if address < base
+                || !(address - base).is_multiple_of(PAGE_SIZE as u64)
+                || end - address < PAGE_SIZE as u64
+            {
+                return Err(invalid("invalid page"));
+            }
+            let mut page = vec![0; PAGE_SIZE];
+            reader.read_exact(&mut page)?;
+            snapshot.pages.push((address, page));
+        }
+        Ok(snapshot)
+    }
+}
+
+fn read_u64(mut reader: impl Read) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        emulator::{
+            bus::{memory::MEMORY_BASE_ADDRESS, region::Region},
+            cpu::csr::Csr,
+            testing, Emulator, StopReason,
+        },
+        isa::csr::user_level::INSTRET,
+    };
+
+    // The scratch register of the UART and the base of a flash region.
+    const SCR: u64 = 0x1000_0007;
+    const FLASH: u64 = 0x2000_0000;
+
+    #[test]
+    fn round_trip_ok() {
+        let mut emulator = testing::counter();
+        emulator.cpu_mut().bus.store64(0x0200_4000, 0x1234);
+        let flash = Region::from_bytes(FLASH, vec![0; 8], true).unwrap();
+        emulator.cpu_mut().bus.map(flash).unwrap();
+        emulator.cpu_mut().bus.store64(FLASH, 0x5678);
+        emulator.cpu_mut().bus.store8(SCR, 0x9a);
+        assert_eq!(emulator.run_for(10), StopReason::InstructionLimit);
+        let snapshot = emulator.snapshot();
+        assert_eq!(snapshot.csr(0, INSTRET), Some(10));
+        assert_eq!(snapshot.pages.len(), 1);
+
+        let mut bytes = vec![];
+        snapshot.write_to(&mut bytes).unwrap();
+        let read = Snapshot::read_from(bytes.as_slice()).unwrap();
+        assert_eq!(read, snapshot);
+
+        // the restored machine continues exactly like the original
+        let mut restored = Emulator::default();
+        let flash = Region::from_bytes(FLASH, vec![0; 8], true).unwrap();
+        restored.cpu_mut().bus.map(flash).unwrap();
+        restored.restore(&read);
+        assert_eq!(restored.snapshot(), snapshot);
+        // the devices and the regions are restored too
+        assert_eq!(restored.cpu().bus.load64(FLASH), 0x5678);
+        assert_eq!(restored.cpu().bus.load8(SCR), 0x9a);
+        emulator.run_for(5);
+        restored.run_for(5);
+        assert_eq!(restored.cpu().x().snapshot(), emulator.cpu().x().snapshot());
+        assert_eq!(restored.cpu().pc(), emulator.cpu().pc());
+        assert_eq!(restored.cpu().csr.read(INSTRET), 15);
+        assert_eq!(restored.cpu().bus.load64(MEMORY_BASE_ADDRESS + 0x100), 5);
+        assert_eq!(restored.cpu().bus.load64(0x0200_4000), 0x1234);
+    }
+
+    #[test]
+    fn memory_ok() {
+        let mut emulator = testing::counter();
+        emulator.run_for(10);
+        let snapshot = emulator.snapshot();
+
+        // the memory of the snapshot replaces a memory with another range
+        let mut restored = Emulator::default();
+        restored.set_memory(0x4000_0000, PAGE_SIZE as u64);
+        restored.restore(&snapshot);
+        assert_eq!(restored.snapshot(), snapshot);
+        assert_eq!(restored.cpu().bus.memory.base(), MEMORY_BASE_ADDRESS);
+        assert_eq!(restored.cpu().bus.load64(MEMORY_BASE_ADDRESS + 0x100), 3);
+        restored.run_for(5);
+        assert_eq!(restored.cpu().bus.load64(MEMORY_BASE_ADDRESS + 0x100), 5);
+    }
+
+    #[test]
+    fn invalid_ok() {
+        let mut bytes = vec![];
+        testing::counter().snapshot().write_to(&mut bytes).unwrap();
+        bytes[8] = 0xff;
+        let error = Snapshot::read_from(bytes.as_slice()).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        bytes[8] = SNAPSHOT_VERSION as u8;
+
+        // the pages must be in the memory
+        let page = bytes.len() - PAGE_SIZE - 8;
+        for address in [0, MEMORY_BASE_ADDRESS + 1, u64::MAX - 1] {
+            let mut bytes = bytes.clone();
+            bytes[page..page + 8].copy_from_slice(&address.to_le_bytes());
+            let error = Snapshot::read_from(bytes.as_slice()).unwrap_err();
+            assert_eq!(error.kind(), ErrorKind::InvalidData);
+        }
+
+        bytes.truncate(100);
+        let error = Snapshot::read_from(bytes.as_slice()).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::UnexpectedEof);
+    }
+}
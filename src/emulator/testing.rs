@@ -0,0 +1,31 @@
+//! The fixtures that the tests of the emulator share.
+
+use crate::emulator::{bus::memory::MEMORY_BASE_ADDRESS, Emulator};
+pub(crate) use crate::output::Output;
+
+/// Returns an emulator with the program at the base of the memory.
+pub(crate) fn emulator(program: &[u32]) -> Emulator {
+    let mut emulator = Emulator::default();
+    for (i, instruction) in program.iter().enumerate() {
+        emulator
+            .cpu_mut()
+            .bus
+            .store32(MEMORY_BASE_ADDRESS + i as u64 * 4, *instruction);
+    }
+    emulator
+}
+
+/// Returns an emulator that counts in a0 and stores the count at 0x100 past the base of the
+/// memory in a loop of three instructions.
+pub(crate) fn counter() -> Emulator {
+    let mut emulator = emulator(&[
+        // addi a0, a0, 1
+        0x0015_0513,
+        // sd a0, 0x100(a1)
+        0x10a5_b023,
+        // jal zero, -8
+        0xff9f_f06f,
+    ]);
+    emulator.cpu_mut().x_mut().writeu(11, MEMORY_BASE_ADDRESS);
+    emulator
+}
@@ -0,0 +1,208 @@
+use crate::{
+    emulator::{
+        bus::Access,
+        cpu::{csr::Csr, Cpu, Step},
+    },
+    isa::{
+        csr::{csrname, machine_level::MHARTID},
+        privileged::mode::PrivilegeMode,
+    },
+};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::ops::Range;
+
+/// The instruction that the hart is about to execute.
+struct Pending {
+    pc: u64,
+    prv: PrivilegeMode,
+    instruction: u32,
+}
+
+/// A writer of one line per retired instruction in the format of Spike's `--log-commits`: the
+/// privilege mode, the pc, the instruction, the registers written and the memory accessed.
+/// Only the CSRs written by CSR instructions are logged.
+pub struct CommitLog {
+    writer: Box<dyn Write>,
+    range: Option<Range<u64>>,
+    prv: Option<PrivilegeMode>,
+    pending: Option<Pending>,
+    error: Option<io::Error>,
+}
+
+impl CommitLog {
+    pub fn new(writer: impl Write + 'static) -> Self {
+        Self {
+            writer: Box::new(writer),
+            range: None,
+            prv: None,
+            pending: None,
+            error: None,
+        }
+    }
+
+    /// Creates a commit log that writes to the file at the path.
+    pub fn create(path: &str) -> io::Result<Self> {
+        Ok(Self::new(BufWriter::new(File::create(path)?)))
+    }
+
+    /// Logs only the instructions whose pc is in the range.
+    pub fn set_range(&mut self, range: Range<u64>) {
+        self.range = Some(range);
+    }
+
+    /// Logs only the instructions executed in the privilege mode.
+    pub fn set_privilege(&mut self, prv: PrivilegeMode) {
+        self.prv = Some(prv);
+    }
+
+    /// Starts recording the effects of the next instruction of the hart.
+    pub(crate) fn begin(&mut self, cpu: &mut Cpu) {
+        let pc = cpu.pc();
+        self.pending = cpu.bus.is_mapped(pc).then(|| Pending {
+            pc,
+            prv: cpu.prv(),
+            instruction: cpu.bus.fetch(pc),
+        });
+        observe(cpu, true);
+    }
+
+    /// Writes the line of the instruction if it has retired.
+    pub(crate) fn commit(&mut self, cpu: &mut Cpu, step: Step) {
+        let xwrites = cpu.x.take_writes();
+        let fwrites = cpu.f.take_writes();
+        let csrwrites = cpu.csr.take_writes();
+        let accesses = cpu.bus.take_accesses();
+        // the hart may not run next, so it stops recording until it does
+        observe(cpu, false);
+        let Some(pending) = self.pending.take() else {
+            return;
+        };
+        if !matches!(step, Step::Retired | Step::Syscall | Step::Semihosting)
+            || self.error.is_some()
+            || self.prv.is_some_and(|prv| prv != pending.prv)
+            || self
+                .range
+                .as_ref()
+                .is_some_and(|range| !range.contains(&pending.pc))
+        {
+            return;
+        }
+        let mut line = format!(
+            "core{:4}: {} 0x{:016x} (0x{:08x})",
+            cpu.csr.read(MHARTID),
+            pending.prv as u64,
+            pending.pc,
+            pending.instruction
+        );
+        // only the last value written to a register is logged
+        for (register, value) in xwrites.into_iter().collect::<BTreeMap<_, _>>() {
+            line += &format!(" x{:<2} 0x{:016x}", register, value);
+        }
+        for (register, value) in fwrites.into_iter().collect::<BTreeMap<_, _>>() {
+            line += &format!(" f{:<2} 0x{:08x}", register, value);
+        }
+        for address in csrwrites {
+            line += &format!(
+                " c{}_{} 0x{:016x}",
+                address,
+                csrname(address),
+                cpu.csr.read(address)
+            );
+        }
+        for access in accesses {
+            line += &format!(" mem 0x{:016x}", access.address);
+            if access.access == Access::Store {
+                line += &format!(
+                    " 0x{:0width$x}",
+                    access.value,
+                    width = access.size as usize * 2
+                );
+            }
+        }
+        if let Err(error) = writeln!(self.writer, "{}", line) {
+            self.error = Some(error);
+        }
+    }
+
+    /// Stops recording the hart, flushes the log and returns the first error of writing it.
+    pub(crate) fn finish(mut self, cpu: &mut Cpu) -> io::Result<()> {
+        observe(cpu, false);
+        match self.error.take() {
+            Some(error) => Err(error),
+            None => self.writer.flush(),
+        }
+    }
+}
+
+fn observe(cpu: &mut Cpu, observing: bool) {
+    cpu.x.set_observing(observing);
+    cpu.f.set_observing(observing);
+    cpu.csr.set_observing(observing);
+    cpu.bus.set_observing(observing);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        emulator::{
+            bus::memory::MEMORY_BASE_ADDRESS,
+            testing::{emulator, Output},
+        },
+        isa::csr::{machine_level::MSTATUS, status::STATUS_FS},
+    };
+
+    fn run(program: &[u32], configure: impl FnOnce(&mut CommitLog)) -> String {
+        let mut emulator = emulator(program);
+        emulator.cpu_mut().csr.write_field(MSTATUS, &STATUS_FS, 1);
+        let shared = Output::default();
+        let mut log = CommitLog::new(shared.clone());
+        configure(&mut log);
+        emulator.set_commit_log(log);
+        emulator.run_for(program.len() as u64);
+        emulator.finish_commit_log().unwrap();
+        let output = shared.0.borrow().clone();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn commit_log_ok() {
+        let output = run(
+            &[
+                // auipc t0, 0
+                0x0000_0297,
+                // sd t0, 0x100(t0)
+                0x1052_b023,
+                // lw a1, 0x100(t0)
+                0x1002_a583,
+                // csrw mscratch, t0
+                0x3402_9073,
+                // fmv.w.x ft1, t0
+                0xf002_80d3,
+            ],
+            |_| {},
+        );
+        let expected = "\
+core   0: 3 0x0000000080000000 (0x00000297) x5  0x0000000080000000
+core   0: 3 0x0000000080000004 (0x1052b023) mem 0x0000000080000100 0x0000000080000000
+core   0: 3 0x0000000080000008 (0x1002a583) x11 0xffffffff80000000 mem 0x0000000080000100
+core   0: 3 0x000000008000000c (0x34029073) c832_mscratch 0x0000000080000000
+core   0: 3 0x0000000080000010 (0xf00280d3) f1  0x80000000
+";
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn filter_ok() {
+        let nop = 0x0000_0013;
+        let output = run(&[nop; 4], |log| {
+            log.set_range(MEMORY_BASE_ADDRESS + 4..MEMORY_BASE_ADDRESS + 12)
+        });
+        assert_eq!(output.lines().count(), 2);
+        assert!(output.starts_with("core   0: 3 0x0000000080000004"));
+        let output = run(&[nop; 4], |log| log.set_privilege(PrivilegeMode::User));
+        assert!(output.is_empty());
+    }
+}
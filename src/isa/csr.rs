@@ -1,4 +1,94 @@
+pub mod debug;
+pub mod interrupt;
 pub mod machine_level;
 pub mod status;
 pub mod supervisor_level;
+pub mod trigger;
 pub mod user_level;
+
+use crate::{
+    bitops::MASK_12BIT,
+    isa::csr::{machine_level::*, supervisor_level::*, user_level::*},
+};
+
+pub fn csrname(address: u64) -> String {
+    let name = match address {
+        USTATUS => "ustatus",
+        UIE => "uie",
+        UTVEC => "utvec",
+        USCRATCH => "uscratch",
+        UEPC => "uepc",
+        UCAUSE => "ucause",
+        UTVAL => "utval",
+        UIP => "uip",
+        FFLAGS => "fflags",
+        FRM => "frm",
+        FCSR => "fcsr",
+        CYCLE => "cycle",
+        TIME => "time",
+        INSTRET => "instret",
+        CYCLEH => "cycleh",
+        TIMEH => "timeh",
+        INSTRETH => "instreth",
+        SSTATUS => "sstatus",
+        SEDELEG => "sedeleg",
+        SIDELEG => "sideleg",
+        SIE => "sie",
+        STVEC => "stvec",
+        SCOUNTEREN => "scounteren",
+        SSCRATCH => "sscratch",
+        SEPC => "sepc",
+        SCAUSE => "scause",
+        STVAL => "stval",
+        SIP => "sip",
+        SATP => "satp",
+        MVENDORID => "mvendorid",
+        MARCHID => "marchid",
+        MIMPID => "mimpid",
+        MHARTID => "mhartid",
+        MSTATUS => "mstatus",
+        MISA => "misa",
+        MEDELEG => "medeleg",
+        MIDELEG => "mideleg",
+        MIE => "mie",
+        MTVEC => "mtvec",
+        MCOUNTEREN => "mcounteren",
+        MSCRATCH => "mscratch",
+        MEPC => "mepc",
+        MCAUSE => "mcause",
+        MTVAL => "mtval",
+        MIP => "mip",
+        MCYCLE => "mcycle",
+        MINSTRET => "minstret",
+        MCYCLEH => "mcycleh",
+        MINSTRETH => "minstreth",
+        MCOUNTINHIBIT => "mcountinhibit",
+        TSELECT => "tselect",
+        TDATA1 => "tdata1",
+        TDATA2 => "tdata2",
+        TDATA3 => "tdata3",
+        TINFO => "tinfo",
+        DCSR => "dcsr",
+        DPC => "dpc",
+        DSCRATCH0 => "dscratch0",
+        DSCRATCH1 => "dscratch1",
+        _ => {
+            return match address {
+                PMPCFG0..=PMPCFG3 => format!("pmpcfg{}", address - PMPCFG0),
+                PMPADDR0..=PMPADDR15 => format!("pmpaddr{}", address - PMPADDR0),
+                HPMCOUNTER3..=HPMCOUNTER31 => format!("hpmcounter{}", address - CYCLE),
+                HPMCOUNTER3H..=HPMCOUNTER31H => format!("hpmcounter{}h", address - CYCLEH),
+                MHPMCOUNTER..=MHPMCOUNTER31 => format!("mhpmcounter{}", address - MCYCLE),
+                MHPMCOUNTER3H..=MHPMCOUNTER31H => format!("mhpmcounter{}h", address - MCYCLEH),
+                MHPMEVENT3..=MHPMEVENT31 => format!("mhpmevent{}", address - MCOUNTINHIBIT),
+                _ => format!("csr{address:x}"),
+            }
+        }
+    };
+    name.to_string()
+}
+
+/// Returns the address of the CSR with the name.
+pub fn csraddress(name: &str) -> Option<u64> {
+    (0..=MASK_12BIT).find(|&address| csrname(address) == name)
+}
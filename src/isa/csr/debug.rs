@@ -0,0 +1,33 @@
+use crate::isa::csr::status::field_mask;
+use std::ops::Range;
+
+// Fields of dcsr.
+pub const DCSR_DEBUGVER: Range<usize> = 28..31;
+pub const DCSR_EBREAKM: Range<usize> = 15..15;
+pub const DCSR_EBREAKS: Range<usize> = 13..13;
+pub const DCSR_EBREAKU: Range<usize> = 12..12;
+pub const DCSR_STEPIE: Range<usize> = 11..11;
+pub const DCSR_STOPCOUNT: Range<usize> = 10..10;
+pub const DCSR_STOPTIME: Range<usize> = 9..9;
+pub const DCSR_CAUSE: Range<usize> = 6..8;
+pub const DCSR_MPRVEN: Range<usize> = 4..4;
+pub const DCSR_NMIP: Range<usize> = 3..3;
+pub const DCSR_STEP: Range<usize> = 2..2;
+pub const DCSR_PRV: Range<usize> = 0..1;
+
+// Version of the debug specification in dcsr.debugver.
+pub const DEBUGVER_1_0: u64 = 4;
+
+// Reasons for entering debug mode in dcsr.cause.
+pub const DEBUG_CAUSE_EBREAK: u64 = 1;
+pub const DEBUG_CAUSE_TRIGGER: u64 = 2;
+pub const DEBUG_CAUSE_HALTREQ: u64 = 3;
+pub const DEBUG_CAUSE_STEP: u64 = 4;
+
+// Fields of dcsr that the debugger can write.
+pub const DCSR_WRITE_MASK: u64 = field_mask(DCSR_EBREAKM)
+    | field_mask(DCSR_EBREAKS)
+    | field_mask(DCSR_EBREAKU)
+    | field_mask(DCSR_STEPIE)
+    | field_mask(DCSR_STEP)
+    | field_mask(DCSR_PRV);
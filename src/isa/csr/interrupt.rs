@@ -0,0 +1,23 @@
+// Bits of the mip/mie registers and their supervisor-level views.
+pub const USI: u64 = 1 << 0; // User software interrupt.
+pub const SSI: u64 = 1 << 1; // Supervisor software interrupt.
+pub const MSI: u64 = 1 << 3; // Machine software interrupt.
+pub const UTI: u64 = 1 << 4; // User timer interrupt.
+pub const STI: u64 = 1 << 5; // Supervisor timer interrupt.
+pub const MTI: u64 = 1 << 7; // Machine timer interrupt.
+pub const UEI: u64 = 1 << 8; // User external interrupt.
+pub const SEI: u64 = 1 << 9; // Supervisor external interrupt.
+pub const MEI: u64 = 1 << 11; // Machine external interrupt.
+
+// Interrupts that can be delegated to lower privilege modes.
+pub const DELEGABLE_INTERRUPTS: u64 = USI | SSI | UTI | STI | UEI | SEI;
+
+// Interrupts implemented by the hart.
+pub const ALL_INTERRUPTS: u64 = DELEGABLE_INTERRUPTS | MSI | MTI | MEI;
+
+// Pending bits that software can write through mip. The machine-level bits are
+// driven by the interrupt controllers.
+pub const MIP_WRITE_MASK: u64 = DELEGABLE_INTERRUPTS;
+
+// Pending bits that software can write through sip.
+pub const SIP_WRITE_MASK: u64 = USI | SSI | UEI;
@@ -116,6 +116,7 @@ pub const TSELECT: u64 = 0x7a0; // Debug/Trace trigger register select.
 pub const TDATA1: u64 = 0x7a1; // First Debug/Trace trigger data register.
 pub const TDATA2: u64 = 0x7a2; // Second Debug/Trace trigger data register.
 pub const TDATA3: u64 = 0x7a3; // Third Debug/Trace trigger data register.
+pub const TINFO: u64 = 0x7a4; // Trigger information register.
 
 // Debug Mode Registers (DRW)
 pub const DCSR: u64 = 0x7b0; // Debug control and status register.
@@ -8,25 +8,56 @@ pub const STATUS_SPIE: Range<usize> = 5..5;
 pub const STATUS_MPIE: Range<usize> = 7..7;
 pub const STATUS_SPP: Range<usize> = 8..8;
 pub const STATUS_MPP: Range<usize> = 11..12;
-#[allow(dead_code)]
 pub const STATUS_FS: Range<usize> = 13..14;
-#[allow(dead_code)]
 pub const STATUS_XS: Range<usize> = 15..16;
-#[allow(dead_code)]
 pub const STATUS_MPRV: Range<usize> = 17..17;
-#[allow(dead_code)]
 pub const STATUS_SUM: Range<usize> = 18..18;
-#[allow(dead_code)]
 pub const STATUS_MXR: Range<usize> = 19..19;
-#[allow(dead_code)]
 pub const STATUS_TVM: Range<usize> = 20..20;
-#[allow(dead_code)]
 pub const STATUS_TW: Range<usize> = 21..21;
-#[allow(dead_code)]
 pub const STATUS_TSR: Range<usize> = 22..22;
-#[allow(dead_code)]
 pub const STATUS_UXL: Range<usize> = 32..33;
-#[allow(dead_code)]
 pub const STATUS_SXL: Range<usize> = 34..35;
-#[allow(dead_code)]
 pub const STATUS_SD: Range<usize> = 63..63;
+
+// Extension context status (FS and XS).
+pub const EXTENSION_STATUS_INITIAL: u64 = 0b01;
+pub const EXTENSION_STATUS_DIRTY: u64 = 0b11;
+
+// Encoding of XLEN in the UXL and SXL fields.
+pub const XLEN_64: u64 = 2;
+
+// Fields of mstatus that software can write.
+pub const MSTATUS_WRITE_MASK: u64 = field_mask(STATUS_UIE)
+    | field_mask(STATUS_SIE)
+    | field_mask(STATUS_MIE)
+    | field_mask(STATUS_UPIE)
+    | field_mask(STATUS_SPIE)
+    | field_mask(STATUS_MPIE)
+    | field_mask(STATUS_SPP)
+    | field_mask(STATUS_MPP)
+    | field_mask(STATUS_FS)
+    | field_mask(STATUS_MPRV)
+    | field_mask(STATUS_SUM)
+    | field_mask(STATUS_MXR)
+    | field_mask(STATUS_TVM)
+    | field_mask(STATUS_TW)
+    | field_mask(STATUS_TSR);
+
+// Fields of mstatus that are visible through sstatus.
+pub const SSTATUS_MASK: u64 = field_mask(STATUS_UIE)
+    | field_mask(STATUS_SIE)
+    | field_mask(STATUS_UPIE)
+    | field_mask(STATUS_SPIE)
+    | field_mask(STATUS_SPP)
+    | field_mask(STATUS_FS)
+    | field_mask(STATUS_XS)
+    | field_mask(STATUS_SUM)
+    | field_mask(STATUS_MXR)
+    | field_mask(STATUS_UXL)
+    | field_mask(STATUS_SD);
+
+/// Returns the mask of a field whose range includes both of its bounds.
+pub const fn field_mask(field: Range<usize>) -> u64 {
+    ((1u64 << (field.end - field.start)) << 1).wrapping_sub(1) << field.start
+}
@@ -0,0 +1,53 @@
+use std::ops::Range;
+
+// Types of a trigger in tdata1.
+pub const TRIGGER_TYPE_MCONTROL: u64 = 2; // Address/data match trigger.
+pub const TRIGGER_TYPE_ICOUNT: u64 = 3; // Instruction count trigger.
+pub const TRIGGER_TYPE_MCONTROL6: u64 = 6; // Address/data match trigger (Sdtrig 1.0).
+pub const TRIGGER_TYPE_DISABLED: u64 = 15; // Trigger that never fires.
+
+// Fields common to all types of tdata1.
+pub const TDATA1_TYPE: Range<usize> = 60..63;
+pub const TDATA1_DMODE: Range<usize> = 59..59;
+
+// Fields of tdata1 for mcontrol. The fields below action are shared with mcontrol6.
+pub const MCONTROL_HIT: Range<usize> = 20..20;
+pub const MCONTROL_SELECT: Range<usize> = 19..19;
+pub const MCONTROL_ACTION: Range<usize> = 12..15;
+pub const MCONTROL_CHAIN: Range<usize> = 11..11;
+pub const MCONTROL_MATCH: Range<usize> = 7..10;
+pub const MCONTROL_M: Range<usize> = 6..6;
+pub const MCONTROL_S: Range<usize> = 4..4;
+pub const MCONTROL_U: Range<usize> = 3..3;
+pub const MCONTROL_EXECUTE: Range<usize> = 2..2;
+pub const MCONTROL_STORE: Range<usize> = 1..1;
+pub const MCONTROL_LOAD: Range<usize> = 0..0;
+
+// Fields of tdata1 for mcontrol6 that differ from mcontrol.
+pub const MCONTROL6_HIT0: Range<usize> = 22..22;
+pub const MCONTROL6_SELECT: Range<usize> = 21..21;
+
+// Fields of tdata1 for icount.
+pub const ICOUNT_HIT: Range<usize> = 24..24;
+pub const ICOUNT_COUNT: Range<usize> = 10..23;
+pub const ICOUNT_M: Range<usize> = 9..9;
+pub const ICOUNT_PENDING: Range<usize> = 8..8;
+pub const ICOUNT_S: Range<usize> = 7..7;
+pub const ICOUNT_U: Range<usize> = 6..6;
+pub const ICOUNT_ACTION: Range<usize> = 0..5;
+
+// Match conditions of mcontrol and mcontrol6.
+pub const MATCH_EQUAL: u64 = 0;
+pub const MATCH_NAPOT: u64 = 1;
+pub const MATCH_GE: u64 = 2;
+pub const MATCH_LT: u64 = 3;
+pub const MATCH_MASK_LOW: u64 = 4;
+pub const MATCH_MASK_HIGH: u64 = 5;
+pub const MATCH_NOT: u64 = 8; // Inverts the other conditions.
+
+// Actions taken when a trigger fires.
+pub const ACTION_BREAKPOINT: u64 = 0; // Raise a breakpoint exception.
+pub const ACTION_DEBUG_MODE: u64 = 1; // Enter debug mode.
+
+// Version of the debug specification in tinfo.
+pub const TINFO_VERSION_1_0: u64 = 1 << 24;
@@ -1,7 +1,9 @@
 pub mod privileged;
+pub mod rv32a;
 pub mod rv32f;
 pub mod rv32i;
 pub mod rv32m;
+pub mod rv64a;
 pub mod rv64f;
 pub mod rv64i;
 pub mod rv64m;
@@ -30,6 +32,14 @@ fn format_upper_immediate(opcode: String, rd: &str, imm: i64) -> String {
     format!("{opcode} {rd},0x{imm:x}")
 }
 
+fn format_load_reserved(opcode: String, rd: &str, rs1: &str) -> String {
+    format!("{opcode} {rd},({rs1})")
+}
+
+fn format_atomic(opcode: String, rd: &str, rs2: &str, rs1: &str) -> String {
+    format!("{opcode} {rd},{rs2},({rs1})")
+}
+
 fn format_offset(opcode: String, rd: &str, offset: i64, rs1: &str) -> String {
     format!("{opcode} {rd},{offset:x}({rs1})")
 }
@@ -41,6 +51,12 @@ pub struct Description {
     pseudocode: String,
 }
 
+impl Description {
+    pub fn assembly(&self) -> &str {
+        &self.assembly
+    }
+}
+
 impl fmt::Display for Description {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(
@@ -55,6 +55,12 @@ impl Describer
                     "mret",
                     "ExceptionReturn(Machine)",
                 ),
+                PrivilegedOpcodeR::Dret => (
+                    "Debug-mode Return",
+                    "dret".to_string(),
+                    "dret",
+                    "pc = dpc; prv = dcsr.prv",
+                ),
                 PrivilegedOpcodeR::Wfi => (
                     "Wait for Interrupt",
                     "wfi".to_string(),
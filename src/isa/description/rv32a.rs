@@ -0,0 +1,115 @@
+use crate::isa::{
+    description::{format_atomic, format_load_reserved, Describer, Description},
+    instruction::{
+        rv32a::{
+            Rv32aOpcodeB, Rv32aOpcodeI, Rv32aOpcodeJ, Rv32aOpcodeR, Rv32aOpcodeS, Rv32aOpcodeU,
+        },
+        Instruction,
+    },
+    register::xname,
+};
+
+impl Describer
+    for Instruction<
+        Rv32aOpcodeR,
+        Rv32aOpcodeI,
+        Rv32aOpcodeS,
+        Rv32aOpcodeB,
+        Rv32aOpcodeU,
+        Rv32aOpcodeJ,
+    >
+{
+    type OpcodeR = Rv32aOpcodeR;
+    type OpcodeI = Rv32aOpcodeI;
+    type OpcodeS = Rv32aOpcodeS;
+    type OpcodeB = Rv32aOpcodeB;
+    type OpcodeU = Rv32aOpcodeU;
+    type OpcodeJ = Rv32aOpcodeJ;
+
+    fn describe(&self) -> Description {
+        let (description, assembly, signature, pseudocode) = match *self {
+            Self::TypeR {
+                opcode,
+                rd,
+                funct3: _,
+                rs1,
+                rs2,
+                funct7: _,
+            } => match opcode {
+                Rv32aOpcodeR::LrW => (
+                    "Load-Reserved Word",
+                    format_load_reserved(opcode.to_string(), xname(rd), xname(rs1)),
+                    "lr.w rd,(rs1)",
+                    "x[rd] = LoadReserved32(M[x[rs1]])",
+                ),
+                Rv32aOpcodeR::ScW => (
+                    "Store-Conditional Word",
+                    format_atomic(opcode.to_string(), xname(rd), xname(rs2), xname(rs1)),
+                    "sc.w rd,rs2,(rs1)",
+                    "x[rd] = StoreConditional32(M[x[rs1]], x[rs2])",
+                ),
+                Rv32aOpcodeR::AmoswapW => (
+                    "Atomic Memory Operation: Swap Word",
+                    format_atomic(opcode.to_string(), xname(rd), xname(rs2), xname(rs1)),
+                    "amoswap.w rd,rs2,(rs1)",
+                    "x[rd] = AMO32(M[x[rs1]] SWAP x[rs2])",
+                ),
+                Rv32aOpcodeR::AmoaddW => (
+                    "Atomic Memory Operation: Add Word",
+                    format_atomic(opcode.to_string(), xname(rd), xname(rs2), xname(rs1)),
+                    "amoadd.w rd,rs2,(rs1)",
+                    "x[rd] = AMO32(M[x[rs1]] + x[rs2])",
+                ),
+                Rv32aOpcodeR::AmoxorW => (
+                    "Atomic Memory Operation: XOR Word",
+                    format_atomic(opcode.to_string(), xname(rd), xname(rs2), xname(rs1)),
+                    "amoxor.w rd,rs2,(rs1)",
+                    "x[rd] = AMO32(M[x[rs1]] ^ x[rs2])",
+                ),
+                Rv32aOpcodeR::AmoandW => (
+                    "Atomic Memory Operation: AND Word",
+                    format_atomic(opcode.to_string(), xname(rd), xname(rs2), xname(rs1)),
+                    "amoand.w rd,rs2,(rs1)",
+                    "x[rd] = AMO32(M[x[rs1]] & x[rs2])",
+                ),
+                Rv32aOpcodeR::AmoorW => (
+                    "Atomic Memory Operation: OR Word",
+                    format_atomic(opcode.to_string(), xname(rd), xname(rs2), xname(rs1)),
+                    "amoor.w rd,rs2,(rs1)",
+                    "x[rd] = AMO32(M[x[rs1]] | x[rs2])",
+                ),
+                Rv32aOpcodeR::AmominW => (
+                    "Atomic Memory Operation: Minimum Word",
+                    format_atomic(opcode.to_string(), xname(rd), xname(rs2), xname(rs1)),
+                    "amomin.w rd,rs2,(rs1)",
+                    "x[rd] = AMO32(M[x[rs1]] MIN x[rs2])",
+                ),
+                Rv32aOpcodeR::AmomaxW => (
+                    "Atomic Memory Operation: Maximum Word",
+                    format_atomic(opcode.to_string(), xname(rd), xname(rs2), xname(rs1)),
+                    "amomax.w rd,rs2,(rs1)",
+                    "x[rd] = AMO32(M[x[rs1]] MAX x[rs2])",
+                ),
+                Rv32aOpcodeR::AmominuW => (
+                    "Atomic Memory Operation: Minimum Unsigned Word",
+                    format_atomic(opcode.to_string(), xname(rd), xname(rs2), xname(rs1)),
+                    "amominu.w rd,rs2,(rs1)",
+                    "x[rd] = AMO32(M[x[rs1]] MINU x[rs2])",
+                ),
+                Rv32aOpcodeR::AmomaxuW => (
+                    "Atomic Memory Operation: Maximum Unsigned Word",
+                    format_atomic(opcode.to_string(), xname(rd), xname(rs2), xname(rs1)),
+                    "amomaxu.w rd,rs2,(rs1)",
+                    "x[rd] = AMO32(M[x[rs1]] MAXU x[rs2])",
+                ),
+            },
+            _ => panic!(),
+        };
+        Description {
+            description: description.to_string(),
+            assembly,
+            singnature: signature.to_string(),
+            pseudocode: pseudocode.to_string(),
+        }
+    }
+}
@@ -0,0 +1,115 @@
+use crate::isa::{
+    description::{format_atomic, format_load_reserved, Describer, Description},
+    instruction::{
+        rv64a::{
+            Rv64aOpcodeB, Rv64aOpcodeI, Rv64aOpcodeJ, Rv64aOpcodeR, Rv64aOpcodeS, Rv64aOpcodeU,
+        },
+        Instruction,
+    },
+    register::xname,
+};
+
+impl Describer
+    for Instruction<
+        Rv64aOpcodeR,
+        Rv64aOpcodeI,
+        Rv64aOpcodeS,
+        Rv64aOpcodeB,
+        Rv64aOpcodeU,
+        Rv64aOpcodeJ,
+    >
+{
+    type OpcodeR = Rv64aOpcodeR;
+    type OpcodeI = Rv64aOpcodeI;
+    type OpcodeS = Rv64aOpcodeS;
+    type OpcodeB = Rv64aOpcodeB;
+    type OpcodeU = Rv64aOpcodeU;
+    type OpcodeJ = Rv64aOpcodeJ;
+
+    fn describe(&self) -> Description {
+        let (description, assembly, signature, pseudocode) = match *self {
+            Self::TypeR {
+                opcode,
+                rd,
+                funct3: _,
+                rs1,
+                rs2,
+                funct7: _,
+            } => match opcode {
+                Rv64aOpcodeR::LrD => (
+                    "Load-Reserved Doubleword",
+                    format_load_reserved(opcode.to_string(), xname(rd), xname(rs1)),
+                    "lr.d rd,(rs1)",
+                    "x[rd] = LoadReserved64(M[x[rs1]])",
+                ),
+                Rv64aOpcodeR::ScD => (
+                    "Store-Conditional Doubleword",
+                    format_atomic(opcode.to_string(), xname(rd), xname(rs2), xname(rs1)),
+                    "sc.d rd,rs2,(rs1)",
+                    "x[rd] = StoreConditional64(M[x[rs1]], x[rs2])",
+                ),
+                Rv64aOpcodeR::AmoswapD => (
+                    "Atomic Memory Operation: Swap Doubleword",
+                    format_atomic(opcode.to_string(), xname(rd), xname(rs2), xname(rs1)),
+                    "amoswap.d rd,rs2,(rs1)",
+                    "x[rd] = AMO64(M[x[rs1]] SWAP x[rs2])",
+                ),
+                Rv64aOpcodeR::AmoaddD => (
+                    "Atomic Memory Operation: Add Doubleword",
+                    format_atomic(opcode.to_string(), xname(rd), xname(rs2), xname(rs1)),
+                    "amoadd.d rd,rs2,(rs1)",
+                    "x[rd] = AMO64(M[x[rs1]] + x[rs2])",
+                ),
+                Rv64aOpcodeR::AmoxorD => (
+                    "Atomic Memory Operation: XOR Doubleword",
+                    format_atomic(opcode.to_string(), xname(rd), xname(rs2), xname(rs1)),
+                    "amoxor.d rd,rs2,(rs1)",
+                    "x[rd] = AMO64(M[x[rs1]] ^ x[rs2])",
+                ),
+                Rv64aOpcodeR::AmoandD => (
+                    "Atomic Memory Operation: AND Doubleword",
+                    format_atomic(opcode.to_string(), xname(rd), xname(rs2), xname(rs1)),
+                    "amoand.d rd,rs2,(rs1)",
+                    "x[rd] = AMO64(M[x[rs1]] & x[rs2])",
+                ),
+                Rv64aOpcodeR::AmoorD => (
+                    "Atomic Memory Operation: OR Doubleword",
+                    format_atomic(opcode.to_string(), xname(rd), xname(rs2), xname(rs1)),
+                    "amoor.d rd,rs2,(rs1)",
+                    "x[rd] = AMO64(M[x[rs1]] | x[rs2])",
+                ),
+                Rv64aOpcodeR::AmominD => (
+                    "Atomic Memory Operation: Minimum Doubleword",
+                    format_atomic(opcode.to_string(), xname(rd), xname(rs2), xname(rs1)),
+                    "amomin.d rd,rs2,(rs1)",
+                    "x[rd] = AMO64(M[x[rs1]] MIN x[rs2])",
+                ),
+                Rv64aOpcodeR::AmomaxD => (
+                    "Atomic Memory Operation: Maximum Doubleword",
+                    format_atomic(opcode.to_string(), xname(rd), xname(rs2), xname(rs1)),
+                    "amomax.d rd,rs2,(rs1)",
+                    "x[rd] = AMO64(M[x[rs1]] MAX x[rs2])",
+                ),
+                Rv64aOpcodeR::AmominuD => (
+                    "Atomic Memory Operation: Minimum Unsigned Doubleword",
+                    format_atomic(opcode.to_string(), xname(rd), xname(rs2), xname(rs1)),
+                    "amominu.d rd,rs2,(rs1)",
+                    "x[rd] = AMO64(M[x[rs1]] MINU x[rs2])",
+                ),
+                Rv64aOpcodeR::AmomaxuD => (
+                    "Atomic Memory Operation: Maximum Unsigned Doubleword",
+                    format_atomic(opcode.to_string(), xname(rd), xname(rs2), xname(rs1)),
+                    "amomaxu.d rd,rs2,(rs1)",
+                    "x[rd] = AMO64(M[x[rs1]] MAXU x[rs2])",
+                ),
+            },
+            _ => panic!(),
+        };
+        Description {
+            description: description.to_string(),
+            assembly,
+            singnature: signature.to_string(),
+            pseudocode: pseudocode.to_string(),
+        }
+    }
+}
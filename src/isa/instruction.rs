@@ -1,17 +1,19 @@
 pub mod privileged;
+pub mod rv32a;
 pub mod rv32f;
 pub mod rv32i;
 pub mod rv32m;
+pub mod rv64a;
 pub mod rv64f;
 pub mod rv64i;
 pub mod rv64m;
 pub mod zicsr;
 pub mod zifencei;
 
-use crate::isa::register::xname;
+use crate::isa::{description::Describer, register::xname};
 use std::fmt;
 
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum Instruction<OpcodeR, OpcodeI, OpcodeS, OpcodeB, OpcodeU, OpcodeJ> {
     TypeR {
         opcode: OpcodeR,
@@ -54,6 +56,28 @@ pub enum Instruction<OpcodeR, OpcodeI, OpcodeS, OpcodeB, OpcodeU, OpcodeJ> {
     },
 }
 
+impl<OpcodeR, OpcodeI, OpcodeS, OpcodeB, OpcodeU, OpcodeJ>
+    Instruction<OpcodeR, OpcodeI, OpcodeS, OpcodeB, OpcodeU, OpcodeJ>
+where
+    Self: Describer,
+{
+    /// Returns the letter of the format, which ends the name of the opcode enum, and the
+    /// mnemonic.
+    pub fn opcode(&self) -> (char, String) {
+        let format = match self {
+            Self::TypeR { .. } => 'R',
+            Self::TypeI { .. } => 'I',
+            Self::TypeS { .. } => 'S',
+            Self::TypeB { .. } => 'B',
+            Self::TypeU { .. } => 'U',
+            Self::TypeJ { .. } => 'J',
+        };
+        let description = self.describe();
+        let mnemonic = description.assembly().split(' ').next().unwrap_or_default();
+        (format, mnemonic.to_string())
+    }
+}
+
 impl<
         OpcodeR: fmt::Display,
         OpcodeI: fmt::Display,
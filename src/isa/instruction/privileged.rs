@@ -5,6 +5,7 @@ pub enum PrivilegedOpcodeR {
     Uret,
     Sret,
     Mret,
+    Dret,
     Wfi,
     SfenceVma,
 }
@@ -15,6 +16,7 @@ impl fmt::Display for PrivilegedOpcodeR {
             Self::Uret => f.write_str("uret"),
             Self::Sret => f.write_str("sret"),
             Self::Mret => f.write_str("mret"),
+            Self::Dret => f.write_str("dret"),
             Self::Wfi => f.write_str("wfi"),
             Self::SfenceVma => f.write_str("sfence.vma"),
         }
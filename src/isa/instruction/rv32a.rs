@@ -0,0 +1,49 @@
+use std::fmt;
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Rv32aOpcodeR {
+    LrW,
+    ScW,
+    AmoswapW,
+    AmoaddW,
+    AmoxorW,
+    AmoandW,
+    AmoorW,
+    AmominW,
+    AmomaxW,
+    AmominuW,
+    AmomaxuW,
+}
+
+impl fmt::Display for Rv32aOpcodeR {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::LrW => f.write_str("lr.w"),
+            Self::ScW => f.write_str("sc.w"),
+            Self::AmoswapW => f.write_str("amoswap.w"),
+            Self::AmoaddW => f.write_str("amoadd.w"),
+            Self::AmoxorW => f.write_str("amoxor.w"),
+            Self::AmoandW => f.write_str("amoand.w"),
+            Self::AmoorW => f.write_str("amoor.w"),
+            Self::AmominW => f.write_str("amomin.w"),
+            Self::AmomaxW => f.write_str("amomax.w"),
+            Self::AmominuW => f.write_str("amominu.w"),
+            Self::AmomaxuW => f.write_str("amomaxu.w"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Rv32aOpcodeI {}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Rv32aOpcodeS {}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Rv32aOpcodeB {}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Rv32aOpcodeU {}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Rv32aOpcodeJ {}
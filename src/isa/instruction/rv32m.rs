@@ -27,6 +27,7 @@ impl fmt::Display for Rv32mOpcodeR {
     }
 }
 
+#[derive(Debug, PartialEq, Copy, Clone)]
 pub enum Rv32mOpcodeI {}
 
 #[derive(Debug, PartialEq, Copy, Clone)]
@@ -0,0 +1,49 @@
+use std::fmt;
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Rv64aOpcodeR {
+    LrD,
+    ScD,
+    AmoswapD,
+    AmoaddD,
+    AmoxorD,
+    AmoandD,
+    AmoorD,
+    AmominD,
+    AmomaxD,
+    AmominuD,
+    AmomaxuD,
+}
+
+impl fmt::Display for Rv64aOpcodeR {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::LrD => f.write_str("lr.d"),
+            Self::ScD => f.write_str("sc.d"),
+            Self::AmoswapD => f.write_str("amoswap.d"),
+            Self::AmoaddD => f.write_str("amoadd.d"),
+            Self::AmoxorD => f.write_str("amoxor.d"),
+            Self::AmoandD => f.write_str("amoand.d"),
+            Self::AmoorD => f.write_str("amoor.d"),
+            Self::AmominD => f.write_str("amomin.d"),
+            Self::AmomaxD => f.write_str("amomax.d"),
+            Self::AmominuD => f.write_str("amominu.d"),
+            Self::AmomaxuD => f.write_str("amomaxu.d"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Rv64aOpcodeI {}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Rv64aOpcodeS {}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Rv64aOpcodeB {}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Rv64aOpcodeU {}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Rv64aOpcodeJ {}
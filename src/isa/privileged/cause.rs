@@ -1,38 +1,34 @@
+use crate::isa::privileged::mode::PrivilegeMode;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum Cause {
-    #[allow(dead_code)]
     Interrupt(Interrupt),
     Exception(Exception),
     ExceptionReturn(ExceptionReturn),
+    DebugReturn,
+    WaitForInterrupt,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum Interrupt {
-    #[allow(dead_code)]
     UserSoftware,
-    #[allow(dead_code)]
     SupervisorSoftware,
-    #[allow(dead_code)]
     MachineSoftware,
-    #[allow(dead_code)]
     UserTimer,
-    #[allow(dead_code)]
     SupervisorTimer,
-    #[allow(dead_code)]
     MachineTimer,
-    #[allow(dead_code)]
     UserExternal,
-    #[allow(dead_code)]
     SupervisorExternal,
-    #[allow(dead_code)]
     MachineExternal,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum Exception {
     #[allow(dead_code)]
     InstructionAddressMisaligned,
     #[allow(dead_code)]
     InstructionAccessFault,
     IllegalInstruction,
-    #[allow(dead_code)]
     Breakpoint,
     #[allow(dead_code)]
     LoadAddressMisaligned,
@@ -53,18 +49,29 @@ pub enum Exception {
     StorePageFault,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum ExceptionReturn {
     User,
     Supervisor,
     Machine,
 }
 
+impl ExceptionReturn {
+    pub fn privilege_mode(&self) -> PrivilegeMode {
+        match self {
+            Self::User => PrivilegeMode::User,
+            Self::Supervisor => PrivilegeMode::Supervisor,
+            Self::Machine => PrivilegeMode::Machine,
+        }
+    }
+}
+
 impl Cause {
     pub fn to_primitive(&self) -> u64 {
         match self {
             Self::Interrupt(interrupt) => interrupt.to_primitive(),
             Self::Exception(exception) => exception.to_primitive(),
-            Self::ExceptionReturn(_) => panic!(),
+            Self::ExceptionReturn(_) | Self::DebugReturn | Self::WaitForInterrupt => panic!(),
         }
     }
 
@@ -76,7 +83,7 @@ impl Cause {
         match self {
             Self::Interrupt(interrupt) => interrupt.to_primitive() & 0b1111,
             Self::Exception(exception) => exception.to_primitive(),
-            Self::ExceptionReturn(_) => panic!(),
+            Self::ExceptionReturn(_) | Self::DebugReturn | Self::WaitForInterrupt => panic!(),
         }
     }
 }
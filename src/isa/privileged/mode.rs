@@ -1,16 +1,11 @@
-#[derive(Copy, Clone, PartialEq)]
+#[derive(Copy, Clone, Debug, PartialEq, Default)]
 pub enum PrivilegeMode {
     User = 0b00,
     Supervisor = 0b01,
+    #[default]
     Machine = 0b11,
 }
 
-impl Default for PrivilegeMode {
-    fn default() -> Self {
-        PrivilegeMode::Machine
-    }
-}
-
 impl PrivilegeMode {
     pub fn from_primitive(mode: u64) -> Self {
         match mode {
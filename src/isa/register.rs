@@ -139,3 +139,13 @@ pub fn fname<'a>(register: usize) -> &'a str {
         _ => "unknown",
     }
 }
+
+/// Returns the integer register with the ABI name or the xN name.
+pub fn xregister(name: &str) -> Option<usize> {
+    (0..32).find(|&register| xname(register) == name || format!("x{register}") == name)
+}
+
+/// Returns the floating point register with the ABI name or the fN name.
+pub fn fregister(name: &str) -> Option<usize> {
+    (0..32).find(|&register| fname(register) == name || format!("f{register}") == name)
+}
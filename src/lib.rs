@@ -1,4 +1,6 @@
 mod bitops;
 pub mod emulator;
 pub mod isa;
+mod output;
+pub mod web;
 extern crate rustc_apfloat;
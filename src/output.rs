@@ -0,0 +1,19 @@
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+/// A writer that keeps the bytes written to it, which its clones share, so that the bytes can
+/// be read after the writer has been handed over.
+#[derive(Clone, Default)]
+pub(crate) struct Output(pub(crate) Rc<RefCell<Vec<u8>>>);
+
+impl Write for Output {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(bytes);
+        Ok(bytes.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,180 @@
+use crate::emulator::{cpu::csr::Csr, Emulator, StopReason};
+use crate::output::Output;
+use js_sys::Function;
+use wasm_bindgen::prelude::*;
+
+/// Why a run has stopped: "breakpoint" at the address in the value, "trap" with the cause in
+/// the value, "exit" with the exit code in the value, "limit", "debug" or "halt".
+#[wasm_bindgen(getter_with_clone)]
+pub struct Stop {
+    pub reason: String,
+    pub value: Option<u64>,
+}
+
+impl From<StopReason> for Stop {
+    fn from(reason: StopReason) -> Self {
+        let (reason, value) = match reason {
+            StopReason::Breakpoint(address) => ("breakpoint", Some(address)),
+            StopReason::Trap(cause) => ("trap", Some(cause.to_primitive())),
+            StopReason::Exit(code) => ("exit", Some(code)),
+            StopReason::InstructionLimit => ("limit", None),
+            StopReason::Condition => ("condition", None),
+            StopReason::DebugMode => ("debug", None),
+            StopReason::Halt => ("halt", None),
+            StopReason::StartOfHistory => ("start-of-history", None),
+        };
+        Self {
+            reason: reason.to_string(),
+            value,
+        }
+    }
+}
+
+/// The emulator for JavaScript. The UART output goes to the callback at the end of every run,
+/// so running in small budgets streams it.
+#[wasm_bindgen]
+pub struct Five {
+    emulator: Emulator,
+    // the bytes that the UART has transmitted since the last run
+    output: Output,
+    callback: Option<Function>,
+}
+
+impl Default for Five {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[wasm_bindgen]
+impl Five {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        #[cfg(feature = "console_error_panic_hook")]
+        console_error_panic_hook::set_once();
+        let output = Output::default();
+        let mut emulator = Emulator::default();
+        emulator.cpu_mut().bus.uart.set_output(output.clone());
+        Self {
+            emulator,
+            output,
+            callback: None,
+        }
+    }
+
+    /// Copies the image to the start of the memory.
+    pub fn load(&mut self, image: &[u8]) -> Result<(), JsError> {
+        self.emulator
+            .load_image(image)
+            .map_err(|e| JsError::new(&e.to_string()))
+    }
+
+    /// Sets the address of the tohost word that the guest writes its exit code to.
+    #[wasm_bindgen(js_name = setTohost)]
+    pub fn set_tohost(&mut self, address: u64) {
+        self.emulator.set_tohost(address);
+    }
+
+    /// Calls the callback with the text that the UART transmits.
+    #[wasm_bindgen(js_name = onUart)]
+    pub fn on_uart(&mut self, callback: Function) {
+        self.callback = Some(callback);
+    }
+
+    pub fn step(&mut self) -> Stop {
+        self.run(1)
+    }
+
+    /// Executes up to the number of instructions.
+    pub fn run(&mut self, budget: u64) -> Stop {
+        let reason = self.emulator.run_for(budget);
+        let output = std::mem::take(&mut *self.output.0.borrow_mut());
+        if let (Some(callback), false) = (&self.callback, output.is_empty()) {
+            let text = JsValue::from_str(&String::from_utf8_lossy(&output));
+            // an exception in the callback does not stop the emulator
+            let _ = callback.call1(&JsValue::NULL, &text);
+        }
+        reason.into()
+    }
+
+    pub fn pc(&self) -> u64 {
+        self.emulator.cpu().pc()
+    }
+
+    /// Reads the integer register.
+    pub fn x(&self, register: usize) -> u64 {
+        self.emulator.cpu().x().readu(register % 32)
+    }
+
+    /// Reads the bits of the floating-point register.
+    pub fn f(&self, register: usize) -> u64 {
+        self.emulator.cpu().f().readd(register % 32)
+    }
+
+    pub fn csr(&self, address: u64) -> u64 {
+        self.emulator.cpu().csr.read(address)
+    }
+
+    #[wasm_bindgen(js_name = readMemory)]
+    pub fn read_memory(&self, address: u64, length: u64) -> Result<Vec<u8>, JsError> {
+        self.emulator
+            .cpu()
+            .bus
+            .memory
+            .load_bytes(address, length)
+            .ok_or_else(|| JsError::new("out of memory"))
+    }
+
+    #[wasm_bindgen(js_name = writeMemory)]
+    pub fn write_memory(&mut self, address: u64, bytes: &[u8]) -> Result<(), JsError> {
+        match self
+            .emulator
+            .cpu_mut()
+            .bus
+            .memory
+            .store_bytes(address, bytes)
+        {
+            true => Ok(()),
+            false => Err(JsError::new("out of memory")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The address that the image is loaded at.
+    const MEMORY_BASE_ADDRESS: u64 = 0x8000_0000;
+
+    #[test]
+    fn run_ok() {
+        let mut five = Five::new();
+        let program = [
+            0x1000_0537u32, // lui a0, 0x10000
+            0x0680_0593,    // li a1, 'h'
+            0x00b5_0023,    // sb a1, 0(a0)
+            0xffdf_f06f,    // jal zero, -4
+        ];
+        let image = program
+            .iter()
+            .flat_map(|i| i.to_le_bytes())
+            .collect::<Vec<_>>();
+        assert!(five.load(&image).is_ok());
+        assert_eq!(five.run(3).reason, "limit");
+        assert_eq!(five.x(10), 0x1000_0000);
+        assert_eq!(five.pc(), MEMORY_BASE_ADDRESS + 12);
+        assert_eq!(
+            five.read_memory(MEMORY_BASE_ADDRESS, 4).ok(),
+            Some(image[..4].to_vec())
+        );
+        // the run takes the output whether or not it has a callback
+        assert!(five.output.0.borrow().is_empty());
+        assert!(five
+            .write_memory(MEMORY_BASE_ADDRESS + 12, &[0x73, 0, 0x10, 0])
+            .is_ok());
+        five.emulator.set_stop_on_trap(true);
+        let stop = five.step();
+        assert_eq!((stop.reason.as_str(), stop.value), ("trap", Some(3)));
+    }
+}
@@ -1,14 +1,17 @@
-use five::{
-    emulator::{
-        cpu::{csr::Csr, Cpu},
-        Emulator,
-    },
-    isa::csr::user_level::CYCLE,
-};
+use five::emulator::{Emulator, StopReason};
 use std::fs::File;
 use std::path::PathBuf;
 
 fn run(name: &str) -> bool {
+    // with the jit, every block is compiled on its first run too
+    #[cfg(feature = "jit")]
+    if !run_with(name, |emulator| emulator.set_jit_threshold(0)) {
+        return false;
+    }
+    run_with(name, |_| {})
+}
+
+fn run_with(name: &str, setup: impl FnOnce(&mut Emulator)) -> bool {
     let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
     path.push("tests");
     path.push("isa");
@@ -16,21 +19,11 @@ fn run(name: &str) -> bool {
     path.set_extension("bin");
     let file = File::open(path.as_path());
     let mut emulator = Emulator::default();
-    let terminator = Some(|cpu: &Cpu| {
-        if cpu.csr.read(CYCLE) > 10000 {
-            println!("timeout");
-            return Some(0);
-        }
-        let value = cpu.bus.load64(0x80001000);
-        if value == 1 {
-            Some(1)
-        } else {
-            None
-        }
-    });
+    emulator.set_tohost(0x80001000);
+    setup(&mut emulator);
     if let Ok(f) = file {
         let _ = emulator.load(f);
-        emulator.run(false, terminator) == 1
+        emulator.run_for(10000) == StopReason::Exit(0)
     } else {
         false
     }
@@ -108,6 +101,29 @@ fn rv64um_p_ok() {
     assert!(run("rv64um-p-remw"), "{}", "rv64um-p-remw");
 }
 
+#[test]
+fn rv64ua_p_ok() {
+    assert!(run("rv64ua-p-amoadd_d"), "{}", "rv64ua-p-amoadd_d");
+    assert!(run("rv64ua-p-amoadd_w"), "{}", "rv64ua-p-amoadd_w");
+    assert!(run("rv64ua-p-amoand_d"), "{}", "rv64ua-p-amoand_d");
+    assert!(run("rv64ua-p-amoand_w"), "{}", "rv64ua-p-amoand_w");
+    assert!(run("rv64ua-p-amomax_d"), "{}", "rv64ua-p-amomax_d");
+    assert!(run("rv64ua-p-amomax_w"), "{}", "rv64ua-p-amomax_w");
+    assert!(run("rv64ua-p-amomaxu_d"), "{}", "rv64ua-p-amomaxu_d");
+    assert!(run("rv64ua-p-amomaxu_w"), "{}", "rv64ua-p-amomaxu_w");
+    assert!(run("rv64ua-p-amomin_d"), "{}", "rv64ua-p-amomin_d");
+    assert!(run("rv64ua-p-amomin_w"), "{}", "rv64ua-p-amomin_w");
+    assert!(run("rv64ua-p-amominu_d"), "{}", "rv64ua-p-amominu_d");
+    assert!(run("rv64ua-p-amominu_w"), "{}", "rv64ua-p-amominu_w");
+    assert!(run("rv64ua-p-amoor_d"), "{}", "rv64ua-p-amoor_d");
+    assert!(run("rv64ua-p-amoor_w"), "{}", "rv64ua-p-amoor_w");
+    assert!(run("rv64ua-p-amoswap_d"), "{}", "rv64ua-p-amoswap_d");
+    assert!(run("rv64ua-p-amoswap_w"), "{}", "rv64ua-p-amoswap_w");
+    assert!(run("rv64ua-p-amoxor_d"), "{}", "rv64ua-p-amoxor_d");
+    assert!(run("rv64ua-p-amoxor_w"), "{}", "rv64ua-p-amoxor_w");
+    assert!(run("rv64ua-p-lrsc"), "{}", "rv64ua-p-lrsc");
+}
+
 #[test]
 fn rv64uf_p_ok() {
     assert!(run("rv64uf-p-fadd"), "{}", "rv64uf-p-fadd");
@@ -123,3 +139,20 @@ fn rv64uf_p_ok() {
     assert!(run("rv64uf-p-move"), "{}", "rv64uf-p-move");
     assert!(run("rv64uf-p-recoding"), "{}", "rv64uf-p-recoding");
 }
+
+#[test]
+fn rv64mi_p_ok() {
+    assert!(run("rv64mi-p-breakpoint"), "{}", "rv64mi-p-breakpoint");
+    assert!(run("rv64mi-p-illegal"), "{}", "rv64mi-p-illegal");
+    assert!(run("rv64mi-p-ma_addr"), "{}", "rv64mi-p-ma_addr");
+    assert!(run("rv64mi-p-sbreak"), "{}", "rv64mi-p-sbreak");
+    assert!(run("rv64mi-p-scall"), "{}", "rv64mi-p-scall");
+}
+
+#[test]
+fn rv64si_p_ok() {
+    assert!(run("rv64si-p-csr"), "{}", "rv64si-p-csr");
+    assert!(run("rv64si-p-sbreak"), "{}", "rv64si-p-sbreak");
+    assert!(run("rv64si-p-scall"), "{}", "rv64si-p-scall");
+    assert!(run("rv64si-p-wfi"), "{}", "rv64si-p-wfi");
+}
@@ -3,11 +3,55 @@
 #![cfg(target_arch = "wasm32")]
 
 extern crate wasm_bindgen_test;
+use five::web::Five;
+use js_sys::{Array, Function};
 use wasm_bindgen_test::*;
 
 wasm_bindgen_test_configure!(run_in_browser);
 
+// The address that the image is loaded at.
+const MEMORY_BASE_ADDRESS: u64 = 0x8000_0000;
+
+fn image(program: &[u32]) -> Vec<u8> {
+    program.iter().flat_map(|i| i.to_le_bytes()).collect()
+}
+
+#[wasm_bindgen_test]
+fn run_ok() {
+    let mut five = Five::new();
+    assert!(five
+        .load(&image(&[
+            0x0015_0513, // addi a0, a0, 1
+            0xffdf_f06f, // jal zero, -4
+        ]))
+        .is_ok());
+    let stop = five.run(5);
+    assert_eq!(stop.reason, "limit");
+    assert_eq!(five.x(10), 3);
+    assert_eq!(five.pc(), MEMORY_BASE_ADDRESS + 4);
+    assert_eq!(
+        five.read_memory(MEMORY_BASE_ADDRESS, 1).ok(),
+        Some(vec![0x13])
+    );
+    assert!(five.read_memory(0, 4).is_err());
+}
+
 #[wasm_bindgen_test]
-fn pass() {
-    assert_eq!(1 + 1, 2);
+fn uart_ok() {
+    let mut five = Five::new();
+    assert!(five
+        .load(&image(&[
+            0x1000_0537, // lui a0, 0x10000
+            0x0680_0593, // li a1, 'h'
+            0x00b5_0023, // sb a1, 0(a0)
+            0x0690_0593, // li a1, 'i'
+            0x00b5_0023, // sb a1, 0(a0)
+            0xffdf_f06f, // jal zero, -4
+        ]))
+        .is_ok());
+    let received = Array::new();
+    let callback = Function::new_with_args("text", "this.push(text)").bind(&received);
+    five.on_uart(callback);
+    five.run(10);
+    assert_eq!(received.join(""), "hi");
 }